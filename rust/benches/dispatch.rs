@@ -0,0 +1,61 @@
+// Benchmarks of dispatching many trivial tasks.
+
+#[macro_use]
+extern crate criterion;
+extern crate ljobs;
+
+use criterion::Criterion;
+use ljobs::{Options, TaskSource};
+use std::ffi::OsString;
+
+const NUMTASKS: usize = 100_000;
+
+// A dry run goes through the whole dispatch path except for spawning.
+fn dispatch(c: &mut Criterion) {
+    let tasks: Vec<OsString> = (0..NUMTASKS)
+        .map(|i| OsString::from(format!("dir/file{}.txt", i)))
+        .collect();
+
+    let opts = Options::new()
+        .command("convert")
+        .args(vec!["-quality", "90", "{}", "{.}.jpg"])
+        .dry_run(true);
+    c.bench_function("dispatch", |b| b.iter(|| {
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap()
+    }));
+
+    let opts = Options::new()
+        .command("echo \"$@\"")
+        .args(vec!["{/}", "{#}"])
+        .shell(Some(OsString::from("/bin/sh")))
+        .dry_run(true);
+    c.bench_function("dispatch_shell", |b| b.iter(|| {
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap()
+    }));
+}
+
+// Starting commands should not slow down as ljobs grows, as it would if the
+// page tables were copied for each child.
+fn spawn(c: &mut Criterion) {
+    let tasks: Vec<OsString> = (0..1000)
+        .map(|i| OsString::from(i.to_string()))
+        .collect();
+    let opts = Options::new().command("true").jobs(8);
+
+    c.bench_function("spawn", |b| b.iter(|| {
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap()
+    }));
+
+    let heap = vec![1u8; 1 << 30];
+    c.bench_function("spawn_large_heap", |b| b.iter(|| {
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap()
+    }));
+    drop(heap);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = dispatch, spawn
+}
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+/*
+** Cache of successful task results.
+*/
+
+// Each cache entry is a directory named by the SHA-256 digest of the
+// command line, optionally also covering the contents of the file named by
+// the task.  The directory holds the standard output, standard error and
+// exit status of the command.  Only successful results are cached.  Entries
+// are written to a temporary directory first then renamed into place, so
+// a reader never sees a partial entry.
+
+use sha2::{Digest, Sha256};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use output::Output;
+
+static TMPCOUNT: AtomicUsize = AtomicUsize::new(0);
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Cache {
+    dir:        PathBuf,
+    hashinput:  bool
+}
+
+impl Cache {
+    pub fn open(dir: &Path, hashinput: bool) -> io::Result<Cache> {
+        match fs::create_dir_all(dir) {
+            Ok(()) => Ok(Cache { dir: dir.to_path_buf(), hashinput }),
+            Err(err) => Err(io::Error::new(err.kind(),
+                format!("could not create cache directory {}: {}",
+                        dir.display(), err)))
+        }
+    }
+
+    pub fn key(&self, argv: &[OsString], task: &OsStr) -> String {
+        let mut hasher = Sha256::new();
+        for arg in argv {
+            hasher.update(arg.as_encoded_bytes());
+            hasher.update(b"\0");
+        }
+        if self.hashinput {
+            if let Ok(mut f) = File::open(task) {
+                let mut buf = [0u8; 8192];
+                hasher.update(b"\0input\0");
+                while let Ok(n) = f.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                }
+            }
+        }
+        hasher.finalize().iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// The standard output and standard error of a cached successful run.
+    pub fn lookup(&self, key: &str) -> Option<(Output, Output)> {
+        let entry = self.dir.join(key);
+        let exit = fs::read_to_string(entry.join("exit")).ok()?;
+        if exit.trim() != "0" {
+            return None;
+        }
+        let stdout = File::open(entry.join("stdout")).ok()?;
+        let stderr = File::open(entry.join("stderr")).ok()?;
+        Some((Output::from_file(stdout).ok()?,
+              Output::from_file(stderr).ok()?))
+    }
+
+    pub fn store(&self,
+                 key: &str,
+                 stdout: &mut Output,
+                 stderr: &mut Output,
+                 exit: i32) -> io::Result<()> {
+
+        let tmp = self.dir.join(format!(".tmp-{}-{}-{}", key, process::id(),
+            TMPCOUNT.fetch_add(1, Ordering::SeqCst)));
+        let res = fs::create_dir(&tmp)
+            .and_then(|_| write_output(&tmp.join("stdout"), stdout))
+            .and_then(|_| write_output(&tmp.join("stderr"), stderr))
+            .and_then(|_| fs::write(tmp.join("exit"), format!("{}\n", exit)));
+        if let Err(err) = res {
+            let _ = fs::remove_dir_all(&tmp);
+            return Err(io::Error::new(err.kind(),
+                format!("cache write error: {}", err)));
+        }
+        if fs::rename(&tmp, self.dir.join(key)).is_err() {
+            // Another run stored the same entry first.
+            let _ = fs::remove_dir_all(&tmp);
+        }
+        Ok(())
+    }
+}
+
+fn write_output(path: &Path, output: &mut Output) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    output.for_each_chunk(|data| f.write_all(data))
+}
@@ -0,0 +1,347 @@
+/*
+** Running jobs in cgroups with resource limits.
+*/
+
+// With `cgroup`, each job is started in a cgroup v2 of its own, with the
+// memory and CPU limits given.  ljobs must be allowed to manage the cgroup
+// that it was started in, as when run with `systemd-run --user --scope -p
+// Delegate=yes`.  A cgroup with processes in it cannot have the controllers
+// enabled for its children, so ljobs first moves itself into a child
+// cgroup, `ljobs.PID`, and then enables the controllers:
+//
+//     CGROUP/                 memory and cpu enabled for the children
+//         ljobs.PID/          ljobs itself
+//         ljobs.PID.1/        a job, with memory.max and cpu.max
+//         ljobs.PID.2/
+//
+// A job is put in its cgroup by the child process itself, before it runs
+// the command, by writing to the cgroup.procs file opened for it.  So the
+// command and anything it starts are in the cgroup from the beginning.
+// Once the job has finished, anything left running in its cgroup is killed
+// and the cgroup is removed.  At the end of the run, ljobs moves itself
+// back and removes its own cgroup.
+//
+// When the memory limit is reached, the kernel kills a process of the job,
+// which is noted in memory.events.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use parse_size;
+
+// The period that the CPU limit is a share of, in microseconds.
+const CPU_PERIOD: u64 = 100_000;
+
+// How long to wait for the processes left in a job's cgroup to be killed.
+const KILL_TRIES: u32 = 10;
+const KILL_WAIT: Duration = Duration::from_millis(10);
+
+/*---------------------------------------------------------------------------*/
+
+/// Resource limits for each job, in its own cgroup.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CgroupLimits {
+    /// The most memory in bytes that the job may use.
+    pub memory:     Option<u64>,
+    /// The most CPU time that the job may use, as a percentage of one CPU.
+    pub cpu:        Option<u64>
+}
+
+/// The cgroups of a run.
+pub struct Cgroups {
+    // The cgroup that ljobs was started in.
+    base:       PathBuf,
+    limits:     CgroupLimits,
+    pid:        u32,
+    next:       usize,
+    // What is to be undone at the end: whether ljobs moved itself, and
+    // the controllers that it enabled.
+    moved:      bool,
+    enabled:    Vec<&'static str>
+}
+
+/// The cgroup of one job, removed when dropped.
+pub struct JobCgroup {
+    path:       PathBuf,
+    procs:      File
+}
+
+impl CgroupLimits {
+    /// Parse limits such as `mem=2G,cpu=150%`.  Memory sizes may have a
+    /// suffix K, M, G or T for binary multiples, and the percent sign is
+    /// optional.
+    pub fn parse(s: &str) -> Option<CgroupLimits> {
+        let mut limits = CgroupLimits::default();
+        for item in s.split(',') {
+            let (key, value) = item.split_once('=')?;
+            match key {
+                "mem" | "memory" => {
+                    limits.memory = Some(parse_size(value)
+                                         .filter(|&n| n > 0)?);
+                },
+                "cpu" => {
+                    let percent = value.strip_suffix('%').unwrap_or(value);
+                    // Too large a percentage would overflow cpu.max.
+                    limits.cpu = Some(percent.parse().ok()
+                                      .filter(|&n: &u64| n > 0)
+                                      .filter(|n| n.checked_mul(CPU_PERIOD)
+                                                   .is_some())?);
+                },
+                _ => return None
+            }
+        }
+        Some(limits)
+    }
+
+    // The controllers that the limits need.
+    fn controllers(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.memory.is_some() {
+            names.push("memory");
+        }
+        if self.cpu.is_some() {
+            names.push("cpu");
+        }
+        names
+    }
+}
+
+// Controllers as written to cgroup.subtree_control to enable or disable
+// them.
+fn subtree_control(names: &[&str], enable: bool) -> String {
+    let sign = if enable { '+' } else { '-' };
+    names.iter()
+        .map(|name| format!("{}{}", sign, name))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The contents of cpu.max for a percentage of one CPU.
+fn cpu_max(percent: u64) -> String {
+    format!("{} {}", percent * CPU_PERIOD / 100, CPU_PERIOD)
+}
+
+// Where the cgroup v2 hierarchy is mounted, from the contents of
+// /proc/self/mountinfo.  It is usually /sys/fs/cgroup, but is
+// /sys/fs/cgroup/unified on systems that still mount cgroup v1 too.
+pub fn cgroup2_mount(mountinfo: &[u8]) -> Option<PathBuf> {
+    let mountinfo = std::str::from_utf8(mountinfo).ok()?;
+    mountinfo.lines().find_map(|line| {
+        let (mount, fs) = line.split_once(" - ")?;
+        if fs.split(' ').next() != Some("cgroup2") {
+            return None;
+        }
+        mount.split(' ').nth(4).map(PathBuf::from)
+    })
+}
+
+// The cgroup v2 path of a process, from the contents of /proc/PID/cgroup.
+pub fn own_cgroup(mount: &Path, contents: &[u8]) -> Option<PathBuf> {
+    let line = contents.split(|&b| b == b'\n')
+        .find_map(|line| line.strip_prefix(b"0::"))?;
+    let path = std::str::from_utf8(line).ok()?;
+    let path = path.strip_prefix('/')?;
+    Some(mount.join(path))
+}
+
+fn job_path(base: &Path, pid: u32, seq: usize) -> PathBuf {
+    base.join(format!("ljobs.{}.{}", pid, seq))
+}
+
+fn path_error(path: &Path, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    OpenOptions::new().write(true).open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| path_error(path, err))
+}
+
+impl Cgroups {
+    /// Move ljobs into a cgroup of its own and enable the controllers for
+    /// the limits.  Fails if cgroup v2 is not there to be managed.
+    pub fn new(limits: CgroupLimits) -> io::Result<Cgroups> {
+        let mountinfo = fs::read("/proc/self/mountinfo")?;
+        let contents = fs::read("/proc/self/cgroup")?;
+        let base = cgroup2_mount(&mountinfo)
+            .and_then(|mount| own_cgroup(&mount, &contents))
+            .ok_or_else(|| io::Error::other("no cgroup v2 hierarchy"))?;
+        let control = base.join("cgroup.subtree_control");
+        let enabled = fs::read_to_string(&control)
+            .map_err(|err| path_error(&control, err))?;
+        let enabled: Vec<&str> = enabled.split_whitespace().collect();
+        let pid = process::id();
+        // Dropping the cgroups undoes what was done if anything fails.
+        let mut cgroups = Cgroups {
+            base,
+            limits,
+            pid,
+            next:    0,
+            moved:   false,
+            enabled: Vec::new()
+        };
+        let own = cgroups.own_path();
+        fs::create_dir(&own).map_err(|err| path_error(&own, err))?;
+        write_file(&own.join("cgroup.procs"), &pid.to_string())?;
+        cgroups.moved = true;
+        let needed: Vec<&'static str> = limits.controllers().into_iter()
+            .filter(|name| !enabled.contains(name))
+            .collect();
+        if !needed.is_empty() {
+            write_file(&control, &subtree_control(&needed, true))?;
+            cgroups.enabled = needed;
+        }
+        Ok(cgroups)
+    }
+
+    /// A new cgroup for a job, with the limits set.
+    pub fn create(&mut self) -> io::Result<JobCgroup> {
+        self.next += 1;
+        let path = job_path(&self.base, self.pid, self.next);
+        fs::create_dir(&path).map_err(|err| path_error(&path, err))?;
+        let procs = match OpenOptions::new().write(true)
+            .open(path.join("cgroup.procs"))
+        {
+            Ok(procs) => procs,
+            Err(err) => {
+                let _ = fs::remove_dir(&path);
+                return Err(path_error(&path, err));
+            }
+        };
+        // Removed again if anything goes wrong from here.
+        let mut cgroup = JobCgroup { path, procs };
+        if let Some(memory) = self.limits.memory {
+            cgroup.set("memory.max", &memory.to_string())?;
+        }
+        if let Some(cpu) = self.limits.cpu {
+            cgroup.set("cpu.max", &cpu_max(cpu))?;
+        }
+        Ok(cgroup)
+    }
+
+    fn own_path(&self) -> PathBuf {
+        self.base.join(format!("ljobs.{}", self.pid))
+    }
+}
+
+impl Drop for Cgroups {
+    fn drop(&mut self) {
+        if !self.enabled.is_empty() {
+            let _ = write_file(&self.base.join("cgroup.subtree_control"),
+                               &subtree_control(&self.enabled, false));
+        }
+        if self.moved {
+            let _ = write_file(&self.base.join("cgroup.procs"),
+                               &self.pid.to_string());
+        }
+        let _ = fs::remove_dir(self.own_path());
+    }
+}
+
+impl JobCgroup {
+    /// The cgroup.procs file of the cgroup, for the child to write "0" to.
+    pub fn procs(&self) -> &File {
+        &self.procs
+    }
+
+    /// Whether the kernel killed a process of the job for reaching the
+    /// memory limit.
+    pub fn oom_killed(&self) -> bool {
+        let events = match fs::read(self.path.join("memory.events")) {
+            Ok(events) => events,
+            Err(_) => return false
+        };
+        events.split(|&b| b == b'\n')
+            .filter_map(|line| line.strip_prefix(b"oom_kill "))
+            .any(|count| count != b"0")
+    }
+
+    fn set(&mut self, file: &str, value: &str) -> io::Result<()> {
+        write_file(&self.path.join(file), value)
+    }
+}
+
+impl Drop for JobCgroup {
+    fn drop(&mut self) {
+        if fs::remove_dir(&self.path).is_ok() {
+            return;
+        }
+        // Kill whatever the job left running.
+        let _ = write_file(&self.path.join("cgroup.kill"), "1");
+        for _ in 0..KILL_TRIES {
+            thread::sleep(KILL_WAIT);
+            if fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+        }
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{cgroup2_mount, cpu_max, job_path, own_cgroup,
+                subtree_control, CgroupLimits};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn parse_limits() {
+        assert_eq!(CgroupLimits::parse("mem=2G,cpu=150%"),
+                   Some(CgroupLimits {
+                       memory: Some(2 << 30),
+                       cpu:    Some(150)
+                   }));
+        assert_eq!(CgroupLimits::parse("memory=512k"),
+                   Some(CgroupLimits { memory: Some(512 << 10), cpu: None }));
+        assert_eq!(CgroupLimits::parse("cpu=50"),
+                   Some(CgroupLimits { memory: None, cpu: Some(50) }));
+        assert_eq!(CgroupLimits::parse("mem=1000"),
+                   Some(CgroupLimits { memory: Some(1000), cpu: None }));
+        assert_eq!(CgroupLimits::parse(""), None);
+        assert_eq!(CgroupLimits::parse("mem=0"), None);
+        assert_eq!(CgroupLimits::parse("mem=2X"), None);
+        assert_eq!(CgroupLimits::parse("cpu=0%"), None);
+        assert_eq!(CgroupLimits::parse("cpu=1000000000000000%"), None);
+        assert_eq!(CgroupLimits::parse("io=1"), None);
+    }
+
+    #[test]
+    fn controllers() {
+        let limits = CgroupLimits { memory: Some(1), cpu: Some(1) };
+        assert_eq!(limits.controllers(), vec!["memory", "cpu"]);
+        assert_eq!(subtree_control(&limits.controllers(), true),
+                   "+memory +cpu");
+        let limits = CgroupLimits { memory: None, cpu: Some(1) };
+        assert_eq!(subtree_control(&limits.controllers(), false), "-cpu");
+        assert_eq!(cpu_max(150), "150000 100000");
+        assert_eq!(cpu_max(5), "5000 100000");
+    }
+
+    #[test]
+    fn paths() {
+        let mountinfo = b"25 1 0:23 / /sys rw - sysfs sysfs rw\n\
+                          36 25 0:31 / /sys/fs/cgroup/unified rw,nosuid \
+                          shared:10 - cgroup2 cgroup2 rw,nsdelegate\n\
+                          37 25 0:32 / /sys/fs/cgroup/cpu rw shared:11 - \
+                          cgroup cgroup rw,cpu\n";
+        let mount = cgroup2_mount(mountinfo).unwrap();
+        assert_eq!(mount, PathBuf::from("/sys/fs/cgroup/unified"));
+        assert_eq!(cgroup2_mount(b"25 1 0:23 / /sys rw - sysfs sysfs rw\n"),
+                   None);
+        let contents = b"1:name=systemd:/x\n\
+                         0::/user.slice/user-1000.slice/run-1.scope\n";
+        assert_eq!(own_cgroup(&mount, contents),
+                   Some(PathBuf::from("/sys/fs/cgroup/unified/user.slice/\
+                                       user-1000.slice/run-1.scope")));
+        assert_eq!(own_cgroup(&mount, b"0::/\n"), Some(mount.clone()));
+        assert_eq!(own_cgroup(&mount, b"1:cpu:/x\n"), None);
+        assert_eq!(job_path(Path::new("/sys/fs/cgroup/a"), 42, 3),
+                   PathBuf::from("/sys/fs/cgroup/a/ljobs.42.3"));
+    }
+}
@@ -0,0 +1,209 @@
+/*
+** Spellings of GNU parallel options.
+*/
+
+// Many options of ljobs are spelled as in GNU parallel, such as -j, -a,
+// --dry-run and --tag.  The options in ALIASES are other spellings of
+// GNU parallel that are taken, with a notice, as the ljobs options that
+// do the same, so that a command line written for parallel does what it
+// did there.  An alias whose argument selects a behaviour maps each value
+// that ljobs can match separately.  Where ljobs has no equivalent, the
+// alias is refused rather than quietly doing something else.  -k and -n
+// keep their ljobs meanings, --keep-going and --dry-run, rather than
+// parallel's --keep-order and --max-args.
+
+use getopt::{Getopt, Matches};
+use ljobs::Error;
+
+/// An option of GNU parallel and what it is in ljobs.
+pub struct Alias {
+    pub short:  &'static str,
+    pub long:   &'static str,
+    /// The hint for the argument, if the option takes one.
+    pub hint:   Option<&'static str>,
+    pub native: Native
+}
+
+/// The ljobs options that an alias stands for.
+pub enum Native {
+    /// These arguments with -c, and none without, as the command is then
+    /// not run by a shell.
+    Shell(&'static [&'static str]),
+    /// These arguments, for an option without an argument.
+    Args(&'static [&'static str]),
+    /// These arguments for each value of the argument that ljobs can do.
+    Values(&'static [(&'static str, &'static [&'static str])]),
+    /// Nothing in ljobs does this, for the given reason.
+    Missing(&'static str)
+}
+
+pub const ALIASES: &[Alias] = &[
+    Alias {
+        short:  "",
+        long:   "keep-order",
+        hint:   None,
+        native: Native::Missing("ljobs prints the output of each task as \
+                                 it finishes")
+    },
+    Alias {
+        short:  "",
+        long:   "halt",
+        hint:   Some("WHEN"),
+        native: Native::Values(&[
+            ("never", &["--keep-going"]),
+            ("0", &["--keep-going"]),
+            ("soon,fail=1", &[]),
+            ("1", &[]),
+            ("now,fail=1", &["--halt-timeout=0"]),
+            ("2", &["--halt-timeout=0"])
+        ])
+    },
+    Alias {
+        short:  "",
+        long:   "line-buffer",
+        hint:   None,
+        native: Native::Missing("ljobs buffers the output of each task \
+                                 until it finishes")
+    },
+    Alias {
+        short:  "",
+        long:   "lb",
+        hint:   None,
+        native: Native::Missing("ljobs buffers the output of each task \
+                                 until it finishes")
+    },
+    Alias {
+        short:  "q",
+        long:   "quote",
+        hint:   None,
+        native: Native::Shell(&["--no-shell-expand"])
+    },
+    Alias {
+        short:  "",
+        long:   "max-args",
+        hint:   Some("NUM"),
+        native: Native::Values(&[("1", &[])])
+    },
+    Alias {
+        short:  "r",
+        long:   "no-run-if-empty",
+        hint:   None,
+        native: Native::Args(&[])
+    }
+];
+
+/*---------------------------------------------------------------------------*/
+
+/// Add the aliases to `getopt`, left out of the usage message.
+pub fn register(getopt: &mut Getopt) {
+    for alias in ALIASES {
+        let desc = "option of GNU parallel";
+        match alias.hint {
+            Some(hint) => getopt.optopt(alias.short, alias.long, desc, hint),
+            None => getopt.optflag(alias.short, alias.long, desc)
+        }
+        getopt.hide_last();
+    }
+}
+
+/// The ljobs arguments for the aliases given, and a notice for each alias.
+pub fn native_args(matches: &Matches)
+    -> Result<(Vec<&'static str>, Vec<String>), Error> {
+
+    let mut args = Vec::new();
+    let mut notices = Vec::new();
+    for alias in ALIASES.iter().filter(|a| matches.opt_present(a.long)) {
+        let value = matches.opt_str(alias.long);
+        let given = match value {
+            Some(ref value) => format!("--{} {}", alias.long, value),
+            None => format!("--{}", alias.long)
+        };
+        let native: &[&str] = match alias.native {
+            Native::Shell(native) if matches.opt_present("c") => native,
+            Native::Shell(_) => &[],
+            Native::Args(native) => native,
+            Native::Values(values) => values.iter()
+                .find(|&&(v, _)| Some(v) == value.as_deref())
+                .map(|&(_, native)| native)
+                .ok_or_else(|| Error::Usage(format!(
+                    "GNU parallel's {} is not supported by ljobs", given)))?,
+            Native::Missing(reason) => return Err(Error::Usage(format!(
+                "GNU parallel's {} is not supported: {}", given, reason)))
+        };
+        notices.push(if native.is_empty() {
+            format!("GNU parallel's {} is what ljobs does anyway", given)
+        } else {
+            format!("GNU parallel's {} is taken as {}", given,
+                    native.join(" "))
+        });
+        args.extend_from_slice(native);
+    }
+    Ok((args, notices))
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{Native, ALIASES};
+    use process_options;
+    use std::ffi::OsString;
+
+    // The options that the command line gives, as dumped by --dump-args.
+    fn options(args: &[&str]) -> String {
+        let argv: Vec<OsString> = ["ljobs"].iter().chain(args)
+            .map(OsString::from)
+            .collect();
+        match process_options(&argv) {
+            Ok((opts, _)) => format!("{:?}", opts),
+            Err(err) => format!("error: {}", err)
+        }
+    }
+
+    #[test]
+    fn same_as_native() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&["--halt", "never"], &["-k"]),
+            (&["--halt=0"], &["--keep-going"]),
+            (&["--halt", "soon,fail=1"], &[]),
+            (&["--halt", "now,fail=1"], &["--halt-timeout", "0"]),
+            (&["--halt=2", "-v"], &["-v", "--halt-timeout=0"]),
+            (&["-q", "-c"], &["-c", "--no-shell-expand"]),
+            (&["-cq"], &["-c", "--no-shell-expand"]),
+            (&["--quote", "-c", "-j2"], &["--no-shell-expand", "-cj", "2"]),
+            (&["--quote"], &[]),
+            (&["--max-args", "1"], &[]),
+            (&["-r"], &[])
+        ];
+        for &(alias, native) in cases {
+            let with = |args: &[&str]| {
+                options(&[args, &["echo", "x"]].concat())
+            };
+            assert_eq!(with(alias), with(native), "{:?}", alias);
+        }
+    }
+
+    #[test]
+    fn refused() {
+        for alias in ALIASES {
+            if let Native::Missing(reason) = alias.native {
+                assert_eq!(options(&[&format!("--{}", alias.long), "echo"]),
+                           format!("error: GNU parallel's --{} is not \
+                                    supported: {}", alias.long, reason));
+            }
+        }
+        assert_eq!(options(&["--halt", "now,fail=2", "echo"]),
+                   "error: GNU parallel's --halt now,fail=2 is not \
+                    supported by ljobs");
+        assert_eq!(options(&["--max-args=2", "echo"]),
+                   "error: GNU parallel's --max-args 2 is not supported \
+                    by ljobs");
+    }
+
+    #[test]
+    fn command_words_kept() {
+        // Aliases after the command word are the command's.
+        assert_eq!(options(&["echo", "--quote"]),
+                   options(&["--", "echo", "--quote"]));
+    }
+}
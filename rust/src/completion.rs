@@ -0,0 +1,269 @@
+/*
+** Shell completion scripts.
+*/
+
+// The scripts are generated from the same table of options that the
+// command line is parsed with, so they cannot drift from it.  Options are
+// completed up to the command word, after which the words belong to the
+// command, as options are only parsed up to the first free argument or
+// "--".  The arguments of options with a few fixed values, such as
+// --color, complete to those values.
+
+use getopt::{Getopt, HasArg, OptDesc};
+
+/*---------------------------------------------------------------------------*/
+
+/// The completion script for `shell`, bash, zsh or fish.
+pub fn script(getopt: &Getopt, shell: &str) -> Option<String> {
+    let opts: Vec<&OptDesc> = getopt.options().collect();
+    match shell {
+        "bash" => Some(bash(&opts)),
+        "zsh" => Some(zsh(&opts)),
+        "fish" => Some(fish(&opts)),
+        _ => None
+    }
+}
+
+// The short options without an argument, and with one, as for a bracket
+// expression matching a cluster such as -kj.
+fn short_sets(opts: &[&OptDesc]) -> (String, String) {
+    let mut flags = String::new();
+    let mut args = String::new();
+    for o in opts {
+        match o.hasarg {
+            _ if o.short.is_empty() => (),
+            HasArg::Yes => args.push_str(&o.short),
+            HasArg::No | HasArg::Maybe => flags.push_str(&o.short)
+        }
+    }
+    (flags, args)
+}
+
+// The long options that take the next argument as theirs.
+fn long_args(opts: &[&OptDesc]) -> Vec<String> {
+    opts.iter()
+        .filter(|o| o.hasarg == HasArg::Yes && !o.long.is_empty())
+        .map(|o| format!("--{}", o.long))
+        .collect()
+}
+
+// The names of an option as given on the command line.
+fn names(o: &OptDesc) -> Vec<String> {
+    let mut names = Vec::new();
+    if !o.short.is_empty() {
+        names.push(format!("-{}", o.short));
+    }
+    if !o.long.is_empty() {
+        names.push(format!("--{}", o.long));
+    }
+    names
+}
+
+// Quote `s` for a POSIX shell or zsh.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// Quote `s` for fish, where a backslash escapes a quote or a backslash.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/*---------------------------------------------------------------------------*/
+
+fn bash(opts: &[&OptDesc]) -> String {
+    let (flags, args) = short_sets(opts);
+    let words: Vec<String> = opts.iter().flat_map(|o| names(o)).collect();
+    let mut values = String::new();
+    for o in opts.iter().filter(|o| !o.choices.is_empty()) {
+        values.push_str(&format!(
+            "            {})\n                \
+             COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;\n",
+            names(o).join("|"), o.choices.join(" ")));
+    }
+    format!(r#"# bash completion for ljobs, from ljobs --completions bash
+
+_ljobs()
+{{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    local prev=${{COMP_WORDS[COMP_CWORD-1]}}
+    local i=1 word opt dashdash=
+
+    # Skip the options and their arguments, up to the command word or --.
+    # Bash splits --opt=ARG into three words.
+    while [ $i -lt $COMP_CWORD ]; do
+        word=${{COMP_WORDS[i]}}
+        if [ "${{COMP_WORDS[i+1]}}" = "=" ]; then
+            i=$((i + 3))
+            continue
+        fi
+        case $word in
+            --)
+                i=$((i + 1))
+                dashdash=1
+                break ;;
+            {longargs})
+                i=$((i + 2)) ;;
+            -?*)
+                if [[ $word =~ ^-[{flags}]*[{args}]$ ]]; then
+                    i=$((i + 2))
+                else
+                    i=$((i + 1))
+                fi ;;
+            *)
+                break ;;
+        esac
+    done
+
+    if [ $i -lt $COMP_CWORD ]; then
+        # An argument of the command.
+        return
+    fi
+    if [ $i -gt $COMP_CWORD ]; then
+        # The argument of an option.
+        opt=$prev
+        if [ "$cur" = "=" ]; then
+            cur=
+        elif [ "$prev" = "=" ]; then
+            opt=${{COMP_WORDS[COMP_CWORD-2]}}
+        fi
+        case $opt in
+{values}        esac
+        return
+    fi
+    case $cur in
+        -*)
+            if [ -z "$dashdash" ]; then
+                COMPREPLY=($(compgen -W "{words}" -- "$cur"))
+            fi ;;
+        *)
+            COMPREPLY=($(compgen -c -- "$cur")) ;;
+    esac
+}}
+
+complete -o default -F _ljobs ljobs
+"#,
+        longargs = long_args(opts).join("|"),
+        flags = flags,
+        args = args,
+        values = values,
+        words = words.join(" "))
+}
+
+/*---------------------------------------------------------------------------*/
+
+fn zsh(opts: &[&OptDesc]) -> String {
+    let mut specs = Vec::new();
+    for o in opts {
+        // Brackets and colons end parts of a spec.
+        let escape = |s: &str| -> String {
+            s.chars()
+                .flat_map(|c| match c {
+                    '[' | ']' | ':' | '\\' => vec!['\\', c],
+                    _ => vec![c]
+                })
+                .collect()
+        };
+        let (desc, hint) = (escape(&o.desc), escape(&o.hint));
+        let action = if !o.choices.is_empty() {
+            format!("({})", o.choices.join(" "))
+        } else if o.hint == "DIR" {
+            String::from("_files -/")
+        } else if o.hint.contains("FILE") {
+            String::from("_files")
+        } else {
+            String::from(" ")
+        };
+        let names = names(o);
+        let exclude = if o.multi {
+            String::from("*")
+        } else {
+            format!("({})", names.join(" "))
+        };
+        for name in &names {
+            let arg = match o.hasarg {
+                HasArg::No => String::new(),
+                HasArg::Yes => format!(":{}:{}", hint, action),
+                HasArg::Maybe => format!("::{}:{}", hint, action)
+            };
+            let suffix = match (o.hasarg, name.starts_with("--")) {
+                (HasArg::No, _) => "",
+                (HasArg::Yes, true) => "=",
+                (HasArg::Yes, false) => "+",
+                (HasArg::Maybe, true) => "=-",
+                (HasArg::Maybe, false) => "-"
+            };
+            let spec = format!("{}{}{}[{}]{}", exclude, name, suffix, desc,
+                               arg);
+            specs.push(format!("    {} \\\n", quote(&spec)));
+        }
+    }
+    format!("#compdef ljobs\n\
+             # zsh completion for ljobs, from ljobs --completions zsh\n\n\
+             # Options end at the command word, or after --.\n\
+             _arguments -s -S -A '-*' \\\n\
+             {}    '1:command:_command_names -e' \\\n    \
+             '*::arguments:_normal'\n",
+            specs.concat())
+}
+
+/*---------------------------------------------------------------------------*/
+
+fn fish(opts: &[&OptDesc]) -> String {
+    let (flags, args) = short_sets(opts);
+    let mut lines = String::new();
+    for o in opts {
+        let mut line = String::from("complete -c ljobs -n __ljobs_options");
+        if !o.short.is_empty() {
+            line.push_str(&format!(" -s {}", o.short));
+        }
+        if !o.long.is_empty() {
+            line.push_str(&format!(" -l {}", o.long));
+        }
+        match o.hasarg {
+            HasArg::No => (),
+            HasArg::Yes if !o.choices.is_empty() => line.push_str(" -x"),
+            HasArg::Yes if o.hint.contains("FILE") || o.hint == "DIR" =>
+                line.push_str(" -r -F"),
+            HasArg::Yes => line.push_str(" -x"),
+            HasArg::Maybe => line.push_str(" -f")
+        }
+        if !o.choices.is_empty() {
+            let choices = o.choices.join(" ");
+            line.push_str(&format!(" -a {}", fish_quote(&choices)));
+        }
+        line.push_str(&format!(" -d {}\n", fish_quote(&o.desc)));
+        lines.push_str(&line);
+    }
+    format!(r#"# fish completion for ljobs, from ljobs --completions fish
+
+# Whether the options have not yet ended at the command word or at --.
+function __ljobs_options
+    set -l words (commandline -opc)
+    set -l i 2
+    while test $i -le (count $words)
+        switch $words[$i]
+            case --
+                return 1
+            case --\*=\*
+            case {longargs}
+                set i (math $i + 1)
+            case '-?*'
+                if string match -qr -- '^-[{flags}]*[{args}]$' $words[$i]
+                    set i (math $i + 1)
+                end
+            case '*'
+                return 1
+        end
+        set i (math $i + 1)
+    end
+    return 0
+end
+
+{lines}complete -c ljobs -n __ljobs_options -f -a '(__fish_complete_command)'
+"#,
+        longargs = long_args(opts).join(" "),
+        flags = flags,
+        args = args,
+        lines = lines)
+}
@@ -0,0 +1,235 @@
+/*
+** Default options from the config file and the environment.
+*/
+
+// Options that a user wants on every run can be put in the config file,
+// $XDG_CONFIG_HOME/ljobs/config, one option per line, or in the LJOBS
+// environment variable.  Both are split into words as a shell would, but
+// without expansions.  They are taken before the command line, config file
+// first, so an option that can be given only once takes its value from the
+// command line if it is there, then from LJOBS, then from the config file.
+// --no-config on the command line ignores both.
+//
+// The config file may also have sections headed [profile.NAME], with
+// options that are only taken with --profile NAME.  Profiles are taken
+// after LJOBS, in the order given, just before the command line.  Options
+// before the first section are the defaults.
+
+use ljobs::Error;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+
+/// The environment variable that holds default options.
+pub const ENV_VAR: &str = "LJOBS";
+
+/// Default arguments and where they came from, for messages.
+pub struct Layer {
+    pub source: String,
+    pub args:   Vec<OsString>
+}
+
+/// What the config file and LJOBS give.
+#[derive(Default)]
+pub struct Config {
+    /// The layers of default arguments, config file first, leaving out any
+    /// source that is not there.
+    pub layers: Vec<Layer>,
+    // The profiles in the config file, in the order of their sections.
+    profiles:   Vec<(String, Vec<OsString>)>
+}
+
+impl Config {
+    /// The arguments of the profile `name`.
+    pub fn profile(&self, name: &str) -> Result<Layer, Error> {
+        match self.profiles.iter().find(|p| p.0 == name) {
+            Some((_, args)) => Ok(Layer {
+                source: format!("profile {}", name),
+                args:   args.clone()
+            }),
+            None if self.profiles.is_empty() => Err(Error::Usage(format!(
+                "unknown profile '{}'; the config file has no profiles",
+                name))),
+            None => Err(Error::Usage(format!(
+                "unknown profile '{}'; the profiles are: {}", name,
+                self.profiles.iter()
+                    .map(|p| p.0.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "))))
+        }
+    }
+}
+
+/// Read the config file and LJOBS.
+pub fn read() -> Result<Config, Error> {
+    let mut config = Config::default();
+    if let Some(path) = config_path() {
+        let text = match fs::read(&path) {
+            Ok(text) => text,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                Vec::new()
+            },
+            Err(err) => return Err(Error::Usage(format!(
+                "cannot read {}: {}", path.display(), err)))
+        };
+        let mut args = Vec::new();
+        let mut section: Option<usize> = None;
+        for (i, line) in text.split(|&c| c == b'\n').enumerate() {
+            let line = line.trim_ascii();
+            if line.is_empty() || line[0] == b'#' {
+                continue;
+            }
+            let error = |msg: &str| {
+                Error::Usage(format!("{}:{}: {}", path.display(), i + 1, msg))
+            };
+            if line[0] == b'[' {
+                let name = profile_name(line)
+                    .ok_or_else(|| error("not a [profile.NAME] section"))?;
+                let profiles = &mut config.profiles;
+                let k = match profiles.iter().position(|p| p.0 == name) {
+                    Some(k) => k,
+                    None => {
+                        profiles.push((name, Vec::new()));
+                        profiles.len() - 1
+                    }
+                };
+                section = Some(k);
+                continue;
+            }
+            let words = split_words(line).map_err(|msg| error(&msg))?;
+            match section {
+                Some(k) => config.profiles[k].1.extend(words),
+                None => args.extend(words)
+            }
+        }
+        if !args.is_empty() {
+            config.layers.push(Layer {
+                source: path.display().to_string(),
+                args
+            });
+        }
+    }
+    if let Some(value) = env::var_os(ENV_VAR) {
+        let args = split_words(value.as_encoded_bytes()).map_err(|msg| {
+            Error::Usage(format!("{}: {}", ENV_VAR, msg))
+        })?;
+        if !args.is_empty() {
+            config.layers.push(Layer {
+                source: String::from(ENV_VAR),
+                args
+            });
+        }
+    }
+    Ok(config)
+}
+
+// The NAME of a [profile.NAME] line.
+fn profile_name(line: &[u8]) -> Option<String> {
+    let name = line.strip_prefix(b"[profile.")?.strip_suffix(b"]")?;
+    let valid = |c: &u8| c.is_ascii_alphanumeric() || b"-_.".contains(c);
+    if name.is_empty() || !name.iter().all(valid) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
+// An XDG_CONFIG_HOME that is not absolute is to be ignored.
+fn config_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(ref dir) if dir.is_absolute() => dir.clone(),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+    Some(base.join("ljobs").join("config"))
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// Split `text` into words at blanks, with single and double quotes and
+/// backslashes as in the shell.  Nothing is expanded.
+pub fn split_words(text: &[u8]) -> Result<Vec<OsString>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<Vec<u8>> = None;
+    let mut bytes = text.iter().cloned();
+
+    while let Some(c) = bytes.next() {
+        match c {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                words.extend(word.take().map(os_string));
+                continue;
+            },
+            _ => {}
+        }
+        let cur = word.get_or_insert_with(Vec::new);
+        match c {
+            b'\'' => loop {
+                match bytes.next() {
+                    Some(b'\'') => break,
+                    Some(c) => cur.push(c),
+                    None => return Err(String::from("unterminated quote"))
+                }
+            },
+            b'"' => loop {
+                match bytes.next() {
+                    Some(b'"') => break,
+                    Some(b'\\') => match bytes.next() {
+                        Some(c) if b"\\\"$`".contains(&c) => cur.push(c),
+                        Some(c) => cur.extend_from_slice(&[b'\\', c]),
+                        None => return Err(String::from("unterminated quote"))
+                    },
+                    Some(c) => cur.push(c),
+                    None => return Err(String::from("unterminated quote"))
+                }
+            },
+            b'\\' => match bytes.next() {
+                Some(c) => cur.push(c),
+                None => return Err(String::from("backslash at the end"))
+            },
+            c => cur.push(c)
+        }
+    }
+    words.extend(word.map(os_string));
+    Ok(words)
+}
+
+// The bytes of a word as an OS string.
+#[cfg(unix)]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from_vec(bytes)
+}
+
+// The bytes of a word as an OS string.  On Windows, where OS strings are
+// not arbitrary bytes, any that are not UTF-8 are replaced.
+#[cfg(windows)]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::split_words;
+    use std::ffi::OsString;
+
+    fn split(text: &str) -> Result<Vec<OsString>, String> {
+        split_words(text.as_bytes())
+    }
+
+    #[test]
+    fn words() {
+        let words = |ws: &[&str]| {
+            Ok(ws.iter().map(OsString::from).collect::<Vec<_>>())
+        };
+        assert_eq!(split(""), words(&[]));
+        assert_eq!(split("  -j 4\t--tag "), words(&["-j", "4", "--tag"]));
+        assert_eq!(split("--label 'job {#}'"), words(&["--label", "job {#}"]));
+        assert_eq!(split(r#"a"b c"d ''"#), words(&["ab cd", ""]));
+        assert_eq!(split(r#""\$x \y" a\ b"#), words(&["$x \\y", "a b"]));
+        assert_eq!(split("'a"), Err(String::from("unterminated quote")));
+        assert_eq!(split("a\\"), Err(String::from("backslash at the end")));
+    }
+}
@@ -0,0 +1,84 @@
+/*
+** Running commands in containers.
+*/
+
+// With `container`, each command is run in a new container from an image,
+// as `ENGINE run --rm --volume DIR:DIR --workdir DIR ARGS... IMAGE CMD...`
+// where ENGINE is podman or docker, whichever is found first, and DIR is
+// the current directory.  The current directory is mounted at the same
+// path in the container and made its working directory, so that tasks
+// which name files relative to it mean the same files inside.  ARGS are
+// the `container_arg` arguments, substituted per task like the command
+// arguments.
+//
+// Engines exit with 125 if they could not run the container, and with 126
+// or 127 if the command in it could not be run or was not found, as shells
+// do.  These are reported as such, though the command may exit with them
+// too.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+use os::from_os_bytes;
+use {Error, Options};
+
+const ENGINES: [&str; 2] = ["podman", "docker"];
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Container {
+    /// The command line up to the arguments for the engine.
+    pub prefix:     Vec<OsString>,
+    pub image:      OsString
+}
+
+impl Container {
+    /// Fails if no engine was given and none is found.
+    pub fn new(opts: &Options, image: &OsStr, cwd: &[u8])
+        -> Result<Container, Error> {
+
+        let engine = match opts.containerengine {
+            Some(ref engine) => engine.clone(),
+            None => find_engine().ok_or_else(|| Error::Usage(format!(
+                "no container engine found: {} or {}",
+                ENGINES[0], ENGINES[1])))?
+        };
+        let mut volume = cwd.to_vec();
+        volume.push(b':');
+        volume.extend_from_slice(cwd);
+        Ok(Container {
+            prefix: vec![
+                engine,
+                OsString::from("run"),
+                OsString::from("--rm"),
+                OsString::from("--volume"),
+                from_os_bytes(volume),
+                OsString::from("--workdir"),
+                from_os_bytes(cwd.to_vec())
+            ],
+            image:  image.to_os_string()
+        })
+    }
+}
+
+/// What an exit status from a command run in a container may mean, if it
+/// is one that the engine uses itself.
+pub fn failure(exit: i32) -> Option<&'static str> {
+    match exit {
+        125 => Some("the container could not be run"),
+        126 => Some("the command could not be run in the container"),
+        127 => Some("the command was not found in the container"),
+        _ => None
+    }
+}
+
+// The first engine found in PATH.
+fn find_engine() -> Option<OsString> {
+    let path = env::var_os("PATH")?;
+    ENGINES.iter()
+        .find(|engine| {
+            let file = format!("{}{}", engine, env::consts::EXE_SUFFIX);
+            env::split_paths(&path).any(|dir| dir.join(&file).is_file())
+        })
+        .map(OsString::from)
+}
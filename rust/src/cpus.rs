@@ -0,0 +1,181 @@
+/*
+** The number of CPUs that ljobs may use.
+*/
+
+// The default number of job slots is the number of CPUs that jobs can
+// actually run on, which in a container or a cgroup with a CPU quota may be
+// far fewer than the CPUs of the machine.  It is found from, in order, the
+// CPU affinity mask of ljobs, the cgroup v2 CPU quota, as cpu.max of the
+// cgroup of ljobs or of any cgroup above it, rounded up to whole CPUs, and
+// the number of CPUs.  The smallest of these is used, and the first of them
+// to give that number is reported as its source.
+
+use num_cpus;
+use std::convert::TryFrom;
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::mem;
+
+#[cfg(target_os = "linux")]
+use cgroup::{cgroup2_mount, own_cgroup};
+#[cfg(target_os = "linux")]
+use libc;
+
+/*---------------------------------------------------------------------------*/
+
+/// The limits on the number of CPUs that ljobs may use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuLimits {
+    /// The number of CPUs of the machine.
+    pub count:      usize,
+    /// The number of CPUs in the CPU affinity mask, if it could be had.
+    pub affinity:   Option<usize>,
+    /// The cgroup CPU quota in whole CPUs, rounded up, if there is one.
+    pub quota:      Option<usize>
+}
+
+/// Which of the limits decided the number of job slots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuSource {
+    Affinity,
+    Quota,
+    Count
+}
+
+impl fmt::Display for CpuSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            CpuSource::Affinity => "CPU affinity",
+            CpuSource::Quota => "cgroup CPU quota",
+            CpuSource::Count => "number of CPUs"
+        })
+    }
+}
+
+impl CpuLimits {
+    /// Find the limits for this process.
+    pub fn detect() -> CpuLimits {
+        CpuLimits {
+            count:    num_cpus::get(),
+            affinity: affinity(),
+            quota:    quota()
+        }
+    }
+
+    /// The number of job slots to have by default, and where it came from.
+    pub fn jobs(&self) -> (usize, CpuSource) {
+        [(self.affinity, CpuSource::Affinity),
+         (self.quota, CpuSource::Quota),
+         (Some(self.count), CpuSource::Count)]
+            .iter()
+            .filter_map(|&(n, source)| {
+                n.filter(|&n| n > 0).map(|n| (n, source))
+            })
+            // The first of the smallest.
+            .fold((usize::MAX, CpuSource::Count), |best, (n, source)| {
+                if n < best.0 { (n, source) } else { best }
+            })
+    }
+}
+
+// The number of CPUs set in an affinity mask.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn mask_cpus(mask: &[u8]) -> usize {
+    mask.iter().map(|b| b.count_ones() as usize).sum()
+}
+
+// The CPU quota in the contents of a cpu.max file, "QUOTA PERIOD" or
+// "max PERIOD", in whole CPUs rounded up.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn quota_cpus(cpu_max: &str) -> Option<usize> {
+    let mut words = cpu_max.split_whitespace();
+    let quota: u64 = words.next()?.parse().ok()?;
+    let period: u64 = match words.next() {
+        Some(p) => p.parse().ok()?,
+        None => 100_000
+    };
+    if period == 0 {
+        return None;
+    }
+    usize::try_from(quota.div_ceil(period)).ok()
+}
+
+#[cfg(target_os = "linux")]
+fn affinity() -> Option<usize> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let size = mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_getaffinity(0, size, &mut set) } < 0 {
+        return None;
+    }
+    let mask = unsafe {
+        std::slice::from_raw_parts(&set as *const _ as *const u8, size)
+    };
+    Some(mask_cpus(mask))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn affinity() -> Option<usize> {
+    None
+}
+
+// The smallest quota of the cgroup of ljobs and the cgroups above it.
+#[cfg(target_os = "linux")]
+fn quota() -> Option<usize> {
+    let mountinfo = fs::read("/proc/self/mountinfo").ok()?;
+    let mount = cgroup2_mount(&mountinfo)?;
+    let contents = fs::read("/proc/self/cgroup").ok()?;
+    let own = own_cgroup(&mount, &contents)?;
+    own.ancestors()
+        .take_while(|dir| dir.starts_with(&mount) && *dir != mount)
+        .filter_map(|dir| fs::read_to_string(dir.join("cpu.max")).ok())
+        .filter_map(|cpu_max| quota_cpus(&cpu_max))
+        .min()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn quota() -> Option<usize> {
+    None
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{mask_cpus, quota_cpus, CpuLimits, CpuSource};
+
+    #[test]
+    fn mask() {
+        assert_eq!(mask_cpus(&[0; 128]), 0);
+        assert_eq!(mask_cpus(&[0x03, 0, 0, 0x80]), 3);
+        assert_eq!(mask_cpus(&[0xff; 8]), 64);
+    }
+
+    #[test]
+    fn cpu_max() {
+        assert_eq!(quota_cpus("max 100000\n"), None);
+        assert_eq!(quota_cpus("200000 100000\n"), Some(2));
+        assert_eq!(quota_cpus("150000 100000"), Some(2));
+        assert_eq!(quota_cpus("50000 100000"), Some(1));
+        assert_eq!(quota_cpus("300000"), Some(3));
+        assert_eq!(quota_cpus("100 0"), None);
+        assert_eq!(quota_cpus(""), None);
+    }
+
+    #[test]
+    fn jobs() {
+        let limits = |affinity, quota| {
+            CpuLimits { count: 64, affinity, quota }
+        };
+        assert_eq!(limits(None, None).jobs(), (64, CpuSource::Count));
+        assert_eq!(limits(Some(64), None).jobs(),
+                   (64, CpuSource::Affinity));
+        assert_eq!(limits(Some(8), Some(2)).jobs(), (2, CpuSource::Quota));
+        assert_eq!(limits(Some(4), Some(16)).jobs(),
+                   (4, CpuSource::Affinity));
+        assert_eq!(limits(Some(2), Some(2)).jobs(),
+                   (2, CpuSource::Affinity));
+        assert_eq!(limits(Some(0), None).jobs(), (64, CpuSource::Count));
+    }
+}
@@ -0,0 +1,100 @@
+/*
+** Output of tasks as rows of comma-separated values.
+*/
+
+// With `csv`, the standard output of each task is printed as a field of a
+// row of CSV instead of as it is, so that the results of a run can be
+// loaded into a spreadsheet.  A header row comes first:
+//
+//     seq,task,exit,duration,output
+//
+// where DURATION is in seconds and EXIT is empty if the command could not
+// be waited for.  The task and the output are always quoted, with any
+// quotes in them doubled, so the output can be written as it is read back
+// in chunks, commas, quotes, newlines and all.  A single newline at the end
+// of the output is left out, as most commands end their output with one.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use output::Output;
+
+pub const HEADER: &[u8] = b"seq,task,exit,duration,output\n";
+
+/// Write the row for a task, with its output if it has any.
+pub fn write_row(out: &mut dyn Write,
+                 tasknum: usize,
+                 task: &[u8],
+                 exit: Option<i32>,
+                 duration: Duration,
+                 output: Option<&mut Output>) -> io::Result<()> {
+
+    write!(out, "{},", tasknum)?;
+    write_quoted(out, task)?;
+    match exit {
+        Some(exit) => write!(out, ",{},", exit)?,
+        None => out.write_all(b",,")?
+    }
+    write!(out, "{:.3},\"", duration.as_secs_f64())?;
+    if let Some(output) = output {
+        // The last newline is held back until there is more output.
+        let mut newline = false;
+        output.for_each_chunk(|buf| {
+            if newline {
+                out.write_all(b"\n")?;
+            }
+            let (buf, nl) = match buf.split_last() {
+                Some((&b'\n', rest)) => (rest, true),
+                _ => (buf, false)
+            };
+            newline = nl;
+            write_escaped(out, buf)
+        })?;
+    }
+    out.write_all(b"\"\n")
+}
+
+fn write_quoted(out: &mut dyn Write, field: &[u8]) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    write_escaped(out, field)?;
+    out.write_all(b"\"")
+}
+
+fn write_escaped(out: &mut dyn Write, text: &[u8]) -> io::Result<()> {
+    for (i, part) in text.split(|&c| c == b'"').enumerate() {
+        if i > 0 {
+            out.write_all(b"\"\"")?;
+        }
+        out.write_all(part)?;
+    }
+    Ok(())
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::write_row;
+    use output::Output;
+    use std::time::Duration;
+
+    fn row(task: &str, exit: Option<i32>, text: &str) -> String {
+        let mut output = Output::new(1 << 20, None);
+        output.collect(&mut Some(text.as_bytes()));
+        let mut out = Vec::new();
+        write_row(&mut out, 3, task.as_bytes(), exit,
+                  Duration::from_millis(1500), Some(&mut output)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(row("a.txt", Some(0), "1,2\n"),
+                   "3,\"a.txt\",0,1.500,\"1,2\"\n");
+        assert_eq!(row("say \"hi\"", Some(2), "x \"y\"\nz\n\n"),
+                   "3,\"say \"\"hi\"\"\",2,1.500,\"x \"\"y\"\"\nz\n\"\n");
+        assert_eq!(row("a", None, ""), "3,\"a\",,1.500,\"\"\n");
+        assert_eq!(row("a", Some(0), "no newline"),
+                   "3,\"a\",0,1.500,\"no newline\"\n");
+    }
+}
@@ -0,0 +1,94 @@
+/*
+** Errors that stop a run.
+*/
+
+use std::error;
+use std::fmt;
+use std::io;
+
+/*---------------------------------------------------------------------------*/
+
+/// Why a run could not be completed.  A task that fails is not an error; it
+/// is reported in the `TaskResult` for the task.
+#[derive(Debug)]
+pub enum Error {
+    /// The options do not make sense, e.g. there is no command.
+    Usage(String),
+    /// Tasks could not be read.
+    Input(io::Error),
+    /// A command could not be started or watched over.
+    Spawn(io::Error),
+    /// A task has no column for a `{N}` substitution string, with `strict`.
+    Template {
+        tasknum:    usize,
+        column:     usize
+    },
+    /// A task has no field for a `{.field}` substitution string, with
+    /// `strict`.
+    Field {
+        tasknum:    usize,
+        field:      String
+    },
+    /// A task is not a valid JSON document, with `json_input`.
+    Json {
+        tasknum:    usize,
+        message:    String
+    },
+    /// The output of a task could not be written.
+    Output(io::Error),
+    /// Standard output or standard error was closed by the reader, as in
+    /// `ljobs ... | head`.
+    BrokenPipe,
+    /// Any other I/O error, e.g. writing the manifest or the cache.
+    Io(io::Error),
+    /// An internal thread of the given name panicked, which is a bug.
+    /// Running commands are asked to terminate but are not waited for.
+    Internal(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usage(ref msg) =>
+                write!(f, "{}", msg),
+            Error::Input(ref err) =>
+                write!(f, "error reading tasks: {}", err),
+            Error::Spawn(ref err) =>
+                write!(f, "spawn error: {}", err),
+            Error::Template { tasknum, column } =>
+                write!(f, "no column {{{}}} in task {}", column, tasknum),
+            Error::Field { tasknum, ref field } =>
+                write!(f, "no field {{.{}}} in task {}", field, tasknum),
+            Error::Json { tasknum, ref message } =>
+                write!(f, "task {} is not valid JSON: {}", tasknum, message),
+            Error::Output(ref err) =>
+                write!(f, "write error: {}", err),
+            Error::BrokenPipe =>
+                write!(f, "write error: broken pipe"),
+            Error::Io(ref err) =>
+                write!(f, "{}", err),
+            Error::Internal(ref name) =>
+                write!(f, "internal error in thread '{}'", name)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Input(ref err) |
+            Error::Spawn(ref err) |
+            Error::Output(ref err) |
+            Error::Io(ref err) => Some(err),
+            Error::Usage(_) | Error::Template { .. } | Error::Field { .. } |
+            Error::Json { .. } | Error::BrokenPipe | Error::Internal(_) =>
+                None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
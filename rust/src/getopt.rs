@@ -0,0 +1,501 @@
+/*
+** Command line option parsing.
+*/
+
+// This is a cut-down replacement for the getopts crate that works with
+// OsStrings, so command words and tasks given on the command line need not
+// be valid Unicode.  It supports the part of getopts that we use, always
+// with the StopAtFirstFree parsing style: option processing ends at the
+// first free argument, or at "--".
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+
+/*---------------------------------------------------------------------------*/
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HasArg {
+    No,
+    Yes,
+    // Optional; a long option only takes it after =.
+    Maybe
+}
+
+pub struct OptDesc {
+    pub short:  String,
+    pub long:   String,
+    pub hint:   String,
+    pub desc:   String,
+    pub hasarg: HasArg,
+    pub multi:  bool,
+    hidden:     bool,
+    // The values that the argument may take, if there are only a few.
+    pub choices: Vec<String>
+}
+
+pub struct Getopt {
+    opts:       Vec<OptDesc>
+}
+
+pub struct Matches {
+    names:      Vec<(String, String)>,
+    vals:       Vec<Vec<Option<OsString>>>,
+    // For each value, the index of the argument list that gave it.
+    layers:     Vec<Vec<usize>>,
+    pub free:   Vec<OsString>
+}
+
+#[derive(Debug)]
+pub enum Fail {
+    ArgumentMissing(String),
+    UnrecognizedOption(String),
+    OptionDuplicated(String),
+    UnexpectedArgument(String),
+    NotAnOption(String)
+}
+
+impl fmt::Display for Fail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fail::ArgumentMissing(ref nm) =>
+                write!(f, "Argument to option '{}' missing", nm),
+            Fail::UnrecognizedOption(ref nm) =>
+                write!(f, "Unrecognized option: '{}'", nm),
+            Fail::OptionDuplicated(ref nm) =>
+                write!(f, "Option '{}' given more than once", nm),
+            Fail::UnexpectedArgument(ref nm) =>
+                write!(f, "Option '{}' does not take an argument", nm),
+            Fail::NotAnOption(ref nm) =>
+                write!(f, "'{}' is not an option", nm)
+        }
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+impl Getopt {
+    pub fn new() -> Getopt {
+        Getopt { opts: Vec::new() }
+    }
+
+    fn add(&mut self, short: &str, long: &str, desc: &str, hint: &str,
+           hasarg: HasArg, multi: bool) {
+        assert!(short.len() <= 1 && long.len() != 1);
+        self.opts.push(OptDesc {
+            short:  short.to_string(),
+            long:   long.to_string(),
+            hint:   hint.to_string(),
+            desc:   desc.to_string(),
+            hasarg,
+            multi,
+            hidden: false,
+            choices: Vec::new()
+        });
+    }
+
+    /// Leave the most recently added option out of the usage message.
+    pub fn hide_last(&mut self) {
+        if let Some(o) = self.opts.last_mut() {
+            o.hidden = true;
+        }
+    }
+
+    /// Give the values that the argument of the most recently added option
+    /// may take, for shell completion.
+    pub fn choices(&mut self, values: &[&str]) {
+        if let Some(o) = self.opts.last_mut() {
+            o.choices = values.iter().map(|v| v.to_string()).collect();
+        }
+    }
+
+    /// The options shown in the usage message, in order.
+    pub fn options(&self) -> impl Iterator<Item = &OptDesc> {
+        self.opts.iter().filter(|o| !o.hidden)
+    }
+
+    pub fn optflag(&mut self, short: &str, long: &str, desc: &str) {
+        self.add(short, long, desc, "", HasArg::No, false);
+    }
+
+    pub fn optflagmulti(&mut self, short: &str, long: &str, desc: &str) {
+        self.add(short, long, desc, "", HasArg::No, true);
+    }
+
+    pub fn optopt(&mut self, short: &str, long: &str, desc: &str,
+                  hint: &str) {
+        self.add(short, long, desc, hint, HasArg::Yes, false);
+    }
+
+    pub fn optmulti(&mut self, short: &str, long: &str, desc: &str,
+                    hint: &str) {
+        self.add(short, long, desc, hint, HasArg::Yes, true);
+    }
+
+    pub fn optflagopt(&mut self, short: &str, long: &str, desc: &str,
+                      hint: &str) {
+        self.add(short, long, desc, hint, HasArg::Maybe, false);
+    }
+
+    fn find_short(&self, c: u8) -> Option<usize> {
+        self.opts.iter().position(|o| o.short.as_bytes() == [c])
+    }
+
+    fn find_long(&self, name: &[u8]) -> Option<usize> {
+        self.opts.iter().position(|o| {
+            !o.long.is_empty() && o.long.as_bytes() == name
+        })
+    }
+
+    fn name(&self, i: usize) -> String {
+        let o = &self.opts[i];
+        if o.short.is_empty() { o.long.clone() } else { o.short.clone() }
+    }
+
+    pub fn parse<S: AsRef<OsStr>>(&self, args: &[S])
+        -> Result<Matches, Fail> {
+
+        let mut vals: Vec<Vec<Option<OsString>>> =
+            self.opts.iter().map(|_| Vec::new()).collect();
+        let mut free = Vec::new();
+        let mut args = args.iter().map(|a| a.as_ref()).peekable();
+
+        while let Some(cur) = args.next() {
+            let bytes = cur.as_encoded_bytes();
+            if !is_option(cur) {
+                free.push(cur.to_os_string());
+                free.extend(args.map(OsStr::to_os_string));
+                break;
+            }
+            if bytes == b"--" {
+                free.extend(args.map(OsStr::to_os_string));
+                break;
+            }
+
+            let id;
+            let given;
+            let mut inline: Option<OsString> = None;
+            let was_long = bytes[1] == b'-';
+            if was_long {
+                let tail = &bytes[2..];
+                let (name, val) = match tail.iter().position(|&c| c == b'=') {
+                    Some(eq) => (&tail[..eq], Some(&tail[eq+1..])),
+                    None => (tail, None)
+                };
+                id = match self.find_long(name) {
+                    Some(id) => id,
+                    None => return Err(Fail::UnrecognizedOption(
+                        String::from_utf8_lossy(name).into_owned()))
+                };
+                given = String::from_utf8_lossy(name).into_owned();
+                inline = val.map(os_string);
+            } else {
+                // In a cluster of short options such as -kvj4, an option
+                // that takes an argument uses the rest of the cluster.
+                let mut found = None;
+                for (j, &c) in bytes.iter().enumerate().skip(1) {
+                    let i = match self.find_short(c) {
+                        Some(i) => i,
+                        None => return Err(Fail::UnrecognizedOption(
+                            String::from_utf8_lossy(&bytes[j..j+1])
+                                .into_owned()))
+                    };
+                    if self.opts[i].hasarg == HasArg::No {
+                        vals[i].push(None);
+                        continue;
+                    }
+                    found = Some(i);
+                    if j + 1 < bytes.len() {
+                        inline = Some(os_string(&bytes[j+1..]));
+                    }
+                    break;
+                }
+                id = match found {
+                    Some(id) => id,
+                    None => continue
+                };
+                given = self.opts[id].short.clone();
+            }
+
+            match self.opts[id].hasarg {
+                HasArg::No => {
+                    if inline.is_some() {
+                        return Err(Fail::UnexpectedArgument(given));
+                    }
+                    vals[id].push(None);
+                },
+                HasArg::Maybe => {
+                    if inline.is_some() {
+                        vals[id].push(inline);
+                    } else if was_long ||
+                              args.peek().is_none_or(|n| is_option(n)) {
+                        vals[id].push(None);
+                    } else {
+                        vals[id].push(args.next().map(OsStr::to_os_string));
+                    }
+                },
+                HasArg::Yes => {
+                    if inline.is_some() {
+                        vals[id].push(inline);
+                    } else if let Some(next) = args.next() {
+                        vals[id].push(Some(next.to_os_string()));
+                    } else {
+                        return Err(Fail::ArgumentMissing(given));
+                    }
+                }
+            }
+        }
+
+        for (i, v) in vals.iter().enumerate() {
+            if !self.opts[i].multi && v.len() > 1 {
+                return Err(Fail::OptionDuplicated(self.name(i)));
+            }
+        }
+
+        Ok(Matches {
+            names: self.opts.iter()
+                .map(|o| (o.short.clone(), o.long.clone()))
+                .collect(),
+            layers: vals.iter().map(|v| vec![0; v.len()]).collect(),
+            vals,
+            free
+        })
+    }
+
+    /// Parse several argument lists as though given one after the other,
+    /// except that an option that may be given only once takes its value
+    /// from the last list that has it.  Only the last list may have free
+    /// arguments.  On failure, also returns the index of the list at fault.
+    pub fn parse_layers<S: AsRef<OsStr>>(&self, lists: &[&[S]])
+        -> Result<Matches, (usize, Fail)> {
+
+        let mut merged = self.parse::<S>(&[]).map_err(|e| (0, e))?;
+        for (k, list) in lists.iter().enumerate() {
+            let m = self.parse(list).map_err(|e| (k, e))?;
+            if k + 1 < lists.len() {
+                if let Some(arg) = m.free.first() {
+                    let arg = arg.to_string_lossy().into_owned();
+                    return Err((k, Fail::NotAnOption(arg)));
+                }
+            }
+            for (i, vals) in m.vals.into_iter().enumerate() {
+                if vals.is_empty() {
+                    continue;
+                }
+                if !self.opts[i].multi {
+                    merged.vals[i].clear();
+                    merged.layers[i].clear();
+                }
+                merged.layers[i].extend(vals.iter().map(|_| k));
+                merged.vals[i].extend(vals);
+            }
+            merged.free = m.free;
+        }
+        Ok(merged)
+    }
+
+    /// The long options shown in the usage message that `name` may be a
+    /// mistyping of, best first.
+    pub fn suggest(&self, name: &str) -> Vec<&str> {
+        suggest(name, self.options().map(|o| o.long.as_str())
+                          .filter(|l| !l.is_empty()))
+    }
+
+    /// Format a usage message listing the options, after `brief`.
+    pub fn usage(&self, brief: &str) -> String {
+        let rows: Vec<String> = self.opts.iter()
+            .filter(|o| !o.hidden)
+            .map(usage_row)
+            .collect();
+        format!("{}\n\nOptions:\n{}\n", brief, rows.join("\n"))
+    }
+}
+
+// A piece of an argument after an ASCII character is itself a valid OS
+// string on every platform.
+fn os_string(bytes: &[u8]) -> OsString {
+    unsafe { OsString::from_encoded_bytes_unchecked(bytes.to_vec()) }
+}
+
+fn is_option(arg: &OsStr) -> bool {
+    let bytes = arg.as_encoded_bytes();
+    bytes.len() > 1 && bytes[0] == b'-'
+}
+
+/// Up to three of `names` that are within a few edits of `name`, closest
+/// first and otherwise in the order given.  A longer name may be further
+/// off.
+pub fn suggest<'a, I>(name: &str, names: I) -> Vec<&'a str>
+    where I: Iterator<Item = &'a str> {
+
+    let limit = match name.len() {
+        0..=3 => 1,
+        4..=8 => 2,
+        _ => 3
+    };
+    let mut near: Vec<(usize, &str)> = names
+        .map(|n| (edit_distance(name.as_bytes(), n.as_bytes()), n))
+        .filter(|&(d, _)| d <= limit)
+        .collect();
+    near.sort_by_key(|&(d, _)| d);
+    near.into_iter().take(3).map(|(_, n)| n).collect()
+}
+
+// The number of insertions, deletions, substitutions and swaps of adjacent
+// characters that turn `a` into `b`.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    // Three rows of the table: for a[..i-2], a[..i-1] and a[..i].
+    let mut prev2: Vec<usize> = Vec::new();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut cur = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = if a[i-1] == b[j-1] { 0 } else { 1 };
+            cur[j] = (prev[j] + 1).min(cur[j-1] + 1).min(prev[j-1] + cost);
+            if i > 1 && j > 1 && a[i-1] == b[j-2] && a[i-2] == b[j-1] {
+                cur[j] = cur[j].min(prev2[j-2] + 1);
+            }
+        }
+        prev2 = prev;
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+const DESC_COLUMN: usize = 24;
+const DESC_WIDTH: usize = 54;
+
+fn usage_row(o: &OptDesc) -> String {
+    let mut row = String::from("    ");
+    if o.short.is_empty() {
+        row.push_str("    ");
+    } else {
+        row.push('-');
+        row.push_str(&o.short);
+        row.push_str(if o.long.is_empty() { " " } else { ", " });
+    }
+    if !o.long.is_empty() {
+        row.push_str("--");
+        row.push_str(&o.long);
+        row.push(' ');
+    }
+    match o.hasarg {
+        HasArg::No => (),
+        HasArg::Yes => row.push_str(&o.hint),
+        HasArg::Maybe => {
+            row.push('[');
+            row.push_str(&o.hint);
+            row.push(']');
+        }
+    }
+
+    let desc_sep = format!("\n{}", " ".repeat(DESC_COLUMN));
+    let len = row.chars().count();
+    if len < DESC_COLUMN {
+        row.push_str(&" ".repeat(DESC_COLUMN - len));
+    } else {
+        row.push_str(&desc_sep);
+    }
+    row.push_str(&wrap_words(&o.desc, DESC_WIDTH).join(&desc_sep));
+    row
+}
+
+fn wrap_words(s: &str, width: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    for word in s.split_whitespace() {
+        let len = row.chars().count() + 1 + word.chars().count();
+        if !row.is_empty() && len > width {
+            rows.push(row);
+            row = String::new();
+        }
+        if !row.is_empty() {
+            row.push(' ');
+        }
+        row.push_str(word);
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    rows
+}
+
+/*---------------------------------------------------------------------------*/
+
+impl Matches {
+    fn index(&self, name: &str) -> usize {
+        self.names.iter()
+            .position(|(s, l)| s == name || l == name)
+            .unwrap_or_else(|| panic!("no such option: {}", name))
+    }
+
+    pub fn opt_present(&self, name: &str) -> bool {
+        !self.vals[self.index(name)].is_empty()
+    }
+
+    /// The number of times the option was given.
+    pub fn opt_count(&self, name: &str) -> usize {
+        self.vals[self.index(name)].len()
+    }
+
+    /// The argument of the option, if given.
+    pub fn opt_os(&self, name: &str) -> Option<OsString> {
+        self.vals[self.index(name)].iter().flatten().next().cloned()
+    }
+
+    /// The arguments of each occurrence of the option.
+    pub fn opt_os_all(&self, name: &str) -> Vec<OsString> {
+        self.vals[self.index(name)].iter().flatten().cloned().collect()
+    }
+
+    /// The argument of the option, if given, converted lossily to a String.
+    pub fn opt_str(&self, name: &str) -> Option<String> {
+        self.opt_os(name).map(|s| s.to_string_lossy().into_owned())
+    }
+
+    /// Each option given, as written with a long name where there is one,
+    /// with its argument and the index of the argument list that gave it.
+    pub fn given(&self) -> Vec<(String, Option<&OsStr>, usize)> {
+        let mut given = Vec::new();
+        for (i, (short, long)) in self.names.iter().enumerate() {
+            let name = if long.is_empty() {
+                format!("-{}", short)
+            } else {
+                format!("--{}", long)
+            };
+            for (val, &layer) in self.vals[i].iter().zip(&self.layers[i]) {
+                given.push((name.clone(), val.as_deref(), layer));
+            }
+        }
+        given
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, suggest};
+
+    #[test]
+    fn distance() {
+        let d = |a: &str, b: &str| edit_distance(a.as_bytes(), b.as_bytes());
+        assert_eq!(d("", ""), 0);
+        assert_eq!(d("", "abc"), 3);
+        assert_eq!(d("drg-run", "dry-run"), 1);
+        assert_eq!(d("dyr-run", "dry-run"), 1);
+        assert_eq!(d("dryrun", "dry-run"), 1);
+        assert_eq!(d("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggestions() {
+        let names = ["dry-run", "delay", "tag", "tagstring", "jobs", "joblog"];
+        let suggest = |name| suggest(name, names.iter().cloned());
+        assert_eq!(suggest("drg-run"), vec!["dry-run"]);
+        assert_eq!(suggest("tga"), vec!["tag"]);
+        assert_eq!(suggest("jobl"), vec!["jobs", "joblog"]);
+        assert_eq!(suggest("tag-string"), vec!["tagstring"]);
+        assert_eq!(suggest("verbose"), Vec::<&str>::new());
+        assert_eq!(suggest("x"), Vec::<&str>::new());
+    }
+}
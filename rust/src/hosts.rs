@@ -0,0 +1,239 @@
+/*
+** Remote hosts to run commands on with ssh.
+*/
+
+// With `ssh_login` or `ssh_login_file`, each command is run on one of a pool
+// of remote hosts.  A host may be given a number of job slots, as in the
+// login file line "8/node1"; the job slots of the run still limit the jobs
+// running on all hosts together.  Each task goes to the least loaded host
+// with a free slot.
+//
+// ssh exits with 255 when it cannot connect, and such tasks are tried again
+// by the master.  If there are other hosts, a host that fails HOST_FAILURES
+// times in a row is not used for HOST_DOWNTIME, so that the tasks go to the
+// other hosts in the meantime.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use os::from_input_bytes;
+
+const HOST_FAILURES: u32 = 3;
+const HOST_DOWNTIME: Duration = Duration::from_secs(1);
+
+/*---------------------------------------------------------------------------*/
+
+/// A remote host to run commands on, and how many at once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SshLogin {
+    pub login:      OsString,
+    /// The job slots of the host, if it has its own limit.
+    pub slots:      Option<usize>
+}
+
+pub struct HostPool {
+    hosts:      Vec<Host>
+}
+
+struct Host {
+    login:      OsString,
+    slots:      usize,
+    running:    usize,
+    failures:   u32,
+    down:       Option<Instant>
+}
+
+/// Read a login file, with one login per line, each optionally preceded by
+/// a number of job slots and a slash.  Blank lines and lines starting with
+/// `#` are ignored.
+pub fn read_login_file(path: &Path) -> io::Result<Vec<SshLogin>> {
+    let file_error = |err: io::Error| {
+        io::Error::new(err.kind(), format!("ssh login file {}: {}",
+                                           path.display(), err))
+    };
+    let contents = fs::read(path).map_err(file_error)?;
+    let mut logins = Vec::new();
+    for (i, line) in contents.split(|&b| b == b'\n').enumerate() {
+        let line = line.trim_ascii();
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        match parse_login(line) {
+            Some(login) => logins.push(login),
+            None => return Err(file_error(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid login on line {}", i + 1))))
+        }
+    }
+    if logins.is_empty() {
+        return Err(file_error(io::Error::new(io::ErrorKind::InvalidData,
+                                             "no logins")));
+    }
+    Ok(logins)
+}
+
+// Parse a line such as "node1", "user@node1" or "8/node1".
+fn parse_login(line: &[u8]) -> Option<SshLogin> {
+    let (slots, login) = match line.iter().position(|&b| b == b'/') {
+        Some(slash) => {
+            let slots = std::str::from_utf8(&line[..slash]).ok()?
+                .parse().ok().filter(|&n| n > 0)?;
+            (Some(slots), line[slash + 1..].trim_ascii())
+        },
+        None => (None, line)
+    };
+    if login.is_empty() {
+        return None;
+    }
+    Some(SshLogin {
+        login: from_input_bytes(login.to_vec()),
+        slots
+    })
+}
+
+impl HostPool {
+    /// Hosts without a limit of their own get `maxjobs` slots.
+    pub fn new(logins: &[SshLogin], maxjobs: usize) -> HostPool {
+        HostPool {
+            hosts: logins.iter().map(|l| Host {
+                login:    l.login.clone(),
+                slots:    l.slots.unwrap_or(maxjobs),
+                running:  0,
+                failures: 0,
+                down:     None
+            }).collect()
+        }
+    }
+
+    pub fn login(&self, host: usize) -> &OsStr {
+        &self.hosts[host].login
+    }
+
+    /// The host to run the next command on, if any has a free slot.  Hosts
+    /// that failed the least often lately go first, then the least loaded,
+    /// in the order given.
+    pub fn choose(&self, now: Instant) -> Option<usize> {
+        (0..self.hosts.len())
+            .filter(|&i| self.hosts[i].available(now))
+            .min_by(|&a, &b| {
+                let (a, b) = (&self.hosts[a], &self.hosts[b]);
+                a.failures.cmp(&b.failures)
+                    .then((a.running * b.slots).cmp(&(b.running * a.slots)))
+            })
+    }
+
+    /// When a host that is not in use for failing will be used again, if
+    /// that is the only reason that no host has a free slot.
+    pub fn next_up(&self) -> Option<Instant> {
+        self.hosts.iter()
+            .filter(|h| h.running < h.slots)
+            .filter_map(|h| h.down)
+            .min()
+    }
+
+    pub fn started(&mut self, host: usize) {
+        self.hosts[host].running += 1;
+    }
+
+    /// A command finished on `host`, or ssh failed to run it.  Returns
+    /// whether the host is now not to be used for a while.
+    pub fn finished(&mut self, host: usize, sshfailed: bool, now: Instant)
+        -> bool {
+
+        let alone = self.hosts.len() == 1;
+        let host = &mut self.hosts[host];
+        host.running -= 1;
+        if !sshfailed {
+            host.failures = 0;
+            host.down = None;
+            return false;
+        }
+        host.failures += 1;
+        if host.failures >= HOST_FAILURES && !alone {
+            host.down = Some(now + HOST_DOWNTIME);
+            return true;
+        }
+        false
+    }
+}
+
+impl Host {
+    fn available(&self, now: Instant) -> bool {
+        self.running < self.slots && self.down.is_none_or(|t| t <= now)
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_login, HostPool, SshLogin, HOST_DOWNTIME};
+    use std::ffi::OsString;
+    use std::time::Instant;
+
+    fn login(login: &str, slots: Option<usize>) -> SshLogin {
+        SshLogin { login: OsString::from(login), slots }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_login(b"node1"), Some(login("node1", None)));
+        assert_eq!(parse_login(b"8/user@node2"),
+                   Some(login("user@node2", Some(8))));
+        assert_eq!(parse_login(b"4/ node3"), Some(login("node3", Some(4))));
+        assert_eq!(parse_login(b"0/node"), None);
+        assert_eq!(parse_login(b"x/node"), None);
+        assert_eq!(parse_login(b"2/"), None);
+    }
+
+    #[test]
+    fn least_loaded() {
+        let now = Instant::now();
+        let mut pool = HostPool::new(&[login("a", Some(2)),
+                                       login("b", Some(1))], 8);
+        let mut chosen = Vec::new();
+        while let Some(host) = pool.choose(now) {
+            pool.started(host);
+            chosen.push(host);
+        }
+        assert_eq!(chosen, vec![0, 1, 0]);
+        pool.finished(1, false, now);
+        assert_eq!(pool.choose(now), Some(1));
+    }
+
+    #[test]
+    fn unhealthy() {
+        let now = Instant::now();
+        let mut pool = HostPool::new(&[login("a", None), login("b", None)], 2);
+        pool.started(1);
+        pool.started(0);
+        assert!(!pool.finished(0, true, now));
+        // Fewer recent failures win over a lighter load.
+        assert_eq!(pool.choose(now), Some(1));
+        pool.started(0);
+        assert!(!pool.finished(0, true, now));
+        pool.started(0);
+        assert!(pool.finished(0, true, now));
+        pool.started(1);
+        assert_eq!(pool.choose(now), None);
+        assert_eq!(pool.next_up(), Some(now + HOST_DOWNTIME));
+        assert_eq!(pool.choose(now + HOST_DOWNTIME), Some(0));
+        pool.started(0);
+        assert!(!pool.finished(0, false, now));
+        assert_eq!(pool.next_up(), None);
+    }
+
+    #[test]
+    fn alone() {
+        let now = Instant::now();
+        let mut pool = HostPool::new(&[login("a", None)], 2);
+        for _ in 0..5 {
+            pool.started(0);
+            assert!(!pool.finished(0, true, now));
+        }
+        assert_eq!(pool.choose(now), Some(0));
+    }
+}
@@ -0,0 +1,822 @@
+/*
+** Reading tasks ahead of dispatch.
+*/
+
+// Each kind of TaskSource is turned into something implementing Tasks, and
+// the rest of the program only ever takes tasks through that trait, so a new
+// way of reading tasks need only implement it.
+//
+// Tasks from a reader are read by a separate thread so that the master can
+// keep handling finished jobs while waiting for slow input.  The reader only
+// reads a line when it holds a permit from the master, and the master only
+// hands out `max_backlog` permits, READAHEAD by default, more than the
+// number of tasks it has taken, so at most that many tasks are read but not
+// yet started.  When input comes faster than tasks are started, the reader
+// blocks instead of buffering it, and so does whatever is writing the
+// input.  Tasks from a slice or iterator are taken directly when needed.
+//
+// With `save_input`, every byte that the reader thread takes from a reader
+// is also written to a file as it is taken, so the file holds exactly the
+// input consumed, including any skipped, and can be given as input to run
+// the same tasks again.
+//
+// Records are read a buffer at a time rather than with read_until, so that
+// with a line limit a record that never ends, such as a stream of binary
+// data without a newline, takes no more memory than the limit.
+//
+// With `pipe`, input is instead cut into blocks of about the block size,
+// each of which is a task.  A block is only cut where one record ends with
+// `recend` and the next starts with `recstart`, so a record of several
+// lines is never split between two tasks.  A block is cut at the last such
+// place within the block size, or if there is none, at the first after it,
+// so a block may be larger than the block size but never splits a record.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use os::from_input_bytes;
+use {spawn_worker, warn, Error, Event, TaskSource, PROG};
+
+/// The number of tasks read ahead of those started, by default.
+pub const READAHEAD: usize = 16;
+
+/// What to do with a task read from input that is longer than the line
+/// limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LongLines {
+    /// Stop the run with an error reading tasks.
+    Fail,
+    /// Keep as much of the start of the task as the limit allows, skip the
+    /// rest, and warn.
+    Truncate
+}
+
+/// How tasks are read from input: each record is terminated by `delim`,
+/// and those longer than a `limit` of bytes are dealt with as it says.
+/// With `blocks`, each task is instead a block of records.
+#[derive(Clone)]
+pub struct RecordFormat {
+    pub delim:  u8,
+    pub limit:  Option<(usize, LongLines)>,
+    pub blocks: Option<BlockFormat>
+}
+
+/// How input is cut into blocks: of about `size` bytes, only where a
+/// record ending with `recend` is followed by one starting with
+/// `recstart`.
+#[derive(Clone)]
+pub struct BlockFormat {
+    pub size:     usize,
+    pub recstart: Vec<u8>,
+    pub recend:   Vec<u8>
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// A task to be run.  Tasks from a slice are borrowed rather than copied.
+pub struct Task<'a> {
+    pub text:   Cow<'a, OsStr>
+}
+
+/// A source of tasks, taken in order.
+pub trait Tasks<'a> {
+    /// The next task, or None after the last.
+    fn next_task(&mut self) -> Option<io::Result<Task<'a>>>;
+
+    /// The number of tasks left, if it is known without reading them.
+    fn remaining(&self) -> Option<usize> {
+        None
+    }
+}
+
+struct SliceTasks<'a> {
+    tasks:      &'a [OsString],
+    next:       usize
+}
+
+impl<'a> Tasks<'a> for SliceTasks<'a> {
+    fn next_task(&mut self) -> Option<io::Result<Task<'a>>> {
+        let task = self.tasks.get(self.next)?;
+        self.next += 1;
+        Some(Ok(Task { text: Cow::Borrowed(task.as_os_str()) }))
+    }
+
+    fn remaining(&self) -> Option<usize> {
+        Some(self.tasks.len() - self.next)
+    }
+}
+
+struct IterTasks<'a> {
+    iter:       Box<dyn Iterator<Item = OsString> + 'a>
+}
+
+impl<'a> Tasks<'a> for IterTasks<'a> {
+    fn next_task(&mut self) -> Option<io::Result<Task<'a>>> {
+        self.iter.next().map(|task| Ok(Task { text: Cow::Owned(task) }))
+    }
+}
+
+// Each record is a task.
+struct RecordTasks<I> {
+    records:    I
+}
+
+impl<'a, I> Tasks<'a> for RecordTasks<I>
+    where I: Iterator<Item = io::Result<OsString>>
+{
+    fn next_task(&mut self) -> Option<io::Result<Task<'a>>> {
+        self.records.next()
+            .map(|record| record.map(|task| Task { text: Cow::Owned(task) }))
+    }
+}
+
+// The tasks after the first `skip`.
+struct SkipTasks<'a> {
+    tasks:      Box<dyn Tasks<'a> + 'a>,
+    skip:       usize
+}
+
+impl<'a> Tasks<'a> for SkipTasks<'a> {
+    fn next_task(&mut self) -> Option<io::Result<Task<'a>>> {
+        while self.skip > 0 {
+            self.skip -= 1;
+            if let Err(err) = self.tasks.next_task()? {
+                return Some(Err(err));
+            }
+        }
+        self.tasks.next_task()
+    }
+
+    fn remaining(&self) -> Option<usize> {
+        self.tasks.remaining().map(|n| n.saturating_sub(self.skip))
+    }
+}
+
+// The Tasks for a source.  Tasks to be read by a reader thread are made in
+// that thread, as standard input is locked by the thread that reads it.
+enum Feed<'a> {
+    Direct(Box<dyn Tasks<'a> + 'a>),
+    Threaded(Box<dyn FnOnce() -> Box<dyn Tasks<'static>> + Send>)
+}
+
+fn feed(source: TaskSource, interleave: bool, skip: usize,
+        format: RecordFormat, save: Option<File>) -> Feed {
+
+    type Lines<'r> = Box<dyn Iterator<Item = io::Result<OsString>> + 'r>;
+    fn lines<'r, R: BufRead + 'r>(reader: R, format: &RecordFormat,
+                                  save: Option<Arc<File>>) -> Lines<'r> {
+
+        let reader: Box<dyn BufRead + 'r> = match save {
+            Some(file) => Box::new(Tee::new(reader, file)),
+            None => Box::new(reader)
+        };
+        match format.blocks {
+            Some(ref blocks) => Box::new(Blocks::new(reader, blocks.clone())),
+            None => Box::new(Records::new(reader, format.delim)
+                                 .limit(format.limit))
+        }
+    }
+    let save = save.map(Arc::new);
+    let feed = match source {
+        TaskSource::Slice(tasks) =>
+            Feed::Direct(Box::new(SliceTasks { tasks, next: 0 })),
+        TaskSource::Iter(iter) =>
+            Feed::Direct(Box::new(IterTasks { iter })),
+        TaskSource::Reader(reader) => Feed::Threaded(Box::new(move || {
+            Box::new(RecordTasks { records: lines(reader, &format, save) })
+        })),
+        TaskSource::Stdin => Feed::Threaded(Box::new(move || {
+            // Lock once rather than for every read.
+            Box::new(RecordTasks {
+                records: lines(io::stdin().lock(), &format, save)
+            })
+        })),
+        TaskSource::Readers(readers) => Feed::Threaded(Box::new(move || {
+            let records = readers.into_iter()
+                .map(move |reader| lines(reader, &format, save.clone()));
+            if interleave {
+                Box::new(RecordTasks {
+                    records: Interleave::new(records.collect())
+                })
+            } else {
+                Box::new(RecordTasks { records: records.flatten() })
+            }
+        }))
+    };
+    if skip == 0 {
+        return feed;
+    }
+    match feed {
+        Feed::Direct(tasks) =>
+            Feed::Direct(Box::new(SkipTasks { tasks, skip })),
+        Feed::Threaded(open) => Feed::Threaded(Box::new(move || {
+            Box::new(SkipTasks { tasks: open(), skip })
+        }))
+    }
+}
+
+/// The first task from `source` after the first `skip`, read directly
+/// rather than by a reader thread, or `None` if there are no more.  Tasks
+/// are taken from several readers in turn if `interleave` is set, and are
+/// read in `format`.
+pub fn first_task(source: TaskSource, interleave: bool, skip: usize,
+                  format: RecordFormat) -> Result<Option<OsString>, Error> {
+
+    let task = match feed(source, interleave, skip, format, None) {
+        Feed::Direct(mut tasks) => tasks.next_task(),
+        Feed::Threaded(open) => open().next_task()
+    };
+    task.transpose()
+        .map(|task| task.map(|task| task.text.into_owned()))
+        .map_err(Error::Input)
+}
+
+/*---------------------------------------------------------------------------*/
+
+pub struct TaskQueue<'a> {
+    direct:     Option<Box<dyn Tasks<'a> + 'a>>,
+    queue:      VecDeque<Task<'static>>,
+    permits:    Option<Sender<()>>,
+    eof:        bool
+}
+
+impl<'a> TaskQueue<'a> {
+    /// Tasks read by a reader thread will be sent on `events`.  Tasks are
+    /// taken from several readers in turn if `interleave` is set.  The
+    /// first `skip` tasks are dropped.  Tasks are read in `format`, and at
+    /// most `backlog` are read before they are taken.  The
+    /// input read is copied to `save` if there is one.
+    pub fn new(source: TaskSource<'a>, interleave: bool, skip: usize,
+               format: RecordFormat, backlog: usize, save: Option<File>,
+               events: &Sender<Event>) -> TaskQueue<'a> {
+
+        let mut q = TaskQueue {
+            direct:     None,
+            queue:      VecDeque::new(),
+            permits:    None,
+            eof:        false
+        };
+        match feed(source, interleave, skip, format, save) {
+            Feed::Direct(tasks) => q.direct = Some(tasks),
+            Feed::Threaded(open) =>
+                q.permits = Some(start_reader(events, open, backlog))
+        }
+        q
+    }
+
+    /// Take the next task if one is available now.
+    pub fn next(&mut self) -> Result<Option<Task<'a>>, Error> {
+        if let Some(ref mut tasks) = self.direct {
+            let task = tasks.next_task().transpose().map_err(Error::Input)?;
+            if task.is_none() {
+                self.eof = true;
+            }
+            return Ok(task);
+        }
+        let task = self.queue.pop_front();
+        if task.is_some() {
+            if let Some(ref permits) = self.permits {
+                let _ = permits.send(());
+            }
+        }
+        Ok(task)
+    }
+
+    /// The number of tasks not yet taken, if it is known.
+    pub fn remaining(&self) -> Option<usize> {
+        match self.direct {
+            Some(ref tasks) => tasks.remaining(),
+            None if self.eof => Some(self.queue.len()),
+            None => None
+        }
+    }
+
+    /// Whether all tasks have been taken.
+    pub fn is_done(&self) -> bool {
+        self.eof && self.queue.is_empty()
+    }
+
+    /// Accept a task sent by the reader thread.
+    pub fn received(&mut self, task: io::Result<Option<Task<'static>>>)
+        -> Result<(), Error> {
+
+        match task.map_err(Error::Input)? {
+            Some(task) => self.queue.push_back(task),
+            None => self.eof = true
+        }
+        Ok(())
+    }
+
+    /// Stop reading tasks.
+    pub fn close(&mut self) {
+        self.permits = None;
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+// Spawn a reader thread to read the tasks made by `open`, returning the
+// sender of its permits, of which it starts with `backlog`.
+fn start_reader<F>(events: &Sender<Event>, open: F, backlog: usize)
+    -> Sender<()>
+    where F: FnOnce() -> Box<dyn Tasks<'static>> + Send + 'static
+{
+    let (permits_tx, permits_rx) = mpsc::channel();
+    let tx = events.clone();
+    spawn_worker("ljobs-reader", events.clone(),
+                 move || reader_thread(open(), permits_rx, tx))
+        .expect("could not start reader thread");
+    for _ in 0..backlog {
+        let _ = permits_tx.send(());
+    }
+    permits_tx
+}
+
+fn reader_thread(mut tasks: Box<dyn Tasks<'static>>,
+                 permits: Receiver<()>,
+                 events: Sender<Event>) {
+
+    // Stop when the master no longer wants tasks, after the end of input, or
+    // after an error.
+    while permits.recv().is_ok() {
+        let task = tasks.next_task().transpose();
+        let more = matches!(task, Ok(Some(_)));
+        if events.send(Event::Task(task)).is_err() || !more {
+            break;
+        }
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// Iterator over the records of a reader, each terminated by a delimiter
+/// byte.  The last record need not be terminated.  Records need not be
+/// valid Unicode.
+pub struct Records<R> {
+    reader:     R,
+    delim:      u8,
+    limit:      Option<(usize, LongLines)>
+}
+
+impl<R: BufRead> Records<R> {
+    pub fn new(reader: R, delim: u8) -> Records<R> {
+        Records { reader, delim, limit: None }
+    }
+
+    /// Deal with records longer than a number of bytes as `limit` says.
+    pub fn limit(mut self, limit: Option<(usize, LongLines)>) -> Records<R> {
+        self.limit = limit;
+        self
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = io::Result<OsString>;
+
+    fn next(&mut self) -> Option<io::Result<OsString>> {
+        let delim = self.delim;
+        let max = self.limit.map_or(usize::MAX, |(max, _)| max);
+        let mut record = Vec::new();
+        let mut len = 0;
+        let mut terminated = false;
+        let mut eof = true;
+        while !terminated {
+            let buf = match self.reader.fill_buf() {
+                Ok([]) => break,
+                Ok(buf) => buf,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+                    continue,
+                Err(err) => return Some(Err(err))
+            };
+            eof = false;
+            let (part, used) = match buf.iter().position(|&c| c == delim) {
+                Some(end) => {
+                    terminated = true;
+                    (&buf[..end], end + 1)
+                },
+                None => (buf, buf.len())
+            };
+            let room = max - record.len();
+            record.extend_from_slice(&part[..part.len().min(room)]);
+            len += part.len();
+            self.reader.consume(used);
+            if let (true, Some((_, LongLines::Fail))) = (len > max, self.limit)
+            {
+                return Some(Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("task longer than {} bytes", max))));
+            }
+        }
+        if eof {
+            return None;
+        }
+        if len > max {
+            warn(format_args!("{}: task of {} bytes truncated to {}\n",
+                              PROG, len, max));
+        }
+        // Lines on Windows usually end with CR LF.
+        if terminated && len <= max && cfg!(windows) && delim == b'\n' &&
+            record.last() == Some(&b'\r')
+        {
+            record.pop();
+        }
+        Some(Ok(from_input_bytes(record)))
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// Iterator over the blocks of a reader, cut at record boundaries.
+pub struct Blocks<R> {
+    reader:     R,
+    format:     BlockFormat,
+    // Input read but not yet in a block.
+    buf:        Vec<u8>,
+    eof:        bool
+}
+
+impl<R: BufRead> Blocks<R> {
+    pub fn new(reader: R, format: BlockFormat) -> Blocks<R> {
+        Blocks { reader, format, buf: Vec::new(), eof: false }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => {
+                    let n = buf.len();
+                    self.eof = n == 0;
+                    self.buf.extend_from_slice(buf);
+                    self.reader.consume(n);
+                    return Ok(());
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+                    continue,
+                Err(err) => return Err(err)
+            }
+        }
+    }
+
+    // Whether a block may end before `buf[at]`.
+    fn boundary(&self, at: usize) -> bool {
+        self.buf[..at].ends_with(&self.format.recend) &&
+            self.buf[at..].starts_with(&self.format.recstart)
+    }
+
+    fn next_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let size = self.format.size;
+        // Where a boundary is can only be told once the start of the
+        // record after it has been read.
+        let look = self.format.recstart.len();
+        while !self.eof && self.buf.len() < size + look {
+            self.fill()?;
+        }
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let mut cut = if self.eof && self.buf.len() <= size {
+            Some(self.buf.len())
+        } else {
+            (1..=size).rev().find(|&at| self.boundary(at))
+        };
+        let mut from = size + 1;
+        while cut.is_none() {
+            let last = if self.eof {
+                self.buf.len()
+            } else {
+                self.buf.len() - look
+            };
+            cut = (from..=last).find(|&at| self.boundary(at));
+            if cut.is_none() && self.eof {
+                cut = Some(self.buf.len());
+            } else if cut.is_none() {
+                from = last + 1;
+                self.fill()?;
+            }
+        }
+        let rest = self.buf.split_off(cut.expect("cut"));
+        Ok(Some(mem::replace(&mut self.buf, rest)))
+    }
+}
+
+impl<R: BufRead> Iterator for Blocks<R> {
+    type Item = io::Result<OsString>;
+
+    fn next(&mut self) -> Option<io::Result<OsString>> {
+        self.next_block().transpose()
+            .map(|block| block.map(from_input_bytes))
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// A reader that copies to `copy` each byte taken from `reader`.  An error
+/// writing the copy is returned by the next read.
+pub struct Tee<R, W> {
+    reader:     R,
+    copy:       W,
+    error:      Option<io::Error>
+}
+
+impl<R: BufRead, W: Write> Tee<R, W> {
+    pub fn new(reader: R, copy: W) -> Tee<R, W> {
+        Tee { reader, copy, error: None }
+    }
+}
+
+impl<R: BufRead, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let buf = self.fill_buf()?;
+            let n = buf.len().min(out.len());
+            out[..n].copy_from_slice(&buf[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead, W: Write> BufRead for Tee<R, W> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if let Some(err) = self.error.take() {
+            return Err(io::Error::new(err.kind(),
+                                      format!("saving input: {}", err)));
+        }
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The bytes consumed are still in the buffer, so this reads nothing.
+        if let (None, Ok(buf)) = (&self.error, self.reader.fill_buf()) {
+            if let Err(err) = self.copy.write_all(&buf[..amt]) {
+                self.error = Some(err);
+            }
+        }
+        self.reader.consume(amt);
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// Iterator taking an item from each of several iterators in turn, skipping
+/// those that are exhausted.
+pub struct Interleave<I> {
+    iters:      Vec<I>,
+    next:       usize
+}
+
+impl<I: Iterator> Interleave<I> {
+    pub fn new(iters: Vec<I>) -> Interleave<I> {
+        Interleave { iters, next: 0 }
+    }
+}
+
+impl<I: Iterator> Iterator for Interleave<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        while !self.iters.is_empty() {
+            if self.next >= self.iters.len() {
+                self.next = 0;
+            }
+            match self.iters[self.next].next() {
+                Some(item) => {
+                    self.next += 1;
+                    return Some(item);
+                },
+                None => {
+                    self.iters.remove(self.next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockFormat, Blocks, Interleave, LongLines, RecordFormat,
+                Records, TaskQueue, Tee};
+    use std::ffi::OsString;
+    use std::io::{self, BufRead, Read};
+    #[cfg(unix)]
+    use std::os::unix::ffi::OsStringExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use {Event, TaskSource};
+
+    fn records(input: &[u8], delim: u8) -> Vec<OsString> {
+        Records::new(input, delim).map(Result::unwrap).collect()
+    }
+
+    fn strings(v: &[&str]) -> Vec<OsString> {
+        v.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn newline() {
+        assert_eq!(records(b"a\nb c\n\nd\n", b'\n'),
+                   strings(&["a", "b c", "", "d"]));
+    }
+
+    #[test]
+    fn nul() {
+        assert_eq!(records(b"a\nb\0c\0", b'\0'), strings(&["a\nb", "c"]));
+    }
+
+    #[test]
+    fn custom_delimiter() {
+        assert_eq!(records(b"a,b,,c", b','), strings(&["a", "b", "", "c"]));
+    }
+
+    #[test]
+    fn partial_last_record() {
+        assert_eq!(records(b"a\nb", b'\n'), strings(&["a", "b"]));
+        assert_eq!(records(b"\n", b'\n'), strings(&[""]));
+        assert_eq!(records(b"", b'\n'), strings(&[]));
+    }
+
+    #[test]
+    fn blocks() {
+        // Read a few bytes at a time, so blocks span several reads.
+        let blocks = |input: &[u8], size, recstart: &str, recend: &str| {
+            let format = BlockFormat {
+                size,
+                recstart: recstart.as_bytes().to_vec(),
+                recend:   recend.as_bytes().to_vec()
+            };
+            Blocks::new(io::BufReader::with_capacity(3, input), format)
+                .map(Result::unwrap)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(blocks(b"a\nbb\nccc\nd", 5, "", "\n"),
+                   strings(&["a\nbb\n", "ccc\n", "d"]));
+        // A record longer than the block size is kept whole.
+        assert_eq!(blocks(b"a\nbbbbbbb\nc\n", 3, "", "\n"),
+                   strings(&["a\n", "bbbbbbb\n", "c\n"]));
+        let fasta = b">1\nAC\nGT\n>2\nA\n>3\nCCCCCC\nG\n";
+        assert_eq!(blocks(fasta, 10, ">", ""),
+                   strings(&[">1\nAC\nGT\n", ">2\nA\n", ">3\nCCCCCC\nG\n"]));
+        assert_eq!(blocks(fasta, 100, ">", ""), strings(&[
+            ">1\nAC\nGT\n>2\nA\n>3\nCCCCCC\nG\n"]));
+        assert_eq!(blocks(b"{1}{2}\n{3}", 4, "{", "}"),
+                   strings(&["{1}", "{2}\n{3}"]));
+        assert_eq!(blocks(b"", 4, "", "\n"), strings(&[]));
+    }
+
+    #[test]
+    fn interleave() {
+        let sources = vec![
+            Records::new(&b"A1\nA2\nA3\nA4\n"[..], b'\n'),
+            Records::new(&b"B1\nB2\n"[..], b'\n'),
+            Records::new(&b""[..], b'\n'),
+            Records::new(&b"C1\nC2\nC3\n"[..], b'\n')
+        ];
+        let tasks: Vec<OsString> =
+            Interleave::new(sources).map(Result::unwrap).collect();
+        assert_eq!(tasks, strings(&["A1", "B1", "C1", "A2", "B2", "C2",
+                                    "A3", "C3", "A4"]));
+    }
+
+    #[test]
+    fn tee() {
+        let input = b"a\nbb\n\nc";
+        let mut copy = Vec::new();
+        {
+            let tee = Tee::new(&input[..], &mut copy);
+            let tasks: Vec<OsString> =
+                Records::new(tee, b'\n').map(Result::unwrap).collect();
+            assert_eq!(tasks, strings(&["a", "bb", "", "c"]));
+        }
+        assert_eq!(copy, input);
+        // Only what was taken is copied.
+        let mut copy = Vec::new();
+        let mut records = Records::new(Tee::new(&input[..], &mut copy), b'\n');
+        records.next();
+        drop(records);
+        assert_eq!(copy, b"a\n");
+    }
+
+    #[test]
+    fn line_limit() {
+        // Megabytes without a newline, read a small buffer at a time.
+        let long = || io::BufReader::with_capacity(
+            4096, io::repeat(b'x').take(8 << 20).chain(&b"\nshort\n"[..]));
+        let mut records = Records::new(long(), b'\n')
+            .limit(Some((1000, LongLines::Fail)));
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "task longer than 1000 bytes");
+
+        let records: Vec<OsString> = Records::new(long(), b'\n')
+            .limit(Some((5, LongLines::Truncate)))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(records, strings(&["xxxxx", "short"]));
+
+        // A record as long as the limit is whole.
+        let records: Vec<OsString> = Records::new(&b"abc\nabcd"[..], b'\n')
+            .limit(Some((3, LongLines::Fail)))
+            .take(1)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(records, strings(&["abc"]));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn not_unicode() {
+        assert_eq!(records(b"\xff\xfe\n", b'\n'),
+                   vec![OsString::from_vec(b"\xff\xfe".to_vec())]);
+    }
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("oops"))
+        }
+    }
+
+    impl BufRead for Failing {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Err(io::Error::other("oops"))
+        }
+        fn consume(&mut self, _: usize) {}
+    }
+
+    #[test]
+    fn error() {
+        let err = Records::new(Failing, b'\n').next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "oops");
+    }
+
+    // Endless input, counting the lines taken from it.
+    struct Endless(Arc<AtomicUsize>);
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.fill_buf()?.read(buf)?;
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for Endless {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Ok(b"x\n")
+        }
+        fn consume(&mut self, n: usize) {
+            if n > 0 {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[test]
+    fn backlog() {
+        let taken = Arc::new(AtomicUsize::new(0));
+        let source = TaskSource::Reader(Box::new(Endless(taken.clone())));
+        let (tx, rx) = mpsc::channel();
+        let format = RecordFormat {
+            delim:  b'\n',
+            limit:  None,
+            blocks: None
+        };
+        let mut queue = TaskQueue::new(source, false, 0, format, 3, None, &tx);
+        let receive = |queue: &mut TaskQueue| {
+            match rx.recv_timeout(Duration::from_millis(200)) {
+                Ok(Event::Task(task)) => {
+                    queue.received(task).unwrap();
+                    true
+                },
+                Ok(_) => panic!("not a task"),
+                Err(_) => false
+            }
+        };
+
+        // With no task started, the reader stops after three.
+        for _ in 0..3 {
+            assert!(receive(&mut queue));
+        }
+        assert!(!receive(&mut queue));
+        assert_eq!(taken.load(Ordering::SeqCst), 3);
+
+        // Each task taken lets it read one more.
+        queue.next().unwrap().unwrap();
+        assert!(receive(&mut queue));
+        assert!(!receive(&mut queue));
+        assert_eq!(taken.load(Ordering::SeqCst), 4);
+        queue.close();
+    }
+}
@@ -0,0 +1,144 @@
+/*
+** I/O scheduling priorities for jobs.
+*/
+
+// With `ionice`, each child sets its own I/O scheduling class and level
+// with the ioprio_set system call, in a pre_exec hook, before it runs the
+// command.  The C library has no wrapper for it, so it is made directly.
+// Where the system call is not available, as on kernels built without it
+// or on other systems, commands are run through the ionice utility
+// instead, as `ionice -c CLASS -n LEVEL COMMAND...`.
+
+#[cfg(target_os = "linux")]
+use libc;
+use std::ffi::OsString;
+#[cfg(target_os = "linux")]
+use std::io;
+
+// From linux/ioprio.h.
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+/*---------------------------------------------------------------------------*/
+
+/// An I/O scheduling class and, for the classes that have them, a level
+/// from 0 (first served) to 7.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoPriority {
+    Realtime(u8),
+    BestEffort(u8),
+    Idle
+}
+
+impl IoPriority {
+    /// Parse a class, `realtime`, `best-effort` or `idle`, optionally
+    /// followed by `:LEVEL` for the first two.  The level is 4 by default,
+    /// as with ionice.
+    pub fn parse(s: &str) -> Option<IoPriority> {
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) =>
+                (class, Some(level.parse().ok().filter(|&n| n <= 7)?)),
+            None => (s, None)
+        };
+        match (class, level) {
+            ("realtime", _) => Some(IoPriority::Realtime(level.unwrap_or(4))),
+            ("best-effort", _) =>
+                Some(IoPriority::BestEffort(level.unwrap_or(4))),
+            ("idle", None) => Some(IoPriority::Idle),
+            _ => None
+        }
+    }
+
+    /// The priority as ioprio_set takes it.
+    pub fn encode(self) -> i32 {
+        let (class, level) = self.class_level();
+        (class << IOPRIO_CLASS_SHIFT) | i32::from(level.unwrap_or(0))
+    }
+
+    /// The command line to run a command at this priority with the ionice
+    /// utility, up to the command.
+    pub fn command_prefix(self) -> Vec<OsString> {
+        let (class, level) = self.class_level();
+        let mut argv = vec![OsString::from("ionice"), OsString::from("-c"),
+                            OsString::from(class.to_string())];
+        if let Some(level) = level {
+            argv.push(OsString::from("-n"));
+            argv.push(OsString::from(level.to_string()));
+        }
+        argv
+    }
+
+    // The number of the class, and the level if the class has levels.
+    fn class_level(self) -> (i32, Option<u8>) {
+        match self {
+            IoPriority::Realtime(level) => (1, Some(level)),
+            IoPriority::BestEffort(level) => (2, Some(level)),
+            IoPriority::Idle => (3, None)
+        }
+    }
+}
+
+/// Whether the calling process can set I/O priorities itself.
+#[cfg(target_os = "linux")]
+pub fn supported() -> bool {
+    let ret = unsafe {
+        libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0)
+    };
+    ret >= 0 ||
+        io::Error::last_os_error().raw_os_error() != Some(libc::ENOSYS)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn supported() -> bool {
+    false
+}
+
+/// Set the I/O priority of the calling process.  Only async-signal-safe
+/// calls are made, so that this may be called between fork and exec.
+#[cfg(target_os = "linux")]
+pub fn set(ioprio: i32) -> io::Result<()> {
+    let ret = unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio)
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::IoPriority;
+
+    #[test]
+    fn parse() {
+        assert_eq!(IoPriority::parse("idle"), Some(IoPriority::Idle));
+        assert_eq!(IoPriority::parse("best-effort"),
+                   Some(IoPriority::BestEffort(4)));
+        assert_eq!(IoPriority::parse("best-effort:7"),
+                   Some(IoPriority::BestEffort(7)));
+        assert_eq!(IoPriority::parse("realtime:0"),
+                   Some(IoPriority::Realtime(0)));
+        assert_eq!(IoPriority::parse("best-effort:8"), None);
+        assert_eq!(IoPriority::parse("best-effort:"), None);
+        assert_eq!(IoPriority::parse("idle:3"), None);
+        assert_eq!(IoPriority::parse("lazy"), None);
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(IoPriority::Idle.encode(), 3 << 13);
+        assert_eq!(IoPriority::BestEffort(7).encode(), (2 << 13) | 7);
+        assert_eq!(IoPriority::Realtime(0).encode(), 1 << 13);
+    }
+
+    #[test]
+    fn command_prefix() {
+        assert_eq!(IoPriority::Idle.command_prefix(), ["ionice", "-c", "3"]);
+        assert_eq!(IoPriority::BestEffort(6).command_prefix(),
+                   ["ionice", "-c", "2", "-n", "6"]);
+    }
+}
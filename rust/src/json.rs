@@ -0,0 +1,434 @@
+/*
+** A minimal JSON parser for tasks given as JSON objects.
+*/
+
+// Only what is needed to pick fields out of a task is here: parsing a whole
+// document into a Value, looking up dotted field paths, and turning a value
+// back into text for a command argument.  Objects can also be built and
+// written out, for the documents that ljobs writes itself.
+
+use std::fmt;
+use std::io::Write;
+
+/*---------------------------------------------------------------------------*/
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    // Numbers are kept as written, as they are only ever substituted.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub msg:    &'static str
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.msg, self.offset)
+    }
+}
+
+struct Parser<'a> {
+    s:          &'a [u8],
+    pos:        usize
+}
+
+// Nesting deeper than this is surely a mistake, and would otherwise risk
+// overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/*---------------------------------------------------------------------------*/
+
+/// Parse a complete JSON document.
+pub fn parse(s: &[u8]) -> Result<Value, ParseError> {
+    let mut p = Parser { s, pos: 0 };
+    let value = p.value(0)?;
+    p.skip_space();
+    if p.pos < s.len() {
+        return Err(p.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+/// An object with `members` in the order given.
+pub fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(members.into_iter()
+        .map(|(key, value)| (String::from(key), value))
+        .collect())
+}
+
+impl Value {
+    /// The value at a path of object keys separated by dots.
+    pub fn lookup(&self, path: &[u8]) -> Option<&Value> {
+        let mut value = self;
+        for key in path.split(|&c| c == b'.') {
+            value = match *value {
+                Value::Object(ref members) => members.iter()
+                    .rev() // the last of duplicate keys wins
+                    .find(|(k, _)| k.as_bytes() == key)
+                    .map(|(_, v)| v)?,
+                _ => return None
+            };
+        }
+        Some(value)
+    }
+
+    /// Append the value as text for a command argument: strings without
+    /// quotes, null as nothing, and arrays and objects as compact JSON.
+    pub fn write_text(&self, out: &mut Vec<u8>) {
+        match *self {
+            Value::Null => (),
+            Value::String(ref s) => out.extend_from_slice(s.as_bytes()),
+            _ => self.write_json(out)
+        }
+    }
+
+    /// Append the value as compact JSON.
+    pub fn write_json(&self, out: &mut Vec<u8>) {
+        match *self {
+            Value::Null => out.extend_from_slice(b"null"),
+            Value::Bool(b) => {
+                out.extend_from_slice(if b { b"true" } else { b"false" })
+            },
+            Value::Number(ref n) => out.extend_from_slice(n.as_bytes()),
+            Value::String(ref s) => write_string(s, out),
+            Value::Array(ref items) => {
+                out.push(b'[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    item.write_json(out);
+                }
+                out.push(b']');
+            },
+            Value::Object(ref members) => {
+                out.push(b'{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    write_string(key, out);
+                    out.push(b':');
+                    value.write_json(out);
+                }
+                out.push(b'}');
+            }
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut Vec<u8>) {
+    out.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => out.extend_from_slice(b"\\\""),
+            '\\' => out.extend_from_slice(b"\\\\"),
+            '\n' => out.extend_from_slice(b"\\n"),
+            '\r' => out.extend_from_slice(b"\\r"),
+            '\t' => out.extend_from_slice(b"\\t"),
+            // Writing to a Vec cannot fail.
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => {
+                let mut buf = [0; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    out.push(b'"');
+}
+
+/*---------------------------------------------------------------------------*/
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &'static str) -> ParseError {
+        ParseError { offset: self.pos, msg }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') =
+            self.peek()
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ParseError> {
+        self.skip_space();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(match c {
+                b':' => "expected ':'",
+                _ => "unexpected character"
+            }))
+        }
+    }
+
+    fn literal(&mut self, word: &[u8], value: Value)
+        -> Result<Value, ParseError> {
+
+        if self.s[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.skip_space();
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.literal(b"null", Value::Null),
+            Some(b't') => self.literal(b"true", Value::Bool(true)),
+            Some(b'f') => self.literal(b"false", Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => self.array(depth),
+            Some(b'{') => self.object(depth),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character"))
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                },
+                _ => return Err(self.error("expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_space();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_space();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected string key"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            let value = self.value(depth + 1)?;
+            members.push((key, value));
+            self.skip_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                },
+                _ => return Err(self.error("expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("expected digit"))
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected digit"));
+            }
+            self.digits();
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected digit"));
+            }
+            self.digits();
+        }
+        // Only ASCII was accepted.
+        let text = String::from_utf8_lossy(&self.s[start..self.pos]);
+        Ok(Value::Number(text.into_owned()))
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut buf = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                },
+                Some(b'\\') => {
+                    self.pos += 1;
+                    self.escape(&mut buf)?;
+                },
+                Some(c) if c < 0x20 => {
+                    return Err(self.error("control character in string"));
+                },
+                Some(c) => {
+                    buf.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        String::from_utf8(buf).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn escape(&mut self, buf: &mut Vec<u8>) -> Result<(), ParseError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("unterminated string"))
+        };
+        self.pos += 1;
+        let unescaped = match c {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let hi = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&hi) {
+                    // A surrogate pair.
+                    if !self.s[self.pos..].starts_with(b"\\u") {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    self.pos += 2;
+                    let lo = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&lo) {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)
+                } else {
+                    hi
+                };
+                match char::from_u32(code) {
+                    Some(c) => c,
+                    None => return Err(self.error("unpaired surrogate"))
+                }
+            },
+            _ => return Err(self.error("invalid escape"))
+        };
+        let mut utf8 = [0; 4];
+        buf.extend_from_slice(unescaped.encode_utf8(&mut utf8).as_bytes());
+        Ok(())
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self.s.get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .filter(|d| d.bytes().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap_or(0))
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    fn text(doc: &str, path: &str) -> Option<String> {
+        let value = parse(doc.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        value.lookup(path.as_bytes())?.write_text(&mut out);
+        Some(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn fields() {
+        let doc = r#" {"name": "cat.jpg", "size": -1.5e3, "ok": true,
+                      "none": null, "tags": ["a", 1], "dim": {"w": 2}} "#;
+        assert_eq!(text(doc, "name").as_deref(), Some("cat.jpg"));
+        assert_eq!(text(doc, "size").as_deref(), Some("-1.5e3"));
+        assert_eq!(text(doc, "ok").as_deref(), Some("true"));
+        assert_eq!(text(doc, "none").as_deref(), Some(""));
+        assert_eq!(text(doc, "tags").as_deref(), Some(r#"["a",1]"#));
+        assert_eq!(text(doc, "dim.w").as_deref(), Some("2"));
+        assert_eq!(text(doc, "dim").as_deref(), Some(r#"{"w":2}"#));
+        assert_eq!(text(doc, "missing"), None);
+        assert_eq!(text(doc, "name.x"), None);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(text(r#"{"s": "a\"b\\c\/d\n\u00e9\ud83d\ude00"}"#, "s")
+                       .as_deref(),
+                   Some("a\"b\\c/d\n\u{e9}\u{1f600}"));
+        let mut out = Vec::new();
+        parse(br#"["a\"\n\u0001"]"#).unwrap().write_text(&mut out);
+        assert_eq!(out, br#"["a\"\n\u0001"]"#);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert_eq!(text(r#"{"a": 1, "a": 2}"#, "a").as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn errors() {
+        for doc in &["", "{", "{\"a\"}", "{\"a\":}", "[1,]", "01", "1.",
+                     "\"abc", "\"\\x\"", "\"\\ud800\"", "nul", "{} x",
+                     "{a: 1}", "\"\t\""] {
+            assert!(parse(doc.as_bytes()).is_err(), "{:?}", doc);
+        }
+        let deep = "[".repeat(1000);
+        assert!(parse(deep.as_bytes()).is_err());
+        assert_eq!(parse(b"[true, false]").unwrap(),
+                   Value::Array(vec![Value::Bool(true), Value::Bool(false)]));
+    }
+}
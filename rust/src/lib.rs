@@ -0,0 +1,3135 @@
+/*
+** ljobs - A tool to execute commands in parallel.
+*/
+
+//! Run a command for each of a list of tasks, several at a time.
+//!
+//! This is the library behind the `ljobs` program.  A command template is
+//! set up with [`Options`], tasks are supplied by a [`TaskSource`], and
+//! [`run`] executes the command once per task and reports the results.
+//! [`run_with_events`] also reports each [`JobEvent`] as it happens.
+//! The substitutions made in command arguments are in [`template`], and
+//! the quoting of command lines for display is in [`quote`].
+//!
+//! ```
+//! use ljobs::{Options, TaskSource};
+//! use std::ffi::OsString;
+//!
+//! let opts = Options::new()
+//!     .command("echo")
+//!     .arg("hello {}")
+//!     .jobs(2);
+//! let tasks = vec![OsString::from("a"), OsString::from("b")];
+//! let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+//!
+//! assert_eq!(summary.errors, 0);
+//! assert_eq!(summary.tasks.len(), 2);
+//! for task in &summary.tasks {
+//!     let expected = format!("hello {}\n",
+//!                            tasks[task.tasknum].to_str().unwrap());
+//!     assert_eq!(task.stdout, expected.as_bytes());
+//! }
+//! ```
+
+#[cfg(unix)]
+extern crate libc;
+extern crate num_cpus;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+extern crate sha2;
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::mpsc::{self, Receiver, RecvError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod cache;
+mod cgroup;
+mod container;
+mod cpus;
+mod csv;
+mod error;
+mod hosts;
+mod input;
+mod ionice;
+mod json;
+mod manifest;
+#[cfg(target_os = "linux")]
+mod namespace;
+mod os;
+mod output;
+mod pattern;
+pub mod quote;
+#[cfg(unix)]
+mod reaper;
+#[cfg(windows)]
+#[path = "reaper_windows.rs"]
+mod reaper;
+mod results;
+mod sandbox;
+#[cfg(all(test, unix))]
+mod sim;
+mod spawn;
+mod stage;
+mod status;
+mod system;
+mod systemd;
+pub mod template;
+pub mod webhook;
+
+use cache::Cache;
+use cgroup::{Cgroups, JobCgroup};
+pub use cgroup::CgroupLimits;
+pub use cpus::{CpuLimits, CpuSource};
+pub use error::Error;
+pub use input::LongLines;
+pub use ionice::IoPriority;
+pub use spawn::ResourceUsage;
+use hosts::{HostPool, SshLogin};
+use input::{BlockFormat, RecordFormat, Task, TaskQueue, READAHEAD};
+use manifest::{Manifest, OutputHash};
+use os::from_os_bytes;
+#[cfg(target_os = "linux")]
+use namespace::Namespaces;
+use output::{Budget, Output};
+use pattern::{LineScan, Pattern};
+use quote::quote_cmd;
+use reaper::Reaper;
+use sandbox::Sandbox;
+use status::{Counts, End, Progress, ProgressFile, StatusFile,
+             STATUS_INTERVAL};
+use system::{Clock, Spawner, SystemClock};
+use template::{CommandTemplate, ShellTemplate};
+
+/*---------------------------------------------------------------------------*/
+
+const PROG: &str = "ljobs";
+
+// The variable that has the label of a job in its environment.
+const LABEL_VAR: &str = "LJOBS_LABEL";
+const SLOT_VAR: &str = "LJOBS_SLOT";
+
+// Commands which could not be started for want of resources, or which ssh
+// could not connect to run, are tried again after a delay, doubled each
+// time.
+const SPAWN_RETRIES: u32 = 5;
+const SPAWN_BACKOFF: Duration = Duration::from_millis(50);
+
+// The longest delay between attempts at a failed task with `retry_until`.
+const RETRY_UNTIL_DELAY: Duration = Duration::from_secs(1);
+
+// The exit status of ssh when it fails itself.
+const SSH_FAILED: i32 = 255;
+
+// What a task that timed out counts as having exited with, as with the
+// timeout utility.
+const TIMEOUT_EXIT: i32 = 124;
+
+// The exit status with `fail_if_empty` when there was no task to run.
+const EMPTY_EXIT: i32 = 123;
+
+// A running job may hold open two pipes, a pidfd and two files of spilled
+// output.  Some files are kept for ljobs itself.
+const FILES_PER_JOB: usize = 5;
+const RESERVED_FILES: usize = 16;
+
+/// Options controlling how tasks are run.
+///
+/// Options are built up by chaining setter methods onto `Options::new()`.
+#[derive(Clone, Debug)]
+pub struct Options {
+    cmd:        OsString,
+    cmdargs:    Vec<OsString>,
+    taskprefix: OsString,
+    tasksuffix: OsString,
+    colsep:     Option<OsString>,
+    stdincolumn: Option<usize>,
+    blocksize:  Option<usize>,
+    recstart:   OsString,
+    recend:     Option<OsString>,
+    strict:     bool,
+    jsoninput:  bool,
+    interleave: bool,
+    null:       bool,
+    maxbacklog: usize,
+    linelimit:  Option<(usize, LongLines)>,
+    skip:       usize,
+    maxtasks:   Option<usize>,
+    skipempty:  bool,
+    filter:     Option<String>,
+    exclude:    Option<String>,
+    keepnumbering: bool,
+    maxjobs:    usize,
+    jobssource: Option<CpuSource>,
+    ramp:       Duration,
+    keepgoing:  bool,
+    haltcmdonly: bool,
+    keepgoingexit: Option<i32>,
+    countfailures: bool,
+    failifempty: bool,
+    failsummary: bool,
+    abortsignal: bool,
+    abortmatch: Option<String>,
+    retryafter: Option<String>,
+    retryuntil: Option<Duration>,
+    halttimeout: Option<Duration>,
+    timeout:    Option<Duration>,
+    timeoutaction: TimeoutAction,
+    reapzombies: bool,
+    openfiles:  Option<usize>,
+    needoutput: bool,
+    warnstderr: bool,
+    failstderr: bool,
+    shell:      Option<OsString>,
+    shellliteral: bool,
+    scriptfile: Option<PathBuf>,
+    runif:      Option<OsString>,
+    sshlogins:  Vec<OsString>,
+    sshloginfile: Option<PathBuf>,
+    transfer:   bool,
+    returns:    Vec<OsString>,
+    cleanup:    bool,
+    container:  Option<OsString>,
+    containerengine: Option<OsString>,
+    containerargs: Vec<OsString>,
+    cgroup:     Option<CgroupLimits>,
+    ionice:     Option<IoPriority>,
+    oomscoreadj: Option<i32>,
+    privatetmp: bool,
+    isolatenet: bool,
+    allowread:  Vec<OsString>,
+    allowwrite: Vec<OsString>,
+    sandboxbest: bool,
+    systemdrun: bool,
+    systemdprops: Vec<OsString>,
+    verbose:    bool,
+    showsteps:  bool,
+    showusage:  bool,
+    separators: Option<(bool, bool)>,
+    smalloutput: Option<u64>,
+    echo:       bool,
+    dots:       bool,
+    dryrun:     bool,
+    color:      bool,
+    tag:        Option<OsString>,
+    label:      Option<OsString>,
+    slotoffset: usize,
+    print:      bool,
+    manifest:   Option<PathBuf>,
+    teemanifest: Option<i32>,
+    statusfile: Option<PathBuf>,
+    progressfile: Option<PathBuf>,
+    saveinput:  Option<PathBuf>,
+    cache:      Option<PathBuf>,
+    cacheinput: bool,
+    results:    Option<OsString>,
+    gzipresults: bool,
+    csv:        bool,
+    spill:      usize,
+    buffer:     Option<usize>
+}
+
+/// Where tasks are taken from.
+pub enum TaskSource<'a> {
+    /// Each string is a task.
+    Slice(&'a [OsString]),
+    /// Each line read is a task.  Lines are read ahead of need in a
+    /// separate thread.
+    Reader(Box<dyn BufRead + Send>),
+    /// Each line read from standard input is a task, as for `Reader`.
+    Stdin,
+    /// Each line read from each reader in turn is a task, as for `Reader`.
+    /// With `interleave`, a line is taken from each reader in turn instead.
+    Readers(Vec<Box<dyn BufRead + Send>>),
+    /// Each item is a task.
+    Iter(Box<dyn Iterator<Item = OsString> + 'a>)
+}
+
+/// How a task finished.
+#[derive(Debug)]
+pub enum TaskStatus {
+    /// The command exited with the given exit status.  A command that
+    /// could not be started because it was not found, or could not be
+    /// executed, has exit status 127 or 126, as in a shell.
+    Exited(i32),
+    /// The command was terminated by the given signal.
+    Signaled(i32),
+    /// The command could not be started or waited on.
+    Error(io::Error),
+    /// The command exited successfully but wrote no output, with
+    /// `fail_on_no_output`.
+    NoOutput,
+    /// The command exited successfully but wrote to standard error, with
+    /// `fail_on_stderr`.
+    WroteStderr,
+    /// The command was not run because of `dry_run`.
+    DryRun,
+    /// The command ran for longer than the `timeout` and was terminated.
+    TimedOut,
+    /// The command was not run because the `run_if` guard failed, or it
+    /// timed out with `TimeoutAction::Skip`.
+    Skipped
+}
+
+/// What a task that runs for longer than the `timeout` counts as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeoutAction {
+    /// A failure, as though the command exited with status 124.
+    Fail,
+    /// Skipped, so that it does not affect the exit status.
+    Skip,
+    /// A failure, but the task is tried again first, a few times.
+    Retry
+}
+
+/// The result of running the command for a single task.
+#[derive(Debug)]
+pub struct TaskResult {
+    /// The task number, counting from 0.
+    pub tasknum:    usize,
+    /// The command line, after substitutions.
+    pub argv:       Vec<OsString>,
+    pub status:     TaskStatus,
+    /// Captured standard output, empty if output was printed.
+    pub stdout:     Vec<u8>,
+    /// Captured standard error, empty if output was printed.
+    pub stderr:     Vec<u8>,
+    /// Time from starting the command until it was reaped.
+    pub duration:   Duration,
+    /// The process id of the command, if it was started.
+    pub pid:        Option<u32>,
+    /// Whether the output was replayed from the cache instead of running
+    /// the command.
+    pub cached:     bool,
+    /// The CPU time and peak memory of the command, if it ran and the
+    /// system says.
+    pub usage:      Option<ResourceUsage>,
+    /// The number of bytes the command wrote to standard error, whether
+    /// or not they were captured.
+    pub stderr_len: u64,
+    /// The label of the job, with `label`.
+    pub label:      Option<OsString>
+}
+
+/// Something that happened to a task during a run, as reported to the
+/// observer passed to [`run_with_events`].  Events for a task arrive in
+/// the order that they happen, and every task started finishes exactly
+/// once, though tasks may also finish without having been started.
+#[derive(Debug)]
+pub enum JobEvent<'a> {
+    /// The command for a task was started.
+    Started {
+        tasknum:    usize,
+        pid:        u32,
+        argv:       &'a [OsString],
+        label:      Option<&'a OsStr>,
+        time:       Instant
+    },
+    /// The command could not be started for want of resources, or ssh
+    /// could not connect to run it, and it will be tried again later.
+    Retrying {
+        tasknum:    usize,
+        argv:       &'a [OsString],
+        error:      &'a io::Error,
+        time:       Instant
+    },
+    /// A task finished, with its result.  The output of the task has been
+    /// printed or written already.
+    Finished {
+        result:     &'a TaskResult,
+        time:       Instant
+    }
+}
+
+/// The outcome of a call to `run`.
+#[derive(Debug)]
+pub struct RunSummary {
+    /// Results of tasks in the order that they finished.
+    pub tasks:      Vec<TaskResult>,
+    /// Number of tasks that were read but filtered out, with `skip_empty`,
+    /// `filter_tasks` or `exclude_tasks`.
+    pub filtered:   usize,
+    /// Number of tasks that failed.
+    pub errors:     u32,
+    /// Number of those tasks whose command itself failed, rather than
+    /// ljobs failing to start it or to wait for it.
+    pub command_errors: u32,
+    /// Exit status corresponding to the most recent failed task, or 255 if
+    /// no task failed.
+    pub failed_exit: i32,
+    /// Whether the run was cut short by a task terminated by a signal,
+    /// with `abort_on_signal`, or by output matching with
+    /// `abort_on_match`.
+    pub aborted:    bool,
+    /// The most output held in memory at once, in bytes, with
+    /// `output_buffer`.
+    pub peak_buffered: usize,
+    /// Time from the start of the run until the last task finished.
+    pub elapsed:    Duration
+}
+
+// Messages to the master.
+enum Event {
+    Task(io::Result<Option<Task<'static>>>),
+    Done(Box<Job>),
+    // A line of output from the task of this number matched the pattern of
+    // `abort_on_match`.
+    Matched(usize),
+    // An internal thread panicked, with the name of the thread.
+    Panicked(String),
+    // Time to rewrite the status file, with `status_file`.
+    Tick
+}
+
+// Tells the master if the thread that holds it panics, so that the master
+// does not wait forever on a thread that has gone away.
+struct PanicGuard {
+    events:     Sender<Event>
+}
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let name = thread::current().name().unwrap_or("").to_string();
+            let _ = self.events.send(Event::Panicked(name));
+        }
+    }
+}
+
+// Start an internal thread named `name`, watched over by a PanicGuard.
+fn spawn_worker<F>(name: &str, events: Sender<Event>, f: F) -> io::Result<()>
+    where F: FnOnce() + Send + 'static
+{
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let _guard = PanicGuard { events };
+            f()
+        })?;
+    Ok(())
+}
+
+// What is known about a job when it is started.
+struct JobInfo {
+    tasknum:    usize,
+    task:       OsString,
+    attempts:   u32,
+    argv:       Vec<OsString>,
+    cachekey:   Option<String>,
+    results:    Option<PathBuf>,
+    // The host that the command runs on with ssh.
+    host:       Option<usize>,
+    label:      Option<OsString>,
+    // The job slot that the job holds, counting from 0.
+    slot:       usize,
+    cgroup:     Option<JobCgroup>,
+    start:      Instant,
+    // With `timeout`, when the command is to be terminated.
+    deadline:   Option<Instant>,
+    // When the first attempt at the task started.
+    first:      Instant
+}
+
+struct Job {
+    info:       JobInfo,
+    pid:        u32,
+    waitresult: io::Result<ExitStatus>,
+    usage:      Option<ResourceUsage>,
+    duration:   Duration,
+    // Whether the command was terminated for running past its deadline.
+    timedout:   bool,
+    stdout:     Output,
+    stderr:     Output
+}
+
+// The master's view of the system.  Jobs that finish and tasks read in the
+// background arrive as events.
+struct System<S, C> {
+    spawner:    S,
+    clock:      C,
+    events:     Receiver<Event>
+}
+
+// A task whose command could not be started for want of resources, or
+// failed to connect with ssh, to be started again later.
+struct Retry {
+    tasknum:    usize,
+    task:       OsString,
+    attempts:   u32,
+    when:       Instant,
+    first:      Instant
+}
+
+// State of the run shared by the master's helpers.
+struct RunState<'a> {
+    maxjobs:    usize,
+    numjobs:    usize,
+    // Which job slots are held by running jobs.
+    slots:      Vec<bool>,
+    retries:    VecDeque<Retry>,
+    throttle:   Option<usize>,
+    summary:    RunSummary,
+    template:   CommandTemplate,
+    hosts:      Option<HostPool>,
+    cgroups:    Option<Cgroups>,
+    sandbox:    Option<Sandbox>,
+    guard:      Option<ShellTemplate>,
+    manifest:   Option<Manifest>,
+    cache:      Option<Cache>,
+    retryafter: Option<Arc<Pattern>>,
+    filter:     Option<Pattern>,
+    exclude:    Option<Pattern>,
+    status:     Option<StatusFile>,
+    progress:   Option<ProgressFile>,
+    dotted:     bool,
+    observer:   &'a mut dyn FnMut(&JobEvent)
+}
+
+/*---------------------------------------------------------------------------*/
+
+// A warning that cannot be written is dropped: there is nowhere else to
+// report it, and it is no reason to abandon the run.
+fn warn(args: fmt::Arguments) {
+    let _ = io::stderr().write_fmt(args);
+}
+
+macro_rules! warn {
+    ( $( $x:expr ),+ ) => { warn(format_args!( $( $x ),+ )) }
+}
+
+/*---------------------------------------------------------------------------*/
+
+impl Default for Options {
+    fn default() -> Options {
+        let (jobs, source) = CpuLimits::detect().jobs();
+        Options {
+            cmd:        OsString::new(),
+            cmdargs:    Vec::new(),
+            taskprefix: OsString::new(),
+            tasksuffix: OsString::new(),
+            colsep:     None,
+            stdincolumn: None,
+            blocksize:  None,
+            recstart:   OsString::new(),
+            recend:     None,
+            strict:     false,
+            jsoninput:  false,
+            interleave: false,
+            null:       false,
+            maxbacklog: READAHEAD,
+            linelimit:  None,
+            skip:       0,
+            maxtasks:   None,
+            skipempty:  false,
+            filter:     None,
+            exclude:    None,
+            keepnumbering: false,
+            maxjobs:    jobs,
+            jobssource: Some(source),
+            ramp:       Duration::from_secs(0),
+            keepgoing:  false,
+            haltcmdonly: false,
+            keepgoingexit: None,
+            countfailures: false,
+            failifempty: false,
+            failsummary: false,
+            abortsignal: false,
+            abortmatch: None,
+            retryafter: None,
+            retryuntil: None,
+            halttimeout: None,
+            timeout:    None,
+            timeoutaction: TimeoutAction::Fail,
+            reapzombies: false,
+            openfiles:  None,
+            needoutput: false,
+            warnstderr: false,
+            failstderr: false,
+            shell:      None,
+            shellliteral: false,
+            scriptfile: None,
+            runif:      None,
+            sshlogins:  Vec::new(),
+            sshloginfile: None,
+            transfer:   false,
+            returns:    Vec::new(),
+            cleanup:    false,
+            container:  None,
+            containerengine: None,
+            containerargs: Vec::new(),
+            cgroup:     None,
+            ionice:     None,
+            oomscoreadj: None,
+            privatetmp: false,
+            isolatenet: false,
+            allowread:  Vec::new(),
+            allowwrite: Vec::new(),
+            sandboxbest: false,
+            systemdrun: false,
+            systemdprops: Vec::new(),
+            verbose:    false,
+            showsteps:  false,
+            showusage:  false,
+            separators: None,
+            smalloutput: None,
+            echo:       false,
+            dots:       false,
+            dryrun:     false,
+            color:      false,
+            tag:        None,
+            label:      None,
+            slotoffset: 0,
+            print:      false,
+            manifest:   None,
+            teemanifest: None,
+            statusfile: None,
+            progressfile: None,
+            saveinput:  None,
+            cache:      None,
+            cacheinput: false,
+            results:    None,
+            gzipresults: false,
+            csv:        false,
+            spill:      output::DEFAULT_SPILL,
+            buffer:     None
+        }
+    }
+}
+
+impl Options {
+    /// Default options: one job slot per processor, stop after the first
+    /// failed task, and capture the output of tasks.
+    pub fn new() -> Options {
+        Options::default()
+    }
+
+    /// Set the command to run for each task.
+    pub fn command<S: Into<OsString>>(mut self, cmd: S) -> Options {
+        self.cmd = cmd.into();
+        self
+    }
+
+    /// Append an argument to the command.  The task substitution strings
+    /// such as `{}` are replaced in arguments.
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> Options {
+        self.cmdargs.push(arg.into());
+        self
+    }
+
+    /// Append several arguments to the command.
+    pub fn args<I, S>(mut self, args: I) -> Options
+        where I: IntoIterator<Item = S>, S: Into<OsString>
+    {
+        self.cmdargs.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Prepend `prefix` to each task before substitution.
+    pub fn task_prefix<S: Into<OsString>>(mut self, prefix: S) -> Options {
+        self.taskprefix = prefix.into();
+        self
+    }
+
+    /// Append `suffix` to each task before substitution.
+    pub fn task_suffix<S: Into<OsString>>(mut self, suffix: S) -> Options {
+        self.tasksuffix = suffix.into();
+        self
+    }
+
+    /// Split each task into columns at each occurrence of `sep`, for the
+    /// `{N}` substitution strings.  Without a separator, a task is a single
+    /// column.
+    pub fn column_separator<S: Into<OsString>>(mut self, sep: S) -> Options {
+        self.colsep = Some(sep.into());
+        self
+    }
+
+    /// Write column `column` of each task, counting from 1 as for `{N}`, to
+    /// the standard input of its command through a pipe, which is then
+    /// closed.  Needs a `column_separator`.  A task without the column
+    /// gives its command empty input.
+    pub fn stdin_column(mut self, column: usize) -> Options {
+        self.stdincolumn = Some(column);
+        self
+    }
+
+    /// Cut the input into blocks of about `block_size` bytes instead of
+    /// reading a task from each line, and write each block to the standard
+    /// input of its command through a pipe, which is then closed, instead
+    /// of passing it as an argument.  Blocks are only cut between records,
+    /// as `record_start` and `record_end` say, so a block may be larger.
+    /// Needs tasks read from input.
+    pub fn pipe(mut self, block_size: usize) -> Options {
+        self.blocksize = Some(block_size.max(1));
+        self
+    }
+
+    /// With `pipe`, only cut blocks before a record starting with `start`.
+    pub fn record_start<S: Into<OsString>>(mut self, start: S) -> Options {
+        self.recstart = start.into();
+        self
+    }
+
+    /// With `pipe`, only cut blocks after a record ending with `end`.  By
+    /// default records end with a newline, unless there is a
+    /// `record_start`, when they may end anywhere.
+    pub fn record_end<S: Into<OsString>>(mut self, end: S) -> Options {
+        self.recend = Some(end.into());
+        self
+    }
+
+    /// Make it an error for a command argument to refer to a column that a
+    /// task does not have, instead of substituting nothing.  Likewise for
+    /// the column of `stdin_column`.
+    pub fn strict(mut self, strict: bool) -> Options {
+        self.strict = strict;
+        self
+    }
+
+    /// Treat each task as a JSON document, for the `{.field}` substitution
+    /// strings.  Nested fields are named by a path such as `{.size.w}`.
+    pub fn json_input(mut self, jsoninput: bool) -> Options {
+        self.jsoninput = jsoninput;
+        self
+    }
+
+    /// Ignore the first `skip` tasks.  The tasks that follow keep their
+    /// task numbers, so the first task started is numbered `skip`.
+    pub fn skip(mut self, skip: usize) -> Options {
+        self.skip = skip;
+        self
+    }
+
+    /// Start only the first `max` tasks, after any skipped, and ignore the
+    /// rest.
+    pub fn max_tasks(mut self, max: usize) -> Options {
+        self.maxtasks = Some(max);
+        self
+    }
+
+    /// Drop tasks that are empty or only whitespace, as xargs ignores blank
+    /// lines.  They are not numbered, unless `keep_numbering`, and are
+    /// counted in the `filtered` of the summary.
+    pub fn skip_empty(mut self, skip: bool) -> Options {
+        self.skipempty = skip;
+        self
+    }
+
+    /// Drop tasks that do not match the regular expression `pattern`, as
+    /// read and before any columns are split off, as with `skip_empty`.
+    /// Needs the `regex` feature.
+    pub fn filter_tasks<S: Into<String>>(mut self, pattern: S) -> Options {
+        self.filter = Some(pattern.into());
+        self
+    }
+
+    /// Drop tasks that match the regular expression `pattern`, as with
+    /// `filter_tasks`.
+    pub fn exclude_tasks<S: Into<String>>(mut self, pattern: S) -> Options {
+        self.exclude = Some(pattern.into());
+        self
+    }
+
+    /// Number the tasks dropped by `skip_empty`, `filter_tasks` or
+    /// `exclude_tasks` too, so that the tasks that run keep the numbers of
+    /// their places in the input.  `max_tasks` then counts them too.
+    pub fn keep_numbering(mut self, keep: bool) -> Options {
+        self.keepnumbering = keep;
+        self
+    }
+
+    /// With `TaskSource::Readers`, take tasks from each reader in turn
+    /// rather than exhausting one reader before the next.
+    pub fn interleave(mut self, interleave: bool) -> Options {
+        self.interleave = interleave;
+        self
+    }
+
+    /// Take tasks read from input to be terminated by NUL rather than by
+    /// newline, as from `find -print0`.
+    pub fn null(mut self, null: bool) -> Options {
+        self.null = null;
+        self
+    }
+
+    /// Read at most `backlog` tasks from input ahead of those started, 16
+    /// by default.  Input that comes faster than tasks can be run is left
+    /// unread, so that whatever writes it waits, rather than being held in
+    /// memory.  Zero is taken as one.
+    pub fn max_backlog(mut self, backlog: usize) -> Options {
+        self.maxbacklog = backlog.max(1);
+        self
+    }
+
+    /// Read tasks of at most `limit` bytes from input, not counting the
+    /// delimiter, and deal with longer ones as `policy` says, so that input
+    /// without delimiters cannot take all the memory.  Zero is taken as
+    /// one.
+    pub fn line_limit(mut self, limit: usize, policy: LongLines) -> Options {
+        self.linelimit = Some((limit.max(1), policy));
+        self
+    }
+
+    /// Set the number of job slots.  Zero means the number of processors
+    /// that jobs may run on, as limited by the CPU affinity of ljobs and
+    /// any cgroup CPU quota; see `CpuLimits`.
+    pub fn jobs(mut self, maxjobs: usize) -> Options {
+        if maxjobs > 0 {
+            self.maxjobs = maxjobs;
+            self.jobssource = None;
+        } else {
+            let (jobs, source) = CpuLimits::detect().jobs();
+            self.maxjobs = jobs;
+            self.jobssource = Some(source);
+        }
+        self
+    }
+
+    /// Start with one job slot and add slots at a steady rate until there
+    /// are `jobs` slots after `ramp` has passed.  This avoids a sudden
+    /// spike of load when a run starts.
+    pub fn ramp(mut self, ramp: Duration) -> Options {
+        self.ramp = ramp;
+        self
+    }
+
+    /// Continue starting tasks even if a previous task failed.
+    pub fn keep_going(mut self, keepgoing: bool) -> Options {
+        self.keepgoing = keepgoing;
+        self
+    }
+
+    /// Without `keep_going`, only stop starting tasks once the command of
+    /// a task has failed, as by exiting with a nonzero status, and not when
+    /// ljobs could not start a command or wait for it.  Such a task still
+    /// counts as failed in the summary and the exit code.
+    pub fn halt_on_command_failure_only(mut self, only: bool) -> Options {
+        self.haltcmdonly = only;
+        self
+    }
+
+    /// With `keep_going`, make the exit code `exit` if any task failed,
+    /// instead of the number of failed tasks.
+    pub fn keep_going_exit(mut self, exit: Option<i32>) -> Options {
+        self.keepgoingexit = exit;
+        self
+    }
+
+    /// Make the exit code the number of failed tasks, up to 255, with or
+    /// without `keep_going`.
+    pub fn count_failures_only(mut self, count: bool) -> Options {
+        self.countfailures = count;
+        self
+    }
+
+    /// Make the exit code 123 if no task was run, because there were none
+    /// or all were filtered out, rather than 0.  The reason is given on
+    /// standard error.
+    pub fn fail_if_empty(mut self, fail: bool) -> Options {
+        self.failifempty = fail;
+        self
+    }
+
+    /// At the end of a run in which tasks failed, list them on standard
+    /// error in task order, each with how it failed and its command line
+    /// quoted for the shell, so that they can be run again by hand.
+    pub fn fail_summary(mut self, summary: bool) -> Options {
+        self.failsummary = summary;
+        self
+    }
+
+    /// Stop starting tasks once a task is terminated by a signal, even with
+    /// `keep_going`.
+    pub fn abort_on_signal(mut self, abortsignal: bool) -> Options {
+        self.abortsignal = abortsignal;
+        self
+    }
+
+    /// Abort the run as soon as a line of output from any task, on either
+    /// stream, matches the regular expression `pattern`.  No more tasks
+    /// are started and the running tasks are asked to terminate, as with
+    /// `abort_on_signal`.  Output is scanned as it arrives, so this
+    /// happens while the task is still running.  Needs the `regex`
+    /// feature.
+    pub fn abort_on_match<S: Into<String>>(mut self, pattern: S) -> Options {
+        self.abortmatch = Some(pattern.into());
+        self
+    }
+
+    /// Try a task that failed again if a line of its output matches the
+    /// regular expression `pattern`, such as `Retry-After: (\d+)`, after
+    /// waiting the number of seconds in the first capture group of the
+    /// pattern, or else in the whole match.  The output of the failed
+    /// attempt is discarded.  A task is tried at most a few times, and then
+    /// fails as usual.  Needs the `regex` feature.
+    pub fn retry_after_pattern<S: Into<String>>(mut self, pattern: S)
+        -> Options {
+
+        self.retryafter = Some(pattern.into());
+        self
+    }
+
+    /// Try a task that failed again and again until it succeeds or `limit`
+    /// has passed since it was first started, e.g. for a task that waits
+    /// for a service to come up.  The delay between attempts starts small
+    /// and doubles each time, up to a second.  The output of each failed
+    /// attempt but the last is discarded.
+    pub fn retry_until(mut self, limit: Duration) -> Options {
+        self.retryuntil = Some(limit);
+        self
+    }
+
+    /// Once no more tasks are to be started because of a failure, wait at
+    /// most `timeout` for the running tasks to finish.  Tasks still running
+    /// after that are left running and are missing from the summary.
+    pub fn halt_timeout(mut self, timeout: Option<Duration>) -> Options {
+        self.halttimeout = timeout;
+        self
+    }
+
+    /// Terminate a command that runs for longer than `timeout`, and if it
+    /// has not exited a second later, kill it.  The task then counts as the
+    /// `timeout_action` says.  Not supported on Windows.
+    pub fn timeout(mut self, timeout: Duration) -> Options {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// What a task that times out counts as: by default, a failure.
+    pub fn timeout_action(mut self, action: TimeoutAction) -> Options {
+        self.timeoutaction = action;
+        self
+    }
+
+    /// When a run stops with an error, kill and wait for any command that
+    /// would otherwise be left behind, e.g. after an internal error or
+    /// past the halt timeout, so that none is left as a zombie of a
+    /// program that goes on after the run.
+    pub fn reap_zombies(mut self, reap: bool) -> Options {
+        self.reapzombies = reap;
+        self
+    }
+
+    /// Run fewer jobs at once than `jobs` if need be, so that running jobs
+    /// have no more than `limit` files open between them.  By default the
+    /// limit is the limit on open files of the process, if there is one.
+    pub fn open_file_limit(mut self, limit: usize) -> Options {
+        self.openfiles = Some(limit);
+        self
+    }
+
+    /// Count a task as failed if it writes nothing to either standard
+    /// output or standard error, even if it exits successfully.
+    pub fn fail_on_no_output(mut self, needoutput: bool) -> Options {
+        self.needoutput = needoutput;
+        self
+    }
+
+    /// Warn of each task that writes anything to standard error, even if
+    /// it exits successfully.
+    pub fn warn_on_stderr(mut self, warn: bool) -> Options {
+        self.warnstderr = warn;
+        self
+    }
+
+    /// Count a task as failed if it writes anything to standard error,
+    /// even if it exits successfully.
+    pub fn fail_on_stderr(mut self, fail: bool) -> Options {
+        self.failstderr = fail;
+        self
+    }
+
+    /// Run the command with the given shell interpreter, passing the
+    /// command arguments as positional parameters.
+    pub fn shell(mut self, shell: Option<OsString>) -> Options {
+        self.shell = shell;
+        self
+    }
+
+    /// With `shell`, run the command as literally as the shell allows: it
+    /// makes no parameter expansion, command substitution, arithmetic
+    /// expansion, tilde expansion or globbing, so that `$` and the like
+    /// may be used in the command without quoting.  The command is still
+    /// split into words and may have quotes, pipes and redirections.  The
+    /// command arguments are appended to the command instead of being
+    /// positional parameters.  Only on Unix.
+    pub fn shell_literal(mut self, literal: bool) -> Options {
+        self.shellliteral = literal;
+        self
+    }
+
+    /// Run the contents of the file at `path` as a shell script for each
+    /// task, instead of a command.  The script is run as with `shell`, or
+    /// else the `default_shell` in `ljobs::template`, and on Unix the
+    /// substitution strings in it are passed to the shell as positional
+    /// parameters, as for `run_if`.  The task is passed as `$1` if there
+    /// are none.
+    pub fn script_file<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.scriptfile = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Before running the command for a task, run the shell command line
+    /// `guard` with the task substituted, and skip the task unless the
+    /// guard succeeds.  Skipped tasks do not count as failed.  The guard is
+    /// run with the `shell`, if any, or else the `default_shell` in
+    /// `ljobs::template`.
+    pub fn run_if<S: AsRef<OsStr>>(mut self, guard: S) -> Options {
+        self.runif = Some(guard.as_ref().to_os_string());
+        self
+    }
+
+    /// Run commands on the remote machine `login`, e.g. `user@host`, with
+    /// `ssh -o BatchMode=yes login command...`.  The command line is quoted
+    /// for the remote shell.  `{host}` in a command argument is replaced
+    /// with the login of the machine that the command runs on.  As ssh
+    /// exits with status 255 if it cannot connect, such a command is tried
+    /// again a few times, on another machine if there is one, before
+    /// counting as failed.  May be given more than once.
+    pub fn ssh_login<S: AsRef<OsStr>>(mut self, login: S) -> Options {
+        self.sshlogins.push(login.as_ref().to_os_string());
+        self
+    }
+
+    /// Also run commands on the remote machines listed in the file at
+    /// `path`, as for `ssh_login`.  Each line has a login, which may be
+    /// preceded by the number of commands to run on that machine at once
+    /// and a slash, as in `8/user@host`.  Blank lines and lines starting
+    /// with `#` are ignored.  Commands are given to the least loaded
+    /// machine, and a machine that ssh fails to connect to several times
+    /// in a row is not used for a while.
+    pub fn ssh_login_file<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.sshloginfile = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// With ssh, copy the file named by a task, if there is one, to a
+    /// directory for the job on the remote host before running the
+    /// command.  The task is replaced by the path of the copy in
+    /// substitutions.
+    pub fn transfer(mut self, transfer: bool) -> Options {
+        self.transfer = transfer;
+        self
+    }
+
+    /// With ssh, copy the file named by substituting the task into
+    /// `pattern` back from the remote host after running the command,
+    /// whatever its exit status.  With `transfer`, the remote file is
+    /// named using the path of the copy of the task, and the local file
+    /// using the task as given.  May be given more than once.
+    pub fn return_file<S: AsRef<OsStr>>(mut self, pattern: S) -> Options {
+        self.returns.push(pattern.as_ref().to_os_string());
+        self
+    }
+
+    /// With ssh, remove the directory for the job on the remote host after
+    /// running the command, whether or not anything failed.
+    pub fn cleanup(mut self, cleanup: bool) -> Options {
+        self.cleanup = cleanup;
+        self
+    }
+
+    /// Run each command in a new container from `image`, with podman or
+    /// docker, whichever is found first.  The current directory is
+    /// mounted at the same path in the container and is its working
+    /// directory.
+    pub fn container<S: AsRef<OsStr>>(mut self, image: S) -> Options {
+        self.container = Some(image.as_ref().to_os_string());
+        self
+    }
+
+    /// Run containers with `engine`, e.g. `docker`, rather than looking
+    /// for one.
+    pub fn container_engine<S: AsRef<OsStr>>(mut self, engine: S)
+        -> Options {
+
+        self.containerengine = Some(engine.as_ref().to_os_string());
+        self
+    }
+
+    /// Append an argument for the container engine, before the image, such
+    /// as `--env=NAME=VALUE`.  The task substitution strings are replaced
+    /// as in command arguments, but do not count as the task being
+    /// substituted.
+    pub fn container_arg<S: Into<OsString>>(mut self, arg: S) -> Options {
+        self.containerargs.push(arg.into());
+        self
+    }
+
+    /// Run each job in a cgroup v2 of its own, with `limits` on the memory
+    /// and CPU time that it may use.  ljobs must be allowed to manage the
+    /// cgroup that it runs in, as when delegated by systemd, and moves
+    /// itself into a child cgroup for the run.  If it cannot, a warning is
+    /// given and the jobs are run without limits.  A job that reaches the
+    /// memory limit has a process killed by the kernel, which is noted
+    /// when it finishes.  Only on Linux.
+    pub fn cgroup(mut self, limits: CgroupLimits) -> Options {
+        self.cgroup = Some(limits);
+        self
+    }
+
+    /// Run each job at the I/O scheduling priority `priority`, such as
+    /// `IoPriority::Idle` so that the jobs only use the disks when nothing
+    /// else wants them.  Each child sets its own priority before running
+    /// the command, or if the system does not let it, the command is run
+    /// through the ionice utility.
+    pub fn ionice(mut self, priority: IoPriority) -> Options {
+        self.ionice = Some(priority);
+        self
+    }
+
+    /// Set the `oom_score_adj` of each job to `adj`, from -1000 to 1000, so
+    /// that when memory runs short the kernel kills jobs before, with a
+    /// positive value, or after, with a negative one, other processes.  A
+    /// job that may not set it, as when lowering it without privileges,
+    /// says so on its standard error and runs anyway.  Only on Linux.
+    pub fn oom_score_adj(mut self, adj: i32) -> Options {
+        self.oomscoreadj = Some(adj);
+        self
+    }
+
+    /// Give each job a private /tmp, an empty tmpfs of its own, in a mount
+    /// namespace of its own.  Without privileges, each job is also put in a
+    /// user namespace of its own, and the run fails if that cannot be done.
+    /// Only on Linux.
+    pub fn private_tmp(mut self, private: bool) -> Options {
+        self.privatetmp = private;
+        self
+    }
+
+    /// Cut each job off from the network, in an empty network namespace of
+    /// its own, as with `private_tmp`.  Only on Linux.
+    pub fn isolate_network(mut self, isolate: bool) -> Options {
+        self.isolatenet = isolate;
+        self
+    }
+
+    /// Confine each job to reading the files under `path`, and under any
+    /// other paths allowed, with Landlock.  The path may have substitution
+    /// strings.  May be given more than once.  Only on Linux 5.13 and
+    /// later; elsewhere the run fails unless `sandbox_best_effort`.
+    pub fn allow_read<S: Into<OsString>>(mut self, path: S) -> Options {
+        self.allowread.push(path.into());
+        self
+    }
+
+    /// As `allow_read`, but allow writing under `path` as well.
+    pub fn allow_write<S: Into<OsString>>(mut self, path: S) -> Options {
+        self.allowwrite.push(path.into());
+        self
+    }
+
+    /// Where jobs cannot be confined with `allow_read` and `allow_write`,
+    /// run them unconfined, with a warning, instead of failing the run.
+    pub fn sandbox_best_effort(mut self, best_effort: bool) -> Options {
+        self.sandboxbest = best_effort;
+        self
+    }
+
+    /// Run each command in a transient scope of the user's systemd service
+    /// manager, with `systemd-run --user --scope`, so that systemd accounts
+    /// for it as a unit of its own.  The command still runs as a child of
+    /// ljobs, and its exit status is passed through.
+    pub fn systemd_run(mut self, systemdrun: bool) -> Options {
+        self.systemdrun = systemdrun;
+        self
+    }
+
+    /// Append a property for the scope of each command with `systemd_run`,
+    /// such as `MemoryMax=2G`.
+    pub fn systemd_prop<S: Into<OsString>>(mut self, prop: S) -> Options {
+        self.systemdprops.push(prop.into());
+        self
+    }
+
+    /// Report the start and end of each task on standard error.
+    pub fn verbose(mut self, verbose: bool) -> Options {
+        self.verbose = verbose;
+        self
+    }
+
+    /// With `transfer`, `return_file` or `cleanup`, have each job report
+    /// the steps that it takes on its standard error.
+    pub fn show_steps(mut self, showsteps: bool) -> Options {
+        self.showsteps = showsteps;
+        self
+    }
+
+    /// With `verbose`, also report the `oom_score_adj` of each task when it
+    /// starts, and the CPU time and peak memory of each task that ran when
+    /// it finishes.
+    pub fn show_usage(mut self, showusage: bool) -> Options {
+        self.showusage = showusage;
+        self
+    }
+
+    /// Whether to set off the printed standard output and standard error of
+    /// each task with separator lines.  By default only standard error is,
+    /// and only with `verbose`.
+    pub fn output_separators(mut self, stdout: bool, stderr: bool)
+        -> Options {
+
+        self.separators = Some((stdout, stderr));
+        self
+    }
+
+    /// Print the output of a task that wrote at most `limit` bytes to
+    /// standard output and standard error together without separator
+    /// lines, and flush it at once, so that the output of quick, mostly
+    /// silent tasks shows up without delay.  Larger outputs keep their
+    /// separators.  Outputs still come in the order that tasks finish, but
+    /// small ones can no longer be told apart by task.
+    pub fn no_buffer_small(mut self, limit: u64) -> Options {
+        self.smalloutput = Some(limit);
+        self
+    }
+
+    /// Write each task to standard error as it is dispatched.
+    pub fn echo(mut self, echo: bool) -> Options {
+        self.echo = echo;
+        self
+    }
+
+    /// Write a single character to standard error as each task finishes:
+    /// `.` if it succeeded, `F` if it failed, `S` if it was terminated by a
+    /// signal, `T` if it timed out, or `s` if it was skipped.  A newline
+    /// follows the last one.
+    pub fn dots(mut self, dots: bool) -> Options {
+        self.dots = dots;
+        self
+    }
+
+    /// Do not run commands, only report them.
+    pub fn dry_run(mut self, dryrun: bool) -> Options {
+        self.dryrun = dryrun;
+        self
+    }
+
+    /// Colorize printed output lines by task number.
+    pub fn color(mut self, color: bool) -> Options {
+        self.color = color;
+        self
+    }
+
+    /// Start each printed output line with the task and `delimiter`, or
+    /// print lines as they are if `None`.
+    pub fn tag(mut self, delimiter: Option<OsString>) -> Options {
+        self.tag = delimiter;
+        self
+    }
+
+    /// Label each job with `template`, in which the task substitution
+    /// strings are replaced as in command arguments, such as
+    /// `batch42:{#}:{/.}`.  The label is set as LJOBS_LABEL in the
+    /// environment of the command, also inside a container, and replaces
+    /// `{label}` in its arguments.
+    /// It is also given in the manifest, the status file and the progress
+    /// shown with `verbose`, and with `tag`, it tags the output lines
+    /// instead of the task.
+    pub fn label<S: AsRef<OsStr>>(mut self, template: S) -> Options {
+        self.label = Some(template.as_ref().to_os_string());
+        self
+    }
+
+    /// Number the job slots from `offset` rather than 0.  Each running job
+    /// holds the lowest numbered slot that is free, which is set as
+    /// LJOBS_SLOT in the environment of the command, also inside a
+    /// container, and replaces `{%}` in its arguments, e.g. to give each
+    /// job a GPU of its own.
+    pub fn slot_offset(mut self, offset: usize) -> Options {
+        self.slotoffset = offset;
+        self
+    }
+
+    /// Write the output of each task to standard output and standard error
+    /// when it finishes, instead of capturing it in the `TaskResult`.
+    /// Dry run commands and task errors are likewise printed.
+    pub fn print_output(mut self, print: bool) -> Options {
+        self.print = print;
+        self
+    }
+
+    /// Keep up to `spill` bytes of the standard output or standard error of
+    /// a running task in memory, and the rest in a temporary file.  Output
+    /// that is captured in the `TaskResult` is read back into memory when
+    /// the task finishes.  The default is 4 MiB.
+    pub fn spill_size(mut self, spill: usize) -> Options {
+        self.spill = spill;
+        self
+    }
+
+    /// Keep up to `size` bytes of the output of all running tasks in memory
+    /// between them, spilling the rest to temporary files as for
+    /// `spill_size`.  By default only `spill_size` applies.
+    pub fn output_buffer(mut self, size: usize) -> Options {
+        self.buffer = Some(size);
+        self
+    }
+
+    /// Write a record of each task that was run to the file at `path`,
+    /// including its command, exit status and a SHA-256 digest of its
+    /// output.
+    pub fn manifest<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.manifest = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also write each record of the manifest to the file descriptor `fd`,
+    /// such as 1 for standard output, as soon as the task finishes.  The
+    /// descriptor is not closed.  Only 1 and 2 are supported on Windows.
+    pub fn tee_manifest(mut self, fd: i32) -> Options {
+        self.teemanifest = Some(fd);
+        self
+    }
+
+    /// Keep a small JSON document about the run in the file at `path`:
+    /// how many tasks have started, completed and failed, the jobs
+    /// running, the number of job slots, the time elapsed and the time
+    /// left, if it can be guessed.  The file is replaced every few seconds,
+    /// atomically, and once more at the end with the exit status.
+    pub fn status_file<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.statusfile = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Keep the counts of the run as a JSON document in the file at
+    /// `path`: how many tasks have completed, out of how many if that is
+    /// known yet, how many are running and how many failed.  The file is
+    /// replaced atomically when the run starts, each time a task finishes,
+    /// and once more at the end, for a dashboard to poll.
+    pub fn progress_file<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.progressfile = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Copy the input that tasks are read from to the file at `path` as it
+    /// is read, so that the same tasks can be run again from the file.
+    /// Only input that was read is copied; that includes the tasks skipped
+    /// with `skip`, but perhaps not the end of the input if the run stops
+    /// early.  Tasks must come from a reader or standard input.
+    pub fn save_input<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.saveinput = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Keep the output of each successful task in the directory `dir`,
+    /// keyed by its command line.  A task whose command line is already in
+    /// the cache is not run again; its cached output is reported instead.
+    pub fn cache<P: AsRef<Path>>(mut self, dir: P) -> Options {
+        self.cache = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Include the contents of the file named by each task in its cache key,
+    /// so that the task is run again when the file changes.
+    pub fn cache_input(mut self, cacheinput: bool) -> Options {
+        self.cacheinput = cacheinput;
+        self
+    }
+
+    /// Write the standard output of each task to a file instead, at the
+    /// path given by substituting the task into `path` as for a command
+    /// argument, e.g. `out/{/.}.log`.  Directories are created as needed.
+    /// The path must contain a substitution string.
+    pub fn results_path<S: AsRef<OsStr>>(mut self, path: S) -> Options {
+        self.results = Some(path.as_ref().to_os_string());
+        self
+    }
+
+    /// Compress each results file with gzip, streaming the output through
+    /// the compressor, and add `.gz` to its name.  Needs the `gzip`
+    /// feature.
+    pub fn compress_results(mut self, compress: bool) -> Options {
+        self.gzipresults = compress;
+        self
+    }
+
+    /// Print a row of comma-separated values for each task as it finishes
+    /// instead of its standard output: its number, the task, its exit
+    /// status, its duration in seconds and its standard output, quoted as
+    /// needed, after a header row.  Standard error is printed as usual.
+    pub fn csv(mut self, csv: bool) -> Options {
+        self.csv = csv;
+        self
+    }
+}
+
+impl TaskStatus {
+    /// Whether the task counts as failed.
+    pub fn failed(&self) -> bool {
+        !matches!(*self, TaskStatus::Exited(0) | TaskStatus::DryRun |
+                         TaskStatus::Skipped)
+    }
+}
+
+impl TaskResult {
+    /// The command line, quoted for the shell.
+    pub fn command(&self) -> String {
+        quote_cmd(&self.argv)
+    }
+
+    // The result of a task whose command did not run.
+    fn unrun(tasknum: usize, argv: Vec<OsString>, label: Option<OsString>,
+             status: TaskStatus) -> TaskResult {
+
+        TaskResult {
+            tasknum,
+            argv,
+            status,
+            stdout:   Vec::new(),
+            stderr:   Vec::new(),
+            duration: Duration::from_secs(0),
+            pid:      None,
+            cached:   false,
+            usage:    None,
+            stderr_len: 0,
+            label
+        }
+    }
+}
+
+impl RunSummary {
+    /// The conventional exit status of the `ljobs` program for this run.
+    ///
+    /// With `count_failures_only` it is the number of failed tasks up to
+    /// 255.  Otherwise with `keep_going` this is the number of failed tasks
+    /// up to 254, or the code given to `keep_going_exit` if any task
+    /// failed, otherwise it is the exit status of a failed task.  If the
+    /// run was aborted by a signal it is the exit status of a failed task
+    /// in either case.  With `fail_if_empty` it is 123 if no task was run.
+    pub fn exit_code(&self, opts: &Options) -> i32 {
+        if opts.failifempty && self.tasks.is_empty() {
+            EMPTY_EXIT
+        } else if opts.countfailures {
+            std::cmp::min(255, self.errors) as i32
+        } else if self.aborted {
+            self.failed_exit
+        } else if opts.keepgoing {
+            match opts.keepgoingexit {
+                Some(exit) if self.errors > 0 => exit,
+                _ => std::cmp::min(254, self.errors as i32)
+            }
+        } else if self.errors > 0 {
+            self.failed_exit
+        } else {
+            0
+        }
+    }
+
+    /// The numbers of the run as a JSON object, for machines: `total`
+    /// tasks finished, of which `ok` succeeded, `failed` failed, counting
+    /// the `signaled`, and `skipped` were skipped, the number `filtered`
+    /// out before they were run, whether the run was `aborted`, the
+    /// `wall_time` of the run in seconds, and the `throughput` in tasks
+    /// finished per second, or null if no time passed.
+    pub fn to_json(&self) -> Vec<u8> {
+        let count = |f: fn(&TaskStatus) -> bool| {
+            let n = self.tasks.iter().filter(|t| f(&t.status)).count();
+            json::Value::Number(n.to_string())
+        };
+        let secs = self.elapsed.as_secs_f64();
+        let throughput = if secs > 0.0 {
+            json::Value::Number(
+                format!("{:.3}", self.tasks.len() as f64 / secs))
+        } else {
+            json::Value::Null
+        };
+        let members = vec![
+            ("total", json::Value::Number(self.tasks.len().to_string())),
+            ("ok", count(|s| matches!(*s, TaskStatus::Exited(0)))),
+            ("failed", json::Value::Number(self.errors.to_string())),
+            ("signaled", count(|s| matches!(*s, TaskStatus::Signaled(_)))),
+            ("timed_out", count(|s| matches!(*s, TaskStatus::TimedOut))),
+            ("skipped", count(|s| matches!(*s, TaskStatus::Skipped))),
+            ("filtered", json::Value::Number(self.filtered.to_string())),
+            ("aborted", json::Value::Bool(self.aborted)),
+            ("wall_time", json::Value::Number(format!("{:.3}", secs))),
+            ("throughput", throughput)
+        ];
+        let mut out = Vec::new();
+        json::object(members).write_json(&mut out);
+        out.push(b'\n');
+        out
+    }
+
+    fn failed(&mut self, exit: i32) {
+        self.errors += 1;
+        self.failed_exit = exit;
+    }
+
+    fn command_failed(&mut self, exit: i32) {
+        self.failed(exit);
+        self.command_errors += 1;
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+/// Parse a number of bytes with an optional binary suffix `K`, `M`, `G` or
+/// `T`, in either case, such as `512k` or `2G`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let shift = match s.as_bytes().last()?.to_ascii_uppercase() {
+        b'K' => 10,
+        b'M' => 20,
+        b'G' => 30,
+        b'T' => 40,
+        _ => 0
+    };
+    let digits = if shift > 0 { &s[..s.len()-1] } else { s };
+    let n: u64 = digits.parse().ok()?;
+    n.checked_mul(1 << shift)
+}
+
+/// Run the command for each task from `source`.
+///
+/// Returns an [`Error`] if tasks could not be read, output could not be
+/// written, or a task could not be substituted into the command.  Failed
+/// tasks are not errors; they are counted in the summary.
+pub fn run(opts: &Options, source: TaskSource) -> Result<RunSummary, Error> {
+    run_with_events(opts, source, |_| ())
+}
+
+/// How the command line is built for `task`, or if it is `None`, for the
+/// first task from `source`, shown step by step without running anything:
+/// the arguments of the command template, the substitution strings found
+/// in each and what they are replaced with, the resulting arguments one
+/// per line, and the shell that runs the command, if any.  The command is
+/// explained as it would run here, not with ssh.
+pub fn explain(opts: &Options, source: TaskSource, task: Option<&OsStr>)
+    -> Result<String, Error> {
+
+    if opts.cmd.is_empty() && opts.scriptfile.is_none() {
+        return Err(Error::Usage(String::from("no command")));
+    }
+    let task = match task {
+        Some(task) => task.to_os_string(),
+        None => {
+            input::first_task(source, opts.interleave, opts.skip,
+                              record_format(opts))?
+                .ok_or_else(|| Error::Usage(String::from(
+                    "no task to explain")))?
+        }
+    };
+    let task = wrap_task(opts, Cow::Owned(task));
+    let tasknum = opts.skip;
+    let label = task_label(opts, tasknum, &task);
+    command_template(opts)?.explain(tasknum, &task, label.as_deref(),
+                                    opts.slotoffset)
+}
+
+/// Like [`run`], but `observer` is called with each [`JobEvent`] as it
+/// happens, in the calling thread.
+pub fn run_with_events<F>(opts: &Options, source: TaskSource, mut observer: F)
+    -> Result<RunSummary, Error>
+    where F: FnMut(&JobEvent)
+{
+    if opts.cmd.is_empty() && opts.scriptfile.is_none() {
+        return Err(Error::Usage(String::from("no command")));
+    }
+    if (opts.transfer || !opts.returns.is_empty() || opts.cleanup) &&
+        opts.sshlogins.is_empty() && opts.sshloginfile.is_none()
+    {
+        return Err(Error::Usage(
+            String::from("staging files needs an ssh login")));
+    }
+    if cfg!(windows) && opts.timeout.is_some() {
+        return Err(Error::Usage(
+            String::from("task timeouts are not supported on Windows")));
+    }
+    if cfg!(not(target_os = "linux")) && opts.oomscoreadj.is_some() {
+        return Err(Error::Usage(
+            String::from("oom_score_adj is only supported on Linux")));
+    }
+    if cfg!(not(target_os = "linux")) && (opts.privatetmp || opts.isolatenet)
+    {
+        return Err(Error::Usage(
+            String::from("private namespaces are only supported on Linux")));
+    }
+    if opts.stdincolumn.is_some() &&
+        opts.colsep.as_ref().is_none_or(|sep| sep.is_empty())
+    {
+        return Err(Error::Usage(
+            String::from("a stdin column needs a column separator")));
+    }
+    if opts.slotoffset.checked_add(opts.maxjobs).is_none() {
+        return Err(Error::Usage(
+            String::from("the job slot offset is too large")));
+    }
+    if opts.blocksize.is_some() {
+        if matches!(source, TaskSource::Slice(_) | TaskSource::Iter(_)) {
+            return Err(Error::Usage(String::from(
+                "cutting input into blocks needs tasks read from input")));
+        }
+        if opts.stdincolumn.is_some() {
+            return Err(Error::Usage(String::from(
+                "a stdin column cannot be used with blocks of input")));
+        }
+    }
+    if opts.shellliteral && (opts.shell.is_none() || cfg!(windows)) {
+        return Err(Error::Usage(
+            String::from("no shell expansion needs -c, on Unix")));
+    }
+    if !opts.systemdprops.is_empty() && !opts.systemdrun {
+        return Err(Error::Usage(
+            String::from("systemd properties need --systemd-run")));
+    }
+    if opts.saveinput.is_some() &&
+        matches!(source, TaskSource::Slice(_) | TaskSource::Iter(_))
+    {
+        return Err(Error::Usage(
+            String::from("saving input needs tasks read from input")));
+    }
+    if let Some(ref path) = opts.results {
+        if results::results_path(path, 0, OsStr::new("")).is_none() {
+            return Err(Error::Usage(
+                String::from("results path has no substitution string")));
+        }
+    }
+    if opts.csv && opts.results.is_some() {
+        return Err(Error::Usage(
+            String::from("CSV output cannot be used with a results path")));
+    }
+    if opts.gzipresults && opts.results.is_none() {
+        return Err(Error::Usage(
+            String::from("compressing results needs a results path")));
+    }
+    if opts.gzipresults && !cfg!(feature = "gzip") {
+        return Err(Error::Usage(
+            String::from("built without gzip compression")));
+    }
+    master(opts, source, &mut observer)
+}
+
+impl<'a> RunState<'a> {
+    fn new(opts: &Options, observer: &'a mut dyn FnMut(&JobEvent))
+        -> Result<RunState<'a>, Error> {
+
+        let maxjobs = max_jobs(opts);
+        #[cfg(target_os = "linux")]
+        if let Some(namespaces) = Namespaces::new(opts) {
+            namespaces.probe().map_err(|err| Error::Spawn(io::Error::new(
+                err.kind(),
+                format!("cannot run jobs in private namespaces: {}", err))))?;
+        }
+        Ok(RunState {
+            maxjobs,
+            numjobs:  0,
+            slots:    Vec::new(),
+            retries:  VecDeque::new(),
+            throttle: None,
+            summary:  RunSummary {
+                tasks:       Vec::new(),
+                filtered:    0,
+                errors:      0,
+                command_errors: 0,
+                failed_exit: 255,
+                aborted:     false,
+                peak_buffered: 0,
+                elapsed:     Duration::from_secs(0)
+            },
+            template: command_template(opts)?,
+            hosts:    ssh_hosts(opts, maxjobs)?,
+            cgroups:  opts.cgroup.and_then(|limits| {
+                match Cgroups::new(limits) {
+                    Ok(cgroups) => Some(cgroups),
+                    Err(err) => {
+                        if opts.print {
+                            warn!("{}: cannot run jobs in cgroups, so \
+                                   running them without limits: {}\n",
+                                  PROG, err);
+                        }
+                        None
+                    }
+                }
+            }),
+            sandbox:  match Sandbox::new(opts) {
+                Ok(sandbox) => sandbox,
+                Err(err) if opts.sandboxbest => {
+                    if opts.print {
+                        warn!("{}: cannot sandbox jobs, so running them \
+                               unconfined: {}\n", PROG, err);
+                    }
+                    None
+                },
+                Err(err) => return Err(Error::Spawn(io::Error::new(
+                    err.kind(), format!("cannot sandbox jobs: {}", err))))
+            },
+            guard:    opts.runif.as_ref().map(|guard| {
+                let shell = opts.shell.clone()
+                    .unwrap_or_else(template::default_shell);
+                ShellTemplate::new(&shell, guard)
+            }),
+            manifest: match (&opts.manifest, opts.teemanifest) {
+                (None, None) => None,
+                (path, fd) => Some(Manifest::create(path.as_deref(), fd)?)
+            },
+            cache:    match opts.cache {
+                Some(ref dir) => Some(Cache::open(dir, opts.cacheinput)?),
+                None => None
+            },
+            retryafter: match opts.retryafter {
+                Some(ref pattern) => Some(Arc::new(Pattern::new(pattern)
+                    .map_err(|err| Error::Usage(format!(
+                        "invalid pattern for retry after: {}", err)))?)),
+                None => None
+            },
+            filter:   match opts.filter {
+                Some(ref pattern) => Some(Pattern::new(pattern)
+                    .map_err(|err| Error::Usage(format!(
+                        "invalid pattern for filter: {}", err)))?),
+                None => None
+            },
+            exclude:  match opts.exclude {
+                Some(ref pattern) => Some(Pattern::new(pattern)
+                    .map_err(|err| Error::Usage(format!(
+                        "invalid pattern for exclude: {}", err)))?),
+                None => None
+            },
+            // Made when the run starts.
+            status:   None,
+            progress: opts.progressfile.as_deref().map(ProgressFile::new),
+            dotted:   false,
+            observer
+        })
+    }
+
+    // The progress shown with `verbose` is built on the same events.
+    fn notify(&mut self, opts: &Options, event: JobEvent) {
+        if opts.verbose {
+            verbose_event(opts, &event);
+        }
+        if let JobEvent::Finished { result, .. } = event {
+            if let (true, Some(dot)) = (opts.dots, dot(&result.status)) {
+                warn!("{}", dot);
+                self.dotted = true;
+            }
+        }
+        if let Some(ref mut status) = self.status {
+            status.event(&event);
+        }
+        (self.observer)(&event);
+    }
+
+    // End the line of dots, if any.
+    fn end_dots(&mut self) {
+        if self.dotted {
+            warn!("\n");
+            self.dotted = false;
+        }
+    }
+}
+
+// The number of jobs to run at once, within the limit on open files.
+fn max_jobs(opts: &Options) -> usize {
+    if let (true, Some(source)) = (opts.verbose, opts.jobssource) {
+        warn!("{}: job slots: {}, from the {}\n", PROG, opts.maxjobs, source);
+    }
+    let limit = match opts.openfiles.or_else(spawn::open_file_limit) {
+        Some(limit) if !opts.dryrun => limit,
+        _ => return opts.maxjobs
+    };
+    let safe = (limit.saturating_sub(RESERVED_FILES) / FILES_PER_JOB).max(1);
+    if opts.maxjobs <= safe {
+        return opts.maxjobs;
+    }
+    if opts.print {
+        warn!("{}: running at most {} jobs at once, with a limit of {} open \
+               files\n", PROG, safe, limit);
+    }
+    safe
+}
+
+fn master(opts: &Options,
+          source: TaskSource,
+          observer: &mut dyn FnMut(&JobEvent)) -> Result<RunSummary, Error> {
+
+    let state = RunState::new(opts, observer)?;
+
+    // The Rust standard library does not provide a way to wait on multiple
+    // child processes at once. Therefore the reaper waits on child processes
+    // in the background then communicates the result back to the parent
+    // through a channel.  Tasks read in the background arrive on the same
+    // channel, so the master only ever blocks in one place.
+    let save = match opts.saveinput {
+        Some(ref path) => Some(fs::File::create(path).map_err(|err| {
+            Error::Io(io::Error::new(err.kind(), format!(
+                "could not create {}: {}", path.display(), err)))
+        })?),
+        None => None
+    };
+    let (tx, rx) = mpsc::channel();
+    let tasks = TaskQueue::new(source, opts.interleave, opts.skip,
+                               record_format(opts), opts.maxbacklog, save,
+                               &tx);
+    let budget = opts.buffer.map(|size| Arc::new(Budget::new(size)));
+    let pattern = match opts.abortmatch {
+        Some(ref pattern) => Some(Arc::new(Pattern::new(pattern)
+            .map_err(|err| Error::Usage(format!(
+                "invalid pattern for abort on match: {}", err)))?)),
+        None => None
+    };
+    if opts.statusfile.is_some() {
+        start_ticker(&tx).map_err(Error::Spawn)?;
+    }
+    let sys = System {
+        spawner: Reaper::new(tx, opts.spill, budget.clone(), pattern),
+        clock:   SystemClock,
+        events:  rx
+    };
+    let mut summary = schedule(opts, state, tasks, sys)?;
+    if let Some(budget) = budget {
+        summary.peak_buffered = budget.peak();
+    }
+    Ok(summary)
+}
+
+fn schedule<S, C>(opts: &Options,
+                  mut state: RunState,
+                  mut tasks: TaskQueue,
+                  mut sys: System<S, C>) -> Result<RunSummary, Error>
+    where S: Spawner, C: Clock
+{
+    let start = sys.clock.now();
+    state.status = opts.statusfile.as_ref()
+        .map(|path| StatusFile::new(path, start));
+    write_status(opts, &state, start, None);
+    write_progress(opts, &state, false);
+    if opts.csv && opts.print && !opts.dryrun {
+        checked_write_all(&mut io::stdout(), csv::HEADER)?;
+    }
+    let mut result = dispatch(opts, &mut state, &mut tasks, &mut sys);
+    tasks.close();
+    if result.is_ok() {
+        result = wait_jobs(opts, &mut state, &mut sys);
+    }
+    state.end_dots();
+    if let Err(err) = result {
+        match err {
+            // Nobody is left to see the output of running tasks.
+            Error::BrokenPipe => sys.spawner.terminate_all(),
+            // The thread that would report the jobs may be the one that
+            // panicked, so do not wait for them.
+            Error::Internal(_) => sys.spawner.terminate_all(),
+            _ => ()
+        }
+        if !matches!(err, Error::Internal(_)) {
+            abandon_jobs(opts, &mut state, &mut sys);
+        }
+        if opts.reapzombies {
+            sys.spawner.reap_all();
+        }
+        let msg = err.to_string();
+        write_status(opts, &state, sys.clock.now(), Some(End::Error(&msg)));
+        write_progress(opts, &state, true);
+        return Err(err);
+    }
+    if let Some(manifest) = state.manifest.take() {
+        manifest.finish()?;
+    }
+    if opts.failsummary {
+        show_failures(&state.summary);
+    }
+    if opts.failifempty && opts.print && state.summary.tasks.is_empty() {
+        match state.summary.filtered {
+            0 => warn!("{}: no tasks were read\n", PROG),
+            1 => warn!("{}: the only task read was filtered out\n", PROG),
+            n => warn!("{}: all {} tasks read were filtered out\n", PROG, n)
+        }
+    }
+    let now = sys.clock.now();
+    state.summary.elapsed = now - start;
+    let exit = state.summary.exit_code(opts);
+    write_status(opts, &state, now, Some(End::Exit(exit)));
+    write_progress(opts, &state, true);
+    Ok(state.summary)
+}
+
+// List the failed tasks of the run, with `fail_summary`.
+fn show_failures(summary: &RunSummary) {
+    let mut failed: Vec<&TaskResult> = summary.tasks.iter()
+        .filter(|result| result.status.failed())
+        .collect();
+    if failed.is_empty() {
+        return;
+    }
+    failed.sort_by_key(|result| result.tasknum);
+    warn!("{}: {} failed {}:\n", PROG, failed.len(),
+          if failed.len() == 1 { "task" } else { "tasks" });
+    for result in failed {
+        let how: Cow<str> = match result.status {
+            TaskStatus::Exited(exit) => format!("exit {}", exit).into(),
+            TaskStatus::Signaled(signal) =>
+                format!("signal {}", signal_name(signal)).into(),
+            TaskStatus::Error(ref err) => format!("error: {}", err).into(),
+            TaskStatus::NoOutput => "no output".into(),
+            TaskStatus::WroteStderr => "stderr".into(),
+            TaskStatus::TimedOut => "timed out".into(),
+            TaskStatus::DryRun | TaskStatus::Skipped =>
+                unreachable!("not failed")
+        };
+        warn!("{}[{}]: {}\t{}\n", PROG, result.tasknum, how, result.command());
+    }
+}
+
+// Start tasks and handle finished jobs until there are no more tasks to
+// start.
+fn dispatch<S, C>(opts: &Options,
+                  state: &mut RunState,
+                  tasks: &mut TaskQueue,
+                  sys: &mut System<S, C>) -> Result<(), Error>
+    where S: Spawner, C: Clock
+{
+    let mut tasknum = opts.skip;
+    let start = sys.clock.now();
+
+    loop {
+        // Start tasks while there are free job slots and tasks to hand.  A
+        // task to be retried goes first, and holds up the rest until then.
+        let (slots, mut nextslot) =
+            job_slots(opts, state.maxjobs, start, sys.clock.now());
+        // The throttle is lifted as soon as a retried task starts.
+        while state.numjobs < state.throttle.map_or(slots, |t| slots.min(t)) &&
+            !stopping(opts, &state.summary)
+        {
+            let host = match state.hosts {
+                Some(ref hosts) => match hosts.choose(sys.clock.now()) {
+                    Some(host) => Some(host),
+                    None => {
+                        if let Some(up) = hosts.next_up() {
+                            nextslot = Some(nextslot.map_or(up,
+                                                            |n| n.min(up)));
+                        }
+                        break;
+                    }
+                },
+                None => None
+            };
+            if let Some(retry) = state.retries.pop_front() {
+                if retry.when > sys.clock.now() {
+                    nextslot = Some(nextslot.map_or(retry.when,
+                                                    |n| n.min(retry.when)));
+                    state.retries.push_front(retry);
+                    break;
+                }
+                start_task(opts, state, sys, retry.tasknum, &retry.task,
+                           Some(&retry), host)?;
+                continue;
+            }
+            if all_started(opts, tasknum) {
+                break;
+            }
+            let task = loop {
+                match tasks.next()? {
+                    Some(task) if filtered_out(opts, state, &task.text) => {
+                        state.summary.filtered += 1;
+                        if opts.keepnumbering {
+                            tasknum += 1;
+                            if all_started(opts, tasknum) {
+                                break None;
+                            }
+                        }
+                    },
+                    task => break task
+                }
+            };
+            let taskarg = match task {
+                Some(task) => wrap_task(opts, task.text),
+                None => break
+            };
+            start_task(opts, state, sys, tasknum, &taskarg, None, host)?;
+            tasknum += 1;
+        }
+
+        // Any running job may yet need to be tried again.
+        if ((tasks.is_done() || all_started(opts, tasknum)) &&
+            state.retries.is_empty() &&
+            (state.numjobs == 0 || !may_retry(opts, state))) ||
+            stopping(opts, &state.summary)
+        {
+            break;
+        }
+
+        if state.status.is_some() || state.progress.is_some() {
+            let taken = tasknum - opts.skip;
+            let total = if all_started(opts, tasknum) {
+                Some(taken)
+            } else {
+                tasks.remaining().map(|left| taken + left)
+            };
+            if let Some(total) = total {
+                let total = opts.maxtasks.map_or(total, |max| total.min(max));
+                if let Some(ref mut status) = state.status {
+                    status.set_total(total);
+                }
+                if let Some(ref mut progress) = state.progress {
+                    progress.set_total(total);
+                }
+            }
+        }
+
+        // Wait for more tasks or for a job to finish, or until another job
+        // slot opens up.
+        let event = sys.clock.wait_until(&sys.events, nextslot)
+            .map_err(recv_error)?;
+        match event {
+            Some(Event::Task(task)) => {
+                tasks.received(task)?;
+            },
+            Some(Event::Done(job)) => {
+                done_job(opts, state, *job)?;
+            },
+            Some(Event::Matched(tasknum)) => {
+                output_matched(opts, state, sys, tasknum);
+            },
+            Some(Event::Panicked(name)) => {
+                return Err(Error::Internal(name));
+            },
+            Some(Event::Tick) => {
+                write_status(opts, state, sys.clock.now(), None);
+            },
+            None => ()
+        }
+    }
+    Ok(())
+}
+
+// With `status_file`, tell the master when to rewrite the status file,
+// until it is no longer listening.
+fn start_ticker(events: &Sender<Event>) -> io::Result<()> {
+    let tx = events.clone();
+    spawn_worker("ljobs-status", events.clone(), move || {
+        loop {
+            thread::sleep(STATUS_INTERVAL);
+            if tx.send(Event::Tick).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+// Rewrite the status file, if there is one, as of `now`.  A status file
+// that cannot be written is not worth stopping the run for.
+fn write_status(opts: &Options, state: &RunState, now: Instant,
+                end: Option<End>) {
+
+    let status = match state.status {
+        Some(ref status) => status,
+        None => return
+    };
+    let (slots, _) = job_slots(opts, state.maxjobs, status.start(), now);
+    let counts = Counts {
+        completed: state.summary.tasks.len(),
+        failed:    state.summary.errors,
+        slots:     state.throttle.map_or(slots, |t| slots.min(t)),
+        stopping:  stopping(opts, &state.summary)
+    };
+    if let (Err(err), true) = (status.write(now, &counts, end), opts.print) {
+        warn!("{}: cannot write status file: {}\n", PROG, err);
+    }
+}
+
+// Rewrite the progress file, if there is one.  As with the status file, a
+// failure to write it does not stop the run.
+fn write_progress(opts: &Options, state: &RunState, finished: bool) {
+    let progress = match state.progress {
+        Some(ref progress) => progress,
+        None => return
+    };
+    let counts = Progress {
+        completed: state.summary.tasks.len(),
+        running:   state.numjobs,
+        failed:    state.summary.errors,
+        finished
+    };
+    if let (Err(err), true) = (progress.write(&counts), opts.print) {
+        warn!("{}: cannot write progress file: {}\n", PROG, err);
+    }
+}
+
+// Whether the tasks before `tasknum` are all that may be started.
+fn all_started(opts: &Options, tasknum: usize) -> bool {
+    opts.maxtasks.is_some_and(|max| tasknum >= opts.skip.saturating_add(max))
+}
+
+fn recv_error(err: RecvError) -> Error {
+    Error::Io(io::Error::other(format!("recv error: {}", err)))
+}
+
+// With a ramp, the number of job slots grows steadily from one to maxjobs
+// over the ramp period.  Returns the number of job slots now, and when the
+// next slot opens up if that is still to come, or None if it never will be
+// as it is beyond what an Instant can hold.
+fn job_slots(opts: &Options, maxjobs: usize, start: Instant, now: Instant)
+    -> (usize, Option<Instant>) {
+
+    let ramp = opts.ramp.as_secs_f64();
+    let elapsed = now.saturating_duration_since(start).as_secs_f64();
+    if maxjobs <= 1 || elapsed >= ramp {
+        return (maxjobs, None);
+    }
+    let step = ramp / (maxjobs - 1) as f64;
+    let slots = 1 + (elapsed / step) as usize;
+    let next = Duration::try_from_secs_f64(step * slots as f64).ok()
+        .and_then(|wait| start.checked_add(wait));
+    (slots, next)
+}
+
+// How tasks are read from input.
+fn record_format(opts: &Options) -> RecordFormat {
+    let recstart = opts.recstart.as_encoded_bytes().to_vec();
+    let recend = match opts.recend {
+        Some(ref end) => end.as_encoded_bytes().to_vec(),
+        None if recstart.is_empty() => b"\n".to_vec(),
+        None => Vec::new()
+    };
+    RecordFormat {
+        delim:  if opts.null { b'\0' } else { b'\n' },
+        limit:  opts.linelimit,
+        blocks: opts.blocksize.map(|size| BlockFormat {
+            size,
+            recstart,
+            recend
+        })
+    }
+}
+
+// The command to run for each task, from the command and its arguments or
+// from the script file.
+fn command_template(opts: &Options) -> Result<CommandTemplate, Error> {
+    match opts.scriptfile {
+        Some(ref path) => CommandTemplate::script(opts, &read_script(path)?),
+        None => CommandTemplate::new(opts, &opts.cmd, &opts.cmdargs)
+    }
+}
+
+fn read_script(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path).map_err(|err| {
+        io::Error::new(err.kind(), format!("script file {}: {}",
+                                           path.display(), err))
+    })
+}
+
+// The hosts to run commands on with ssh, if any.
+fn ssh_hosts(opts: &Options, maxjobs: usize)
+    -> Result<Option<HostPool>, Error> {
+
+    let mut logins: Vec<SshLogin> = opts.sshlogins.iter()
+        .map(|login| SshLogin { login: login.clone(), slots: None })
+        .collect();
+    if let Some(ref path) = opts.sshloginfile {
+        logins.extend(hosts::read_login_file(path)?);
+    }
+    if logins.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(HostPool::new(&logins, maxjobs)))
+}
+
+// Whether the task is to be dropped rather than run.
+fn filtered_out(opts: &Options, state: &RunState, task: &OsStr) -> bool {
+    let task = task.as_encoded_bytes();
+    (opts.skipempty && task.iter().all(u8::is_ascii_whitespace)) ||
+        state.filter.as_ref().is_some_and(|p| !p.is_match(task)) ||
+        state.exclude.as_ref().is_some_and(|p| p.is_match(task))
+}
+
+// Add the task prefix and suffix, if any.
+fn wrap_task<'a>(opts: &Options, task: Cow<'a, OsStr>) -> Cow<'a, OsStr> {
+    if opts.taskprefix.is_empty() && opts.tasksuffix.is_empty() {
+        return task;
+    }
+    let mut wrapped = OsString::with_capacity(
+        opts.taskprefix.len() + task.len() + opts.tasksuffix.len());
+    wrapped.push(&opts.taskprefix);
+    wrapped.push(&task);
+    wrapped.push(&opts.tasksuffix);
+    Cow::Owned(wrapped)
+}
+
+// Whether a job that finishes may have to be tried again: with ssh, when
+// the connection fails, or when the task asks or has to be.
+fn may_retry(opts: &Options, state: &RunState) -> bool {
+    state.hosts.is_some() || state.retryafter.is_some() ||
+        opts.retryuntil.is_some() ||
+        (opts.timeout.is_some() && opts.timeoutaction == TimeoutAction::Retry)
+}
+
+fn stopping(opts: &Options, summary: &RunSummary) -> bool {
+    let errors = if opts.haltcmdonly {
+        summary.command_errors
+    } else {
+        summary.errors
+    };
+    (errors > 0 && !opts.keepgoing) || summary.aborted
+}
+
+fn start_task<S, C>(opts: &Options,
+                    state: &mut RunState,
+                    sys: &mut System<S, C>,
+                    tasknum: usize,
+                    taskarg: &OsStr,
+                    retry: Option<&Retry>,
+                    host: Option<usize>) -> Result<(), Error>
+    where S: Spawner, C: Clock
+{
+    let attempts = retry.map_or(0, |retry| retry.attempts);
+    if opts.echo && attempts == 0 {
+        echo_task(taskarg)?;
+    }
+
+    // The command line is only quoted when it is shown.
+    let login = state.hosts.as_ref().zip(host)
+        .map(|(hosts, host)| hosts.login(host));
+    let label = task_label(opts, tasknum, taskarg);
+    // The job takes the lowest job slot that is free.
+    let slot = state.slots.iter().position(|&held| !held)
+        .unwrap_or(state.slots.len());
+    let slotnum = slot + opts.slotoffset;
+    let argv = state.template.build_argv(tasknum, taskarg, login,
+                                         label.as_deref(), Some(slotnum))?;
+    let input = state.template.build_input(tasknum, taskarg)?;
+
+    if opts.dryrun {
+        if opts.print {
+            dryrun(tasknum, &argv)?;
+        }
+        let time = sys.clock.now();
+        let result = TaskResult::unrun(tasknum, argv, label,
+                                       TaskStatus::DryRun);
+        return finish_task(opts, state, result, None, time);
+    }
+
+    // A task being retried has passed its guard already.
+    if let (Some(guard), 0) = (&state.guard, attempts) {
+        let guardargv = guard.build_argv(tasknum, taskarg);
+        let start = sys.clock.now();
+        match sys.spawner.check(&guardargv) {
+            Ok(true) => (),
+            Ok(false) => {
+                let time = sys.clock.now();
+                let result = TaskResult::unrun(tasknum, argv, label,
+                                               TaskStatus::Skipped);
+                return finish_task(opts, state, result, None, time);
+            },
+            Err(err) => {
+                let time = sys.clock.now();
+                return spawn_failed(opts, state, taskarg, TaskResult {
+                    duration: time - start,
+                    ..TaskResult::unrun(tasknum, argv, label,
+                                        TaskStatus::Error(err))
+                }, time);
+            }
+        }
+    }
+
+    let results = opts.results.as_ref()
+        .and_then(|path| results::results_path(path, tasknum, taskarg));
+    let cachekey = state.cache.as_ref().map(|c| c.key(&argv, taskarg));
+    if let (Some(cache), Some(key)) = (&state.cache, &cachekey) {
+        if let Some((stdout, stderr)) = cache.lookup(key) {
+            let info = JobInfo {
+                tasknum,
+                task: taskarg.to_os_string(),
+                attempts,
+                argv,
+                cachekey,
+                results,
+                host,
+                label,
+                slot,
+                cgroup: None,
+                start:  sys.clock.now(),
+                deadline: None,
+                first:  sys.clock.now()
+            };
+            return replay_task(opts, state, info, stdout, stderr);
+        }
+    }
+
+    let start = sys.clock.now();
+    let first = retry.map_or(start, |retry| retry.first);
+    let ruleset = state.sandbox.as_ref()
+        .map(|sandbox| sandbox.ruleset(tasknum, taskarg))
+        .transpose();
+    let mut env: Vec<(OsString, OsString)> = label.iter()
+        .map(|label| (OsString::from(LABEL_VAR), label.clone()))
+        .collect();
+    env.push((OsString::from(SLOT_VAR), OsString::from(slotnum.to_string())));
+    // Each error is paired with whether it came from starting the command
+    // itself, rather than from setting up for it.
+    let spawned = ruleset.map_err(|err| (err, false)).and_then(|ruleset| {
+        let cgroup = match state.cgroups {
+            Some(ref mut cgroups) => {
+                Some(cgroups.create().map_err(|err| (err, false))?)
+            },
+            None => None
+        };
+        let procs = cgroup.as_ref().map(|cgroup| cgroup.procs());
+        sys.spawner.spawn(opts, &argv, &env, procs, ruleset.as_ref(),
+                          input.as_deref())
+            .map(|child| (child, cgroup))
+            .map_err(|err| (err, true))
+    });
+    match spawned {
+        Ok((child, cgroup)) => {
+            state.numjobs += 1;
+            match state.slots.get_mut(slot) {
+                Some(held) => *held = true,
+                None => state.slots.push(true)
+            }
+            if let (Some(hosts), Some(host)) = (&mut state.hosts, host) {
+                hosts.started(host);
+            }
+            if attempts > 0 {
+                state.throttle = None;
+            }
+            state.notify(opts, JobEvent::Started {
+                tasknum,
+                pid:   sys.spawner.pid(&child),
+                argv:  &argv,
+                label: label.as_deref(),
+                time:  start
+            });
+            let info = JobInfo {
+                tasknum,
+                task: taskarg.to_os_string(),
+                attempts,
+                argv,
+                cachekey,
+                results,
+                host,
+                label,
+                slot,
+                cgroup,
+                start,
+                // A timeout too long to reach is no timeout at all.
+                deadline: opts.timeout
+                    .and_then(|timeout| start.checked_add(timeout)),
+                first
+            };
+            sys.spawner.watch(info, child)
+                .map_err(Error::Spawn)
+        },
+        Err((ref err, _))
+            if attempts < SPAWN_RETRIES && spawn::transient(err) => {
+            let retry = Retry {
+                tasknum,
+                task:     taskarg.to_os_string(),
+                attempts: attempts + 1,
+                when:     start,
+                first
+            };
+            retry_later(opts, state, retry, spawn_backoff(attempts + 1), &argv,
+                        err);
+            Ok(())
+        },
+        Err((err, exec)) => {
+            let time = sys.clock.now();
+            // A command that cannot be executed fails as in a shell.
+            let status = match spawn::exit_status(&err) {
+                Some(exit) if exec => TaskStatus::Exited(exit),
+                _ => TaskStatus::Error(err)
+            };
+            spawn_failed(opts, state, taskarg, TaskResult {
+                duration: time.saturating_duration_since(start),
+                ..TaskResult::unrun(tasknum, argv, label, status)
+            }, time)
+        }
+    }
+}
+
+// Try a task again once there is less going on, after `delay`.
+fn retry_later(opts: &Options,
+               state: &mut RunState,
+               mut retry: Retry,
+               delay: Duration,
+               argv: &[OsString],
+               err: &io::Error) {
+
+    state.notify(opts, JobEvent::Retrying {
+        tasknum: retry.tasknum,
+        argv,
+        error:   err,
+        time:    retry.when
+    });
+    retry.when += delay;
+    state.retries.push_back(retry);
+    state.throttle = Some(state.numjobs.max(1));
+}
+
+// The delay before retry number `retries`, which doubles with each retry.
+fn spawn_backoff(retries: u32) -> Duration {
+    SPAWN_BACKOFF * 2u32.pow(retries - 1)
+}
+
+// The delay before retry number `retries` with `retry_until`, which doubles
+// with each retry up to RETRY_UNTIL_DELAY.
+fn retry_until_backoff(retries: u32) -> Duration {
+    SPAWN_BACKOFF.saturating_mul(2u32.saturating_pow(retries - 1))
+        .min(RETRY_UNTIL_DELAY)
+}
+
+// The delay that the output of a task asks for before it is tried again.
+fn retry_delay(pattern: &Arc<Pattern>, outputs: [&mut Output; 2])
+    -> Option<Duration> {
+
+    for output in outputs {
+        let mut scan = LineScan::new(pattern.clone());
+        output.for_each_chunk(|data| -> io::Result<()> {
+            scan.feed(data);
+            Ok(())
+        }).ok()?;
+        scan.finish();
+        if let Some(line) = scan.matched_line() {
+            return pattern.delay(line);
+        }
+    }
+    None
+}
+
+// A command that could not be started counts as failed.  The `result` has
+// the error, or the exit status that a shell would give if the command was
+// not found or could not be executed.
+fn spawn_failed(opts: &Options,
+                state: &mut RunState,
+                task: &OsStr,
+                result: TaskResult,
+                time: Instant) -> Result<(), Error> {
+
+    match result.status {
+        TaskStatus::Exited(exit) => {
+            if opts.print {
+                warn!("{}[{}]: {}\t{}\n", PROG, result.tasknum,
+                      if exit == 127 {
+                          "command not found"
+                      } else {
+                          "cannot execute"
+                      },
+                      result.command());
+            }
+            state.summary.failed(exit);
+        },
+        ref status => {
+            if let (true, TaskStatus::Error(err)) = (opts.print, status) {
+                warn!("{}[{}]: error\t{}: {}\n",
+                      PROG, result.tasknum, result.command(), err);
+            }
+            state.summary.errors += 1;
+        }
+    }
+    if opts.csv && opts.print {
+        print_csv_row(result.tasknum, task, &result.status, result.duration,
+                      None)?;
+    }
+    finish_task(opts, state, result, Some(OutputHash::new()), time)
+}
+
+// The label of the job for a task, with `label`.
+fn task_label(opts: &Options, tasknum: usize, task: &OsStr)
+    -> Option<OsString> {
+
+    opts.label.as_ref().map(|label| {
+        match template::subst(label.as_encoded_bytes(), tasknum,
+                              task.as_encoded_bytes()) {
+            Some(label) => from_os_bytes(label),
+            None => label.clone()
+        }
+    })
+}
+
+// Report the cached output of a task instead of running it again.
+fn replay_task(opts: &Options,
+               state: &mut RunState,
+               info: JobInfo,
+               mut stdout: Output,
+               mut stderr: Output) -> Result<(), Error> {
+
+    report_output(opts, &info, &mut stdout, &mut stderr)?;
+    if opts.csv && opts.print {
+        print_csv_row(info.tasknum, &info.task, &TaskStatus::Exited(0),
+                      Duration::from_secs(0), Some(&mut stdout))?;
+    }
+
+    let hash = output_hash(state, &mut stdout, &mut stderr)?;
+    let stderr_len = stderr.len();
+    finish_task(opts, state, TaskResult {
+        tasknum:  info.tasknum,
+        argv:     info.argv,
+        status:   TaskStatus::Exited(0),
+        stdout:   captured(opts, stdout)?,
+        stderr:   captured(opts, stderr)?,
+        duration: Duration::from_secs(0),
+        pid:      None,
+        cached:   true,
+        usage:    None,
+        stderr_len,
+        label:    info.label
+    }, hash, info.start)
+}
+
+/*---------------------------------------------------------------------------*/
+
+fn dryrun(tasknum: usize, argv: &[OsString]) -> Result<(), Error> {
+    checked_write_fmt(&mut io::stdout(),
+                      format_args!("[{}]\t{}\n", tasknum, quote_cmd(argv)))
+}
+
+// The task is written as is, as it need not be valid Unicode.
+fn echo_task(taskarg: &OsStr) -> Result<(), Error> {
+    let mut err = io::stderr().lock();
+    checked_write_all(&mut err, taskarg.as_encoded_bytes())?;
+    checked_write_all(&mut err, b"\n")
+}
+
+/*---------------------------------------------------------------------------*/
+
+fn wait_jobs<S, C>(opts: &Options,
+                   state: &mut RunState,
+                   sys: &mut System<S, C>) -> Result<(), Error>
+    where S: Spawner, C: Clock
+{
+    let mut deadline = None;
+    while state.numjobs > 0 {
+        if deadline.is_none() && stopping(opts, &state.summary) {
+            let now = sys.clock.now();
+            deadline = opts.halttimeout.and_then(|t| now.checked_add(t));
+        }
+        let event = sys.clock.wait_until(&sys.events, deadline)
+            .map_err(recv_error)?;
+        match event {
+            Some(Event::Done(job)) => {
+                done_job(opts, state, *job)?;
+            },
+            Some(Event::Task(_)) => {
+                // No longer wanted.
+            },
+            Some(Event::Matched(tasknum)) => {
+                output_matched(opts, state, sys, tasknum);
+            },
+            Some(Event::Panicked(name)) => {
+                return Err(Error::Internal(name));
+            },
+            Some(Event::Tick) => {
+                write_status(opts, state, sys.clock.now(), None);
+            },
+            None => {
+                if opts.print {
+                    warn!("{}: halt timeout: {} jobs still running\n",
+                          PROG, state.numjobs);
+                }
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+// With `abort_on_match`, the output of a task matched, so no more tasks
+// are started and the jobs still running are asked to terminate.
+fn output_matched<S, C>(opts: &Options,
+                        state: &mut RunState,
+                        sys: &mut System<S, C>,
+                        tasknum: usize)
+    where S: Spawner, C: Clock
+{
+    if state.summary.aborted {
+        return;
+    }
+    if opts.print {
+        warn!("{}[{}]: output matched, aborting\n", PROG, tasknum);
+    }
+    state.summary.aborted = true;
+    sys.spawner.terminate_all();
+}
+
+// After an error, wait for the jobs still running without reporting them,
+// so that no command is left behind unreaped.  This is bounded by the halt
+// timeout, if any and if it can be reached.
+fn abandon_jobs<S, C>(opts: &Options,
+                      state: &mut RunState,
+                      sys: &mut System<S, C>)
+    where S: Spawner, C: Clock
+{
+    let now = sys.clock.now();
+    let deadline = opts.halttimeout.and_then(|t| now.checked_add(t));
+    while state.numjobs > 0 {
+        let event = sys.clock.wait_until(&sys.events, deadline)
+            .ok().flatten();
+        match event {
+            Some(Event::Done(_)) => state.numjobs -= 1,
+            Some(Event::Task(_)) | Some(Event::Matched(_)) |
+            Some(Event::Tick) => (),
+            Some(Event::Panicked(_)) | None => break
+        }
+    }
+}
+
+fn done_job(opts: &Options, state: &mut RunState, job: Job)
+    -> Result<(), Error> {
+
+    let mut info = job.info;
+    state.numjobs -= 1;
+    if let Some(held) = state.slots.get_mut(info.slot) {
+        *held = false;
+    }
+
+    // Anything that the job left running in its cgroup is killed as the
+    // cgroup is removed.
+    let oomkilled = info.cgroup.take()
+        .is_some_and(|cgroup| cgroup.oom_killed());
+
+    let sshfailed = matches!(job.waitresult,
+        Ok(ref status) if status.code() == Some(SSH_FAILED));
+    let mut login = None;
+    if let (Some(hosts), Some(host)) = (&mut state.hosts, info.host) {
+        let now = info.start + job.duration;
+        let login = login.insert(hosts.login(host).to_os_string());
+        if hosts.finished(host, sshfailed, now) && opts.print {
+            warn!("{}: ssh to {} keeps failing, not using it for a while\n",
+                  PROG, login.to_string_lossy());
+        }
+    }
+
+    if let (true, Some(login)) = (sshfailed, &login) {
+        if info.attempts < SPAWN_RETRIES && !stopping(opts, &state.summary) {
+            let err = io::Error::other(format!("ssh to {} failed",
+                                               login.to_string_lossy()));
+            let retry = Retry {
+                tasknum:  info.tasknum,
+                task:     info.task,
+                attempts: info.attempts + 1,
+                when:     info.start + job.duration,
+                first:    info.first
+            };
+            retry_later(opts, state, retry, spawn_backoff(info.attempts + 1),
+                        &info.argv, &err);
+            return Ok(());
+        }
+    }
+
+    // The reaper collected all output while the job ran.
+    let mut stdout = job.stdout;
+    let mut stderr = job.stderr;
+
+    if job.timedout && opts.timeoutaction == TimeoutAction::Retry &&
+        info.attempts < SPAWN_RETRIES && !stopping(opts, &state.summary)
+    {
+        let err = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        let retry = Retry {
+            tasknum:  info.tasknum,
+            task:     info.task,
+            attempts: info.attempts + 1,
+            when:     info.start + job.duration,
+            first:    info.first
+        };
+        retry_later(opts, state, retry, spawn_backoff(info.attempts + 1),
+                    &info.argv, &err);
+        return Ok(());
+    }
+
+    // A task that failed saying how long to wait is tried again after that
+    // long.
+    let failed = matches!(job.waitresult,
+        Ok(ref status) if status.code().is_some_and(|exit| exit != 0));
+    if let (true, Some(pattern)) = (failed, state.retryafter.clone()) {
+        if info.attempts < SPAWN_RETRIES && !stopping(opts, &state.summary) {
+            if let Some(delay) =
+                retry_delay(&pattern, [&mut stdout, &mut stderr])
+            {
+                let err = io::Error::other(format!(
+                    "asked to retry after {:?}", delay));
+                let retry = Retry {
+                    tasknum:  info.tasknum,
+                    task:     info.task,
+                    attempts: info.attempts + 1,
+                    when:     info.start + job.duration,
+                    first:    info.first
+                };
+                retry_later(opts, state, retry, delay, &info.argv, &err);
+                return Ok(());
+            }
+        }
+    }
+
+    // With a deadline, a failed task is tried again while there is time.
+    if let (true, Some(limit)) = (failed, opts.retryuntil) {
+        let now = info.start + job.duration;
+        let delay = retry_until_backoff(info.attempts + 1);
+        if now + delay <= info.first + limit &&
+            !stopping(opts, &state.summary)
+        {
+            let err = io::Error::other(format!(
+                "failed, {:?} left to succeed",
+                (info.first + limit).saturating_duration_since(now)));
+            let retry = Retry {
+                tasknum:  info.tasknum,
+                task:     info.task,
+                attempts: info.attempts + 1,
+                when:     now,
+                first:    info.first
+            };
+            retry_later(opts, state, retry, delay, &info.argv, &err);
+            return Ok(());
+        }
+    }
+
+    report_output(opts, &info, &mut stdout, &mut stderr)?;
+
+    let summary = &mut state.summary;
+    let status = match job.waitresult {
+        Ok(_) if job.timedout => {
+            if opts.print {
+                warn!("{}[{}]: the command ran past the timeout and was \
+                       terminated\n", PROG, info.tasknum);
+            }
+            if opts.timeoutaction == TimeoutAction::Skip {
+                TaskStatus::Skipped
+            } else {
+                summary.command_failed(TIMEOUT_EXIT);
+                TaskStatus::TimedOut
+            }
+        },
+        Ok(exitstatus) => {
+            match exitstatus.code() {
+                Some(0) if opts.needoutput &&
+                           stdout.is_empty() && stderr.is_empty() => {
+                    summary.command_failed(1);
+                    TaskStatus::NoOutput
+                },
+                Some(0) if opts.failstderr && !stderr.is_empty() => {
+                    summary.command_failed(1);
+                    TaskStatus::WroteStderr
+                },
+                Some(0) => TaskStatus::Exited(0),
+                Some(exit) => {
+                    if let (SSH_FAILED, true, Some(login)) =
+                        (exit, opts.print, &login)
+                    {
+                        warn!("{}[{}]: ssh to {} failed, or the command \
+                               exited with {}\n", PROG, info.tasknum,
+                              login.to_string_lossy(), SSH_FAILED);
+                    }
+                    if let (Some(failure), true, true) =
+                        (container::failure(exit), opts.print,
+                         opts.container.is_some())
+                    {
+                        warn!("{}[{}]: {} (or the command exited with \
+                               {})\n", PROG, info.tasknum, failure, exit);
+                    }
+                    summary.command_failed(exit);
+                    TaskStatus::Exited(exit)
+                },
+                None => {
+                    match term_signal(&exitstatus) {
+                        Some(signal) => {
+                            summary.command_failed(128 + signal);
+                            if opts.abortsignal {
+                                summary.aborted = true;
+                            }
+                            TaskStatus::Signaled(signal)
+                        },
+                        None => {
+                            // Should not happen.
+                            wait_error(opts, summary, job.pid,
+                                io::Error::other(
+                                    "child terminated for unknown reason"))
+                        }
+                    }
+                }
+            }
+        },
+        Err(err) => wait_error(opts, summary, job.pid, err)
+    };
+    if oomkilled && opts.print {
+        warn!("{}[{}]: a process was killed for reaching the memory limit\n",
+              PROG, info.tasknum);
+    }
+
+    if opts.csv && opts.print {
+        print_csv_row(info.tasknum, &info.task, &status, job.duration,
+                      Some(&mut stdout))?;
+    }
+
+    if let TaskStatus::Exited(0) = status {
+        if let (Some(cache), Some(key)) = (&state.cache, &info.cachekey) {
+            cache.store(key, &mut stdout, &mut stderr, 0)?;
+        }
+    }
+
+    let hash = output_hash(state, &mut stdout, &mut stderr)?;
+    let stderr_len = stderr.len();
+    finish_task(opts, state, TaskResult {
+        tasknum:  info.tasknum,
+        argv:     info.argv,
+        status,
+        stdout:   captured(opts, stdout)?,
+        stderr:   captured(opts, stderr)?,
+        duration: job.duration,
+        pid:      Some(job.pid),
+        cached:   false,
+        usage:    job.usage,
+        stderr_len,
+        label:    info.label
+    }, hash, info.start + job.duration)
+}
+
+// A command whose exit status could not be had counts as failed.
+fn wait_error(opts: &Options, summary: &mut RunSummary, pid: u32,
+              err: io::Error) -> TaskStatus {
+
+    if opts.print {
+        warn!("wait error pid {}: {}\n", pid, err);
+    }
+    summary.failed(255);
+    TaskStatus::Error(err)
+}
+
+// The signal that terminated a command.  Commands on Windows only ever
+// exit with a status.
+#[cfg(unix)]
+fn term_signal(status: &ExitStatus) -> Option<i32> {
+    status.signal()
+}
+
+#[cfg(windows)]
+fn term_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+// The name of a signal for people to read, or its number if it is not a
+// common one.
+#[cfg(unix)]
+fn signal_name(signal: i32) -> Cow<'static, str> {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return Cow::Owned(signal.to_string())
+    };
+    Cow::Borrowed(name)
+}
+
+#[cfg(windows)]
+fn signal_name(signal: i32) -> Cow<'static, str> {
+    Cow::Owned(signal.to_string())
+}
+
+// Record the result of a task whose output has already been shown, which
+// finished at `time`.
+fn finish_task(opts: &Options,
+               state: &mut RunState,
+               result: TaskResult,
+               hash: Option<OutputHash>,
+               time: Instant) -> Result<(), Error> {
+
+    if (opts.warnstderr || opts.failstderr) && opts.print &&
+       result.stderr_len > 0
+    {
+        warn!("{}[{}]: wrote {} bytes to standard error\n",
+              PROG, result.tasknum, result.stderr_len);
+    }
+    if let (Some(manifest), Some(hash)) = (state.manifest.as_mut(), hash) {
+        manifest.record(result.tasknum, &result.status, hash,
+                        result.usage, result.label.as_deref(),
+                        &result.command())?;
+    }
+    state.notify(opts, JobEvent::Finished { result: &result, time });
+    state.summary.tasks.push(result);
+    write_progress(opts, state, false);
+    Ok(())
+}
+
+// The character for a finished task, with `dots`.
+fn dot(status: &TaskStatus) -> Option<char> {
+    match *status {
+        TaskStatus::Exited(0) => Some('.'),
+        TaskStatus::Exited(_) | TaskStatus::Error(_) | TaskStatus::NoOutput |
+        TaskStatus::WroteStderr => Some('F'),
+        TaskStatus::TimedOut => Some('T'),
+        TaskStatus::Signaled(_) => Some('S'),
+        TaskStatus::Skipped => Some('s'),
+        TaskStatus::DryRun => None
+    }
+}
+
+// Show progress on standard error, with `verbose`.
+fn verbose_event(opts: &Options, event: &JobEvent) {
+    match *event {
+        JobEvent::Started { tasknum, argv, label, .. } => {
+            match (opts.showusage, opts.oomscoreadj) {
+                (true, Some(adj)) =>
+                    warn!("{}[{}]: start (oom_score_adj {})\t{}{}\n",
+                          PROG, tasknum, adj, labelled(label),
+                          quote_cmd(argv)),
+                _ => warn!("{}[{}]: start\t{}{}\n",
+                           PROG, tasknum, labelled(label), quote_cmd(argv))
+            }
+        },
+        JobEvent::Retrying { tasknum, argv, error, .. } =>
+            warn!("{}[{}]: retry\t{}: {}\n",
+                  PROG, tasknum, quote_cmd(argv), error),
+        JobEvent::Finished { result, .. } => {
+            let what: Cow<str> = match result.status {
+                TaskStatus::Exited(0) if result.cached => "cached".into(),
+                TaskStatus::Exited(0) => "done".into(),
+                TaskStatus::Exited(exit) => format!("exit {}", exit).into(),
+                TaskStatus::Signaled(signal) =>
+                    format!("signal {}", signal_name(signal)).into(),
+                TaskStatus::NoOutput => "no output".into(),
+                TaskStatus::WroteStderr => "stderr".into(),
+                TaskStatus::TimedOut => "timed out".into(),
+                TaskStatus::Skipped => "skip".into(),
+                // Errors are reported as they happen.
+                TaskStatus::Error(_) | TaskStatus::DryRun => return
+            };
+            let what = match (opts.showusage, result.usage) {
+                (true, Some(usage)) =>
+                    format!("{} (cpu {:.3}s, maxrss {} KiB)", what,
+                            usage.cpu.as_secs_f64(), usage.maxrss / 1024)
+                        .into(),
+                _ => what
+            };
+            warn!("{}[{}]: {}\t{}{}\n",
+                  PROG, result.tasknum, what,
+                  labelled(result.label.as_deref()), quote_cmd(&result.argv));
+        }
+    }
+}
+
+// The label of a job followed by a tab, to go before its command in the
+// progress shown with `verbose`, or nothing.
+fn labelled(label: Option<&OsStr>) -> String {
+    match label {
+        Some(label) => format!("{}\t", label.to_string_lossy()),
+        None => String::new()
+    }
+}
+
+// Output is only kept in the result if it was not printed.
+fn captured(opts: &Options, output: Output) -> Result<Vec<u8>, Error> {
+    if opts.print {
+        Ok(Vec::new())
+    } else {
+        Ok(output.into_vec()?)
+    }
+}
+
+// The digest of the output for the manifest, if there is one.
+fn output_hash(state: &RunState, stdout: &mut Output, stderr: &mut Output)
+    -> Result<Option<OutputHash>, Error> {
+
+    if state.manifest.is_none() {
+        return Ok(None);
+    }
+    let mut hash = OutputHash::new();
+    for output in [stdout, stderr] {
+        output.for_each_chunk(|data| -> Result<(), Error> {
+            hash.update(data);
+            Ok(())
+        })?;
+    }
+    Ok(Some(hash))
+}
+
+// Write the standard output of a task to its results file, if any, and
+// print what is to be printed.
+fn report_output(opts: &Options,
+                 info: &JobInfo,
+                 stdout: &mut Output,
+                 stderr: &mut Output) -> Result<(), Error> {
+
+    if let Some(ref path) = info.results {
+        results::write(path, stdout, opts.gzipresults)?;
+    }
+    if opts.print {
+        print_output(opts, info, stdout, stderr)?;
+    }
+    Ok(())
+}
+
+fn print_output(opts: &Options,
+                info: &JobInfo,
+                stdout: &mut Output,
+                stderr: &mut Output) -> Result<(), Error> {
+
+    let small = opts.smalloutput
+        .is_some_and(|limit| stdout.len() + stderr.len() <= limit);
+    let (outsep, errsep) = match opts.separators {
+        _ if small => (false, false),
+        Some(separators) => separators,
+        None => (false, opts.verbose)
+    };
+    let tag = opts.tag.as_ref().map(|delim| {
+        let task = info.label.as_ref().unwrap_or(&info.task);
+        let mut tag = task.as_encoded_bytes().to_vec();
+        tag.extend_from_slice(delim.as_encoded_bytes());
+        tag
+    });
+    let mut style = LineStyle { color: None, tag: tag.as_deref() };
+    show_output(&mut io::stderr(), stderr, info, errsep, &style)?;
+    // Standard output goes to the results file or in the row instead.
+    if opts.results.is_some() || opts.csv {
+        return Ok(());
+    }
+    if opts.color {
+        style.color = Some(task_color(info.tasknum));
+    }
+    let mut out = io::stdout();
+    show_output(&mut out, stdout, info, outsep, &style)?;
+    if small {
+        // Standard output is line buffered.
+        out.flush().map_err(write_error)?;
+    }
+    Ok(())
+}
+
+// Print the row for a task, with `csv`.  A command that failed without an
+// exit status, such as one that succeeded but wrote to standard error with
+// `fail_on_stderr`, is given 1, and one killed by a signal 128 plus the
+// signal, as a shell would.
+fn print_csv_row(tasknum: usize,
+                 task: &OsStr,
+                 status: &TaskStatus,
+                 duration: Duration,
+                 output: Option<&mut Output>) -> Result<(), Error> {
+
+    let exit = match *status {
+        TaskStatus::Exited(exit) => Some(exit),
+        TaskStatus::Signaled(signal) => Some(128 + signal),
+        TaskStatus::NoOutput | TaskStatus::WroteStderr => Some(1),
+        TaskStatus::TimedOut => Some(TIMEOUT_EXIT),
+        TaskStatus::Error(_) | TaskStatus::DryRun |
+        TaskStatus::Skipped => None
+    };
+    let mut out = io::stdout().lock();
+    csv::write_row(&mut out, tasknum, task.as_encoded_bytes(), exit,
+                   duration, output).map_err(write_error)
+}
+
+// How each line of printed output is decorated.
+struct LineStyle<'a> {
+    color:      Option<&'a str>,
+    // The task or its label and the delimiter, with `tag`.
+    tag:        Option<&'a [u8]>
+}
+
+fn show_output(out: &mut dyn Write,
+               output: &mut Output,
+               info: &JobInfo,
+               sep: bool,
+               style: &LineStyle) -> Result<(), Error> {
+
+    if output.is_empty() {
+        return Ok(());
+    }
+    if sep {
+        checked_write_fmt(out,
+            format_args!("-------- {}[{}]: {} --------\n",
+                PROG, info.tasknum, quote_cmd(&info.argv)))?;
+    }
+    match *style {
+        LineStyle { color: None, tag: None } =>
+            output.for_each_chunk(|buf| checked_write_all(out, buf))?,
+        _ => {
+            let mut midline = false;
+            output.for_each_chunk(|buf| {
+                write_lines(out, buf, style, &mut midline)
+            })?;
+            if let (true, Some(_)) = (midline, style.color) {
+                checked_write_all(out, COLOR_RESET.as_bytes())?;
+            }
+        }
+    }
+    if sep {
+        checked_write_fmt(out, format_args!("--------\n"))?;
+    }
+    Ok(())
+}
+
+// Colors for task output lines, cycled through by task number.
+const PALETTE: [&str; 6] = [
+    "\x1b[31m", "\x1b[32m", "\x1b[33m", "\x1b[34m", "\x1b[35m", "\x1b[36m"
+];
+const COLOR_RESET: &str = "\x1b[0m";
+
+fn task_color(tasknum: usize) -> &'static str {
+    PALETTE[tasknum % PALETTE.len()]
+}
+
+// Output arrives in pieces, so a line may be continued from the previous
+// piece if `midline` is set.
+fn write_lines(out: &mut dyn Write,
+               buf: &[u8],
+               style: &LineStyle,
+               midline: &mut bool) -> Result<(), Error> {
+
+    for line in buf.split_inclusive(|&c| c == b'\n') {
+        let (text, nl) = match line.split_last() {
+            Some((&b'\n', text)) => (text, true),
+            _ => (line, false)
+        };
+        if !*midline {
+            if let Some(color) = style.color {
+                checked_write_all(out, color.as_bytes())?;
+            }
+            if let Some(tag) = style.tag {
+                checked_write_all(out, tag)?;
+            }
+        }
+        checked_write_all(out, text)?;
+        if nl {
+            if style.color.is_some() {
+                checked_write_all(out, COLOR_RESET.as_bytes())?;
+            }
+            checked_write_all(out, b"\n")?;
+        }
+        *midline = !nl;
+    }
+    Ok(())
+}
+
+fn checked_write_all(f: &mut dyn Write, buf: &[u8]) -> Result<(), Error> {
+    f.write_all(buf).map_err(write_error)
+}
+
+fn checked_write_fmt(f: &mut dyn Write, args: fmt::Arguments)
+    -> Result<(), Error> {
+    f.write_fmt(args).map_err(write_error)
+}
+
+// A closed pipe is not really an error; the reader has seen enough.
+fn write_error(err: io::Error) -> Error {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        Error::BrokenPipe
+    } else {
+        Error::Output(err)
+    }
+}
+
+/*---------------------------------------------------------------------------*/
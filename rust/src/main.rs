@@ -2,634 +2,1245 @@
 ** ljobs - A tool to execute commands in parallel.
 */
 
-extern crate getopts;
-extern crate num_cpus;
-
-use getopts::Options as Getopt;
-use std::cmp::min;
+extern crate ljobs;
+
+mod compat;
+mod completion;
+mod config;
+mod getopt;
+
+use getopt::Getopt;
+use ljobs::quote::quote_arg;
+use ljobs::template;
+use ljobs::webhook;
+use ljobs::{CgroupLimits, CpuLimits, Error, IoPriority, LongLines,
+            Options, TaskSource, TimeoutAction};
+use std::convert::TryFrom;
 use std::env;
+use std::ffi::OsString;
 use std::fmt;
-use std::io::{self, Read, Write, Result};
-use std::os::unix::process::ExitStatusExt;
-use std::process::{exit, Command, Stdio, Child, ExitStatus};
-use std::sync::mpsc::{self, Receiver};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::mem;
+use std::panic::{self, PanicHookInfo};
+use std::path::Path;
+use std::process::exit;
 use std::thread;
+use std::time::Duration;
 
-/*---------------------------------------------------------------------------*/
-
-const PROG: &'static str = "ljobs";
-
-struct Options {
-    maxjobs:    usize,
-    keepgoing:  bool,
-    shell:      Option<String>,
-    verbose:    bool,
-    dryrun:     bool
-}
-
-struct Job {
-    tasknum:    usize,
-    quotedcmd:  String,
-    child:      Child,
-    waitresult: Result<ExitStatus>
-}
+// Exit status after a bug in ljobs, as EX_SOFTWARE in sysexits.h.
+const EXIT_INTERNAL: i32 = 70;
 
 /*---------------------------------------------------------------------------*/
 
+// A warning that cannot be written is dropped: there is nowhere else to
+// report it, and it is no reason to abandon the run.
 fn warn(args: fmt::Arguments) {
-    io::stderr().write_fmt(args).expect("Could not write to stderr");
-}
-
-fn die(args: fmt::Arguments) -> ! {
-    warn(args);
-    exit(255);
+    let _ = io::stderr().write_fmt(args);
 }
 
 macro_rules! warn {
     ( $( $x:expr ),+ ) => { warn(format_args!( $( $x ),+ )) }
 }
 
-macro_rules! die {
-    ( $( $x:expr ),+ ) => { die(format_args!( $( $x ),+ )) }
+fn invalid_argument(option: &str) -> Error {
+    Error::Usage(format!("invalid argument for --{}", option))
 }
 
 /*---------------------------------------------------------------------------*/
 
-fn process_options(argv: &Vec<String>) -> (Options, Vec<String>) {
+// What the command line says besides the options for the library.
+struct Args {
+    free:       Vec<OsString>,
+    argfiles:   Vec<OsString>,
+    // What separates the command from the tasks, ::: by default.
+    argsep:     OsString,
+    // A file with the command on its first line, then tasks.
+    selfcontained: Option<OsString>,
+    // A file with only the command.
+    cmdfile:    Option<OsString>,
+    seq:        Option<Seq>,
+    script:     bool,
+    // Whether no command is an error rather than tasks being commands.
+    noshell:    bool,
+    sigpipe:    bool,
+    summaryjson: Option<OsString>,
+    webhook:    Option<String>,
+    // With --explain, the task to explain, or None for the first.
+    explain:    Option<Option<OsString>>,
+    dumpargs:   bool
+}
+
+fn process_options(argv: &[OsString]) -> Result<(Options, Args), Error> {
 
     let mut getopt = Getopt::new();
     getopt.optflagmulti("h", "help", "print this help menu");
+    getopt.optflag("", "dump-args", "print the parsed options and exit");
+    getopt.hide_last();
+    getopt.optflag("", "no-config", "ignore the config file and LJOBS");
+    getopt.optmulti("", "profile", "also take the options in the section \
+        [profile.NAME] of the config file", "NAME");
+    getopt.optflag("", "dump-config", "print the options given and where \
+        each came from, then exit");
+    getopt.optopt("", "completions", "print a completion script for SHELL \
+        (bash, zsh or fish) and exit", "SHELL");
+    getopt.choices(&["bash", "zsh", "fish"]);
+    getopt.optflag("", "show-limits", "print the limits on the number of \
+        job slots and exit");
+    getopt.optopt("", "task-prefix", "prepend PREFIX to each task",
+        "PREFIX");
+    getopt.optopt("", "task-suffix", "append SUFFIX to each task", "SUFFIX");
+    getopt.optmulti("a", "arg-file", "read tasks from FILE (- for standard \
+        input); may be repeated", "FILE");
+    getopt.optopt("", "arg-sep", "separate the command from the tasks \
+        with STRING instead of :::", "STRING");
+    getopt.optopt("", "self-contained", "take the command from the first \
+        line of FILE that is not blank or a comment, and tasks from the \
+        lines after it", "FILE");
+    getopt.optopt("f", "command-file", "read the command and its \
+        arguments from FILE, split into words as by a shell", "FILE");
+    getopt.optflag("", "no-implicit-shell", "without a command, fail \
+        instead of running each task as a command line with the shell");
+    getopt.optopt("", "seq", "run the numbers in RANGE as tasks: LAST, \
+        FIRST-LAST, or 'FIRST LAST [STEP]'", "RANGE");
+    getopt.optopt("", "input-range", "same as --seq", "RANGE");
+    getopt.optopt("", "skip", "ignore the first NUM tasks", "NUM");
+    getopt.optopt("", "max-tasks", "run only the first NUM tasks", "NUM");
+    getopt.optopt("", "head", "same as --max-tasks", "NUM");
+    getopt.optflag("", "skip-empty", "ignore tasks that are empty or only \
+        whitespace");
+    getopt.optopt("", "filter-tasks", "run only the tasks that match REGEX",
+        "REGEX");
+    getopt.optopt("", "exclude", "ignore the tasks that match REGEX",
+        "REGEX");
+    getopt.optflag("", "keep-numbering", "number the tasks ignored by \
+        --skip-empty, --filter-tasks or --exclude too");
+    getopt.optflag("0", "null", "tasks in input are terminated by NUL, not \
+        newline");
+    getopt.optopt("", "max-backlog", "read at most NUM tasks ahead of \
+        those started (default 16)", "NUM");
+    getopt.optopt("", "stdin-buffer-line-limit", "read tasks of at most \
+        SIZE bytes from input (suffixes K, M, G)", "SIZE");
+    getopt.optopt("", "long-line-policy", "with --stdin-buffer-line-limit, \
+        fail or truncate longer tasks (default fail)", "POLICY");
+    getopt.choices(&["fail", "truncate"]);
+    getopt.optflag("", "interleave", "take tasks from each --arg-file in \
+        turn");
+    getopt.optopt("", "save-input", "copy the input that tasks are read \
+        from to FILE as it is read", "FILE");
+    getopt.optflag("", "json-input", "tasks are JSON objects, for {.FIELD}");
+    getopt.optopt("", "colsep", "split tasks into columns at SEP", "SEP");
+    getopt.optopt("", "stdin-per-task-from-column", "with --colsep, write \
+        column NUM of each task to the standard input of its command", "NUM");
+    getopt.optflag("", "strict", "fail if a task has no column for {N}");
+    getopt.optflag("", "pipe", "cut the input into blocks of records and \
+        write each to the standard input of a command");
+    getopt.optopt("", "block-size", "with --pipe, the size of a block, \
+        1M by default", "SIZE");
+    getopt.optopt("", "recstart", "with --pipe, records start with STR",
+        "STR");
+    getopt.optopt("", "recend", "with --pipe, records end with STR, a \
+        newline by default unless --recstart is given", "STR");
     getopt.optopt("j", "jobs", "number of job slots", "NUM");
+    getopt.optopt("", "ramp", "increase the number of job slots from one \
+        over TIME (e.g. 30s, 5m)", "TIME");
     getopt.optflagmulti("k", "keep-going", "keep going even if a task failed");
+    getopt.optflag("", "halt-on-nonzero-from-command-only", "stop starting \
+        tasks only once a command fails, not when one cannot be run");
+    getopt.optflagopt("", "keep-going-default-exit", "with --keep-going, \
+        exit with CODE (default 1) if any task failed", "CODE");
+    getopt.optflag("", "count-failures-only", "exit with the number of \
+        failed tasks, up to 255, with or without --keep-going");
+    getopt.optflag("", "fail-if-empty", "exit with 123 if there was no task \
+        to run");
+    getopt.optflag("", "fail-summary", "list the failed tasks and their \
+        commands at the end");
+    getopt.optflag("", "abort-on-signal",
+        "stop starting tasks if a task was killed by a signal");
+    getopt.optopt("", "abort-on-match", "stop the run, terminating running \
+        tasks, once a line of output matches REGEX", "REGEX");
+    getopt.optopt("", "retry-after-pattern", "try a failed task again if \
+        its output matches REGEX, after the number of seconds in its first \
+        group", "REGEX");
+    getopt.optopt("", "retry-until", "try a failed task again until it \
+        succeeds or TIME (e.g. 30s, 5m) has passed since it first started",
+        "TIME");
+    getopt.optopt("", "halt-timeout", "after a failure, wait at most \
+        TIME (e.g. 30s, 5m) for running tasks", "TIME");
+    getopt.optopt("", "timeout", "terminate a command that runs for longer \
+        than TIME (e.g. 30s, 5m)", "TIME");
+    getopt.optopt("", "timeout-action", "with --timeout, count a task that \
+        times out as failed, skipped, or failed after retrying it (default \
+        fail)", "ACTION");
+    getopt.choices(&["fail", "skip", "retry"]);
+    getopt.optflag("", "reap-zombies", "after an error, kill and wait for \
+        any task left running");
+    getopt.optopt("", "limit-open-files", "run fewer jobs at once if need \
+        be so that they have at most NUM files open", "NUM");
+    getopt.optflag("", "sigpipe-exit", "exit with status 141 instead of 0 \
+        if output is closed early");
+    getopt.optflag("", "fail-on-no-output",
+        "count a task that writes no output as failed");
+    getopt.optflag("", "warn-on-stderr",
+        "warn of each task that writes to standard error");
+    getopt.optflag("", "fail-on-stderr",
+        "count a task that writes to standard error as failed");
     getopt.optflag("c", "", "run shell command");
+    getopt.optflag("", "no-shell-expand", "with -c, run the command with no \
+        expansions by the shell, appending the arguments");
+    getopt.optopt("", "script-file", "run the shell script in FILE, with \
+        substitutions, instead of a command", "FILE");
+    getopt.optopt("", "run-if", "skip tasks for which the shell command \
+        GUARD fails", "GUARD");
+    getopt.optmulti("", "sshlogin", "run commands on LOGIN with ssh; may be \
+        repeated", "LOGIN");
+    getopt.optflag("", "transfer", "with ssh, copy the file named by each \
+        task to the host");
+    getopt.optmulti("", "return", "with ssh, copy the file named by PATTERN, \
+        with substitutions, back from the host; may be repeated", "PATTERN");
+    getopt.optflag("", "cleanup", "with ssh, remove the files copied to the \
+        host");
+    getopt.optopt("", "sshloginfile", "run commands on the logins listed \
+        in FILE, one per line as [SLOTS/]LOGIN", "FILE");
+    getopt.optopt("", "container", "run each command in a new container \
+        from IMAGE, with podman or docker", "IMAGE");
+    getopt.optopt("", "container-engine", "run containers with ENGINE \
+        rather than the first of podman or docker found", "ENGINE");
+    getopt.optmulti("", "container-arg", "pass ARG, with substitutions, to \
+        the container engine; may be repeated", "ARG");
+    getopt.optflag("", "systemd-run", "run each command in a transient \
+        systemd scope");
+    getopt.optmulti("", "systemd-prop", "with --systemd-run, set PROPERTY \
+        on each scope, e.g. MemoryMax=2G; may be repeated", "PROPERTY");
+    getopt.optopt("", "ionice", "run each job at I/O scheduling CLASS: \
+        idle, best-effort or realtime, with an optional :LEVEL from 0 to 7",
+        "CLASS[:LEVEL]");
+    getopt.choices(&["idle", "best-effort", "realtime"]);
+    getopt.optopt("", "oom-score-adj", "set the oom_score_adj of each job \
+        to NUM, from -1000 to 1000", "NUM");
+    getopt.optflag("", "private-tmp", "give each job an empty /tmp of its \
+        own");
+    getopt.optopt("", "net", "with none, run each job with no network",
+        "MODE");
+    getopt.choices(&["none", "host"]);
+    getopt.optmulti("", "allow-read", "confine each job to reading files \
+        under PATH, with substitutions, and other paths allowed; may be \
+        repeated", "PATH");
+    getopt.optmulti("", "allow-write", "as --allow-read, but allow writing \
+        files under PATH too", "PATH");
+    getopt.optflag("", "sandbox-best-effort", "run jobs unconfined if the \
+        kernel cannot confine them");
+    getopt.optopt("", "cgroup", "run each job in a cgroup of its own with \
+        LIMITS, e.g. mem=2G,cpu=150%", "LIMITS");
     getopt.optflagmulti("v", "verbose", "verbose output");
+    getopt.optopt("", "output-separators", "set off output of tasks on \
+        STREAMS (stdout, stderr, both or none)", "STREAMS");
+    getopt.choices(&["stdout", "stderr", "both", "none"]);
+    getopt.optflagopt("", "no-buffer-small", "print outputs of at most \
+        SIZE bytes (default 1K) at once, without separators", "SIZE");
+    getopt.optflag("", "echo", "print each task to stderr as it starts");
+    getopt.optflag("", "dots", "print a character to stderr as each task \
+        finishes");
     getopt.optflagmulti("n", "dry-run", "print commands but do not run them");
-    getopt.parsing_style(getopts::ParsingStyle::StopAtFirstFree);
-
-    let matches = match getopt.parse(&argv[1..]) {
+    getopt.optflag("", "explain", "show how the command line is built for \
+        the first task and exit");
+    getopt.optopt("", "explain-task", "with --explain, explain TASK \
+        instead; implies --explain", "TASK");
+    getopt.optopt("", "manifest", "write a record of tasks run to FILE",
+        "FILE");
+    getopt.optflagopt("", "tee-manifest", "also write each record of the \
+        manifest to FD (default 1) as the task finishes", "FD");
+    getopt.optopt("", "summary-json", "write the numbers of the run as JSON \
+        to FILE (- for stdout) at the end", "FILE");
+    getopt.optopt("", "webhook", "POST the numbers of the run as JSON to \
+        URL at the end", "URL");
+    getopt.optopt("", "status-file", "keep the status of the run as JSON \
+        in FILE, rewritten every few seconds", "FILE");
+    getopt.optopt("", "progress-to-file", "keep the counts of completed, \
+        running and failed tasks as JSON in FILE, rewritten as each task \
+        finishes", "FILE");
+    getopt.optopt("", "results-path", "write the output of each task to the \
+        file at TEMPLATE, with substitutions", "TEMPLATE");
+    getopt.optflag("", "quote-for-csv", "print a row of CSV for each task \
+        with its number, task, exit status, duration and output");
+    getopt.optflag("", "output-compress", "gzip each results file, adding \
+        .gz to its name");
+    getopt.optopt("", "cache", "replay the output of tasks that succeeded \
+        before with the same command, from DIR", "DIR");
+    getopt.optflag("", "cache-input", "include the contents of the file \
+        named by the task in cache keys");
+    getopt.optopt("", "spill-size", "keep up to SIZE bytes of output of a \
+        running task in memory (suffixes K, M, G)", "SIZE");
+    getopt.optopt("", "output-buffer", "keep up to SIZE bytes of output of \
+        all running tasks in memory (suffixes K, M, G)", "SIZE");
+    getopt.optflagopt("", "color", "colorize output lines by task \
+        (WHEN is auto, always or never)", "WHEN");
+    getopt.choices(&["auto", "always", "never"]);
+    getopt.optflag("", "tag", "start each output line with the task and a \
+        tab");
+    getopt.optopt("", "tag-delimiter", "with --tag, put STR between the \
+        task and the line instead of a tab; implies --tag", "STR");
+    getopt.optopt("", "label", "label each job with TEMPLATE, substituted \
+        for the task, in LJOBS_LABEL, {label}, the manifest and --tag",
+        "TEMPLATE");
+    getopt.optopt("", "start-slot-env-offset", "number the job slots in \
+        LJOBS_SLOT and {%} from NUM instead of 0", "NUM");
+
+    compat::register(&mut getopt);
+
+    let mut matches = match getopt.parse(&argv[1..]) {
         Ok(m) => m,
-        Err(err) => die!("{}\n", err)
+        Err(err) => return Err(Error::Usage(parse_failure(&getopt, &err)))
     };
 
+    // The config file and LJOBS come before the command line, and then
+    // any profiles.
+    let noconfig = matches.opt_present("no-config");
+    let mut config = if noconfig {
+        config::Config::default()
+    } else {
+        config::read()?
+    };
+    let mut layers = mem::take(&mut config.layers);
+    layers.push(config::Layer {
+        source: String::from("command line"),
+        args: argv[1..].to_vec()
+    });
+    if layers.len() > 1 {
+        matches = parse_layers(&getopt, &layers)?;
+    }
+    let profiles: Vec<String> = matches.opt_os_all("profile").iter()
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    if !profiles.is_empty() {
+        if noconfig {
+            return Err(Error::Usage(String::from(
+                "--profile cannot be used with --no-config")));
+        }
+        let at = layers.len() - 1;
+        for (i, name) in profiles.iter().enumerate() {
+            layers.insert(at + i, config.profile(name)?);
+        }
+        matches = parse_layers(&getopt, &layers)?;
+    }
+    let configured = layers.len() > 1;
+
+    // Parse again with the ljobs options for any GNU parallel aliases,
+    // which stand in for the aliases wherever they were given.
+    let (native, notices) = compat::native_args(&matches)?;
+    for notice in notices {
+        warn!("ljobs: {}\n", notice);
+    }
+    if !native.is_empty() {
+        let at = layers.len() - 1;
+        layers.insert(at, config::Layer {
+            source: String::from("GNU parallel options"),
+            args: native.iter().map(OsString::from).collect()
+        });
+        matches = parse_layers(&getopt, &layers)?;
+    }
+
+    if matches.opt_present("dump-config") {
+        print!("{}", given_options(&matches, &layers));
+        exit(0);
+    }
+    if matches.opt_count("v") > 1 && configured {
+        warn!("{}", given_options(&matches, &layers).lines()
+            .map(|line| format!("ljobs: {}\n", line))
+            .collect::<String>());
+    }
+
     if matches.opt_present("h") {
         usage(getopt);
         exit(255);
     }
 
-    let mut opts = Options {
-        maxjobs:    0,
-        keepgoing:  false,
-        shell:      None,
-        verbose:    false,
-        dryrun:     false
-    };
-
-    if let Some(s) = matches.opt_str("j") {
-        opts.maxjobs = s.parse().unwrap_or(0);
-        if opts.maxjobs < 1 {
-            die!("invalid argument for --jobs\n");
+    if let Some(shell) = matches.opt_str("completions") {
+        match completion::script(&getopt, &shell) {
+            Some(script) => {
+                print!("{}", script);
+                exit(0);
+            },
+            None => return Err(invalid_argument("completions"))
         }
     }
-    if opts.maxjobs < 1 {
-        opts.maxjobs = num_cpus::get();
+
+    if matches.opt_present("show-limits") {
+        show_limits(&CpuLimits::detect());
+        exit(0);
     }
 
-    opts.keepgoing = matches.opt_present("k");
+    let mut opts = Options::new().print_output(true);
 
-    if matches.opt_present("c") {
-        match env::var("SHELL") {
-            Ok(val) =>
-                opts.shell = Some(val),
-            Err(env::VarError::NotPresent) =>
-                opts.shell = Some(String::from("/bin/sh")),
-            Err(env::VarError::NotUnicode(_)) =>
-                die!("SHELL value not Unicode\n")
+    if let Some(prefix) = matches.opt_os("task-prefix") {
+        opts = opts.task_prefix(prefix);
+    }
+
+    if let Some(suffix) = matches.opt_os("task-suffix") {
+        opts = opts.task_suffix(suffix);
+    }
+
+    if let Some(s) = matches.opt_str("skip") {
+        match s.parse() {
+            Ok(skip) => opts = opts.skip(skip),
+            _ => return Err(invalid_argument("skip"))
         }
     }
 
-    opts.verbose = matches.opt_present("v");
+    let mut seq = None;
+    for name in &["seq", "input-range"] {
+        if let Some(s) = matches.opt_str(name) {
+            match parse_seq(&s) {
+                Some(range) => seq = Some(range),
+                None => return Err(invalid_argument(name))
+            }
+        }
+    }
 
-    opts.dryrun = matches.opt_present("n");
+    for name in &["max-tasks", "head"] {
+        if let Some(s) = matches.opt_str(name) {
+            match s.parse() {
+                Ok(max) => opts = opts.max_tasks(max),
+                _ => return Err(invalid_argument(name))
+            }
+        }
+    }
 
-    return (opts, matches.free);
-}
+    opts = opts.skip_empty(matches.opt_present("skip-empty"));
+    if let Some(pattern) = matches.opt_str("filter-tasks") {
+        opts = opts.filter_tasks(pattern);
+    }
+    if let Some(pattern) = matches.opt_str("exclude") {
+        opts = opts.exclude_tasks(pattern);
+    }
+    if matches.opt_present("keep-numbering") {
+        if !["skip-empty", "filter-tasks", "exclude"].iter()
+            .any(|name| matches.opt_present(name))
+        {
+            return Err(Error::Usage(String::from(
+                "--keep-numbering needs --skip-empty, --filter-tasks or \
+                 --exclude")));
+        }
+        opts = opts.keep_numbering(true);
+    }
 
-fn usage(getopt: Getopt) {
-    let head = vec![
-        "Usage:\n",
-        "    ljobs [OPTIONS...] COMMAND [CMD-ARGS...] ::: TASKS...\n",
-        "    ljobs [OPTIONS...] COMMAND [CMD-ARGS...] < TASKS"
-    ];
-    let tail = vec![
-        "String substitutions in command arguments:\n",
-        "    {}                  task\n",
-        "    {.}                 task without extension\n",
-        "    {/}                 basename of task\n",
-        "    {//}                dirname of task\n",
-        "    {/.}                basename of task without extension\n",
-        "    {#}                 task number\n",
-        "\n"
-    ];
+    if let Some(s) = matches.opt_str("max-backlog") {
+        match s.parse() {
+            Ok(backlog) if backlog > 0 => opts = opts.max_backlog(backlog),
+            _ => return Err(invalid_argument("max-backlog"))
+        }
+    }
 
-    for x in head {
-        print!("{}", x);
+    let policy = match matches.opt_str("long-line-policy").as_deref() {
+        None | Some("fail") => LongLines::Fail,
+        Some("truncate") => LongLines::Truncate,
+        Some(_) => return Err(invalid_argument("long-line-policy"))
+    };
+    match matches.opt_str("stdin-buffer-line-limit").map(|s| parse_size(&s)) {
+        Some(Some(limit)) if limit > 0 =>
+            opts = opts.line_limit(limit, policy),
+        Some(_) => return Err(invalid_argument("stdin-buffer-line-limit")),
+        None if matches.opt_present("long-line-policy") => {
+            return Err(Error::Usage(String::from(
+                "--long-line-policy needs --stdin-buffer-line-limit")));
+        },
+        None => ()
     }
-    print!("{}\n", getopt.usage(""));
-    for x in tail {
-        print!("{}", x);
+
+    opts = opts.interleave(matches.opt_present("interleave"));
+    opts = opts.null(matches.opt_present("null"));
+
+    if let Some(path) = matches.opt_os("save-input") {
+        opts = opts.save_input(path);
     }
-}
 
-/*---------------------------------------------------------------------------*/
+    opts = opts.json_input(matches.opt_present("json-input"));
 
-fn main() {
-    // Possibly we should work with OsStrings but getopts does not support
-    // OsStrings for now so we would need to switch to another option parser.
-    let argv = std::env::args().collect();
-    let (opts, freeargs) = process_options(&argv);
+    if let Some(sep) = matches.opt_os("colsep") {
+        if sep.is_empty() {
+            return Err(invalid_argument("colsep"));
+        }
+        opts = opts.column_separator(sep);
+    }
 
-    if freeargs.len() == 0 || freeargs[0] == ":::" {
-        die!("no command\n");
+    if let Some(s) = matches.opt_str("stdin-per-task-from-column") {
+        match s.parse() {
+            Ok(column) if column > 0 => opts = opts.stdin_column(column),
+            _ => return Err(invalid_argument("stdin-per-task-from-column"))
+        }
     }
-    let cmd = &freeargs[0];
 
-    let (cmdargs, taskargs, taskstdin);
-    match freeargs.iter().position(|x| x == ":::") {
-        Some(i) => {
-            cmdargs = &freeargs[1..i];
-            taskargs = &freeargs[i+1..];
-            taskstdin = false;
-        },
-        None => {
-            cmdargs = &freeargs[1..];
-            taskargs = &[];
-            taskstdin = true;
+    if matches.opt_present("pipe") {
+        let size = match matches.opt_str("block-size") {
+            Some(s) => match parse_size(&s) {
+                Some(size) if size > 0 => size,
+                _ => return Err(invalid_argument("block-size"))
+            },
+            None => 1 << 20
+        };
+        opts = opts.pipe(size);
+        if let Some(start) = matches.opt_os("recstart") {
+            opts = opts.record_start(start);
         }
-    };
+        if let Some(end) = matches.opt_os("recend") {
+            opts = opts.record_end(end);
+        }
+    } else if let Some(name) = ["block-size", "recstart", "recend"].iter()
+        .find(|name| matches.opt_present(name))
+    {
+        return Err(Error::Usage(format!("--{} needs --pipe", name)));
+    }
 
-    let (errs, failedexit) = master(&opts, cmd, cmdargs, taskstdin, taskargs);
+    opts = opts.strict(matches.opt_present("strict"));
 
-    exit(
-        if opts.keepgoing {
-            min(254, errs as i32)
-        } else if errs > 0 {
-            failedexit as i32
-        } else {
-            0
+    if let Some(s) = matches.opt_str("j") {
+        let maxjobs: usize = s.parse().unwrap_or(0);
+        if maxjobs < 1 {
+            return Err(invalid_argument("jobs"));
         }
-    );
-}
+        opts = opts.jobs(maxjobs);
+    }
 
-fn master(opts: &Options,
-          cmd: &String,
-          cmdargs: &[String],
-          taskstdin: bool,
-          taskargs: &[String]) -> (u32, i32) {
-
-    let mut numjobs = 0;
-    let mut tasknum = 0;
-    let mut errs = 0;
-    let mut failedexit = 255;
-
-    // The Rust standard library does not provide a way to wait on multiple
-    // child processes at once. Therefore we spawn a thread to wait on each
-    // individual child process then communicate the result back to the parent
-    // through a channel.
-    let (tx, mut rx) = mpsc::channel();
-
-    'main: loop {
-        let taskarg: String;
-        if taskstdin {
-            let mut line = String::new();
-            match io::stdin().read_line(&mut line) {
-                Ok(0) => // eof
-                    break 'main,
-                Ok(_) => {
-                    chomp(&mut line);
-                    taskarg = line;
-                },
-                Err(err) => {
-                    die!("error reading standard input: {}\n", err);
-                }
-            }
-        } else {
-            if tasknum >= taskargs.len() {
-                break 'main;
-            }
-            taskarg = taskargs[tasknum].clone();
+    if let Some(s) = matches.opt_str("ramp") {
+        match parse_duration(&s) {
+            Some(ramp) => opts = opts.ramp(ramp),
+            None => return Err(invalid_argument("ramp"))
         }
+    }
 
-        let argv = build_argv(&opts, cmd, cmdargs, tasknum, &taskarg);
-        let quotedcmd = quote_cmd(&argv);
+    opts = opts.keep_going(matches.opt_present("k"));
+    opts = opts.halt_on_command_failure_only(
+        matches.opt_present("halt-on-nonzero-from-command-only"));
 
-        if opts.dryrun {
-            dryrun(tasknum, &quotedcmd);
-        } else {
-            let mut command = Command::new(&argv[0]);
-            command.args(&argv[1..]);
-            command.stdin(Stdio::null());
-            command.stdout(Stdio::piped());
-            command.stderr(Stdio::piped());
-
-            if opts.verbose {
-                warn!("{}[{}]: start\t{}\n", PROG, tasknum, quotedcmd);
-            }
+    if matches.opt_present("keep-going-default-exit") &&
+        matches.opt_present("count-failures-only")
+    {
+        return Err(Error::Usage(String::from(
+            "--keep-going-default-exit cannot be used with \
+             --count-failures-only")));
+    }
 
-            match command.spawn() {
-                Ok(mut child) => {
-                    numjobs += 1;
-                    let thread_tx = tx.clone();
-                    thread::spawn(move || {
-                        let res = child.wait();
-                        let job = Job {
-                            tasknum: tasknum,
-                            quotedcmd: quotedcmd,
-                            child: child,
-                            waitresult: res
-                        };
-                        match thread_tx.send(job) {
-                            Ok(_) => (),
-                            Err(err) => die!("send error: {}\n", err)
-                        }
-                    });
-                },
-                Err(err) => {
-                    warn!("{}[{}]: error\t{}: {}\n",
-                          PROG, tasknum, quotedcmd, err);
-                    errs += 1;
-                }
+    opts = opts.count_failures_only(
+        matches.opt_present("count-failures-only"));
+    opts = opts.fail_summary(matches.opt_present("fail-summary"));
+    opts = opts.fail_if_empty(matches.opt_present("fail-if-empty"));
+
+    if matches.opt_present("keep-going-default-exit") {
+        let exit = match matches.opt_str("keep-going-default-exit") {
+            None => 1,
+            Some(s) => match s.parse() {
+                Ok(exit) if (1..=255).contains(&exit) => exit,
+                _ => return Err(invalid_argument("keep-going-default-exit"))
             }
+        };
+        opts = opts.keep_going_exit(Some(exit));
+    }
+
+    opts = opts.abort_on_signal(matches.opt_present("abort-on-signal"));
+
+    if let Some(pattern) = matches.opt_str("abort-on-match") {
+        opts = opts.abort_on_match(pattern);
+    }
+
+    if let Some(pattern) = matches.opt_str("retry-after-pattern") {
+        opts = opts.retry_after_pattern(pattern);
+    }
+
+    if let Some(s) = matches.opt_str("retry-until") {
+        match parse_duration(&s) {
+            Some(limit) => opts = opts.retry_until(limit),
+            None => return Err(invalid_argument("retry-until"))
         }
+    }
 
-        if numjobs >= opts.maxjobs {
-            wait_jobs(&opts, &mut numjobs, &mut rx, false,
-                      &mut errs, &mut failedexit);
+    if let Some(s) = matches.opt_str("timeout") {
+        match parse_duration(&s) {
+            Some(timeout) if !timeout.is_zero() =>
+                opts = opts.timeout(timeout),
+            _ => return Err(invalid_argument("timeout"))
         }
+    }
+    match matches.opt_str("timeout-action").as_deref() {
+        None => (),
+        Some(_) if !matches.opt_present("timeout") => {
+            return Err(Error::Usage(String::from(
+                "--timeout-action needs --timeout")));
+        },
+        Some("fail") => opts = opts.timeout_action(TimeoutAction::Fail),
+        Some("skip") => opts = opts.timeout_action(TimeoutAction::Skip),
+        Some("retry") => opts = opts.timeout_action(TimeoutAction::Retry),
+        Some(_) => return Err(invalid_argument("timeout-action"))
+    }
+
+    opts = opts.reap_zombies(matches.opt_present("reap-zombies"));
 
-        if errs > 0 && !opts.keepgoing {
-            break;
+    if let Some(s) = matches.opt_str("halt-timeout") {
+        match parse_duration(&s) {
+            Some(timeout) => opts = opts.halt_timeout(Some(timeout)),
+            None => return Err(invalid_argument("halt-timeout"))
         }
+    }
 
-        tasknum += 1;
+    if let Some(s) = matches.opt_str("limit-open-files") {
+        match s.parse() {
+            Ok(limit) if limit > 0 => opts = opts.open_file_limit(limit),
+            _ => return Err(invalid_argument("limit-open-files"))
+        }
     }
 
-    wait_jobs(&opts, &mut numjobs, &mut rx, true, &mut errs, &mut failedexit);
-    return (errs, failedexit);
-}
+    opts = opts.fail_on_no_output(matches.opt_present("fail-on-no-output"));
+    opts = opts.warn_on_stderr(matches.opt_present("warn-on-stderr"));
+    opts = opts.fail_on_stderr(matches.opt_present("fail-on-stderr"));
 
-/*---------------------------------------------------------------------------*/
+    if matches.opt_present("c") {
+        opts = opts.shell(Some(template::default_shell()));
+    }
+    opts = opts.shell_literal(matches.opt_present("no-shell-expand"));
 
-fn build_argv(opts: &Options,
-              cmd: &String,
-              cmdargs: &[String],
-              tasknum: usize,
-              task: &String) -> Vec<String> {
-
-    let mut argv: Vec<String> = Vec::new();
-    let mut havetask = false;
-
-    match opts.shell {
-        Some(ref shell) => {
-            argv.push(shell.clone());
-            argv.push(String::from("-c"));
-            argv.push(cmd.clone());
-            argv.push(String::from("-"));
-        },
-        None => {
-            argv.push(cmd.clone());
+    if let Some(path) = matches.opt_os("script-file") {
+        opts = opts.script_file(path);
+    }
+
+    if let Some(guard) = matches.opt_os("run-if") {
+        opts = opts.run_if(guard);
+    }
+
+    for login in matches.opt_os_all("sshlogin") {
+        opts = opts.ssh_login(login);
+    }
+
+    if let Some(path) = matches.opt_os("sshloginfile") {
+        opts = opts.ssh_login_file(path);
+    }
+
+    opts = opts.transfer(matches.opt_present("transfer"));
+
+    for pattern in matches.opt_os_all("return") {
+        opts = opts.return_file(pattern);
+    }
+
+    opts = opts.cleanup(matches.opt_present("cleanup"));
+
+    if let Some(image) = matches.opt_os("container") {
+        opts = opts.container(image);
+    }
+
+    if let Some(engine) = matches.opt_os("container-engine") {
+        opts = opts.container_engine(engine);
+    }
+
+    for arg in matches.opt_os_all("container-arg") {
+        opts = opts.container_arg(arg);
+    }
+
+    opts = opts.systemd_run(matches.opt_present("systemd-run"));
+
+    for prop in matches.opt_os_all("systemd-prop") {
+        opts = opts.systemd_prop(prop);
+    }
+
+    if let Some(s) = matches.opt_str("ionice") {
+        match IoPriority::parse(&s) {
+            Some(priority) => opts = opts.ionice(priority),
+            None => return Err(invalid_argument("ionice"))
         }
-    };
+    }
 
-    for arg in cmdargs {
-        match subst(arg, tasknum, task) {
-            Some(substarg) => {
-                argv.push(substarg);
-                havetask = true;
-            },
-            None => {
-                argv.push(arg.clone());
-            }
+    if let Some(s) = matches.opt_str("oom-score-adj") {
+        match s.parse() {
+            Ok(adj) if (-1000..=1000).contains(&adj) =>
+                opts = opts.oom_score_adj(adj),
+            _ => return Err(invalid_argument("oom-score-adj"))
         }
     }
 
-    if !havetask {
-        argv.push(task.clone());
+    opts = opts.private_tmp(matches.opt_present("private-tmp"));
+    match matches.opt_str("net").as_deref() {
+        None | Some("host") => (),
+        Some("none") => opts = opts.isolate_network(true),
+        Some(_) => return Err(invalid_argument("net"))
     }
 
-    return argv;
-}
+    for path in matches.opt_os_all("allow-read") {
+        opts = opts.allow_read(path);
+    }
+    for path in matches.opt_os_all("allow-write") {
+        opts = opts.allow_write(path);
+    }
+    opts = opts.sandbox_best_effort(
+        matches.opt_present("sandbox-best-effort"));
 
-fn subst(s: &str, tasknum: usize, task: &str) -> Option<String> {
-    let mut acc = String::new();
-    let mut ss = s;
-    let mut found = false;
-
-    while ss.len() > 0 {
-        if let Some(open) = ss.find('{') {
-            if let Some(close0) = ss[open..].find('}') {
-                acc.push_str(&ss[..open]);
-                let close = open + close0;
-                let mid = &ss[open+1..close];
-                let next;
-                match mid {
-                    "" => {
-                        acc.push_str(task);
-                        next = close+1;
-                        found = true;
-                    },
-                    "." => {
-                        acc.push_str(remove_extension(task));
-                        next = close+1;
-                        found = true;
-                    },
-                    "/" => {
-                        acc.push_str(basename(task));
-                        next = close+1;
-                        found = true;
-                    },
-                    "//" => {
-                        acc.push_str(dirname(task));
-                        next = close+1;
-                        found = true;
-                    },
-                    "/." => {
-                        acc.push_str(remove_extension(basename(task)));
-                        next = close+1;
-                        found = true;
-                    },
-                    "#" => {
-                        acc.push_str(&tasknum.to_string());
-                        next = close+1;
-                        found = true;
-                    },
-                    _ => {
-                        acc.push_str("{");
-                        next = open+1;
-                    }
-                }
-                ss = &ss[next..];
-            } else {
-                break;
-            }
-        } else {
-            break;
+    if let Some(s) = matches.opt_str("cgroup") {
+        match CgroupLimits::parse(&s) {
+            Some(limits) => opts = opts.cgroup(limits),
+            None => return Err(invalid_argument("cgroup"))
         }
     }
 
-    acc.push_str(ss);
+    opts = opts.verbose(matches.opt_present("v"))
+        .show_steps(matches.opt_count("v") > 1)
+        .show_usage(matches.opt_count("v") > 1);
+
+    if let Some(s) = matches.opt_str("output-separators") {
+        opts = match s.as_str() {
+            "stdout" => opts.output_separators(true, false),
+            "stderr" => opts.output_separators(false, true),
+            "both" => opts.output_separators(true, true),
+            "none" => opts.output_separators(false, false),
+            _ => return Err(invalid_argument("output-separators"))
+        };
+    }
 
-    if found {
-        Some(acc)
-    } else {
-        None
+    if matches.opt_present("no-buffer-small") {
+        match matches.opt_str("no-buffer-small").map(|s| parse_size(&s)) {
+            None => opts = opts.no_buffer_small(1024),
+            Some(Some(size)) => opts = opts.no_buffer_small(size as u64),
+            Some(None) => return Err(invalid_argument("no-buffer-small"))
+        }
     }
-}
 
-/*
-fn subst(s: &str, tasknum: usize, task: &str) -> String {
+    opts = opts.echo(matches.opt_present("echo"));
+
+    opts = opts.dots(matches.opt_present("dots"));
 
-    // Using regex as Rust standard library does not provide a simple way to
-    // perform multiple string replacement. It could be written more directly.
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r"\{\}|\{\.\}|\{/\}|\{//\}|\{/\.\}|\{#\}").unwrap();
+    opts = opts.dry_run(matches.opt_present("n"));
+
+    if let Some(path) = matches.opt_os("manifest") {
+        opts = opts.manifest(path);
     }
-    let subster = Subster {
-        tasknum: tasknum,
-        task: task
-    };
-    return RE.replace_all(s, subster);
-}
 
-struct Subster<'a> {
-    tasknum: usize,
-    task: &'a str
-}
+    if matches.opt_present("tee-manifest") {
+        let fd = match matches.opt_str("tee-manifest") {
+            None => 1,
+            Some(s) => match s.parse() {
+                Ok(fd) if fd >= 0 => fd,
+                _ => return Err(invalid_argument("tee-manifest"))
+            }
+        };
+        opts = opts.tee_manifest(fd);
+    }
 
-impl<'a> regex::Replacer for Subster<'a> {
+    if let Some(path) = matches.opt_os("status-file") {
+        opts = opts.status_file(path);
+    }
+    if let Some(path) = matches.opt_os("progress-to-file") {
+        opts = opts.progress_file(path);
+    }
+
+    if let Some(dir) = matches.opt_os("cache") {
+        opts = opts.cache(dir);
+    }
+
+    opts = opts.cache_input(matches.opt_present("cache-input"));
 
-    fn reg_replace(&mut self, caps: &regex::Captures) -> Cow<str> {
-        match caps.at(0) {
-            Some("{}") => Borrowed(&self.task),
-            Some("{.}") => Borrowed(remove_extension(&self.task)),
-            Some("{/}") => Borrowed(basename(&self.task)),
-            Some("{//}") => Borrowed(dirname(&self.task)),
-            Some("{/.}") => Borrowed(remove_extension(basename(&self.task))),
-            Some("{#}") => Owned(self.tasknum.to_string()),
-            Some(s) => panic!("unexpected capture {}", s),
-            None => panic!("unexpected capture None")
+    if let Some(path) = matches.opt_os("results-path") {
+        opts = opts.results_path(path);
+    }
+    opts = opts.compress_results(matches.opt_present("output-compress"))
+        .csv(matches.opt_present("quote-for-csv"));
+
+    if let Some(s) = matches.opt_str("spill-size") {
+        match parse_size(&s) {
+            Some(size) => opts = opts.spill_size(size),
+            None => return Err(invalid_argument("spill-size"))
         }
     }
 
-    fn no_expand(&mut self) -> Option<Cow<str>> {
-        None
+    if let Some(s) = matches.opt_str("output-buffer") {
+        match parse_size(&s) {
+            Some(size) => opts = opts.output_buffer(size),
+            None => return Err(invalid_argument("output-buffer"))
+        }
     }
-}
-*/
 
-// std::path is too subtle...
+    if matches.opt_present("color") {
+        opts = opts.color(match matches.opt_str("color") {
+            None => want_color(),
+            Some(ref s) if s == "auto" => want_color(),
+            Some(ref s) if s == "always" => true,
+            Some(ref s) if s == "never" => false,
+            Some(_) => return Err(invalid_argument("color"))
+        });
+    }
 
-fn basename(s: &str) -> &str {
-    match s.rfind('/') {
-        None => s,
-        Some(i) => &s[i+1..]
+    if let Some(delim) = matches.opt_os("tag-delimiter") {
+        opts = opts.tag(Some(delim));
+    } else if matches.opt_present("tag") {
+        opts = opts.tag(Some(OsString::from("\t")));
+    }
+    if let Some(label) = matches.opt_os("label") {
+        opts = opts.label(label);
+    }
+    if let Some(s) = matches.opt_str("start-slot-env-offset") {
+        match s.parse() {
+            Ok(offset) => opts = opts.slot_offset(offset),
+            Err(_) => return Err(invalid_argument("start-slot-env-offset"))
+        }
     }
-}
 
-fn extension(s: &str) -> Option<&str> {
-    let base = basename(s);
-    match base.rfind('.') {
-        None => None,
-        Some(i) => Some(&base[i..]) // including dot
+    let webhook = matches.opt_str("webhook");
+    if webhook.as_ref().is_some_and(|url| !webhook::valid_url(url)) {
+        return Err(invalid_argument("webhook"));
     }
-}
 
-fn remove_extension(s: &str) -> &str {
-    match extension(s) {
-        None => s,
-        Some(ext) => &s[..s.len()-ext.len()]
+    // The separator must stand out as a word of its own.
+    let argsep = matches.opt_os("arg-sep")
+        .unwrap_or_else(|| OsString::from(":::"));
+    if argsep.is_empty() ||
+       argsep.as_encoded_bytes().iter().any(u8::is_ascii_whitespace)
+    {
+        return Err(invalid_argument("arg-sep"));
     }
+
+    let args = Args {
+        argfiles: matches.opt_os_all("a"),
+        argsep,
+        selfcontained: matches.opt_os("self-contained"),
+        cmdfile:  matches.opt_os("f"),
+        seq,
+        script:   matches.opt_present("script-file"),
+        noshell:  matches.opt_present("no-implicit-shell"),
+        sigpipe:  matches.opt_present("sigpipe-exit"),
+        summaryjson: matches.opt_os("summary-json"),
+        webhook,
+        explain:  match matches.opt_os("explain-task") {
+            Some(task) => Some(Some(task)),
+            None if matches.opt_present("explain") => Some(None),
+            None => None
+        },
+        dumpargs: matches.opt_present("dump-args"),
+        free:     matches.free
+    };
+    Ok((opts, args))
 }
 
-fn dirname(s: &str) -> &str {
-    let s = remove_redundant_trailing_slashes(s);
-    match s.rfind('/') {
-        None => ".",
-        Some(0) => "/",
-        Some(i) => remove_redundant_trailing_slashes(&s[..i])
-    }
+// A number of bytes as for ljobs::parse_size, that fits in memory.
+fn parse_size(s: &str) -> Option<usize> {
+    ljobs::parse_size(s).and_then(|n| usize::try_from(n).ok())
 }
 
-// Remove trailing slashes but not a leading slash.
-fn remove_redundant_trailing_slashes(s: &str) -> &str {
-    if s.len() > 1 && s.ends_with('/') {
-        remove_redundant_trailing_slashes(&s[..s.len()-1])
-    } else {
-        s
-    }
+// The numbers of --seq, from `next` by `step` as far as `last`, made as
+// they are needed.
+struct Seq {
+    next:       Option<i64>,
+    last:       i64,
+    step:       i64
 }
 
-fn chomp(s: &mut String) {
-    if s.ends_with('\n') {
-        let n = s.len() - 1;
-        s.truncate(n);
+impl Iterator for Seq {
+    type Item = OsString;
+
+    fn next(&mut self) -> Option<OsString> {
+        let n = self.next?;
+        if (self.step > 0 && n > self.last) || (self.step < 0 && n < self.last)
+        {
+            return None;
+        }
+        self.next = n.checked_add(self.step);
+        Some(OsString::from(n.to_string()))
     }
 }
 
-/*---------------------------------------------------------------------------*/
+// LAST, FIRST-LAST, or FIRST LAST [STEP] separated by spaces.  Without a
+// step, count up by one, or down if FIRST is more than LAST, except that
+// LAST alone always counts up from 1, as with seq.
+fn parse_seq(s: &str) -> Option<Seq> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+    let (first, last, step) = match words[..] {
+        [range] => match range.get(1..).and_then(|r| r.find('-')) {
+            Some(i) => (&range[..=i], &range[i+2..], None),
+            None => ("1", range, Some("1"))
+        },
+        [first, last] => (first, last, None),
+        [first, last, step] => (first, last, Some(step)),
+        _ => return None
+    };
+    let (first, last): (i64, i64) = (first.parse().ok()?, last.parse().ok()?);
+    let step = match step {
+        Some(step) => step.parse().ok().filter(|&step| step != 0)?,
+        None if first > last => -1,
+        None => 1
+    };
+    Some(Seq { next: Some(first), last, step })
+}
 
-fn quote_cmd(args: &Vec<String>) -> String {
-    let v: Vec<String> = args.iter().map(quote_arg).collect();
-    return v.join(" ");
+// A number of seconds with an optional suffix of s, m or h.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (digits, unit) = match s.as_bytes().last() {
+        Some(b's') => (&s[..s.len()-1], 1.0),
+        Some(b'm') => (&s[..s.len()-1], 60.0),
+        Some(b'h') => (&s[..s.len()-1], 3600.0),
+        _ => (s, 1.0)
+    };
+    let secs: f64 = digits.parse().ok()?;
+    Duration::try_from_secs_f64(secs * unit).ok()
 }
 
-fn quote_arg(s: &String) -> String {
-    if s == "" {
-        String::from("''")
-    } else if shell_safe_chars(s) {
-        s.clone()
-    } else {
-        String::from("'") + &s.replace("'", "'\"'\"'") + "'"
-    }
+// Color automatically only if stdout is a terminal and NO_COLOR is not set.
+fn want_color() -> bool {
+    let nocolor = match env::var_os("NO_COLOR") {
+        Some(val) => !val.is_empty(),
+        None => false
+    };
+    !nocolor && io::stdout().is_terminal()
 }
 
-fn shell_safe_chars(s: &str) -> bool {
-    for c in s.chars() {
-        match c {
-            'A'...'Z'|'a'...'z'|'0'...'9' => (),
-            '_'|'%'|'+'|','|'-'|'.'|'/'|':'|'='|'@' => (),
-            _ => return false
+// Parse the layers of arguments, naming the source of any error in the
+// defaults.
+fn parse_layers(getopt: &Getopt, layers: &[config::Layer])
+    -> Result<getopt::Matches, Error> {
+
+    let lists: Vec<&[OsString]> = layers.iter()
+        .map(|layer| &layer.args[..])
+        .collect();
+    getopt.parse_layers(&lists).map_err(|(k, err)| {
+        let msg = parse_failure(getopt, &err);
+        if k + 1 == layers.len() {
+            Error::Usage(msg)
+        } else {
+            Error::Usage(format!("{}: {}", layers[k].source, msg))
         }
+    })
+}
+
+// The message for a failure to parse options, suggesting the options that
+// an unrecognized long option may be a mistyping of.
+fn parse_failure(getopt: &Getopt, err: &getopt::Fail) -> String {
+    let near = match *err {
+        getopt::Fail::UnrecognizedOption(ref name) if name.len() > 1 => {
+            getopt.suggest(name)
+        },
+        _ => Vec::new()
+    };
+    if near.is_empty() {
+        return err.to_string();
     }
-    return true;
+    let near: Vec<String> = near.iter().map(|n| format!("'--{}'", n))
+        .collect();
+    format!("{}; did you mean {}?", err, near.join(" or "))
 }
 
-/*---------------------------------------------------------------------------*/
+// A line for each option given, with where it came from.
+fn given_options(matches: &getopt::Matches, layers: &[config::Layer])
+    -> String {
 
-fn dryrun(tasknum: usize, quotedcmd: &str) {
-    print!("[{}]\t{}\n", tasknum, quotedcmd);
+    matches.given().iter().map(|&(ref name, val, k)| {
+        let source = &layers[k].source;
+        match val {
+            Some(val) => format!("{} {}\t({})\n", name,
+                                 quote_arg(&val.to_string_lossy()), source),
+            None => format!("{}\t({})\n", name, source)
+        }
+    }).collect()
 }
 
-/*---------------------------------------------------------------------------*/
+// Print the limits that the default number of job slots is chosen from.
+fn show_limits(limits: &CpuLimits) {
+    let show = |n: Option<usize>| match n {
+        Some(n) => n.to_string(),
+        None => String::from("none")
+    };
+    let (jobs, source) = limits.jobs();
+    println!("CPUs:           {}", limits.count);
+    println!("CPU affinity:   {}", show(limits.affinity));
+    println!("CPU quota:      {}", show(limits.quota));
+    println!("job slots:      {} (from the {})", jobs, source);
+}
 
-fn wait_jobs(opts: &Options,
-             numjobs: &mut usize,
-             rx: &mut Receiver<Job>,
-             waitall: bool,
-             errs: &mut u32,
-             failedexit: &mut i32) {
-
-    while *numjobs > 0 {
-        match rx.recv() {
-            Ok(ref mut job) => {
-                *numjobs -= 1;
-                done_job(opts, job, errs, failedexit);
-            },
-            Err(err) => {
-                die!("recv error: {}\n", err);
-            }
+fn usage(getopt: Getopt) {
+    let head = vec![
+        "Usage:\n",
+        "    ljobs [OPTIONS...] COMMAND [CMD-ARGS...] ::: TASKS...\n",
+        "    ljobs [OPTIONS...] COMMAND [CMD-ARGS...] < TASKS\n",
+        "    ljobs [OPTIONS...] < COMMANDS"
+    ];
+    let tail = vec![
+        "String substitutions in command arguments:\n",
+        "    {}                  task\n",
+        "    {.}                 task without extension\n",
+        "    {/}                 basename of task\n",
+        "    {//}                dirname of task\n",
+        "    {/.}                basename of task without extension\n",
+        "    {#}                 task number\n",
+        "    {cwd}               current directory\n",
+        "    {host}              login of the host with --sshlogin\n",
+        "    {label}             label of the job with --label\n",
+        "    {%}                 job slot, also in LJOBS_SLOT\n",
+        "    {N}                 column N of task (see --colsep)\n",
+        "    {.FIELD}            field of task (see --json-input)\n",
+        "\n",
+        "Basenames and dirnames are as by basename(1) and dirname(1), but\n",
+        "the basename of an empty task is empty.  A dot at the start of a\n",
+        "basename does not begin an extension, e.g. .bashrc has none.\n",
+        "\n"
+    ];
+
+    for x in head {
+        print!("{}", x);
+    }
+    println!("{}", getopt.usage(""));
+    for x in tail {
+        print!("{}", x);
+    }
+}
+
+// Open the files to read tasks from, where "-" is standard input.
+fn open_arg_files(paths: &[OsString])
+    -> Result<Vec<Box<dyn BufRead + Send>>, Error> {
+
+    let mut readers: Vec<Box<dyn BufRead + Send>> = Vec::new();
+    for path in paths {
+        if path == "-" {
+            readers.push(Box::new(BufReader::new(io::stdin())));
+            continue;
+        }
+        match File::open(path) {
+            Ok(file) => readers.push(Box::new(BufReader::new(file))),
+            Err(err) => return Err(Error::Input(io::Error::new(err.kind(),
+                format!("{}: {}", Path::new(path).display(), err))))
         }
+    }
+    Ok(readers)
+}
 
-        if !waitall {
-            break;
+// Read the words of the command from a file for --command-file, leaving out
+// blank lines and comments.
+fn read_command_file(path: &OsString) -> Result<Vec<OsString>, Error> {
+    let path = Path::new(path);
+    let text = fs::read(path).map_err(|err| {
+        Error::Io(io::Error::new(err.kind(),
+            format!("command file {}: {}", path.display(), err)))
+    })?;
+    let mut words = Vec::new();
+    for (i, line) in text.split(|&c| c == b'\n').enumerate() {
+        let line = line.trim_ascii();
+        if line.is_empty() || line[0] == b'#' {
+            continue;
         }
+        words.extend(config::split_words(line).map_err(|msg| {
+            Error::Usage(format!("{}:{}: {}", path.display(), i + 1, msg))
+        })?);
+    }
+    if words.is_empty() {
+        return Err(Error::Usage(format!("{}: no command", path.display())));
     }
+    Ok(words)
 }
 
-fn done_job(opts: &Options,
-            job: &mut Job,
-            errs: &mut u32,
-            failedexit: &mut i32) {
-
-    if let Some(ref mut f) = job.child.stderr {
-        show_output(&mut io::stderr(), f, job.tasknum, &job.quotedcmd,
-            opts.verbose);
-    }
-    if let Some(ref mut f) = job.child.stdout {
-        show_output(&mut io::stdout(), f, job.tasknum, &job.quotedcmd,
-            false);
-    }
-
-    match job.waitresult {
-        Ok(ref exitstatus) => {
-            match exitstatus.code() {
-                Some(0) => {
-                    if opts.verbose {
-                        warn!("{}[{}]: done\t{}\n",
-                              PROG, job.tasknum, job.quotedcmd);
-                    }
-                },
-                Some(exit) => {
-                    if opts.verbose {
-                        warn!("{}[{}]: exit {}\t{}\n",
-                            PROG, job.tasknum, exit, job.quotedcmd);
-                    }
-                    *errs += 1;
-                    *failedexit = exit;
-                },
-                None => {
-                    match exitstatus.signal() {
-                        Some(signal) => {
-                            if opts.verbose {
-                                warn!("{}[{}]: signal {}\t{}\n",
-                                    PROG, job.tasknum, signal, job.quotedcmd);
-                            }
-                            *errs += 1;
-                            *failedexit = 128 + signal;
-                        },
-                        None => {
-                            // Should not happen.
-                            panic!("child terminated for unknown reason");
-                        }
-                    }
-                }
-            }
-        },
-        Err(ref err) => {
-            warn!("wait error pid {}: {}\n", job.child.id(), err);
-            *errs += 1;
-            *failedexit = 255;
+// Open a job file for --self-contained, and read the command from its
+// first line that is not blank or a comment, leaving the rest as tasks.
+fn open_job_file(path: &OsString)
+    -> Result<(Vec<OsString>, Box<dyn BufRead + Send>), Error> {
+
+    let path = Path::new(path);
+    let error = |err: io::Error| {
+        Error::Input(io::Error::new(err.kind(),
+            format!("{}: {}", path.display(), err)))
+    };
+    let mut file = BufReader::new(File::open(path).map_err(error)?);
+    let mut line = Vec::new();
+    let mut lineno = 0;
+    loop {
+        line.clear();
+        if file.read_until(b'\n', &mut line).map_err(error)? == 0 {
+            return Err(Error::Usage(format!("{}: no command",
+                                            path.display())));
         }
+        lineno += 1;
+        let text = line.trim_ascii();
+        if text.is_empty() || text[0] == b'#' {
+            continue;
+        }
+        let command = config::split_words(text).map_err(|msg| {
+            Error::Usage(format!("{}:{}: {}", path.display(), lineno, msg))
+        })?;
+        return Ok((command, Box::new(file)));
+    }
+}
+
+// For debugging: show how the command line was understood.
+fn dump_args(opts: &Options, source: &TaskSource) {
+    println!("{:#?}", opts);
+    match *source {
+        TaskSource::Slice(tasks) => println!("tasks: {:?}", tasks),
+        TaskSource::Readers(ref readers) =>
+            println!("tasks: {} files", readers.len()),
+        TaskSource::Iter(_) => println!("tasks: --seq"),
+        _ => println!("tasks: standard input")
     }
 }
 
-fn show_output(out: &mut Write,
-               inp: &mut Read,
-               tasknum: usize,
-               quotedcmd: &str,
-               sep: bool) {
-
-    let mut buf = Vec::new();
-    match inp.read_to_end(&mut buf) {
-        Ok(0) => (),
-        Ok(_) => {
-            if sep {
-                checked_write_fmt(out,
-                    format_args!("-------- {}[{}]: {} --------\n",
-                        PROG, tasknum, quotedcmd));
+/*---------------------------------------------------------------------------*/
+
+// Run ljobs, returning the exit code.
+fn ljobs_main(argv: &[OsString]) -> Result<i32, Error> {
+    let (opts, args) = process_options(argv)?;
+    let sep = &args.argsep;
+    let sepused = |what: &str| {
+        Err(Error::Usage(format!("{} cannot be used with {}",
+                                 sep.to_string_lossy(), what)))
+    };
+
+    // A self-contained job file gives the command and the tasks.
+    let (command, mut jobfile) = match args.selfcontained {
+        Some(ref path) => {
+            match args.free.first() {
+                Some(arg) if arg == sep => return sepused("--self-contained"),
+                Some(_) => return Err(Error::Usage(String::from(
+                    "a command cannot be given with --self-contained"))),
+                None => ()
             }
-            checked_write_all(out, &buf);
-            if sep {
-                checked_write_fmt(out, format_args!("--------\n"));
+            let (command, rest) = open_job_file(path)?;
+            (Some(command), Some(rest))
+        },
+        None => (None, None)
+    };
+    // A command file gives the command, and the tasks are given as usual.
+    let command = match (command, &args.cmdfile) {
+        (Some(_), Some(_)) => return Err(Error::Usage(String::from(
+            "--command-file cannot be used with --self-contained"))),
+        (None, Some(path)) => {
+            if args.free.first().is_some_and(|arg| arg != sep) {
+                return Err(Error::Usage(String::from(
+                    "a command cannot be given with --command-file")));
             }
+            let mut words = read_command_file(path)?;
+            words.extend(args.free.iter().cloned());
+            Some(words)
+        },
+        (command, None) => command
+    };
+    let freeargs = command.as_ref().unwrap_or(&args.free);
+
+    // A script file takes the place of the command.  Without either, each
+    // task is a command line for the shell, appended to its arguments.
+    let cmdlen = freeargs.iter().position(|x| x == sep)
+        .unwrap_or(freeargs.len());
+    let mut shellargs = Vec::new();
+    let (opts, first) = match (args.script, cmdlen) {
+        (true, 0) => (opts, 0),
+        (true, _) => {
+            return Err(Error::Usage(
+                String::from("a command cannot be given with --script-file")));
+        },
+        (false, 0) if args.noshell => {
+            return Err(Error::Usage(String::from("no command")));
+        },
+        (false, 0) => {
+            shellargs = template::shell_invocation();
+            let shell = shellargs.remove(0);
+            (opts.shell(None).command(shell), 0)
+        },
+        (false, _) => (opts.command(freeargs[0].as_os_str()), 1)
+    };
+
+    let (opts, source) = match freeargs.iter().position(|x| x == sep) {
+        Some(_) if !args.argfiles.is_empty() => return sepused("--arg-file"),
+        Some(_) if args.seq.is_some() => return sepused("--seq"),
+        Some(_) if jobfile.is_some() => return sepused("--self-contained"),
+        None if jobfile.is_some() &&
+                (args.seq.is_some() || !args.argfiles.is_empty()) => {
+            return Err(Error::Usage(String::from(
+                "--self-contained cannot be used with --arg-file or --seq")));
         },
-        Err(err) => {
-            die!("read error: {}\n", err)
+        None if jobfile.is_some() => {
+            (opts.args(&freeargs[first..]),
+             TaskSource::Readers(jobfile.take().into_iter().collect()))
+        },
+        None if args.seq.is_some() && !args.argfiles.is_empty() => {
+            return Err(Error::Usage(
+                String::from("--seq cannot be used with --arg-file")));
+        },
+        None if args.seq.is_some() => {
+            (opts.args(&freeargs[first..]),
+             TaskSource::Iter(Box::new(args.seq.expect("seq"))))
+        },
+        Some(i) => {
+            (opts.args(&freeargs[first..i]),
+             TaskSource::Slice(&freeargs[i+1..]))
+        },
+        None if !args.argfiles.is_empty() => {
+            (opts.args(&freeargs[first..]),
+             TaskSource::Readers(open_arg_files(&args.argfiles)?))
+        },
+        None => {
+            (opts.args(&freeargs[first..]),
+             TaskSource::Stdin)
+        }
+    };
+
+    let opts = if shellargs.is_empty() { opts } else {
+        opts.args(&shellargs)
+    };
+
+    if args.dumpargs {
+        dump_args(&opts, &source);
+        return Ok(0);
+    }
+
+    if let Some(task) = args.explain {
+        let explanation = ljobs::explain(&opts, source, task.as_deref())?;
+        io::stdout().write_all(explanation.as_bytes()).map_err(Error::Io)?;
+        return Ok(0);
+    }
+
+    let summary = match ljobs::run(&opts, source) {
+        Ok(summary) => summary,
+        // Whoever closed the pipe did not want more output, so there is
+        // nothing to complain about, unless asked.
+        Err(Error::BrokenPipe) =>
+            return Ok(if args.sigpipe { 128 + 13 } else { 0 }),
+        Err(err) => return Err(err)
+    };
+    if let Some(ref path) = args.summaryjson {
+        write_summary_json(path, &summary.to_json())?;
+    }
+    let code = summary.exit_code(&opts);
+    if let Some(ref url) = args.webhook {
+        let body = webhook::payload(&summary, code, &webhook::hostname());
+        if let Err(err) = webhook::post(url, &body) {
+            warn!("ljobs: webhook {}: {}\n", url, err);
         }
     }
+    Ok(code)
 }
 
-fn checked_write_all(f: &mut Write, buf: &[u8]) {
-    match f.write_all(buf) {
-        Ok(_n) => (),
-        Err(e) => die!("write error: {}\n", e)
-    }
+// Write the summary for --summary-json, where "-" is standard output.
+fn write_summary_json(path: &OsString, json: &[u8]) -> Result<(), Error> {
+    let result = if path == "-" {
+        io::stdout().write_all(json)
+    } else {
+        File::create(path).and_then(|mut file| file.write_all(json))
+    };
+    result.map_err(|err| Error::Io(io::Error::new(err.kind(),
+        format!("cannot write summary to {}: {}",
+                Path::new(path).display(), err))))
 }
 
-fn checked_write_fmt(f: &mut Write, args: fmt::Arguments) {
-    match f.write_fmt(args) {
-        Ok(_n) => (),
-        Err(e) => die!("write error: {}\n", e)
+// Report a panic with the name of the thread it happened in.  A panic in a
+// thread of the library stops the run with Error::Internal, and one in the
+// main thread is caught in `main`.  Nothing here may panic again, even if
+// standard error is gone, or the process would abort rather than exit.
+fn panic_hook(info: &PanicHookInfo) {
+    let thread = thread::current();
+    let _ = writeln!(io::stderr(), "ljobs: thread '{}' {}",
+                     thread.name().unwrap_or("<unnamed>"), info);
+}
+
+// Errors are reported here and only here, besides panics.
+fn main() {
+    panic::set_hook(Box::new(panic_hook));
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    match panic::catch_unwind(|| ljobs_main(&argv)) {
+        Ok(Ok(code)) => exit(code),
+        Ok(Err(err)) => {
+            warn!("{}\n", err);
+            exit(match err {
+                Error::Internal(_) => EXIT_INTERNAL,
+                _ => 255
+            });
+        },
+        Err(_) => exit(EXIT_INTERNAL)
     }
 }
 
@@ -0,0 +1,189 @@
+/*
+** Manifest of tasks that were run.
+*/
+
+// The manifest has one line per task, in the order that tasks finished:
+//
+//     TASKNUM <tab> STATUS <tab> SHA256 <tab> CPU <tab> MAXRSS <tab> LABEL
+//     <tab> COMMAND
+//
+// where STATUS is "exit N", "signal N", "error", "no output", "stderr" or
+// "timed out", and SHA256 is the hex digest of the standard output of the
+// task followed by its standard error.  CPU is the user and system time of
+// the command in seconds and MAXRSS its peak resident set size in KiB, or
+// "-" where they are not known, as for output replayed from the cache or on
+// Windows.
+// LABEL is the label of the job, or "-" without labels, so that the columns
+// are the same either way.
+//
+// The records may also be teed to a file descriptor, such as a pipe to a
+// process that acts on tasks as they finish.  Each record is written there
+// whole and flushed at once.  Records teed to standard output go through
+// the same buffer as the output of tasks, so they fall between the outputs
+// of tasks rather than in the middle of one.
+
+#[cfg(unix)]
+use libc;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+#[cfg(unix)]
+use std::mem::ManuallyDrop;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+
+use {ResourceUsage, TaskStatus};
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Manifest {
+    out:        Option<BufWriter<File>>,
+    tee:        Option<Tee>
+}
+
+enum Tee {
+    Stdout,
+    Stderr,
+    // A descriptor that belongs to the caller, so it is never closed.
+    #[cfg(unix)]
+    Fd(ManuallyDrop<File>)
+}
+
+/// Incremental digest of the output of a task.
+pub struct OutputHash {
+    hasher:     Sha256
+}
+
+impl Manifest {
+    /// A manifest written to the file at `path`, if any, and teed to the
+    /// file descriptor `teefd`, if any.
+    pub fn create(path: Option<&Path>, teefd: Option<i32>)
+        -> io::Result<Manifest> {
+
+        let out = match path.map(File::create) {
+            Some(Ok(f)) => Some(BufWriter::new(f)),
+            Some(Err(err)) => return Err(io::Error::new(err.kind(),
+                format!("could not create manifest {}: {}",
+                        path.expect("path").display(), err))),
+            None => None
+        };
+        let tee = match teefd {
+            Some(fd) => Some(Tee::open(fd).map_err(|err| {
+                io::Error::new(err.kind(),
+                    format!("could not write manifest to fd {}: {}", fd, err))
+            })?),
+            None => None
+        };
+        Ok(Manifest { out, tee })
+    }
+
+    pub fn record(&mut self,
+                  tasknum: usize,
+                  status: &TaskStatus,
+                  hash: OutputHash,
+                  usage: Option<ResourceUsage>,
+                  label: Option<&OsStr>,
+                  quotedcmd: &str) -> io::Result<()> {
+
+        let status = match *status {
+            TaskStatus::Exited(exit) => format!("exit {}", exit),
+            TaskStatus::Signaled(signal) => format!("signal {}", signal),
+            TaskStatus::Error(_) => String::from("error"),
+            TaskStatus::NoOutput => String::from("no output"),
+            TaskStatus::WroteStderr => String::from("stderr"),
+            TaskStatus::TimedOut => String::from("timed out"),
+            // Nothing ran.
+            TaskStatus::DryRun | TaskStatus::Skipped => return Ok(())
+        };
+        let (cpu, maxrss) = match usage {
+            Some(usage) => (format!("{:.3}", usage.cpu.as_secs_f64()),
+                            (usage.maxrss / 1024).to_string()),
+            None => (String::from("-"), String::from("-"))
+        };
+        let label = match label {
+            Some(label) => label.to_string_lossy(),
+            None => Cow::Borrowed("-")
+        };
+        let line = format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                           tasknum, status, hash.hex(), cpu, maxrss, label,
+                           quotedcmd);
+        if let Some(ref mut out) = self.out {
+            out.write_all(line.as_bytes()).map_err(manifest_error)?;
+        }
+        if let Some(ref mut tee) = self.tee {
+            tee.write_line(line.as_bytes()).map_err(manifest_error)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.out {
+            Some(mut out) => out.flush().map_err(manifest_error),
+            None => Ok(())
+        }
+    }
+}
+
+impl Tee {
+    #[cfg(unix)]
+    fn open(fd: i32) -> io::Result<Tee> {
+        match fd {
+            1 => Ok(Tee::Stdout),
+            2 => Ok(Tee::Stderr),
+            _ => {
+                if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // Safe as the descriptor is open and is never closed here.
+                let file = unsafe { File::from_raw_fd(fd) };
+                Ok(Tee::Fd(ManuallyDrop::new(file)))
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    fn open(fd: i32) -> io::Result<Tee> {
+        match fd {
+            1 => Ok(Tee::Stdout),
+            2 => Ok(Tee::Stderr),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported,
+                                    "only fds 1 and 2 are supported"))
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        match *self {
+            Tee::Stdout => {
+                let mut out = io::stdout().lock();
+                out.write_all(line)?;
+                out.flush()
+            },
+            Tee::Stderr => io::stderr().write_all(line),
+            #[cfg(unix)]
+            Tee::Fd(ref mut file) => file.write_all(line)
+        }
+    }
+}
+
+fn manifest_error(err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("manifest write error: {}", err))
+}
+
+impl OutputHash {
+    pub fn new() -> OutputHash {
+        OutputHash { hasher: Sha256::new() }
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        self.hasher.update(buf);
+    }
+
+    fn hex(self) -> String {
+        self.hasher.finalize().iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
@@ -0,0 +1,214 @@
+/*
+** Running jobs in private namespaces.
+*/
+
+// With `private_tmp`, each child unshares its mount namespace in a pre_exec
+// hook and mounts a fresh tmpfs on /tmp, after making every mount in the
+// new namespace private so that nothing is propagated back to the rest of
+// the system.  With `isolate_network`, the child unshares its network
+// namespace, which leaves it with only a loopback interface, and that down.
+//
+// Both need CAP_SYS_ADMIN.  Without it, as for an ordinary user, the child
+// also enters a new user namespace of its own, in which it has every
+// capability until it executes the command, and maps its user and group ids
+// to themselves so that files that it creates are owned as usual.  The gid
+// map may only be written once setgroups is denied.
+//
+// The setup is tried once in a child of ljobs before any job is started,
+// so that where it cannot be done, e.g. because user namespaces are
+// disabled, the run fails rather than running jobs unconfined.
+
+use libc;
+use std::fs;
+use std::io;
+use std::ptr;
+
+use Options;
+
+// The bit for CAP_SYS_ADMIN in the capability sets of /proc/PID/status.
+const CAP_SYS_ADMIN: u32 = 21;
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Namespaces {
+    flags:      libc::c_int,
+    privatetmp: bool,
+    idmaps:     Option<IdMaps>
+}
+
+// The contents for /proc/self/uid_map and /proc/self/gid_map.
+struct IdMaps {
+    uid:        String,
+    gid:        String
+}
+
+impl Namespaces {
+    /// None if jobs share the namespaces of ljobs.
+    pub fn new(opts: &Options) -> Option<Namespaces> {
+        if !opts.privatetmp && !opts.isolatenet {
+            return None;
+        }
+        let idmaps = if privileged() {
+            None
+        } else {
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            Some(IdMaps {
+                uid: format!("{} {} 1", uid, uid),
+                gid: format!("{} {} 1", gid, gid)
+            })
+        };
+        Some(Namespaces {
+            flags: flags(opts.privatetmp, opts.isolatenet, idmaps.is_some()),
+            privatetmp: opts.privatetmp,
+            idmaps
+        })
+    }
+
+    /// Try entering the namespaces in a child process, and say why not if
+    /// they cannot be.
+    pub fn probe(&self) -> io::Result<()> {
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if pid == 0 {
+            let code = match self.enter() {
+                Ok(()) => 0,
+                Err(err) => err.raw_os_error().unwrap_or(libc::EINVAL)
+            };
+            unsafe { libc::_exit(code) };
+        }
+        let mut status = 0;
+        while unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+        match libc::WEXITSTATUS(status) {
+            0 if libc::WIFEXITED(status) => Ok(()),
+            code => Err(io::Error::from_raw_os_error(code))
+        }
+    }
+
+    /// Move the calling process into the namespaces.  Only
+    /// async-signal-safe calls are made, so that this may be called between
+    /// fork and exec.
+    pub fn enter(&self) -> io::Result<()> {
+        check(unsafe { libc::unshare(self.flags) })?;
+        if let Some(ref idmaps) = self.idmaps {
+            // Kernels before 3.19 have no setgroups file, and need none.
+            match write_file(b"/proc/self/setgroups\0", b"deny") {
+                Err(ref err) if err.raw_os_error() == Some(libc::ENOENT) =>
+                    (),
+                result => result?
+            }
+            write_file(b"/proc/self/uid_map\0", idmaps.uid.as_bytes())?;
+            write_file(b"/proc/self/gid_map\0", idmaps.gid.as_bytes())?;
+        }
+        if self.privatetmp {
+            let root = b"/\0".as_ptr() as *const libc::c_char;
+            check(unsafe {
+                libc::mount(ptr::null(), root, ptr::null(),
+                            libc::MS_REC | libc::MS_PRIVATE, ptr::null())
+            })?;
+            let tmpfs = b"tmpfs\0".as_ptr() as *const libc::c_char;
+            let tmp = b"/tmp\0".as_ptr() as *const libc::c_char;
+            let data = b"mode=1777\0".as_ptr() as *const libc::c_void;
+            check(unsafe {
+                libc::mount(tmpfs, tmp, tmpfs,
+                            libc::MS_NOSUID | libc::MS_NODEV, data)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+// The namespaces to unshare.
+fn flags(privatetmp: bool, isolatenet: bool, userns: bool) -> libc::c_int {
+    let mut flags = 0;
+    if privatetmp {
+        flags |= libc::CLONE_NEWNS;
+    }
+    if isolatenet {
+        flags |= libc::CLONE_NEWNET;
+    }
+    if userns {
+        flags |= libc::CLONE_NEWUSER;
+    }
+    flags
+}
+
+// Whether ljobs may create namespaces without a user namespace.
+fn privileged() -> bool {
+    fs::read_to_string("/proc/self/status")
+        .is_ok_and(|status| has_cap_sys_admin(&status))
+}
+
+// Whether the effective capabilities in the contents of /proc/PID/status
+// include CAP_SYS_ADMIN.
+fn has_cap_sys_admin(status: &str) -> bool {
+    status.lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_SYS_ADMIN) != 0)
+}
+
+// Write all of `data` to the file at the NUL-terminated `path`, in a single
+// write, as the files under /proc/self need.
+fn write_file(path: &[u8], data: &[u8]) -> io::Result<()> {
+    let fd = unsafe {
+        libc::open(path.as_ptr() as *const libc::c_char,
+                   libc::O_WRONLY | libc::O_CLOEXEC)
+    };
+    check(fd)?;
+    let n = unsafe {
+        libc::write(fd, data.as_ptr() as *const libc::c_void, data.len())
+    };
+    let err = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+    if n != data.len() as isize {
+        return Err(if n < 0 { err } else {
+            io::Error::from_raw_os_error(libc::EIO)
+        });
+    }
+    Ok(())
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{flags, has_cap_sys_admin};
+    use libc;
+
+    #[test]
+    fn unshare_flags() {
+        assert_eq!(flags(true, false, false), libc::CLONE_NEWNS);
+        assert_eq!(flags(false, true, false), libc::CLONE_NEWNET);
+        assert_eq!(flags(true, true, true),
+                   libc::CLONE_NEWNS | libc::CLONE_NEWNET |
+                   libc::CLONE_NEWUSER);
+    }
+
+    #[test]
+    fn cap_sys_admin() {
+        let status = "Name:\tljobs\nCapInh:\t0000000000000000\n\
+                      CapPrm:\t000001ffffffffff\n\
+                      CapEff:\t000001ffffffffff\n";
+        assert!(has_cap_sys_admin(status));
+        let status = "CapPrm:\t0000000000200000\n\
+                      CapEff:\t0000000000000000\n";
+        assert!(!has_cap_sys_admin(status));
+        assert!(has_cap_sys_admin("CapEff:\t0000000000200000\n"));
+        assert!(!has_cap_sys_admin("CapEff:\t00000000001fffff\n"));
+        assert!(!has_cap_sys_admin("Name:\tljobs\n"));
+    }
+}
@@ -0,0 +1,38 @@
+/*
+** Platform differences in OS strings.
+*/
+
+// Substitutions and option parsing work on the bytes of OS strings.  On
+// Unix these are arbitrary bytes.  On Windows they are an encoding of
+// UTF-16 which is a superset of UTF-8, so bytes read from elsewhere must be
+// checked before they can be used as an OS string.
+
+use std::ffi::OsString;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+
+/// Convert bytes made from pieces of OS strings, split at ASCII characters,
+/// back into an OS string.
+pub fn from_os_bytes(bytes: Vec<u8>) -> OsString {
+    // Safe because splitting the encoded bytes of OS strings at ASCII
+    // characters and joining the pieces gives valid encoded bytes.
+    unsafe { OsString::from_encoded_bytes_unchecked(bytes) }
+}
+
+/// Convert bytes read from a file or pipe into an OS string.
+#[cfg(unix)]
+pub fn from_input_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from_vec(bytes)
+}
+
+/// Convert bytes read from a file or pipe into an OS string.  Bytes which
+/// are not valid UTF-8 are replaced.
+#[cfg(windows)]
+pub fn from_input_bytes(bytes: Vec<u8>) -> OsString {
+    match String::from_utf8(bytes) {
+        Ok(s) => OsString::from(s),
+        Err(err) => {
+            OsString::from(String::from_utf8_lossy(err.as_bytes()).as_ref())
+        }
+    }
+}
@@ -0,0 +1,353 @@
+/*
+** Collecting the output of a task.
+*/
+
+// The output of a task is kept in memory up to a threshold, after which it
+// is spilled to an anonymous temporary file, so that tasks with very large
+// output do not use up memory.  The output is collected while the task runs
+// so that the task never blocks on a full pipe.
+//
+// Many tasks with output under the threshold may still use a lot of memory
+// between them, so the output of all tasks may also be held to a Budget.
+// Output that would take more memory than is left in the budget is spilled
+// instead, and the memory is returned to the budget when the output is
+// spilled, taken as a Vec or dropped.
+
+#[cfg(target_os = "linux")]
+use libc;
+use std::env;
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::FromRawFd;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use pattern::{LineScan, Pattern};
+
+/// Default size of output kept in memory before spilling to a file.
+pub const DEFAULT_SPILL: usize = 4 * 1024 * 1024;
+
+const CHUNK: usize = 64 * 1024;
+const COLLECT_CHUNKS: usize = 16;
+
+#[cfg(windows)]
+const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+
+static TMPCOUNT: AtomicUsize = AtomicUsize::new(0);
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Output {
+    buf:        Vec<u8>,
+    file:       Option<File>,
+    len:        u64,
+    spill:      usize,
+    budget:     Option<Arc<Budget>>,
+    error:      Option<io::Error>,
+    // With `abort_on_match`, the scan of lines as they are collected, and
+    // whether a line matched since last asked.
+    scan:       Option<LineScan>,
+    matched:    bool
+}
+
+/// The memory that the output of all tasks may take between them.
+pub struct Budget {
+    cap:        usize,
+    held:       AtomicUsize,
+    peak:       AtomicUsize
+}
+
+impl Output {
+    /// Output beyond `spill` bytes, or beyond what is left in `budget`,
+    /// is spilled to a file.
+    pub fn new(spill: usize, budget: Option<Arc<Budget>>) -> Output {
+        Output {
+            buf:    Vec::new(),
+            file:   None,
+            len:    0,
+            spill,
+            budget,
+            error:  None,
+            scan:   None,
+            matched: false
+        }
+    }
+
+    /// Scan lines for `pattern` as they are collected.
+    pub fn scan(mut self, pattern: Option<&Arc<Pattern>>) -> Output {
+        self.scan = pattern.map(|p| LineScan::new(p.clone()));
+        self
+    }
+
+    /// Whether a line has matched the pattern since last asked.
+    pub fn take_match(&mut self) -> bool {
+        std::mem::take(&mut self.matched)
+    }
+
+    /// Output already written to `file`.
+    pub fn from_file(file: File) -> io::Result<Output> {
+        let len = file.metadata()?.len();
+        Ok(Output {
+            buf:    Vec::new(),
+            file:   Some(file),
+            len,
+            spill:  0,
+            budget: None,
+            error:  None,
+            scan:   None,
+            matched: false
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bytes written, including any not kept.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    // A write error is kept until the output is used, as output is
+    // collected where errors cannot be reported.
+    fn append(&mut self, data: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        self.len += data.len() as u64;
+        let fits = |out: &Output| {
+            out.buf.len() + data.len() <= out.spill && out.take(data.len())
+        };
+        if self.file.is_none() && !fits(self) {
+            match tempfile() {
+                Ok(f) => self.file = Some(f),
+                Err(err) => {
+                    self.error = Some(spill_error(err));
+                    return;
+                }
+            }
+            let buf = self.take_buf();
+            self.append_file(&buf);
+        }
+        if self.file.is_some() {
+            self.append_file(data);
+        } else {
+            self.buf.extend_from_slice(data);
+        }
+    }
+
+    // Take `n` bytes from the budget, if there is one and it has room.
+    fn take(&self, n: usize) -> bool {
+        match self.budget {
+            Some(ref budget) => budget.take(n),
+            None => true
+        }
+    }
+
+    // Take the buffer, returning its memory to the budget.
+    fn take_buf(&mut self) -> Vec<u8> {
+        let buf = std::mem::take(&mut self.buf);
+        if let Some(ref budget) = self.budget {
+            budget.give(buf.len());
+        }
+        buf
+    }
+
+    fn append_file(&mut self, data: &[u8]) {
+        if let Some(ref mut f) = self.file {
+            if let Err(err) = f.write_all(data) {
+                self.error = Some(spill_error(err));
+            }
+        }
+    }
+
+    // Read once from `inp`, returning the number of bytes read, or zero at
+    // the end of input.
+    fn read_from(&mut self, inp: &mut dyn Read) -> io::Result<usize> {
+        let mut chunk = [0u8; CHUNK];
+        loop {
+            match inp.read(&mut chunk) {
+                Ok(n) => {
+                    self.append(&chunk[..n]);
+                    if let Some(ref mut scan) = self.scan {
+                        let found = if n == 0 {
+                            scan.finish()
+                        } else {
+                            scan.feed(&chunk[..n])
+                        };
+                        self.matched |= found;
+                    }
+                    return Ok(n);
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(io::Error::new(err.kind(),
+                                        format!("read error: {}", err)))
+            }
+        }
+    }
+
+    /// Read what is available from `pipe`, which should be non-blocking,
+    /// if it is still open.  The pipe is closed at the end of input or after
+    /// an error.
+    pub fn collect<R: Read>(&mut self, pipe: &mut Option<R>) {
+        let open = match *pipe {
+            Some(ref mut inp) => self.collect_some(inp),
+            None => false
+        };
+        if !open {
+            *pipe = None;
+        }
+    }
+
+    // Read at most COLLECT_CHUNKS chunks, so that one busy pipe cannot hold
+    // up the others.  Returns false at the end of input or after an error.
+    fn collect_some(&mut self, inp: &mut dyn Read) -> bool {
+        for _ in 0..COLLECT_CHUNKS {
+            match self.read_from(inp) {
+                Ok(0) => return false,
+                Ok(_) => (),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock =>
+                    return true,
+                Err(err) => {
+                    if self.error.is_none() {
+                        self.error = Some(err);
+                    }
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Pass the output to `f` in pieces.  Errors from `f` are passed on as
+    /// they are.
+    pub fn for_each_chunk<F, E>(&mut self, mut f: F) -> Result<(), E>
+        where F: FnMut(&[u8]) -> Result<(), E>, E: From<io::Error>
+    {
+        if let Some(ref err) = self.error {
+            return Err(io::Error::new(err.kind(), err.to_string()).into());
+        }
+        match self.file {
+            Some(ref mut file) => {
+                file.seek(SeekFrom::Start(0)).map_err(spill_error)?;
+                let mut chunk = vec![0u8; CHUNK];
+                loop {
+                    match file.read(&mut chunk) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => f(&chunk[..n])?,
+                        Err(ref err)
+                            if err.kind() == io::ErrorKind::Interrupted => (),
+                        Err(err) => return Err(spill_error(err).into())
+                    }
+                }
+            },
+            None => {
+                if self.buf.is_empty() {
+                    Ok(())
+                } else {
+                    f(&self.buf)
+                }
+            }
+        }
+    }
+
+    pub fn into_vec(mut self) -> io::Result<Vec<u8>> {
+        if self.file.is_none() && self.error.is_none() {
+            return Ok(self.take_buf());
+        }
+        let mut v = Vec::with_capacity(self.len as usize);
+        self.for_each_chunk(|data| -> io::Result<()> {
+            v.extend_from_slice(data);
+            Ok(())
+        })?;
+        Ok(v)
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        self.take_buf();
+    }
+}
+
+impl Budget {
+    pub fn new(cap: usize) -> Budget {
+        Budget {
+            cap,
+            held: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0)
+        }
+    }
+
+    /// Take `n` bytes if that would not go over the cap.
+    fn take(&self, n: usize) -> bool {
+        let result = self.held.fetch_update(Ordering::SeqCst, Ordering::SeqCst,
+            |held| Some(held + n).filter(|&total| total <= self.cap));
+        match result {
+            Ok(held) => {
+                self.peak.fetch_max(held + n, Ordering::SeqCst);
+                true
+            },
+            Err(_) => false
+        }
+    }
+
+    fn give(&self, n: usize) {
+        self.held.fetch_sub(n, Ordering::SeqCst);
+    }
+
+    /// The most memory held at once.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+fn spill_error(err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("output spill error: {}", err))
+}
+
+// An anonymous temporary file, which goes away when closed.  On Windows an
+// open file cannot be removed, so it is marked to be deleted on close.
+fn tempfile() -> io::Result<File> {
+    let dir = env::temp_dir();
+
+    #[cfg(target_os = "linux")]
+    {
+        let cdir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(io::Error::other)?;
+        let fd = unsafe {
+            libc::open(cdir.as_ptr(),
+                       libc::O_TMPFILE | libc::O_RDWR | libc::O_CLOEXEC,
+                       0o600)
+        };
+        if fd >= 0 {
+            return Ok(unsafe { File::from_raw_fd(fd) });
+        }
+        // Fall back if the file system does not support O_TMPFILE.
+    }
+
+    let path = dir.join(format!("ljobs-{}-{}", process::id(),
+                                TMPCOUNT.fetch_add(1, Ordering::SeqCst)));
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    #[cfg(windows)]
+    options.custom_flags(FILE_FLAG_DELETE_ON_CLOSE);
+    let file = options.open(&path)?;
+    #[cfg(unix)]
+    fs::remove_file(&path)?;
+    Ok(file)
+}
@@ -0,0 +1,202 @@
+/*
+** Watching the output of tasks for a pattern.
+*/
+
+// With `abort_on_match`, each line of output from each task is matched
+// against a regular expression as the output is collected, so that the
+// master hears of a match while the task is still running.  Output arrives
+// in pieces, so a line split between reads is held until the rest of it
+// arrives.  A line longer than MAX_LINE is matched in pieces of that size.
+//
+// With `retry_after_pattern`, the output of a task that failed is scanned
+// the same way once it has finished, and the number in the first line that
+// matches is the delay before the task is tried again.
+//
+// With `filter_tasks` and `exclude_tasks`, each task as read is matched
+// whole.
+//
+// Regular expressions need the `regex` feature, which is on by default.
+
+#[cfg(feature = "regex")]
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_LINE: usize = 64 * 1024;
+
+/*---------------------------------------------------------------------------*/
+
+/// A compiled regular expression, matched against lines of bytes.
+pub struct Pattern {
+    #[cfg(feature = "regex")]
+    regex:      Regex
+}
+
+/// Finds the first line of a stream that matches a pattern.
+pub struct LineScan {
+    pattern:    Arc<Pattern>,
+    partial:    Vec<u8>,
+    matched:    bool,
+    line:       Option<Vec<u8>>
+}
+
+impl Pattern {
+    #[cfg(feature = "regex")]
+    pub fn new(pattern: &str) -> Result<Pattern, String> {
+        Regex::new(pattern)
+            .map(|regex| Pattern { regex })
+            .map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub fn new(_pattern: &str) -> Result<Pattern, String> {
+        Err(String::from("built without regular expressions"))
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        self.regex.is_match(line)
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub fn is_match(&self, _line: &[u8]) -> bool {
+        false
+    }
+
+    /// The number of seconds that a matching line gives, in the first
+    /// capture group or else in the whole match.  None if the line does
+    /// not match or the number is not a valid delay.
+    #[cfg(feature = "regex")]
+    pub fn delay(&self, line: &[u8]) -> Option<Duration> {
+        let caps = self.regex.captures(line)?;
+        let secs = caps.get(1).or_else(|| caps.get(0))?.as_bytes();
+        let secs: f64 = std::str::from_utf8(secs).ok()?.trim().parse().ok()?;
+        Duration::try_from_secs_f64(secs).ok()
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub fn delay(&self, _line: &[u8]) -> Option<Duration> {
+        None
+    }
+}
+
+impl LineScan {
+    pub fn new(pattern: Arc<Pattern>) -> LineScan {
+        LineScan {
+            pattern,
+            partial: Vec::new(),
+            matched: false,
+            line:    None
+        }
+    }
+
+    /// Scan the next piece of the stream.  Returns true the first time a
+    /// line matches, and false ever after.
+    pub fn feed(&mut self, data: &[u8]) -> bool {
+        if self.matched {
+            return false;
+        }
+        let mut rest = data;
+        while let Some(nl) = rest.iter().position(|&b| b == b'\n') {
+            let line = if self.partial.is_empty() {
+                Cow::Borrowed(&rest[..nl])
+            } else {
+                self.partial.extend_from_slice(&rest[..nl]);
+                Cow::Owned(std::mem::take(&mut self.partial))
+            };
+            if self.pattern.is_match(&line) {
+                return self.found(line.into_owned());
+            }
+            rest = &rest[nl+1..];
+        }
+        self.partial.extend_from_slice(rest);
+        if self.partial.len() >= MAX_LINE {
+            return self.finish();
+        }
+        false
+    }
+
+    /// At the end of the stream, scan the last line if it had no newline.
+    pub fn finish(&mut self) -> bool {
+        if self.matched || self.partial.is_empty() {
+            return false;
+        }
+        let line = std::mem::take(&mut self.partial);
+        if self.pattern.is_match(&line) {
+            return self.found(line);
+        }
+        false
+    }
+
+    /// The line that matched, if one did.
+    pub fn matched_line(&self) -> Option<&[u8]> {
+        self.line.as_deref()
+    }
+
+    fn found(&mut self, line: Vec<u8>) -> bool {
+        self.matched = true;
+        self.partial = Vec::new();
+        self.line = Some(line);
+        true
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(all(test, feature = "regex"))]
+mod tests {
+    use super::{LineScan, Pattern, MAX_LINE};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn scan(pattern: &str) -> LineScan {
+        LineScan::new(Arc::new(Pattern::new(pattern).unwrap()))
+    }
+
+    #[test]
+    fn lines() {
+        let mut s = scan("^FATAL");
+        assert!(!s.feed(b"ok\nnot FATAL\n"));
+        assert!(!s.feed(b"FA"));
+        assert!(s.feed(b"TAL: oops\nFATAL again\n"));
+        assert!(!s.feed(b"FATAL\n"));
+        assert!(!s.finish());
+        assert_eq!(s.matched_line(), Some(&b"FATAL: oops"[..]));
+    }
+
+    #[test]
+    fn last_line() {
+        let mut s = scan("end$");
+        assert!(!s.feed(b"the e"));
+        assert!(!s.feed(b"nd"));
+        assert!(s.finish());
+    }
+
+    #[test]
+    fn long_line() {
+        let mut s = scan("x");
+        assert!(!s.feed(&vec![b'a'; MAX_LINE - 1]));
+        // Matched as a line of its own once it is long enough.
+        assert!(!s.feed(b"a"));
+        assert!(s.feed(b"x\n"));
+    }
+
+    #[test]
+    fn delay() {
+        let p = Pattern::new(r"^Retry-After: *(\S+)").unwrap();
+        assert_eq!(p.delay(b"Retry-After: 3"), Some(Duration::from_secs(3)));
+        assert_eq!(p.delay(b"Retry-After: 0.5"),
+                   Some(Duration::from_millis(500)));
+        assert_eq!(p.delay(b"Retry-After: soon"), None);
+        assert_eq!(p.delay(b"Retry-After: -1"), None);
+        assert_eq!(p.delay(b"retry after 3"), None);
+        let p = Pattern::new(r"\d+$").unwrap();
+        assert_eq!(p.delay(b"wait 7"), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Pattern::new("(").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+/*
+** Quoting of command lines for display.
+*/
+
+//! Quoting of command lines for display.
+//!
+//! Command lines are quoted as a shell would need them on Unix, and as the
+//! Microsoft C runtime parses them on Windows.  Command lines to be run by
+//! a remote shell are quoted for a POSIX shell everywhere.
+
+use std::ffi::OsString;
+#[cfg(windows)]
+use std::iter;
+
+use os::from_os_bytes;
+
+/// Quote each argument and join them with spaces.  Arguments which are not
+/// valid Unicode are converted lossily.
+///
+/// ```
+/// # #[cfg(unix)] {
+/// use ljobs::quote::quote_cmd;
+/// use std::ffi::OsString;
+///
+/// let args = ["cp", "my file", "dir/"].map(OsString::from);
+/// assert_eq!(quote_cmd(&args), "cp 'my file' dir/");
+/// # }
+/// ```
+pub fn quote_cmd(args: &[OsString]) -> String {
+    let v: Vec<String> = args.iter()
+        .map(|s| quote_arg(&s.to_string_lossy()))
+        .collect();
+    v.join(" ")
+}
+
+/// Quote each argument for a POSIX shell and join them with spaces, as for
+/// a command line to be run by a remote shell.  Unlike `quote_cmd`, no
+/// argument need be valid Unicode.
+///
+/// ```
+/// use ljobs::quote::quote_sh_cmd;
+/// use std::ffi::OsString;
+///
+/// let args = ["echo", "it's", "$HOME"].map(OsString::from);
+/// assert_eq!(quote_sh_cmd(&args), "echo 'it'\"'\"'s' '$HOME'");
+/// ```
+pub fn quote_sh_cmd(args: &[OsString]) -> OsString {
+    let mut cmd = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            cmd.push(b' ');
+        }
+        quote_sh(arg.as_encoded_bytes(), &mut cmd);
+    }
+    from_os_bytes(cmd)
+}
+
+/// Quote an argument if a shell would not take it as a single word as it
+/// is.
+#[cfg(unix)]
+pub fn quote_arg(s: &str) -> String {
+    let mut quoted = Vec::with_capacity(s.len() + 2);
+    quote_sh(s.as_bytes(), &mut quoted);
+    // Quoting at ASCII characters keeps the string valid UTF-8.
+    String::from_utf8(quoted).expect("quoted UTF-8")
+}
+
+// Append `s` to `out`, quoted if a POSIX shell would not take it as a
+// single word as it is.
+fn quote_sh(s: &[u8], out: &mut Vec<u8>) {
+    if s.is_empty() {
+        out.extend_from_slice(b"''");
+    } else if s.iter().all(|&c| shell_safe_char(c)) {
+        out.extend_from_slice(s);
+    } else {
+        out.push(b'\'');
+        for &c in s {
+            if c == b'\'' {
+                out.extend_from_slice(b"'\"'\"'");
+            } else {
+                out.push(c);
+            }
+        }
+        out.push(b'\'');
+    }
+}
+
+fn shell_safe_char(c: u8) -> bool {
+    matches!(c, b'A'..=b'Z'|b'a'..=b'z'|b'0'..=b'9'|
+                b'_'|b'%'|b'+'|b','|b'-'|b'.'|b'/'|b':'|b'='|b'@')
+}
+
+/// Quote an argument if a Windows program would not take it as a single
+/// argument as it is.
+// Backslashes are only special before a double quote, where they must be
+// doubled, as they must before the closing quote.
+#[cfg(windows)]
+pub fn quote_arg(s: &str) -> String {
+    if !s.is_empty() && !s.contains([' ', '\t', '\n', '"']) {
+        return s.to_string();
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in s.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let n = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+        quoted.extend(iter::repeat_n('\\', n));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::quote_arg;
+
+    #[test]
+    fn plain() {
+        assert_eq!(quote_arg("abc"), "abc");
+        assert_eq!(quote_arg(r"C:\dir\file.txt"), r"C:\dir\file.txt");
+        assert_eq!(quote_arg("it's"), "it's");
+    }
+
+    #[test]
+    fn spaces() {
+        assert_eq!(quote_arg(""), r#""""#);
+        assert_eq!(quote_arg("a b"), r#""a b""#);
+        assert_eq!(quote_arg(r"C:\My Files\"), r#""C:\My Files\\""#);
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(quote_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_arg(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_arg(r"a\\b c"), r#""a\\b c""#);
+    }
+}
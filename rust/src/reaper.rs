@@ -0,0 +1,473 @@
+/*
+** Waiting for child processes to terminate and collecting their output.
+*/
+
+// A single I/O thread serves all running children, rather than one or more
+// threads per child.  The standard output and standard error pipes of each
+// child are made non-blocking and polled, and whatever is read is added to
+// the output collected for the job, so that a child never blocks writing to
+// a full pipe.  On Linux each child is also watched through a pidfd, which
+// becomes readable when the child terminates, so we only ever reap our own
+// children and never steal the exit status of processes started by anyone
+// else in the same program.  Without pidfds, children are checked every
+// POLL_INTERVAL milliseconds instead.  A job is complete once its child has
+// terminated and closed both pipes.
+//
+// New children are handed to the thread through a channel, and a byte is
+// written to a wake pipe so that the thread notices.
+//
+// A child that runs past the deadline in its JobInfo is sent SIGTERM, and
+// if it has still not terminated KILL_GRACE later, SIGKILL.
+//
+// The pid of each child is also kept in a list shared with the thread
+// until the child is reaped, so that after an error the master can kill
+// and wait for any that would otherwise be left behind, even if the thread
+// is gone.  The thread reaps with the list locked, so a pid in the list
+// has not been reused.
+
+use libc;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::process::ExitStatus;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use output::{Budget, Output};
+use pattern::Pattern;
+use spawn::Process;
+use {spawn_worker, Event, Job, JobInfo};
+
+const POLL_INTERVAL: libc::c_int = 20;
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Reaper {
+    done:       Sender<Event>,
+    spill:      usize,
+    budget:     Option<Arc<Budget>>,
+    pattern:    Option<Arc<Pattern>>,
+    unreaped:   Arc<Mutex<Vec<u32>>>,
+    thread:     Option<IoThread>
+}
+
+/// Handle to the I/O thread.
+struct IoThread {
+    tx:         Sender<Request>,
+    wake:       File
+}
+
+// Requests to the I/O thread.
+enum Request {
+    Watch(Box<Watched>),
+    Terminate
+}
+
+struct Watched {
+    info:       JobInfo,
+    child:      Process,
+    pidfd:      Option<OwnedFd>,
+    status:     Option<io::Result<ExitStatus>>,
+    // When the child is next to be terminated or killed, if ever.
+    deadline:   Option<Instant>,
+    timedout:   bool,
+    stdout:     Output,
+    stderr:     Output
+}
+
+impl Reaper {
+    /// Completed jobs will be sent on `done`.  Output beyond `spill` bytes
+    /// per stream, or beyond what is left in `budget`, is spilled to
+    /// temporary files.  A line of output that matches `pattern` is also
+    /// reported on `done`.
+    pub fn new(done: Sender<Event>, spill: usize, budget: Option<Arc<Budget>>,
+               pattern: Option<Arc<Pattern>>) -> Reaper {
+
+        Reaper {
+            done,
+            spill,
+            budget,
+            pattern,
+            unreaped: Arc::new(Mutex::new(Vec::new())),
+            thread: None
+        }
+    }
+
+    /// Collect the output of `child` and wait for it to terminate in the
+    /// background.  Fails if the I/O thread cannot be started.
+    pub fn watch(&mut self, info: JobInfo, child: Process) -> io::Result<()> {
+        lock(&self.unreaped).push(child.id());
+        let watched = Watched::new(info, child, self.spill, &self.budget,
+                                   &self.pattern);
+        let thread = match self.thread {
+            Some(ref mut thread) => thread,
+            None => self.thread.insert(IoThread::start(
+                self.done.clone(), self.unreaped.clone())?)
+        };
+        thread.send(Request::Watch(Box::new(watched)));
+        Ok(())
+    }
+
+    /// Ask all children still running to terminate.  They are reaped as
+    /// usual.
+    pub fn terminate_all(&mut self) {
+        if let Some(ref mut thread) = self.thread {
+            thread.send(Request::Terminate);
+        }
+    }
+
+    /// Kill and wait for all children not yet reaped, so that none is left
+    /// as a zombie.  Their jobs may be reported as failed to wait for.
+    pub fn reap_all(&mut self) {
+        let mut unreaped = lock(&self.unreaped);
+        for &pid in unreaped.iter() {
+            let pid = pid as libc::pid_t;
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+                while libc::waitpid(pid, std::ptr::null_mut(), 0) < 0 &&
+                      io::Error::last_os_error().kind() ==
+                          io::ErrorKind::Interrupted {}
+            }
+        }
+        unreaped.clear();
+    }
+}
+
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        // The master panicked, so the children will not be waited for.
+        if thread::panicking() {
+            self.terminate_all();
+        }
+    }
+}
+
+impl IoThread {
+    fn start(done: Sender<Event>, unreaped: Arc<Mutex<Vec<u32>>>)
+        -> io::Result<IoThread> {
+
+        let (wakerd, wakewr) = wake_pipe()?;
+        let (tx, rx) = mpsc::channel();
+        spawn_worker("ljobs-reaper", done.clone(),
+                     move || io_loop(rx, wakerd, done, &unreaped))?;
+        Ok(IoThread {
+            tx,
+            wake: wakewr
+        })
+    }
+
+    fn send(&mut self, request: Request) {
+        // The thread only goes away early if it panicked, and then the
+        // master hears of it anyway.
+        let _ = self.tx.send(request);
+        // A full pipe is fine; the thread will wake anyway.
+        let _ = self.wake.write(b"x");
+    }
+}
+
+impl Drop for IoThread {
+    fn drop(&mut self) {
+        // Wake the thread so it notices the channel is disconnected.
+        let _ = self.wake.write(b"x");
+    }
+}
+
+impl Watched {
+    fn new(info: JobInfo, child: Process, spill: usize,
+           budget: &Option<Arc<Budget>>, pattern: &Option<Arc<Pattern>>)
+        -> Watched {
+
+        if let Some(ref pipe) = child.stdout {
+            set_nonblocking(pipe.as_raw_fd());
+        }
+        if let Some(ref pipe) = child.stderr {
+            set_nonblocking(pipe.as_raw_fd());
+        }
+        Watched {
+            deadline: info.deadline,
+            info,
+            pidfd:  open_pidfd(&child),
+            child,
+            status: None,
+            timedout: false,
+            stdout: Output::new(spill, budget.clone())
+                .scan(pattern.as_ref()),
+            stderr: Output::new(spill, budget.clone())
+                .scan(pattern.as_ref())
+        }
+    }
+
+    fn check_exit(&mut self, unreaped: &Mutex<Vec<u32>>) {
+        if self.status.is_none() {
+            let mut unreaped = lock(unreaped);
+            match self.child.try_wait() {
+                Ok(Some(status)) => self.status = Some(Ok(status)),
+                Ok(None) => return,
+                Err(err) => self.status = Some(Err(err))
+            }
+            let pid = self.child.id();
+            unreaped.retain(|&p| p != pid);
+        }
+    }
+
+    // Terminate or kill the child if its deadline has passed by `now`.
+    // Returns the next deadline, if any.
+    fn enforce_deadline(&mut self, now: Instant) -> Option<Instant> {
+        // The pid of a reaped child may be reused.
+        if self.status.is_some() {
+            return None;
+        }
+        let deadline = self.deadline?;
+        if now < deadline {
+            return Some(deadline);
+        }
+        if self.timedout {
+            self.child.kill();
+            self.deadline = None;
+        } else {
+            self.child.terminate();
+            self.timedout = true;
+            self.deadline = Some(now + KILL_GRACE);
+        }
+        self.deadline
+    }
+
+    fn is_done(&self) -> bool {
+        self.status.is_some() &&
+            self.child.stdout.is_none() && self.child.stderr.is_none()
+    }
+
+    fn into_job(self) -> Job {
+        Job {
+            duration:   self.info.start.elapsed(),
+            info:       self.info,
+            pid:        self.child.id(),
+            waitresult: self.status.expect("job not done"),
+            usage:      self.child.usage(),
+            timedout:   self.timedout,
+            stdout:     self.stdout,
+            stderr:     self.stderr
+        }
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[derive(Clone, Copy)]
+enum Target {
+    Exit,
+    Stdout,
+    Stderr
+}
+
+fn io_loop(rx: Receiver<Request>, wake: File, done: Sender<Event>,
+           unreaped: &Mutex<Vec<u32>>) {
+
+    let mut watched: Vec<Watched> = Vec::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        serve(&mut watched, rx, wake, &done, unreaped)
+    }));
+    if let Err(payload) = result {
+        // Nothing else will reap the children now.
+        terminate(&watched);
+        panic::resume_unwind(payload);
+    }
+}
+
+fn serve(watched: &mut Vec<Watched>, rx: Receiver<Request>, mut wake: File,
+         done: &Sender<Event>, unreaped: &Mutex<Vec<u32>>) {
+
+    let mut open = true;
+
+    while open || !watched.is_empty() {
+        // Each pollfd after the wake pipe is for the pidfd or an output pipe
+        // of a watched child.
+        let mut pollfds: Vec<libc::pollfd> =
+            Vec::with_capacity(watched.len() * 3 + 1);
+        let mut targets: Vec<(usize, Target)> =
+            Vec::with_capacity(watched.len() * 3);
+        let mut timeout = -1;
+        let now = Instant::now();
+        let next = watched.iter_mut()
+            .filter_map(|w| w.enforce_deadline(now))
+            .min();
+        if let Some(next) = next {
+            timeout = millis_until(next, now);
+        }
+        // Once disconnected the pipe would always be readable, so stop
+        // polling it (poll ignores negative fds).
+        pollfds.push(pollin(if open { wake.as_raw_fd() } else { -1 }));
+        for (i, w) in watched.iter().enumerate() {
+            if w.status.is_none() {
+                match w.pidfd {
+                    Some(ref pidfd) => {
+                        pollfds.push(pollin(pidfd.as_raw_fd()));
+                        targets.push((i, Target::Exit));
+                    },
+                    None if timeout < 0 => timeout = POLL_INTERVAL,
+                    None => timeout = timeout.min(POLL_INTERVAL)
+                }
+            }
+            if let Some(ref pipe) = w.child.stdout {
+                pollfds.push(pollin(pipe.as_raw_fd()));
+                targets.push((i, Target::Stdout));
+            }
+            if let Some(ref pipe) = w.child.stderr {
+                pollfds.push(pollin(pipe.as_raw_fd()));
+                targets.push((i, Target::Stderr));
+            }
+        }
+
+        if let Err(err) = poll(&mut pollfds, timeout) {
+            panic!("poll error: {}", err);
+        }
+
+        for (&(i, target), pollfd) in targets.iter().zip(&pollfds[1..]) {
+            if pollfd.revents == 0 {
+                continue;
+            }
+            let w = &mut watched[i];
+            match target {
+                Target::Exit => w.check_exit(unreaped),
+                Target::Stdout => w.stdout.collect(&mut w.child.stdout),
+                Target::Stderr => w.stderr.collect(&mut w.child.stderr)
+            }
+            if w.stdout.take_match() | w.stderr.take_match() {
+                let _ = done.send(Event::Matched(w.info.tasknum));
+            }
+        }
+        for w in watched.iter_mut() {
+            if w.pidfd.is_none() {
+                w.check_exit(unreaped);
+            }
+        }
+
+        let mut i = 0;
+        while i < watched.len() {
+            if watched[i].is_done() {
+                let job = watched.swap_remove(i).into_job();
+                // The receiver only goes away if the master gave up.
+                let _ = done.send(Event::Done(Box::new(job)));
+            } else {
+                i += 1;
+            }
+        }
+
+        if pollfds[0].revents != 0 {
+            let mut buf = [0u8; 64];
+            while let Ok(n) = wake.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+            loop {
+                match rx.try_recv() {
+                    Ok(Request::Watch(w)) => watched.push(*w),
+                    Ok(Request::Terminate) => terminate(watched),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        open = false;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn terminate(watched: &[Watched]) {
+    for w in watched {
+        // The pid of a reaped child may be reused.
+        if w.status.is_none() {
+            w.child.terminate();
+        }
+    }
+}
+
+// The list is only ever left whole, so a panic while it was held does not
+// matter.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The time from `now` until `when` in milliseconds, rounded up so as not to
+// wake before it.
+fn millis_until(when: Instant, now: Instant) -> libc::c_int {
+    let wait = when - now;
+    let millis = wait.as_micros().div_ceil(1000);
+    millis.min(libc::c_int::MAX as u128) as libc::c_int
+}
+
+fn pollin(fd: RawFd) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0
+    }
+}
+
+// Wait until one of the fds is ready or the timeout in milliseconds passes,
+// ignoring interruptions.
+fn poll(pollfds: &mut [libc::pollfd], timeout: libc::c_int)
+    -> io::Result<()> {
+
+    loop {
+        let n = unsafe {
+            libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t,
+                       timeout)
+        };
+        if n >= 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn wake_pipe() -> io::Result<(File, File)> {
+    let mut fds = [0 as libc::c_int; 2];
+    let flags = libc::O_CLOEXEC | libc::O_NONBLOCK;
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe {
+        Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])))
+    }
+}
+
+// Setting the flag on a valid fd cannot fail.
+fn set_nonblocking(fd: RawFd) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(target_os = "linux")]
+fn open_pidfd(child: &Process) -> Option<OwnedFd> {
+    let pid = child.id() as libc::pid_t;
+    // pidfds are always created close-on-exec.
+    let fd = unsafe {
+        libc::syscall(libc::SYS_pidfd_open, pid, 0)
+    };
+    if fd < 0 {
+        None
+    } else {
+        Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_pidfd(_child: &Process) -> Option<OwnedFd> {
+    None
+}
+
+/*---------------------------------------------------------------------------*/
@@ -0,0 +1,104 @@
+/*
+** Waiting for child processes to terminate and collecting their output,
+** on Windows.
+*/
+
+// Windows has no poll for pipes and processes, so each child is served by
+// its own thread, plus another to read its standard error.  The threads
+// read until both pipes are closed, then wait for the child to terminate.
+
+use std::io;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+
+use output::{Budget, Output};
+use pattern::Pattern;
+use spawn::Process;
+use {spawn_worker, Event, Job, JobInfo};
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Reaper {
+    done:       Sender<Event>,
+    spill:      usize,
+    budget:     Option<Arc<Budget>>,
+    pattern:    Option<Arc<Pattern>>
+}
+
+impl Reaper {
+    /// Completed jobs will be sent on `done`.  Output beyond `spill` bytes
+    /// per stream, or beyond what is left in `budget`, is spilled to
+    /// temporary files.
+    pub fn new(done: Sender<Event>, spill: usize, budget: Option<Arc<Budget>>,
+               pattern: Option<Arc<Pattern>>) -> Reaper {
+
+        Reaper {
+            done,
+            spill,
+            budget,
+            pattern
+        }
+    }
+
+    /// Collect the output of `child` and wait for it to terminate in the
+    /// background.  Fails if the thread cannot be started.
+    pub fn watch(&mut self, info: JobInfo, mut child: Process)
+        -> io::Result<()> {
+
+        let done = self.done.clone();
+        let spill = self.spill;
+        let budget = self.budget.clone();
+        let errbudget = self.budget.clone();
+        let pattern = self.pattern.clone();
+        let tasknum = info.tasknum;
+        spawn_worker("ljobs-wait", self.done.clone(), move || {
+            let mut errpipe = child.stderr.take();
+            let errdone = done.clone();
+            let mut stderr = Output::new(spill, errbudget)
+                .scan(pattern.as_ref());
+            let errthread = thread::Builder::new()
+                .name(String::from("ljobs-wait-stderr"))
+                .spawn(move || {
+                    while errpipe.is_some() {
+                        stderr.collect(&mut errpipe);
+                        if stderr.take_match() {
+                            let _ = errdone.send(Event::Matched(tasknum));
+                        }
+                    }
+                    stderr
+                })
+                .expect("could not start stderr thread");
+            let mut stdout = Output::new(spill, budget).scan(pattern.as_ref());
+            while child.stdout.is_some() {
+                stdout.collect(&mut child.stdout);
+                if stdout.take_match() {
+                    let _ = done.send(Event::Matched(tasknum));
+                }
+            }
+            let stderr = errthread.join().expect("stderr thread panicked");
+            let waitresult = child.wait();
+            let job = Job {
+                duration:   info.start.elapsed(),
+                info,
+                pid:        child.id(),
+                waitresult,
+                usage:      child.usage(),
+                timedout:   false,
+                stdout,
+                stderr
+            };
+            // The receiver only goes away if the master gave up.
+            let _ = done.send(Event::Done(Box::new(job)));
+        })?;
+        Ok(())
+    }
+
+    /// Ask all children still running to terminate.  The child processes
+    /// belong to their threads, so on Windows they are left to finish.
+    pub fn terminate_all(&mut self) {}
+
+    /// Kill and wait for all children not yet reaped.  Windows has no
+    /// zombies, and each thread waits for its own child.
+    pub fn reap_all(&mut self) {}
+}
@@ -0,0 +1,78 @@
+/*
+** Results files for the standard output of tasks.
+*/
+
+// With a results path, the standard output of each task is written to a
+// file named by substituting the task into the path, instead of being
+// printed.  Directories leading to the file are created as needed, so the
+// results can be laid out as a tree, e.g. `out/{//}/{/.}.log`.  With
+// `compress_results` the output is gzipped on its way to the file, which
+// has .gz added to its name.
+
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+#[cfg(feature = "gzip")]
+use std::io::BufWriter;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use os::from_os_bytes;
+use output::Output;
+use template::subst;
+
+/*---------------------------------------------------------------------------*/
+
+/// The results file for a task, or None if `template` has no substitution
+/// strings.
+pub fn results_path(template: &OsStr, tasknum: usize, task: &OsStr)
+    -> Option<PathBuf> {
+
+    subst(template.as_encoded_bytes(), tasknum, task.as_encoded_bytes())
+        .map(|path| PathBuf::from(from_os_bytes(path)))
+}
+
+/// Write `output` to the file at `path`, replacing any previous file.  With
+/// `gzip`, the output is compressed and .gz is added to the path.
+pub fn write(path: &Path, output: &mut Output, gzip: bool)
+    -> io::Result<()> {
+
+    let path = if gzip {
+        let mut path = OsString::from(path);
+        path.push(".gz");
+        PathBuf::from(path)
+    } else {
+        path.to_path_buf()
+    };
+    write_file(&path, output, gzip).map_err(|err| io::Error::new(err.kind(),
+        format!("could not write results file {}: {}", path.display(), err)))
+}
+
+fn write_file(path: &Path, output: &mut Output, gzip: bool)
+    -> io::Result<()> {
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = File::create(path)?;
+    if gzip {
+        return write_gzip(file, output);
+    }
+    output.for_each_chunk(|buf| file.write_all(buf))
+}
+
+#[cfg(feature = "gzip")]
+fn write_gzip(file: File, output: &mut Output) -> io::Result<()> {
+    let mut gz = GzEncoder::new(BufWriter::new(file), Compression::default());
+    output.for_each_chunk(|buf| gz.write_all(buf))?;
+    gz.finish()?.flush()
+}
+
+// The run is refused before it starts.
+#[cfg(not(feature = "gzip"))]
+fn write_gzip(_file: File, _output: &mut Output) -> io::Result<()> {
+    Err(io::Error::other("built without gzip compression"))
+}
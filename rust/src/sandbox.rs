@@ -0,0 +1,403 @@
+/*
+** Confining the files that jobs may use.
+*/
+
+// With `allow_read` or `allow_write`, each job may only use the files under
+// the paths given, enforced by the Landlock LSM of Linux 5.13 and later.
+// The paths may have substitution strings, so for each job ljobs opens the
+// paths for its task and builds a Landlock ruleset from them before
+// starting it.  The child sets no_new_privs, as Landlock requires of an
+// unprivileged process, and restricts itself to the ruleset in a pre_exec
+// hook, so that the command and everything that it runs are confined.  A
+// command that goes outside the paths gets EACCES, and fails as it would
+// for any file that it may not use.
+//
+// Only the access rights known to the version of Landlock in the kernel,
+// its ABI, are restricted.  The ABI is found once before any job is
+// started, so that where Landlock is missing the run fails rather than
+// running jobs unconfined, unless `sandbox_best_effort` asks for that.
+
+#[cfg(target_os = "linux")]
+use libc;
+use std::ffi::{OsStr, OsString};
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem::MaybeUninit;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::ptr;
+
+use os::from_os_bytes;
+use template::subst;
+use Options;
+
+// Access rights to files, from linux/landlock.h.
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+// Bits 4 to 12 are for removing and making files of each type.
+const ACCESS_REFER: u64 = 1 << 13;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_IOCTL_DEV: u64 = 1 << 15;
+
+// The rights of the first ABI.
+const ACCESS_ABI_1: u64 = (1 << 13) - 1;
+
+/// The rights given under a path allowed for reading.
+pub const READ: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+
+/// The rights given under a path allowed for writing.
+pub const WRITE: u64 = !0;
+
+// The rights that apply to files other than directories.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const ACCESS_FILE: u64 = ACCESS_EXECUTE | ACCESS_WRITE_FILE |
+    ACCESS_READ_FILE | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV;
+
+#[cfg(target_os = "linux")]
+const CREATE_RULESET_VERSION: u32 = 1;
+#[cfg(target_os = "linux")]
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd:      i32
+}
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Sandbox {
+    // The rights that rulesets restrict.
+    handled:    u64,
+    read:       Vec<OsString>,
+    write:      Vec<OsString>
+}
+
+/// The Landlock ruleset for one job.
+#[cfg(target_os = "linux")]
+pub struct Ruleset {
+    fd:         OwnedFd
+}
+
+/// There is no Landlock but on Linux.
+#[cfg(not(target_os = "linux"))]
+pub enum Ruleset {}
+
+impl Sandbox {
+    /// None if jobs are not sandboxed.  An error if they are to be but
+    /// cannot be.
+    pub fn new(opts: &Options) -> io::Result<Option<Sandbox>> {
+        if opts.allowread.is_empty() && opts.allowwrite.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Sandbox {
+            handled: handled(abi()?),
+            read:    opts.allowread.clone(),
+            write:   opts.allowwrite.clone()
+        }))
+    }
+
+    /// The paths that the job for a task may use, with the rights given
+    /// under each.
+    pub fn paths(&self, tasknum: usize, task: &OsStr) -> Vec<(OsString, u64)> {
+        let task = task.as_encoded_bytes();
+        let read = self.read.iter().map(|path| (path, READ));
+        let write = self.write.iter().map(|path| (path, WRITE));
+        read.chain(write)
+            .map(|(path, access)| {
+                let path = match subst(path.as_encoded_bytes(), tasknum, task)
+                {
+                    Some(path) => from_os_bytes(path),
+                    None => path.clone()
+                };
+                (path, access)
+            })
+            .collect()
+    }
+
+    /// Build the ruleset for the job for a task.
+    #[cfg(target_os = "linux")]
+    pub fn ruleset(&self, tasknum: usize, task: &OsStr)
+        -> io::Result<Ruleset> {
+
+        let attr = RulesetAttr { handled_access_fs: self.handled };
+        let fd = check(unsafe {
+            libc::syscall(libc::SYS_landlock_create_ruleset,
+                          &attr as *const RulesetAttr,
+                          std::mem::size_of::<RulesetAttr>(), 0u32)
+        })?;
+        let ruleset = Ruleset { fd: unsafe { OwnedFd::from_raw_fd(fd) } };
+        for (path, access) in self.paths(tasknum, task) {
+            ruleset.allow(&path, access & self.handled).map_err(|err| {
+                io::Error::new(err.kind(), format!(
+                    "cannot allow {}: {}", path.to_string_lossy(), err))
+            })?;
+        }
+        Ok(ruleset)
+    }
+
+    /// Build the ruleset for the job for a task.
+    #[cfg(not(target_os = "linux"))]
+    pub fn ruleset(&self, _tasknum: usize, _task: &OsStr)
+        -> io::Result<Ruleset> {
+
+        Err(io::Error::new(io::ErrorKind::Unsupported,
+                           "Landlock is only on Linux"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Ruleset {
+    // Give `access` under `path`, or what of it applies to a file that is
+    // not a directory.
+    fn allow(&self, path: &OsStr, access: u64) -> io::Result<()> {
+        let mut cpath = path.as_bytes().to_vec();
+        cpath.push(0);
+        let fd = check(unsafe {
+            libc::open(cpath.as_ptr() as *const libc::c_char,
+                       libc::O_PATH | libc::O_CLOEXEC) as libc::c_long
+        })?;
+        let parent = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut stat = MaybeUninit::<libc::stat>::uninit();
+        check(unsafe {
+            libc::fstat(parent.as_raw_fd(), stat.as_mut_ptr()) as libc::c_long
+        })?;
+        let mode = unsafe { stat.assume_init() }.st_mode;
+        let access = if mode & libc::S_IFMT == libc::S_IFDIR {
+            access
+        } else {
+            access & ACCESS_FILE
+        };
+        let rule = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd:      parent.as_raw_fd()
+        };
+        check(unsafe {
+            libc::syscall(libc::SYS_landlock_add_rule, self.fd.as_raw_fd(),
+                          RULE_PATH_BENEATH,
+                          &rule as *const PathBeneathAttr, 0u32)
+        })?;
+        Ok(())
+    }
+
+    /// The file descriptor to pass to `enforce`.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Restrict the calling process to the ruleset `fd`.  Only
+/// async-signal-safe calls are made, so that this may be called between
+/// fork and exec.
+#[cfg(target_os = "linux")]
+pub fn enforce(fd: RawFd) -> io::Result<()> {
+    check(unsafe {
+        libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as libc::c_long
+    })?;
+    check(unsafe {
+        libc::syscall(libc::SYS_landlock_restrict_self, fd, 0u32)
+    })?;
+    Ok(())
+}
+
+/// The version of Landlock in the kernel, or why it cannot be used.
+#[cfg(target_os = "linux")]
+pub fn abi() -> io::Result<i32> {
+    let version = unsafe {
+        libc::syscall(libc::SYS_landlock_create_ruleset,
+                      ptr::null::<RulesetAttr>(), 0usize,
+                      CREATE_RULESET_VERSION)
+    };
+    if version < 0 {
+        let err = io::Error::last_os_error();
+        let errno = err.raw_os_error().unwrap_or(0);
+        return Err(unsupported(errno).unwrap_or(err));
+    }
+    Ok(version as i32)
+}
+
+/// The version of Landlock in the kernel, or why it cannot be used.
+#[cfg(not(target_os = "linux"))]
+pub fn abi() -> io::Result<i32> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "Landlock is only on Linux"))
+}
+
+// Explain the errors that mean the kernel has no usable Landlock.
+#[cfg(target_os = "linux")]
+fn unsupported(errno: i32) -> Option<io::Error> {
+    let why = match errno {
+        libc::ENOSYS =>
+            "the kernel is too old for Landlock, which needs Linux 5.13",
+        libc::EOPNOTSUPP =>
+            "Landlock is not enabled in the kernel; see the lsm= boot option",
+        _ => return None
+    };
+    Some(io::Error::new(io::ErrorKind::Unsupported, why))
+}
+
+// The rights that the ABI `version` knows of.
+fn handled(version: i32) -> u64 {
+    let mut access = ACCESS_ABI_1;
+    if version >= 2 {
+        access |= ACCESS_REFER;
+    }
+    if version >= 3 {
+        access |= ACCESS_TRUNCATE;
+    }
+    if version >= 5 {
+        access |= ACCESS_IOCTL_DEV;
+    }
+    access
+}
+
+#[cfg(target_os = "linux")]
+fn check(ret: libc::c_long) -> io::Result<RawFd> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as RawFd)
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{handled, Sandbox, READ, WRITE};
+    #[cfg(target_os = "linux")]
+    use super::{abi, enforce, unsupported};
+    #[cfg(target_os = "linux")]
+    use libc;
+    use std::ffi::{OsStr, OsString};
+    #[cfg(target_os = "linux")]
+    use std::io;
+    #[cfg(target_os = "linux")]
+    use std::os::unix::ffi::OsStrExt;
+    use Options;
+
+    fn sandbox(read: &[&str], write: &[&str]) -> Sandbox {
+        let strings = |paths: &[&str]| -> Vec<OsString> {
+            paths.iter().map(OsString::from).collect()
+        };
+        Sandbox {
+            handled: handled(1),
+            read:    strings(read),
+            write:   strings(write)
+        }
+    }
+
+    #[test]
+    fn options() {
+        let opts = Options::new().command("true");
+        assert!(Sandbox::new(&opts).unwrap().is_none());
+        let opts = opts.allow_read("/usr").allow_write("{//}");
+        assert_eq!(opts.allowread, ["/usr"]);
+        assert_eq!(opts.allowwrite, ["{//}"]);
+    }
+
+    #[test]
+    fn paths() {
+        let sandbox = sandbox(&["/usr", "{//}"], &["out/{/.}", "/tmp"]);
+        assert_eq!(sandbox.paths(3, OsStr::new("in/a.txt")), [
+            (OsString::from("/usr"), READ),
+            (OsString::from("in"), READ),
+            (OsString::from("out/a"), WRITE),
+            (OsString::from("/tmp"), WRITE)
+        ]);
+        assert_eq!(sandbox.paths(0, OsStr::new("b"))[1].0, ".");
+    }
+
+    #[test]
+    fn abi_rights() {
+        assert_eq!(handled(1), 0x1fff);
+        assert_eq!(handled(2), 0x3fff);
+        assert_eq!(handled(4), 0x7fff);
+        assert_eq!(handled(7), 0xffff);
+        assert_eq!(READ & !handled(1), 0);
+        assert_eq!(WRITE & handled(7), handled(7));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detect() {
+        assert!(unsupported(libc::ENOSYS).unwrap().to_string()
+            .contains("too old"));
+        assert!(unsupported(libc::EOPNOTSUPP).unwrap().to_string()
+            .contains("not enabled"));
+        assert!(unsupported(libc::EINVAL).is_none());
+        match abi() {
+            Ok(version) => assert!(version >= 1),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::Unsupported)
+        }
+    }
+
+    // Only where the kernel has Landlock.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn enforced() {
+        let version = match abi() {
+            Ok(version) => version,
+            Err(_) => return
+        };
+        let dir = std::env::temp_dir()
+            .join(format!("ljobs-sandbox-{}", std::process::id()));
+        let (inside, outside) = (dir.join("in"), dir.join("out"));
+        std::fs::create_dir_all(&inside).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(inside.join("f"), "").unwrap();
+        std::fs::write(outside.join("f"), "").unwrap();
+
+        let sandbox = Sandbox {
+            handled: handled(version),
+            read:    vec![inside.clone().into_os_string()],
+            write:   Vec::new()
+        };
+        let ruleset = sandbox.ruleset(0, OsStr::new("")).unwrap();
+        let path = |p: std::path::PathBuf| {
+            let mut bytes = p.as_os_str().as_bytes().to_vec();
+            bytes.push(0);
+            bytes
+        };
+        let (ok, denied) = (path(inside.join("f")), path(outside.join("f")));
+
+        // In a child, as the restriction cannot be lifted.
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            let open = |p: &[u8]| unsafe {
+                libc::open(p.as_ptr() as *const libc::c_char, libc::O_RDONLY)
+            };
+            let code = if enforce(ruleset.fd()).is_err() {
+                1
+            } else if open(&ok) < 0 {
+                2
+            } else if open(&denied) >= 0 ||
+                io::Error::last_os_error().raw_os_error() !=
+                    Some(libc::EACCES)
+            {
+                3
+            } else {
+                0
+            };
+            unsafe { libc::_exit(code) };
+        }
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+    }
+}
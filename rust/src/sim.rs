@@ -0,0 +1,464 @@
+/*
+** A simulated system for testing the master.
+*/
+
+// Commands are not run but take a scripted time and then exit with a
+// scripted status, and time only passes when the master waits, jumping
+// straight to the next job to finish or the deadline.  This makes tests of
+// scheduling both fast and deterministic.
+//
+// The command for a task is written as the task itself: "SECS:EXIT" runs
+// for SECS seconds and exits with EXIT, and "SECS:EXIT:TEXT" also writes
+// TEXT to its standard output.  A prefix "busyN:" makes the first N
+// attempts to start the command fail for want of resources, and a prefix
+// "downT:" makes it exit with 1 instead if it starts before T seconds into
+// the run, as if waiting for a service that is not up yet.
+
+use libc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvError};
+use std::time::{Duration, Instant};
+
+use input::{RecordFormat, TaskQueue, READAHEAD};
+use output::{Output, DEFAULT_SPILL};
+use sandbox::Ruleset;
+use system::{Clock, Spawner};
+use {schedule, Error, Event, Job, JobInfo, Options, RunState, RunSummary,
+     System, TaskSource};
+
+/*---------------------------------------------------------------------------*/
+
+#[derive(Clone)]
+pub struct Sim {
+    world:      Rc<RefCell<World>>
+}
+
+struct World {
+    origin:     Instant,
+    now:        Instant,
+    running:    Vec<Running>,
+    attempts:   HashMap<OsString, u32>,
+    started:    Vec<(usize, Duration)>
+}
+
+struct Running {
+    until:      Instant,
+    info:       JobInfo,
+    status:     ExitStatus,
+    timedout:   bool,
+    stdout:     String
+}
+
+pub struct Script {
+    busy:       u32,
+    down:       Duration,
+    time:       Duration,
+    exit:       i32,
+    stdout:     String
+}
+
+/// What happened in a simulated run.
+pub struct Trace {
+    pub result:     Result<RunSummary, Error>,
+    /// The task number of each command started, and when.
+    pub started:    Vec<(usize, Duration)>,
+    /// When the run ended.
+    pub end:        Duration
+}
+
+/// Run the tasks as the master would, in a simulated system.
+pub fn run(opts: &Options, tasks: &[&str]) -> Trace {
+    let tasks: Vec<OsString> = tasks.iter().map(OsString::from).collect();
+    let sim = Sim::new();
+    // Kept so that the channel stays connected.
+    let (tx, rx) = mpsc::channel();
+    let result = RunState::new(opts, &mut |_| ()).and_then(|state| {
+        let format = RecordFormat {
+            delim:  b'\n',
+            limit:  None,
+            blocks: None
+        };
+        let queue = TaskQueue::new(TaskSource::Slice(&tasks), false, 0, format,
+                                   READAHEAD, None, &tx);
+        let sys = System {
+            spawner: sim.clone(),
+            clock:   sim.clone(),
+            events:  rx
+        };
+        schedule(opts, state, queue, sys)
+    });
+    let world = sim.world.borrow();
+    Trace {
+        result,
+        started: world.started.clone(),
+        end:     world.now - world.origin
+    }
+}
+
+impl Sim {
+    fn new() -> Sim {
+        let origin = Instant::now();
+        Sim {
+            world: Rc::new(RefCell::new(World {
+                origin,
+                now:      origin,
+                running:  Vec::new(),
+                attempts: HashMap::new(),
+                started:  Vec::new()
+            }))
+        }
+    }
+}
+
+impl Script {
+    fn parse(task: &str) -> Script {
+        let (busy, rest) = match task.strip_prefix("busy") {
+            Some(rest) => {
+                let (n, rest) = rest.split_once(':').expect("busy count");
+                (n.parse().expect("busy count"), rest)
+            },
+            None => (0, task)
+        };
+        let (down, rest) = match rest.strip_prefix("down") {
+            Some(rest) => {
+                let (t, rest) = rest.split_once(':').expect("down time");
+                (t.parse().expect("down time"), rest)
+            },
+            None => (0.0, rest)
+        };
+        let (time, rest) = rest.split_once(':').expect("SECS:EXIT");
+        let (exit, stdout) = rest.split_once(':').unwrap_or((rest, ""));
+        Script {
+            busy,
+            down:   Duration::from_secs_f64(down),
+            time:   Duration::from_secs_f64(time.parse().expect("SECS")),
+            exit:   exit.parse().expect("EXIT"),
+            stdout: String::from(stdout)
+        }
+    }
+}
+
+impl Spawner for Sim {
+    type Child = Script;
+
+    fn spawn(&mut self, _opts: &Options, argv: &[OsString],
+             _env: &[(OsString, OsString)],
+             _cgroup: Option<&File>, _sandbox: Option<&Ruleset>,
+             _input: Option<&[u8]>)
+        -> io::Result<Script> {
+
+        let task = argv.last().expect("task");
+        let script = Script::parse(task.to_str().expect("UTF-8 task"));
+        let mut world = self.world.borrow_mut();
+        let attempts = world.attempts.entry(task.clone()).or_insert(0);
+        *attempts += 1;
+        if *attempts <= script.busy {
+            return Err(io::Error::from_raw_os_error(libc::EAGAIN));
+        }
+        Ok(script)
+    }
+
+    fn pid(&self, _script: &Script) -> u32 {
+        0
+    }
+
+    fn watch(&mut self, info: JobInfo, script: Script) -> io::Result<()> {
+        let mut world = self.world.borrow_mut();
+        let since = world.now - world.origin;
+        world.started.push((info.tasknum, since));
+        let exit = if since < script.down { 1 } else { script.exit };
+        let until = info.start + script.time;
+        // A command that runs past its deadline is terminated then.
+        let running = match info.deadline {
+            Some(deadline) if deadline < until => Running {
+                until:  deadline,
+                info,
+                status: ExitStatus::from_raw(libc::SIGTERM),
+                timedout: true,
+                stdout: script.stdout
+            },
+            _ => Running {
+                until,
+                info,
+                status: ExitStatus::from_raw(exit << 8),
+                timedout: false,
+                stdout: script.stdout
+            }
+        };
+        world.running.push(running);
+        Ok(())
+    }
+
+    fn terminate_all(&mut self) {
+        let mut world = self.world.borrow_mut();
+        let now = world.now;
+        for r in &mut world.running {
+            r.until = now;
+            r.status = ExitStatus::from_raw(libc::SIGTERM);
+        }
+    }
+
+    // Killed and waited for, so never reported.
+    fn reap_all(&mut self) {
+        self.world.borrow_mut().running.clear();
+    }
+
+    // Guards take no time and always pass.
+    fn check(&mut self, _argv: &[OsString]) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+impl Clock for Sim {
+    fn now(&self) -> Instant {
+        self.world.borrow().now
+    }
+
+    fn wait_until(&mut self, events: &Receiver<Event>,
+                  deadline: Option<Instant>)
+        -> Result<Option<Event>, RecvError> {
+
+        if let Ok(event) = events.try_recv() {
+            return Ok(Some(event));
+        }
+        let mut world = self.world.borrow_mut();
+        // The job started first wins a tie.
+        let next = (0..world.running.len())
+            .min_by_key(|&i| world.running[i].until)
+            .filter(|&i| {
+                deadline.is_none_or(|when| world.running[i].until <= when)
+            });
+        match (next, deadline) {
+            (Some(i), _) => {
+                let r = world.running.remove(i);
+                world.now = world.now.max(r.until);
+                let mut stdout = Output::new(DEFAULT_SPILL, None);
+                stdout.collect(&mut Some(r.stdout.as_bytes()));
+                Ok(Some(Event::Done(Box::new(Job {
+                    duration:   r.until - r.info.start,
+                    info:       r.info,
+                    pid:        0,
+                    waitresult: Ok(r.status),
+                    usage:      None,
+                    timedout:   r.timedout,
+                    stdout,
+                    stderr:     Output::new(0, None)
+                }))))
+            },
+            (None, Some(when)) => {
+                world.now = world.now.max(when);
+                Ok(None)
+            },
+            // Nothing more can ever happen.
+            (None, None) => Err(RecvError)
+        }
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use json;
+    use std::time::Duration;
+    use {Options, TimeoutAction};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn tasknums(trace: &super::Trace) -> Vec<usize> {
+        let summary = trace.result.as_ref().unwrap();
+        summary.tasks.iter().map(|t| t.tasknum).collect()
+    }
+
+    #[test]
+    fn stop_after_failure() {
+        // Task 1 fails at 2s, so task 3 is never started, but task 2 which
+        // was started at 1s is waited for.
+        let opts = Options::new().command("job").jobs(2);
+        let trace = run(&opts, &["1:0", "2:3", "5:0", "1:0"]);
+        assert_eq!(trace.started,
+                   vec![(0, ms(0)), (1, ms(0)), (2, ms(1000))]);
+        assert_eq!(tasknums(&trace), vec![0, 1, 2]);
+        assert_eq!(trace.end, ms(6000));
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 3);
+    }
+
+    #[test]
+    fn keep_going() {
+        let opts = Options::new().command("job").jobs(2).keep_going(true);
+        let trace = run(&opts, &["1:0", "2:3", "5:0", "1:4"]);
+        assert_eq!(trace.started, vec![(0, ms(0)), (1, ms(0)),
+                                       (2, ms(1000)), (3, ms(2000))]);
+        assert_eq!(tasknums(&trace), vec![0, 1, 3, 2]);
+        let summary = trace.result.unwrap();
+        assert_eq!(summary.errors, 2);
+        assert_eq!(summary.exit_code(&opts), 2);
+        let opts = opts.keep_going_exit(Some(9));
+        assert_eq!(summary.exit_code(&opts), 9);
+    }
+
+    #[test]
+    fn halt_timeout() {
+        // Task 0 fails at 1s, and task 1 is given up on 2s later.
+        let opts = Options::new().command("job").jobs(2)
+            .halt_timeout(Some(ms(2000)));
+        let trace = run(&opts, &["1:1", "10:0", "1:0"]);
+        assert_eq!(tasknums(&trace), vec![0]);
+        assert_eq!(trace.end, ms(3000));
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 1);
+    }
+
+    #[test]
+    fn timeout_action() {
+        // Task 1 is terminated at 2s, and counts as the action says.
+        let opts = Options::new().command("job").jobs(2).timeout(ms(2000));
+        let tasks = ["1:0", "10:0"];
+        let trace = run(&opts, &tasks);
+        assert_eq!(trace.end, ms(2000));
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 124);
+
+        let opts = opts.timeout_action(TimeoutAction::Skip);
+        let trace = run(&opts, &tasks);
+        assert_eq!(trace.end, ms(2000));
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 0);
+
+        // Retries back off as for a failure to start.
+        let opts = opts.timeout_action(TimeoutAction::Retry);
+        let trace = run(&opts, &tasks);
+        assert_eq!(trace.started, vec![(0, ms(0)), (1, ms(0)),
+                                       (1, ms(2050)), (1, ms(4150)),
+                                       (1, ms(6350)), (1, ms(8750)),
+                                       (1, ms(11550))]);
+        assert_eq!(trace.end, ms(13550));
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 124);
+    }
+
+    #[test]
+    fn ramp() {
+        let opts = Options::new().command("job").jobs(3).ramp(ms(4000));
+        let trace = run(&opts, &["10:0", "10:0", "10:0", "1:0"]);
+        assert_eq!(trace.started, vec![(0, ms(0)), (1, ms(2000)),
+                                       (2, ms(4000)), (3, ms(10000))]);
+    }
+
+    #[test]
+    fn spawn_retry() {
+        // Retries back off from 50ms, and hold up the following tasks.
+        let opts = Options::new().command("job").jobs(2);
+        let trace = run(&opts, &["busy2:1:0", "1:0"]);
+        assert_eq!(trace.started, vec![(0, ms(150)), (1, ms(150))]);
+        assert_eq!(trace.end, ms(1150));
+    }
+
+    #[test]
+    fn halt_on_command_failure_only() {
+        // Task 0 cannot be started, which only stops the run without
+        // the option.  Task 2 fails itself, which stops it either way.
+        let tasks = ["busy9:1:0", "1:0", "1:3", "1:0"];
+        let opts = Options::new().command("job").jobs(1);
+        let trace = run(&opts, &tasks);
+        assert_eq!(tasknums(&trace), vec![0]);
+        let summary = trace.result.unwrap();
+        assert_eq!((summary.errors, summary.command_errors), (1, 0));
+
+        let opts = opts.halt_on_command_failure_only(true);
+        let trace = run(&opts, &tasks);
+        assert_eq!(tasknums(&trace), vec![0, 1, 2]);
+        let summary = trace.result.unwrap();
+        assert_eq!((summary.errors, summary.command_errors), (2, 1));
+        assert_eq!(summary.failed_exit, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn retry_after() {
+        // Task 0 asks to be tried again 3s after it fails, holding up the
+        // rest, until it has been retried a few times.  Task 2 fails
+        // without asking.
+        let opts = Options::new().command("job").jobs(1).keep_going(true)
+            .retry_after_pattern(r"^Retry-After: (\d+)");
+        let trace = run(&opts, &["1:1:Retry-After: 3", "1:0", "1:2:wait"]);
+        assert_eq!(trace.started, vec![(0, ms(0)), (0, ms(4000)),
+                                       (0, ms(8000)), (0, ms(12000)),
+                                       (0, ms(16000)), (0, ms(20000)),
+                                       (1, ms(21000)), (2, ms(22000))]);
+        assert_eq!(tasknums(&trace), vec![0, 1, 2]);
+        assert_eq!(trace.result.unwrap().errors, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn retry_after_last_running() {
+        // Task 0 asks to be tried again while it is the only one left
+        // running, after all tasks have started.
+        let opts = Options::new().command("job").jobs(2)
+            .retry_after_pattern(r"^Retry-After: (\d+)");
+        let trace = run(&opts, &["1:1:Retry-After: 2", "0.5:0"]);
+        assert_eq!(&trace.started[..3], &[(0, ms(0)), (1, ms(0)),
+                                          (0, ms(3000))]);
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 1);
+    }
+
+    #[test]
+    fn retry_until() {
+        // Task 0 fails until 2s into the run and is tried again until it
+        // succeeds.  Task 1 never succeeds and is given up on after 3s.
+        let opts = Options::new().command("job").jobs(2).keep_going(true)
+            .retry_until(Duration::from_secs(3));
+        let trace = run(&opts, &["down2:0.5:0", "0.5:1"]);
+        let times = |tasknum| trace.started.iter()
+            .filter(|&&(n, _)| n == tasknum)
+            .map(|&(_, time)| time)
+            .collect::<Vec<_>>();
+        assert_eq!(times(0), vec![ms(0), ms(550), ms(1150), ms(1850),
+                                  ms(2750)]);
+        assert_eq!(times(1).last(), Some(&ms(2750)));
+        assert_eq!(tasknums(&trace), vec![0, 1]);
+        assert_eq!(trace.end, ms(3250));
+        assert_eq!(trace.result.unwrap().errors, 1);
+    }
+
+    #[test]
+    fn retry_until_last_running() {
+        // Task 0 fails while it is the only one left running, after all
+        // tasks have started, and is still tried again.
+        let opts = Options::new().command("job").jobs(2)
+            .retry_until(Duration::from_secs(5));
+        let trace = run(&opts, &["down2:1:0", "0.5:0"]);
+        assert_eq!(trace.started, vec![(0, ms(0)), (1, ms(0)),
+                                       (0, ms(1050)), (0, ms(2150))]);
+        assert_eq!(trace.end, ms(3150));
+        assert_eq!(trace.result.unwrap().exit_code(&opts), 0);
+    }
+
+    #[test]
+    fn summary_json() {
+        let opts = Options::new().command("job").jobs(2).keep_going(true);
+        let trace = run(&opts, &["1:0", "3:2", "1:0", "2:0"]);
+        let summary = trace.result.unwrap();
+        assert_eq!(summary.elapsed, ms(4000));
+        let doc = json::parse(&summary.to_json()).unwrap();
+        let field = |path: &str| {
+            let mut text = Vec::new();
+            doc.lookup(path.as_bytes()).unwrap().write_text(&mut text);
+            String::from_utf8(text).unwrap()
+        };
+        assert_eq!(field("total"), "4");
+        assert_eq!(field("ok"), "3");
+        assert_eq!(field("failed"), "1");
+        assert_eq!(field("signaled"), "0");
+        assert_eq!(field("aborted"), "false");
+        assert_eq!(field("wall_time"), "4.000");
+        assert_eq!(field("throughput"), "1.000");
+    }
+}
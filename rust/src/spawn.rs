@@ -0,0 +1,778 @@
+/*
+** Starting child processes.
+*/
+
+// Commands are started with posix_spawn where possible.  Unlike fork, which
+// copies the page tables of the parent, posix_spawn can use vfork or clone
+// internally, so it stays cheap however large ljobs grows.  When the child
+// needs setting up with pre_exec hooks, which posix_spawn cannot run, the
+// command is started through the standard library's Command instead.
+//
+// A child to be put in a cgroup (see cgroup.rs) joins it in a pre_exec
+// hook, by writing "0" to the cgroup.procs file opened for it.  With
+// `ionice`, the child sets its I/O priority in another (see ionice.rs), and
+// with `oom_score_adj`, its badness to the OOM killer in a third.  With
+// `private_tmp` or `isolate_network`, it enters namespaces of its own (see
+// namespace.rs) in a fourth, and with a sandbox it confines itself to the
+// files allowed (see sandbox.rs) in the last.
+//
+// Either way the child has standard input from /dev/null, standard output
+// and standard error connected to pipes, the environment of ljobs with any
+// variables given for the job, such as LJOBS_LABEL, and the default
+// disposition of SIGPIPE and an empty signal mask, as Command
+// would give it.  With `stdin_column`, standard input is a pipe instead,
+// which a thread of its own fills and then closes, so that a child that is
+// slow to read its input, or never does, holds nothing else up.
+//
+// On Unix children are reaped with wait4, however they were started, for
+// the resources that they used.
+//
+// There is no posix_spawn on Windows, so Command is always used there.
+
+#[cfg(unix)]
+use libc;
+#[cfg(unix)]
+use std::ffi::CString;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, PipeReader, Write};
+#[cfg(unix)]
+use std::mem::MaybeUninit;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+#[cfg(windows)]
+use std::os::windows::io::OwnedHandle;
+#[cfg(windows)]
+use std::process::Child;
+use std::process::{Command, ExitStatus, Stdio};
+#[cfg(unix)]
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use ionice;
+#[cfg(target_os = "linux")]
+use namespace::Namespaces;
+#[cfg(target_os = "linux")]
+use sandbox;
+use sandbox::Ruleset;
+use Options;
+
+/*---------------------------------------------------------------------------*/
+
+/// A running child process.
+pub struct Process {
+    pid:        u32,
+    #[cfg(windows)]
+    child:      Child,
+    #[cfg(unix)]
+    status:     Option<ExitStatus>,
+    #[cfg(unix)]
+    usage:      Option<ResourceUsage>,
+    pub stdout: Option<File>,
+    pub stderr: Option<File>
+}
+
+/// Resources that a command used, counting its children that it waited
+/// for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// User and system CPU time together.
+    pub cpu:        Duration,
+    /// The largest resident set size, in bytes.
+    pub maxrss:     u64
+}
+
+impl Process {
+    pub fn id(&self) -> u32 {
+        self.pid
+    }
+
+    /// Reap the child if it has terminated, without blocking.
+    #[cfg(unix)]
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.status.is_none() {
+            let mut status = 0;
+            let mut rusage = MaybeUninit::<libc::rusage>::zeroed();
+            let pid = unsafe {
+                libc::wait4(self.pid as libc::pid_t, &mut status,
+                            libc::WNOHANG, rusage.as_mut_ptr())
+            };
+            if pid < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if pid > 0 {
+                self.status = Some(ExitStatus::from_raw(status));
+                self.usage = Some(resource_usage(unsafe {
+                    &rusage.assume_init()
+                }));
+            }
+        }
+        Ok(self.status)
+    }
+
+    /// The resources that the child used, once it has been reaped.
+    #[cfg(unix)]
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        self.usage
+    }
+
+    /// The resources that the child used.  Windows does not say.
+    #[cfg(windows)]
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        None
+    }
+
+    /// Ask the child to terminate.  It must not have been reaped yet, or the
+    /// process ID may belong to another process.
+    #[cfg(unix)]
+    pub fn terminate(&self) {
+        unsafe {
+            libc::kill(self.pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+
+    /// Kill the child.  It must not have been reaped yet.
+    #[cfg(unix)]
+    pub fn kill(&self) {
+        unsafe {
+            libc::kill(self.pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+
+    /// Wait for the child to terminate.
+    #[cfg(windows)]
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.child.wait()
+    }
+}
+
+/// Start the command `argv`, with the variables `env` added to its
+/// environment, in the cgroup whose cgroup.procs file is `cgroup` if there
+/// is one, confined to `sandbox` if there is one, with `input` on its
+/// standard input if there is some.
+#[cfg(unix)]
+pub fn spawn(opts: &Options, argv: &[OsString], env: &[(OsString, OsString)],
+             cgroup: Option<&File>, sandbox: Option<&Ruleset>,
+             input: Option<&[u8]>)
+    -> io::Result<Process> {
+
+    let stdin = input.map(input_pipe).transpose()?;
+    if cgroup.is_none() && sandbox.is_none() && !needs_pre_exec(opts) {
+        return posix_spawn(argv, env, stdin);
+    }
+    let mut command = command(argv, env, stdin);
+    if let Some(procs) = cgroup {
+        join_cgroup(&mut command, procs);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(priority) = opts.ionice {
+        set_io_priority(&mut command, priority.encode());
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(adj) = opts.oomscoreadj {
+        set_oom_score_adj(&mut command, adj);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(namespaces) = Namespaces::new(opts) {
+        enter_namespaces(&mut command, namespaces);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(ruleset) = sandbox {
+        enter_sandbox(&mut command, ruleset);
+    }
+    start(&mut command)
+}
+
+/// Start the command `argv`, with the variables `env` added to its
+/// environment, with `input` on its standard input if there is some.  There
+/// are no cgroups or sandboxes on Windows.
+#[cfg(windows)]
+pub fn spawn(_opts: &Options, argv: &[OsString], env: &[(OsString, OsString)],
+             _cgroup: Option<&File>, _sandbox: Option<&Ruleset>,
+             input: Option<&[u8]>)
+    -> io::Result<Process> {
+
+    let stdin = input.map(input_pipe).transpose()?;
+    spawn_command(argv, env, stdin)
+}
+
+/// Run the command `argv` to completion, with its standard output thrown
+/// away, and return whether it succeeded.
+pub fn succeeds(argv: &[OsString]) -> io::Result<bool> {
+    let status = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()?;
+    Ok(status.success())
+}
+
+/// Whether starting a command failed for want of resources, so that it may
+/// work if tried again later.
+#[cfg(unix)]
+pub fn transient(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(),
+             Some(libc::EAGAIN) | Some(libc::EMFILE) | Some(libc::ENFILE))
+}
+
+/// Whether starting a command failed for want of resources, so that it may
+/// work if tried again later.
+#[cfg(windows)]
+pub fn transient(err: &io::Error) -> bool {
+    // ERROR_TOO_MANY_OPEN_FILES, ERROR_NOT_ENOUGH_MEMORY
+    matches!(err.raw_os_error(), Some(4) | Some(8))
+}
+
+/// The exit status that a shell gives a command that it could not start,
+/// if starting it failed for the reason `err`: 127 if the command was not
+/// found, or 126 if it could not be executed.
+pub fn exit_status(err: &io::Error) -> Option<i32> {
+    match err.kind() {
+        io::ErrorKind::NotFound => Some(127),
+        io::ErrorKind::PermissionDenied => Some(126),
+        _ if not_executable(err) => Some(126),
+        _ => None
+    }
+}
+
+#[cfg(unix)]
+fn not_executable(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENOEXEC)
+}
+
+#[cfg(windows)]
+fn not_executable(err: &io::Error) -> bool {
+    // ERROR_BAD_EXE_FORMAT
+    err.raw_os_error() == Some(193)
+}
+
+/// The most files the process may have open at once, if there is a limit.
+#[cfg(unix)]
+pub fn open_file_limit() -> Option<usize> {
+    let mut rlim = MaybeUninit::<libc::rlimit>::uninit();
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, rlim.as_mut_ptr()) } < 0 {
+        return None;
+    }
+    let rlim = unsafe { rlim.assume_init() };
+    if rlim.rlim_cur == libc::RLIM_INFINITY {
+        None
+    } else {
+        Some(rlim.rlim_cur as usize)
+    }
+}
+
+/// The most files the process may have open at once, if there is a limit.
+/// Windows has no limit worth speaking of.
+#[cfg(windows)]
+pub fn open_file_limit() -> Option<usize> {
+    None
+}
+
+// Whether the child must be set up in a way that posix_spawn cannot do.
+#[cfg(target_os = "linux")]
+fn needs_pre_exec(opts: &Options) -> bool {
+    opts.ionice.is_some() || opts.oomscoreadj.is_some() ||
+        opts.privatetmp || opts.isolatenet
+}
+
+// Elsewhere the ionice utility sets the I/O priority, if anything.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn needs_pre_exec(_opts: &Options) -> bool {
+    false
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(any(windows, test))]
+fn spawn_command(argv: &[OsString], env: &[(OsString, OsString)],
+                 stdin: Option<PipeReader>)
+    -> io::Result<Process> {
+
+    start(&mut command(argv, env, stdin))
+}
+
+// The end of a pipe for the standard input of a child, with `input` written
+// to the other end in the background.  The writer gives up if the child
+// exits without reading it all.
+fn input_pipe(input: &[u8]) -> io::Result<PipeReader> {
+    let (reader, mut writer) = io::pipe()?;
+    let input = input.to_vec();
+    thread::Builder::new()
+        .name(String::from("stdin"))
+        .spawn(move || {
+            let _ = writer.write_all(&input);
+        })?;
+    Ok(reader)
+}
+
+// Only async-signal-safe calls may be made in pre_exec hooks, between fork
+// and exec.
+#[cfg(unix)]
+fn join_cgroup(command: &mut Command, procs: &File) {
+    let fd = procs.as_raw_fd();
+    unsafe {
+        command.pre_exec(move || {
+            if libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// Without the system call, the command is run through the ionice utility
+// instead (see template.rs).
+#[cfg(target_os = "linux")]
+fn set_io_priority(command: &mut Command, ioprio: i32) {
+    unsafe {
+        command.pre_exec(move || {
+            match ionice::set(ioprio) {
+                Err(ref err) if err.raw_os_error() == Some(libc::ENOSYS) =>
+                    Ok(()),
+                result => result
+            }
+        });
+    }
+}
+
+// Lowering the value may need privileges.  A child that may not set it
+// says so on its standard error, and runs the command anyway.
+#[cfg(target_os = "linux")]
+fn set_oom_score_adj(command: &mut Command, adj: i32) {
+    let value = adj.to_string();
+    let warning = format!("ljobs: cannot set oom_score_adj to {}\n", adj);
+    unsafe {
+        command.pre_exec(move || {
+            let fd = libc::open(
+                b"/proc/self/oom_score_adj\0".as_ptr() as *const libc::c_char,
+                libc::O_WRONLY | libc::O_CLOEXEC);
+            let written = if fd >= 0 {
+                let n = libc::write(fd, value.as_ptr() as *const libc::c_void,
+                                    value.len());
+                libc::close(fd);
+                n
+            } else {
+                -1
+            };
+            if written != value.len() as isize {
+                libc::write(2, warning.as_ptr() as *const libc::c_void,
+                            warning.len());
+            }
+            Ok(())
+        });
+    }
+}
+
+// After the other hooks, so that they act with the credentials of ljobs
+// rather than those of a new user namespace.
+#[cfg(target_os = "linux")]
+fn enter_namespaces(command: &mut Command, namespaces: Namespaces) {
+    unsafe {
+        command.pre_exec(move || namespaces.enter());
+    }
+}
+
+// Last, as the other hooks use files under /proc that the sandbox may not
+// allow.  The ruleset stays open in ljobs until the child has started.
+#[cfg(target_os = "linux")]
+fn enter_sandbox(command: &mut Command, ruleset: &Ruleset) {
+    let fd = ruleset.fd();
+    unsafe {
+        command.pre_exec(move || sandbox::enforce(fd));
+    }
+}
+
+fn command(argv: &[OsString], env: &[(OsString, OsString)],
+           stdin: Option<PipeReader>) -> Command {
+
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    command.envs(env.iter().map(|(name, value)| (name, value)));
+    command.stdin(stdin.map_or_else(Stdio::null, Stdio::from));
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    command
+}
+
+fn start(command: &mut Command) -> io::Result<Process> {
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().map(into_file);
+    let stderr = child.stderr.take().map(into_file);
+    Ok(Process {
+        pid:    child.id(),
+        #[cfg(windows)]
+        child,
+        #[cfg(unix)]
+        status: None,
+        #[cfg(unix)]
+        usage:  None,
+        stdout,
+        stderr
+    })
+}
+
+#[cfg(unix)]
+fn into_file<P: Into<OwnedFd>>(pipe: P) -> File {
+    File::from(pipe.into())
+}
+
+#[cfg(windows)]
+fn into_file<P: Into<OwnedHandle>>(pipe: P) -> File {
+    File::from(pipe.into())
+}
+
+#[cfg(unix)]
+fn posix_spawn(argv: &[OsString], env: &[(OsString, OsString)],
+               stdin: Option<PipeReader>)
+    -> io::Result<Process> {
+
+    let cargs = c_strings(argv.iter().map(|arg| arg.as_bytes().to_vec()))?;
+    let ptrs = null_terminated(&cargs);
+    // The environment of ljobs is passed as it is if nothing is added.
+    let cenv = match env {
+        [] => Vec::new(),
+        _ => c_strings(environment(env))?
+    };
+    let envptrs = null_terminated(&cenv);
+    let envp = match env {
+        [] => environ(),
+        _ => envptrs.as_ptr()
+    };
+
+    // The parent's ends are close-on-exec, and dup2 clears the flag on the
+    // child's copies.
+    let (outrd, outwr) = pipe()?;
+    let (errrd, errwr) = pipe()?;
+
+    let mut actions = FileActions::new()?;
+    match stdin {
+        Some(ref pipe) => actions.dup2(pipe.as_raw_fd(), 0)?,
+        None => actions.open(0, b"/dev/null\0", libc::O_RDONLY)?
+    }
+    actions.dup2(outwr.as_raw_fd(), 1)?;
+    actions.dup2(errwr.as_raw_fd(), 2)?;
+
+    let mut attr = SpawnAttr::new()?;
+    attr.reset_signals()?;
+
+    let mut pid = 0;
+    let ret = unsafe {
+        libc::posix_spawnp(&mut pid, cargs[0].as_ptr(), &actions.0, &attr.0,
+                           ptrs.as_ptr(), envp)
+    };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    Ok(Process {
+        pid:    pid as u32,
+        status: None,
+        usage:  None,
+        stdout: Some(File::from(outrd)),
+        stderr: Some(File::from(errrd))
+    })
+}
+
+#[cfg(unix)]
+fn c_strings<I: Iterator<Item=Vec<u8>>>(strings: I)
+    -> io::Result<Vec<CString>> {
+
+    strings.map(CString::new)
+        .collect::<Result<Vec<_>, _>>()
+        // The same error as from Command.
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+                                    "nul byte found in provided data"))
+}
+
+#[cfg(unix)]
+fn null_terminated(strings: &[CString]) -> Vec<*mut libc::c_char> {
+    let mut ptrs: Vec<*mut libc::c_char> = strings.iter()
+        .map(|s| s.as_ptr() as *mut libc::c_char)
+        .collect();
+    ptrs.push(ptr::null_mut());
+    ptrs
+}
+
+// The NAME=VALUE strings of the environment of ljobs with `env` added, each
+// variable replacing any of the same name.
+#[cfg(unix)]
+fn environment(env: &[(OsString, OsString)])
+    -> impl Iterator<Item=Vec<u8>> + '_ {
+
+    let assignment = |name: &OsString, value: &OsString| {
+        let mut var = name.as_bytes().to_vec();
+        var.push(b'=');
+        var.extend_from_slice(value.as_bytes());
+        var
+    };
+    std::env::vars_os()
+        .filter(move |(name, _)| !env.iter().any(|(n, _)| n == name))
+        .map(move |(name, value)| assignment(&name, &value))
+        .chain(env.iter().map(move |(name, value)| assignment(name, value)))
+}
+
+// ru_maxrss is in kilobytes, except on macOS where it is in bytes.
+#[cfg(unix)]
+fn resource_usage(rusage: &libc::rusage) -> ResourceUsage {
+    let time = |tv: libc::timeval| {
+        Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+    };
+    let unit = if cfg!(target_vendor = "apple") { 1 } else { 1024 };
+    ResourceUsage {
+        cpu:    time(rusage.ru_utime) + time(rusage.ru_stime),
+        maxrss: rusage.ru_maxrss as u64 * unit
+    }
+}
+
+#[cfg(unix)]
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(ret))
+    }
+}
+
+#[cfg(unix)]
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0 as libc::c_int; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe {
+        Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])))
+    }
+}
+
+#[cfg(all(unix, not(target_vendor = "apple")))]
+fn environ() -> *const *mut libc::c_char {
+    extern "C" {
+        static environ: *const *mut libc::c_char;
+    }
+    unsafe { environ }
+}
+
+#[cfg(target_vendor = "apple")]
+fn environ() -> *const *mut libc::c_char {
+    unsafe { *libc::_NSGetEnviron() as *const *mut libc::c_char }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(unix)]
+struct FileActions(libc::posix_spawn_file_actions_t);
+
+#[cfg(unix)]
+impl FileActions {
+    fn new() -> io::Result<FileActions> {
+        let mut actions = MaybeUninit::uninit();
+        check(unsafe {
+            libc::posix_spawn_file_actions_init(actions.as_mut_ptr())
+        })?;
+        Ok(FileActions(unsafe { actions.assume_init() }))
+    }
+
+    // `path` must be nul-terminated.
+    fn open(&mut self, fd: libc::c_int, path: &'static [u8],
+            flags: libc::c_int) -> io::Result<()> {
+
+        check(unsafe {
+            libc::posix_spawn_file_actions_addopen(&mut self.0, fd,
+                path.as_ptr() as *const libc::c_char, flags, 0)
+        })
+    }
+
+    fn dup2(&mut self, fd: libc::c_int, newfd: libc::c_int)
+        -> io::Result<()> {
+
+        check(unsafe {
+            libc::posix_spawn_file_actions_adddup2(&mut self.0, fd, newfd)
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for FileActions {
+    fn drop(&mut self) {
+        unsafe {
+            libc::posix_spawn_file_actions_destroy(&mut self.0);
+        }
+    }
+}
+
+#[cfg(unix)]
+struct SpawnAttr(libc::posix_spawnattr_t);
+
+#[cfg(unix)]
+impl SpawnAttr {
+    fn new() -> io::Result<SpawnAttr> {
+        let mut attr = MaybeUninit::uninit();
+        check(unsafe { libc::posix_spawnattr_init(attr.as_mut_ptr()) })?;
+        Ok(SpawnAttr(unsafe { attr.assume_init() }))
+    }
+
+    // The Rust runtime ignores SIGPIPE, but children should not.
+    fn reset_signals(&mut self) -> io::Result<()> {
+        unsafe {
+            let mut set = MaybeUninit::uninit();
+            libc::sigemptyset(set.as_mut_ptr());
+            let mut set = set.assume_init();
+            check(libc::posix_spawnattr_setsigmask(&mut self.0, &set))?;
+            libc::sigaddset(&mut set, libc::SIGPIPE);
+            check(libc::posix_spawnattr_setsigdefault(&mut self.0, &set))?;
+            let flags = libc::POSIX_SPAWN_SETSIGMASK |
+                libc::POSIX_SPAWN_SETSIGDEF;
+            check(libc::posix_spawnattr_setflags(&mut self.0,
+                                                 flags as libc::c_short))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SpawnAttr {
+    fn drop(&mut self) {
+        unsafe {
+            libc::posix_spawnattr_destroy(&mut self.0);
+        }
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+    use std::thread;
+    use std::time::Duration;
+
+    type Spawn = fn(&[OsString], &[(OsString, OsString)],
+                    Option<PipeReader>) -> io::Result<Process>;
+
+    const BACKENDS: [Spawn; 2] = [posix_spawn, spawn_command];
+
+    struct Finished {
+        status: ExitStatus,
+        stdout: String,
+        stderr: String
+    }
+
+    fn run(spawn: Spawn, args: &[&str]) -> io::Result<Finished> {
+        run_with_input(spawn, args, None)
+    }
+
+    fn run_with_input(spawn: Spawn, args: &[&str], input: Option<&[u8]>)
+        -> io::Result<Finished> {
+
+        run_with_env(spawn, args, &[], input)
+    }
+
+    fn run_with_env(spawn: Spawn, args: &[&str], env: &[(&str, &str)],
+                    input: Option<&[u8]>)
+        -> io::Result<Finished> {
+
+        let argv: Vec<OsString> = args.iter().map(OsString::from).collect();
+        let env: Vec<(OsString, OsString)> = env.iter()
+            .map(|&(name, value)| (name.into(), value.into()))
+            .collect();
+        let stdin = input.map(input_pipe).transpose()?;
+        let mut process = spawn(&argv, &env, stdin)?;
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        process.stdout.take().unwrap().read_to_string(&mut stdout)?;
+        process.stderr.take().unwrap().read_to_string(&mut stderr)?;
+        loop {
+            if let Some(status) = process.try_wait()? {
+                return Ok(Finished { status, stdout, stderr });
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn sh(spawn: Spawn, script: &str) -> Finished {
+        run(spawn, &["sh", "-c", script]).unwrap()
+    }
+
+    #[test]
+    fn stdio() {
+        for &spawn in &BACKENDS {
+            let f = sh(spawn, "echo out; echo err >&2; cat; echo end");
+            assert!(f.status.success());
+            assert_eq!(f.stdout, "out\nend\n");
+            assert_eq!(f.stderr, "err\n");
+        }
+    }
+
+    #[test]
+    fn input() {
+        // More than a pipe holds, and a child that reads none of it.
+        let input = vec![b'x'; 1 << 20];
+        for &spawn in &BACKENDS {
+            let f = run_with_input(spawn, &["wc", "-c"], Some(&input))
+                .unwrap();
+            assert_eq!(f.stdout.trim(), "1048576");
+            let f = run_with_input(spawn, &["echo", "ok"], Some(&input))
+                .unwrap();
+            assert_eq!(f.stdout, "ok\n");
+        }
+    }
+
+    #[test]
+    fn environment() {
+        // Setting a variable in the test process would race with other
+        // tests running commands, so only PATH is relied on here.
+        let path = env::var("PATH").unwrap();
+        for &spawn in &BACKENDS {
+            let f = sh(spawn, "echo \"$PATH\"");
+            assert_eq!(f.stdout, format!("{}\n", path));
+            // Added variables replace those of ljobs.
+            let script = "echo \"$HOME $LJOBS_LABEL\"";
+            let env = [("HOME", "another"), ("LJOBS_LABEL", "x")];
+            let f = run_with_env(spawn, &["sh", "-c", script], &env, None)
+                .unwrap();
+            assert_eq!(f.stdout, "another x\n");
+        }
+    }
+
+    #[test]
+    fn exit_status() {
+        for &spawn in &BACKENDS {
+            assert_eq!(sh(spawn, "exit 3").status.code(), Some(3));
+            assert_eq!(sh(spawn, "kill -TERM $$").status.signal(),
+                       Some(libc::SIGTERM));
+        }
+    }
+
+    #[test]
+    fn sigpipe() {
+        // The writer is killed quietly rather than reporting an error.
+        for &spawn in &BACKENDS {
+            let f = sh(spawn, "yes | head -c 3");
+            assert_eq!(f.stdout, "y\ny");
+            assert_eq!(f.stderr, "");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let errors = |args: &[&str]| -> Vec<io::Error> {
+            BACKENDS.iter()
+                .map(|&spawn| run(spawn, args).err().unwrap())
+                .collect()
+        };
+        for errs in &[errors(&["ljobs-no-such-command"]),
+                      errors(&["/dev/null"]),
+                      errors(&["echo", "nul\0byte"])] {
+            assert_eq!(errs[0].kind(), errs[1].kind());
+            assert_eq!(errs[0].to_string(), errs[1].to_string());
+        }
+        assert_eq!(errors(&["ljobs-no-such-command"])[0].kind(),
+                   io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,204 @@
+/*
+** Staging files to and from remote hosts.
+*/
+
+// With `transfer`, `return_file` or `cleanup`, a command to be run with ssh
+// is wrapped in a local shell script that takes these steps in turn:
+//
+//  1. Make a directory for the job on the remote host, `.ljobs/PID.TASKNUM`
+//     under the remote home directory.
+//  2. With `transfer`, if the task names a local file, copy it into that
+//     directory.  The task is then replaced by the remote path of the copy
+//     in substitutions.
+//  3. Run the command.
+//  4. Copy back each file named by substituting the task into a
+//     `return_file` pattern, once the command has run.  The remote path
+//     has the task as the command saw it, and the local path the task as
+//     it was given.
+//  5. With `cleanup`, remove the directory, whatever happened before.
+//
+// Files are copied by piping them through `ssh LOGIN cat`, so nothing but a
+// POSIX shell is needed at either end.  Each step is a command line quoted
+// with quote_sh_cmd like the command itself.  A step that fails is
+// reported on the standard error of the job, and the job then exits with
+// the status of the first step or command to fail.
+
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::process;
+
+use os::from_os_bytes;
+use quote::quote_sh_cmd;
+use template::{basename, ssh_prefix, subst};
+use {Options, PROG};
+
+/*---------------------------------------------------------------------------*/
+
+pub struct Staging {
+    transfer:   bool,
+    returns:    Vec<OsString>,
+    cleanup:    bool,
+    showsteps:  bool,
+    pid:        u32
+}
+
+// A shell script being built up, for one job.
+struct Script {
+    text:       Vec<u8>
+}
+
+impl Staging {
+    /// None if there are no files to stage.
+    pub fn new(opts: &Options) -> Option<Staging> {
+        if !opts.transfer && opts.returns.is_empty() && !opts.cleanup {
+            return None;
+        }
+        Some(Staging {
+            transfer:  opts.transfer,
+            returns:   opts.returns.clone(),
+            cleanup:   opts.cleanup,
+            showsteps: opts.showsteps,
+            pid:       process::id()
+        })
+    }
+
+    /// The remote path that the file named by `task` is copied to, if it
+    /// is to be transferred.
+    pub fn remote_task(&self, tasknum: usize, task: &[u8]) -> Option<Vec<u8>> {
+        let local = from_os_bytes(task.to_vec());
+        if !self.transfer || !Path::new(&local).is_file() {
+            return None;
+        }
+        let mut path = self.dir(tasknum);
+        path.push(b'/');
+        path.extend_from_slice(basename(task));
+        Some(path)
+    }
+
+    /// The command line of a local shell script to run `argv` on `login`
+    /// with ssh, staging files as it goes.  `remote` is the path that the
+    /// task was transferred to, if it was.
+    pub fn script(&self, tasknum: usize, login: &OsStr, task: &[u8],
+                  remote: Option<&[u8]>, argv: &[OsString]) -> Vec<OsString> {
+
+        let dir = self.dir(tasknum);
+        let ssh = |remotecmd: Vec<u8>| {
+            let mut argv = ssh_prefix(login);
+            argv.push(from_os_bytes(remotecmd));
+            sh_bytes(&argv)
+        };
+        let login = login.to_string_lossy();
+        let mut script = Script::new(tasknum, self.showsteps);
+
+        script.step(false, &ssh(sh_words(&[b"mkdir", b"-p", &dir])),
+                    &format!("could not make directory {} on {}",
+                             String::from_utf8_lossy(&dir), login));
+
+        if let Some(remote) = remote {
+            let mut line = ssh([&b"cat > "[..], &sh_words(&[remote])]
+                               .concat());
+            line.extend_from_slice(b" < ");
+            line.extend_from_slice(&sh_words(&[task]));
+            script.step(false, &line,
+                        &format!("could not transfer {} to {}",
+                                 String::from_utf8_lossy(task), login));
+        }
+
+        script.command(&ssh(quote_sh_cmd(argv).into_encoded_bytes()));
+
+        for pattern in &self.returns {
+            let pattern = pattern.as_encoded_bytes();
+            let from = subst(pattern, tasknum, remote.unwrap_or(task))
+                .unwrap_or_else(|| pattern.to_vec());
+            let to = subst(pattern, tasknum, task)
+                .unwrap_or_else(|| pattern.to_vec());
+            let part = [&to[..], b".ljobs-part"].concat();
+            let mut line = ssh(sh_words(&[b"cat", &from]));
+            line.extend_from_slice(b" > ");
+            line.extend_from_slice(&sh_words(&[&part]));
+            line.extend_from_slice(b" && mv -f ");
+            line.extend_from_slice(&sh_words(&[&part, &to]));
+            line.extend_from_slice(b" || { rm -f ");
+            line.extend_from_slice(&sh_words(&[&part]));
+            line.extend_from_slice(b"; false; }");
+            script.returned(&line,
+                            &format!("could not return {} from {}",
+                                     String::from_utf8_lossy(&from), login));
+        }
+
+        if self.cleanup {
+            script.step(true, &ssh(sh_words(&[b"rm", b"-rf", &dir])),
+                        &format!("could not remove directory {} on {}",
+                                 String::from_utf8_lossy(&dir), login));
+        }
+
+        script.text.extend_from_slice(b"exit $s\n");
+        vec![OsString::from("sh"), OsString::from("-c"),
+             from_os_bytes(script.text)]
+    }
+
+    // The directory for a job on the remote host.
+    fn dir(&self, tasknum: usize) -> Vec<u8> {
+        format!(".ljobs/{}.{}", self.pid, tasknum).into_bytes()
+    }
+}
+
+impl Script {
+    // The shell functions `step` and `run` take a step or run the command,
+    // given as a command line to be evaluated.  A step that fails is
+    // reported with the message given after it.
+    fn new(tasknum: usize, showsteps: bool) -> Script {
+        let show = if showsteps {
+            format!("printf '%s\\n' \"{}[{}]: step\t$1\" >&2; ",
+                    PROG, tasknum)
+        } else {
+            String::new()
+        };
+        let text = format!(
+            "s=0 c=0\n\
+             step() {{ {show}eval \"$1\" || {{ r=$?; \
+             printf '%s\\n' \"{prog}[{tasknum}]: $2\" >&2; \
+             test $s = 0 && s=$r; }}; }}\n\
+             run() {{ {show}eval \"$1\"; s=$? c=1; }}\n",
+            show = show, prog = PROG, tasknum = tasknum);
+        Script { text: text.into_bytes() }
+    }
+
+    // A step that is only taken if nothing failed before, unless `always`.
+    fn step(&mut self, always: bool, line: &[u8], failure: &str) {
+        if !always {
+            self.text.extend_from_slice(b"test $s = 0 && ");
+        }
+        self.call(b"step", &[line, failure.as_bytes()]);
+    }
+
+    // Copying back a file, once the command has run.
+    fn returned(&mut self, line: &[u8], failure: &str) {
+        self.text.extend_from_slice(b"test $c = 1 && ");
+        self.call(b"step", &[line, failure.as_bytes()]);
+    }
+
+    fn command(&mut self, line: &[u8]) {
+        self.text.extend_from_slice(b"test $s = 0 && ");
+        self.call(b"run", &[line]);
+    }
+
+    fn call(&mut self, func: &[u8], args: &[&[u8]]) {
+        self.text.extend_from_slice(func);
+        self.text.push(b' ');
+        self.text.extend_from_slice(&sh_words(args));
+        self.text.push(b'\n');
+    }
+}
+
+// Words quoted for the shell and joined with spaces.
+fn sh_words(words: &[&[u8]]) -> Vec<u8> {
+    let words: Vec<OsString> = words.iter()
+        .map(|w| from_os_bytes(w.to_vec()))
+        .collect();
+    sh_bytes(&words)
+}
+
+fn sh_bytes(argv: &[OsString]) -> Vec<u8> {
+    quote_sh_cmd(argv).into_encoded_bytes()
+}
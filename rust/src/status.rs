@@ -0,0 +1,368 @@
+/*
+** Status and progress files for monitoring a run.
+*/
+
+// With `status_file`, the master rewrites a small JSON document about the
+// run when it starts, every STATUS_INTERVAL while it goes on, as a ticker
+// thread tells it to, and once more when it ends.  Each version is written
+// to a temporary file beside the status file and renamed over it, so that
+// a reader never sees a partial document.  What is running is followed
+// from the same events that the progress shown with `verbose` is built on.
+//
+// The document looks like
+//
+//     {"started":3,"completed":1,"failed":0,
+//      "running":[{"task":1,"pid":123,"command":"sleep 5","seconds":2.0}],
+//      "slots":2,"elapsed":4.0,"eta":8.0,"finished":false}
+//
+// where a job with a label also has it as "label" after its "command", and
+// "eta" is the number of seconds until the run is expected to end, or null
+// until all tasks have been read and one has finished.  The last version
+// has "finished":true and the "exit_status" of ljobs, or null and an
+// "error" if the run failed.
+//
+// With `progress_file`, a smaller document of just the counts is rewritten
+// the same way when the run starts, each time a task finishes, and when
+// the run ends:
+//
+//     {"completed":1,"total":5,"running":2,"failed":0,"finished":false}
+//
+// where "total" is null until all tasks have been read.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use json::{self, Value};
+use quote::quote_cmd;
+use JobEvent;
+
+/// How often the status file is rewritten during a run.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(2);
+
+/*---------------------------------------------------------------------------*/
+
+pub struct StatusFile {
+    path:       PathBuf,
+    tmppath:    PathBuf,
+    start:      Instant,
+    started:    usize,
+    // Tasks to be tried again, which are not counted twice as started.
+    retrying:   HashSet<usize>,
+    running:    BTreeMap<usize, Running>,
+    // The number of tasks in the run, once they have all been read.
+    total:      Option<usize>
+}
+
+struct Running {
+    pid:        u32,
+    command:    String,
+    label:      Option<String>,
+    time:       Instant
+}
+
+/// The numbers of the run that the master keeps.
+pub struct Counts {
+    pub completed:  usize,
+    pub failed:     u32,
+    pub slots:      usize,
+    // Whether no more tasks will be started, so there is no ETA.
+    pub stopping:   bool
+}
+
+/// A progress file, rewritten as each task finishes.
+pub struct ProgressFile {
+    path:       PathBuf,
+    tmppath:    PathBuf,
+    total:      Option<usize>
+}
+
+/// The counts of the run for the progress file.
+pub struct Progress {
+    pub completed:  usize,
+    pub running:    usize,
+    pub failed:     u32,
+    pub finished:   bool
+}
+
+/// How the run ended, for the last version of the file.
+pub enum End<'a> {
+    Exit(i32),
+    Error(&'a str)
+}
+
+impl StatusFile {
+    pub fn new(path: &Path, start: Instant) -> StatusFile {
+        StatusFile {
+            path:     path.to_path_buf(),
+            tmppath:  tmp_path(path),
+            start,
+            started:  0,
+            retrying: HashSet::new(),
+            running:  BTreeMap::new(),
+            total:    None
+        }
+    }
+
+    /// The time that the run started.
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    /// Follow the jobs that are running.
+    pub fn event(&mut self, event: &JobEvent) {
+        match *event {
+            JobEvent::Started { tasknum, pid, argv, label, time } => {
+                if !self.retrying.remove(&tasknum) {
+                    self.started += 1;
+                }
+                self.running.insert(tasknum, Running {
+                    pid,
+                    command: quote_cmd(argv),
+                    label:   label.map(|l| l.to_string_lossy().into_owned()),
+                    time
+                });
+            },
+            JobEvent::Retrying { tasknum, .. } => {
+                self.retrying.insert(tasknum);
+                self.running.remove(&tasknum);
+            },
+            JobEvent::Finished { result, .. } => {
+                self.running.remove(&result.tasknum);
+            }
+        }
+    }
+
+    /// All tasks have been read, and there are `total` of them.
+    pub fn set_total(&mut self, total: usize) {
+        self.total = Some(total);
+    }
+
+    /// Replace the status file with the status as of `now`.
+    pub fn write(&self, now: Instant, counts: &Counts, end: Option<End>)
+        -> io::Result<()> {
+
+        let doc = self.document(now, counts, end);
+        replace(&self.path, &self.tmppath, &doc)
+    }
+
+    fn document(&self, now: Instant, counts: &Counts, end: Option<End>)
+        -> Vec<u8> {
+
+        let elapsed = now.saturating_duration_since(self.start);
+        let running = self.running.iter()
+            .map(|(&tasknum, job)| {
+                let mut members = vec![
+                    ("task", number(tasknum)),
+                    ("pid", number(job.pid)),
+                    ("command", Value::String(job.command.clone()))
+                ];
+                if let Some(ref label) = job.label {
+                    members.push(("label", Value::String(label.clone())));
+                }
+                let age = now.saturating_duration_since(job.time);
+                members.push(("seconds", seconds(age)));
+                json::object(members)
+            })
+            .collect();
+        let eta = match self.total {
+            Some(total) if counts.completed > 0 && !counts.stopping &&
+                           end.is_none() => {
+                let left = total.saturating_sub(counts.completed);
+                seconds(elapsed.mul_f64(left as f64 /
+                                        counts.completed as f64))
+            },
+            _ => Value::Null
+        };
+        let mut members = vec![
+            ("started", number(self.started)),
+            ("completed", number(counts.completed)),
+            ("failed", number(counts.failed)),
+            ("running", Value::Array(running)),
+            ("slots", number(counts.slots)),
+            ("elapsed", seconds(elapsed)),
+            ("eta", eta),
+            ("finished", Value::Bool(end.is_some()))
+        ];
+        match end {
+            Some(End::Exit(status)) =>
+                members.push(("exit_status", number(status))),
+            Some(End::Error(msg)) => {
+                members.push(("exit_status", Value::Null));
+                members.push(("error", Value::String(String::from(msg))));
+            },
+            None => ()
+        }
+        let mut doc = Vec::new();
+        json::object(members).write_json(&mut doc);
+        doc.push(b'\n');
+        doc
+    }
+}
+
+impl ProgressFile {
+    pub fn new(path: &Path) -> ProgressFile {
+        ProgressFile {
+            path:    path.to_path_buf(),
+            tmppath: tmp_path(path),
+            total:   None
+        }
+    }
+
+    /// All tasks have been read, and there are `total` of them.
+    pub fn set_total(&mut self, total: usize) {
+        self.total = Some(total);
+    }
+
+    /// Replace the progress file with these counts.
+    pub fn write(&self, progress: &Progress) -> io::Result<()> {
+        replace(&self.path, &self.tmppath, &self.document(progress))
+    }
+
+    fn document(&self, progress: &Progress) -> Vec<u8> {
+        let members = vec![
+            ("completed", number(progress.completed)),
+            ("total", self.total.map_or(Value::Null, number)),
+            ("running", number(progress.running)),
+            ("failed", number(progress.failed)),
+            ("finished", Value::Bool(progress.finished))
+        ];
+        let mut doc = Vec::new();
+        json::object(members).write_json(&mut doc);
+        doc.push(b'\n');
+        doc
+    }
+}
+
+// The temporary file beside `path` that each version is written to first.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmppath = OsString::from(path);
+    tmppath.push(".tmp");
+    PathBuf::from(tmppath)
+}
+
+// Replace the file at `path` with `doc` atomically, by way of `tmppath`.
+fn replace(path: &Path, tmppath: &Path, doc: &[u8]) -> io::Result<()> {
+    fs::write(tmppath, doc)?;
+    fs::rename(tmppath, path)
+}
+
+fn number<N: ToString>(n: N) -> Value {
+    Value::Number(n.to_string())
+}
+
+fn seconds(d: Duration) -> Value {
+    Value::Number(format!("{:.3}", d.as_secs_f64()))
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{Counts, End, Progress, ProgressFile, StatusFile};
+    use json::{self, Value};
+    use std::ffi::{OsStr, OsString};
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+    use JobEvent;
+
+    fn field(doc: &Value, path: &str) -> String {
+        let mut text = Vec::new();
+        doc.lookup(path.as_bytes()).expect(path).write_text(&mut text);
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn document() {
+        let start = Instant::now();
+        let mut status = StatusFile::new(Path::new("status.json"), start);
+        let argv = [OsString::from("sleep"), OsString::from("5")];
+        for tasknum in 0..3 {
+            status.event(&JobEvent::Started {
+                tasknum,
+                pid:   100 + tasknum as u32,
+                argv:  &argv,
+                label: None,
+                time:  start + Duration::from_secs(tasknum as u64)
+            });
+        }
+        let err = ::std::io::Error::other("busy");
+        status.event(&JobEvent::Retrying {
+            tasknum: 2,
+            argv:    &argv,
+            error:   &err,
+            time:    start
+        });
+        let counts = Counts {
+            completed: 1,
+            failed:    0,
+            slots:     2,
+            stopping:  false
+        };
+        let now = start + Duration::from_secs(4);
+        let doc = json::parse(&status.document(now, &counts, None)).unwrap();
+        assert_eq!(field(&doc, "started"), "3");
+        assert_eq!(field(&doc, "completed"), "1");
+        assert_eq!(field(&doc, "slots"), "2");
+        assert_eq!(field(&doc, "elapsed"), "4.000");
+        assert_eq!(field(&doc, "eta"), "");
+        assert_eq!(field(&doc, "finished"), "false");
+        assert_eq!(field(&doc, "running"),
+                   "[{\"task\":0,\"pid\":100,\"command\":\"sleep 5\",\
+                     \"seconds\":4.000},\
+                     {\"task\":1,\"pid\":101,\"command\":\"sleep 5\",\
+                     \"seconds\":3.000}]");
+
+        // A task tried again is not started twice.
+        status.event(&JobEvent::Started {
+            tasknum: 2,
+            pid:     103,
+            argv:    &argv,
+            label:   Some(OsStr::new("batch:2")),
+            time:    now
+        });
+        status.set_total(5);
+        let doc = json::parse(&status.document(now, &counts, None)).unwrap();
+        assert_eq!(field(&doc, "started"), "3");
+        assert_eq!(field(&doc, "eta"), "16.000");
+        assert_eq!(field(&doc, "running"),
+                   "[{\"task\":0,\"pid\":100,\"command\":\"sleep 5\",\
+                     \"seconds\":4.000},\
+                     {\"task\":1,\"pid\":101,\"command\":\"sleep 5\",\
+                     \"seconds\":3.000},\
+                     {\"task\":2,\"pid\":103,\"command\":\"sleep 5\",\
+                     \"label\":\"batch:2\",\"seconds\":0.000}]");
+
+        let doc = json::parse(&status.document(now, &counts,
+                                               Some(End::Exit(3))))
+            .unwrap();
+        assert_eq!(field(&doc, "finished"), "true");
+        assert_eq!(field(&doc, "exit_status"), "3");
+        assert_eq!(field(&doc, "eta"), "");
+        let doc = json::parse(&status.document(now, &counts,
+                                               Some(End::Error("oops"))))
+            .unwrap();
+        assert_eq!(field(&doc, "exit_status"), "");
+        assert_eq!(field(&doc, "error"), "oops");
+    }
+
+    #[test]
+    fn progress() {
+        let mut progress = ProgressFile::new(Path::new("progress.json"));
+        let counts = Progress {
+            completed: 1,
+            running:   2,
+            failed:    1,
+            finished:  false
+        };
+        assert_eq!(progress.document(&counts),
+                   &b"{\"completed\":1,\"total\":null,\"running\":2,\
+                      \"failed\":1,\"finished\":false}\n"[..]);
+        progress.set_total(5);
+        let doc = json::parse(&progress.document(&counts)).unwrap();
+        assert_eq!(field(&doc, "total"), "5");
+    }
+}
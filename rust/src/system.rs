@@ -0,0 +1,122 @@
+/*
+** What the master needs from the system: starting commands and the time.
+*/
+
+// The master only starts commands through a Spawner and only tells the time
+// or waits through a Clock, so that its scheduling can be tested against a
+// simulation instead of real processes and real time (see sim.rs).  The
+// real Spawner is the Reaper, with commands started by spawn::spawn.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::sync::mpsc::{Receiver, RecvError, RecvTimeoutError};
+use std::time::Instant;
+
+use reaper::Reaper;
+use sandbox::Ruleset;
+use spawn::{self, Process};
+use {Event, JobInfo, Options};
+
+/*---------------------------------------------------------------------------*/
+
+pub trait Spawner {
+    type Child;
+
+    /// Start the command `argv`, with the variables `env` added to its
+    /// environment, in the cgroup whose cgroup.procs file is `cgroup` if
+    /// there is one, confined to `sandbox` if there is one, with `input` on
+    /// its standard input if there is some.
+    fn spawn(&mut self, opts: &Options, argv: &[OsString],
+             env: &[(OsString, OsString)],
+             cgroup: Option<&File>, sandbox: Option<&Ruleset>,
+             input: Option<&[u8]>)
+        -> io::Result<Self::Child>;
+
+    /// The process id of `child`.
+    fn pid(&self, child: &Self::Child) -> u32;
+
+    /// Wait for `child` to finish in the background.  The finished job is
+    /// sent to the master as an event.
+    fn watch(&mut self, info: JobInfo, child: Self::Child) -> io::Result<()>;
+
+    /// Ask all children still running to terminate.
+    fn terminate_all(&mut self);
+
+    /// Kill and wait for all children not yet waited for.
+    fn reap_all(&mut self);
+
+    /// Run the command `argv` to completion, and return whether it
+    /// succeeded.
+    fn check(&mut self, argv: &[OsString]) -> io::Result<bool>;
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    /// Wait for the next event, but only until `deadline` if there is one.
+    /// Returns None if the deadline passed first.
+    fn wait_until(&mut self, events: &Receiver<Event>,
+                  deadline: Option<Instant>)
+        -> Result<Option<Event>, RecvError>;
+}
+
+/*---------------------------------------------------------------------------*/
+
+impl Spawner for Reaper {
+    type Child = Process;
+
+    fn spawn(&mut self, opts: &Options, argv: &[OsString],
+             env: &[(OsString, OsString)],
+             cgroup: Option<&File>, sandbox: Option<&Ruleset>,
+             input: Option<&[u8]>)
+        -> io::Result<Process> {
+
+        spawn::spawn(opts, argv, env, cgroup, sandbox, input)
+    }
+
+    fn pid(&self, child: &Process) -> u32 {
+        child.id()
+    }
+
+    fn watch(&mut self, info: JobInfo, child: Process) -> io::Result<()> {
+        Reaper::watch(self, info, child)
+    }
+
+    fn terminate_all(&mut self) {
+        Reaper::terminate_all(self)
+    }
+
+    fn reap_all(&mut self) {
+        Reaper::reap_all(self)
+    }
+
+    fn check(&mut self, argv: &[OsString]) -> io::Result<bool> {
+        spawn::succeeds(argv)
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wait_until(&mut self, events: &Receiver<Event>,
+                  deadline: Option<Instant>)
+        -> Result<Option<Event>, RecvError> {
+
+        match deadline {
+            Some(when) => {
+                let timeout = when.saturating_duration_since(Instant::now());
+                match events.recv_timeout(timeout) {
+                    Ok(event) => Ok(Some(event)),
+                    Err(RecvTimeoutError::Timeout) => Ok(None),
+                    Err(RecvTimeoutError::Disconnected) => Err(RecvError)
+                }
+            },
+            None => events.recv().map(Some)
+        }
+    }
+}
@@ -0,0 +1,81 @@
+/*
+** Running jobs in transient systemd scopes.
+*/
+
+// With `systemd_run`, each command is run as
+//
+//     systemd-run --user --scope --quiet --collect --unit=ljobs-PID-TASKNUM
+//         -p PROPERTY... -- COMMAND...
+//
+// so that the user's service manager accounts for each job in a scope unit
+// of its own, with the resource control properties given, and the job can
+// be seen with `systemctl --user status`.  With --scope, systemd-run moves
+// itself into the new scope and then executes the command in place, so the
+// child that ljobs waits for is the command itself and its exit status
+// passes through.  --collect lets the unit go even if the command failed,
+// so the name is free again if the task is retried.
+
+use std::ffi::OsString;
+use std::process;
+
+use Options;
+
+/*---------------------------------------------------------------------------*/
+
+pub struct SystemdScope {
+    pid:        u32,
+    props:      Vec<OsString>
+}
+
+impl SystemdScope {
+    /// None if jobs are not run in scopes.
+    pub fn new(opts: &Options) -> Option<SystemdScope> {
+        if !opts.systemdrun {
+            return None;
+        }
+        Some(SystemdScope {
+            pid:   process::id(),
+            props: opts.systemdprops.clone()
+        })
+    }
+
+    /// The command line to run the command of a task in its scope, up to
+    /// the command.
+    pub fn prefix(&self, tasknum: usize) -> Vec<OsString> {
+        let mut argv: Vec<OsString> = [
+            "systemd-run", "--user", "--scope", "--quiet", "--collect"
+        ].iter().map(OsString::from).collect();
+        argv.push(OsString::from(format!("--unit=ljobs-{}-{}",
+                                         self.pid, tasknum)));
+        for prop in &self.props {
+            argv.push(OsString::from("-p"));
+            argv.push(prop.clone());
+        }
+        argv.push(OsString::from("--"));
+        argv
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::SystemdScope;
+    use std::ffi::OsString;
+
+    #[test]
+    fn prefix() {
+        let scope = SystemdScope {
+            pid:   42,
+            props: vec![OsString::from("MemoryMax=2G"),
+                        OsString::from("CPUWeight=50")]
+        };
+        assert_eq!(scope.prefix(7),
+                   ["systemd-run", "--user", "--scope", "--quiet",
+                    "--collect", "--unit=ljobs-42-7", "-p", "MemoryMax=2G",
+                    "-p", "CPUWeight=50", "--"]);
+        let scope = SystemdScope { pid: 1, props: Vec::new() };
+        assert_eq!(scope.prefix(0).last().unwrap(), "--");
+        assert_eq!(scope.prefix(0)[5], "--unit=ljobs-1-0");
+    }
+}
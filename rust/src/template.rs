@@ -0,0 +1,1000 @@
+/*
+** Command line construction and task string substitutions.
+*/
+
+//! Substitution of tasks into command arguments.
+//!
+//! Command words and tasks are OsStrings, which on Unix are arbitrary
+//! bytes, so substitutions work on bytes.  The path helpers treat a task as
+//! a path the way the POSIX `basename` and `dirname` utilities do, except
+//! that on Windows both `/` and `\` separate path components, and the
+//! basename of an empty path is empty.  POSIX has no notion of an
+//! extension: here it is the last dot and what follows in the basename,
+//! unless the basename has only dots before it, as in `.bashrc`.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process;
+
+use container::Container;
+use ionice::{self, IoPriority};
+use json;
+use os::from_os_bytes;
+use quote::{quote_arg, quote_cmd, quote_sh_cmd};
+use stage::Staging;
+use systemd::SystemdScope;
+use {Error, Options, LABEL_VAR, SLOT_VAR};
+
+/*---------------------------------------------------------------------------*/
+
+/// The parts of the command line which do not depend on the task, worked
+/// out once for the whole run.
+pub(crate) struct CommandTemplate {
+    prefix:     Vec<OsString>,
+    args:       Vec<TemplateArg>,
+    colsep:     Option<OsString>,
+    strict:     bool,
+    maxcolumn:  usize,
+    // With `stdin_column`, the column for standard input.
+    stdincolumn: Option<usize>,
+    // With `pipe`, the whole task is standard input instead.
+    pipe:       bool,
+    // With JSON input, the field paths referred to by `{.field}` tokens.
+    json:       bool,
+    fields:     Vec<Vec<u8>>,
+    // The current directory for `{cwd}`, if it is used.
+    cwd:        Option<Vec<u8>>,
+    // With `ionice`, the priority to run the command at with the ionice
+    // utility, if children cannot set it themselves.
+    ionice:     Option<IoPriority>,
+    // With `systemd_run`, how to run the command in a scope.
+    scope:      Option<SystemdScope>,
+    // With `container`, how to run the command in a container, and the
+    // arguments for the engine.
+    container:  Option<Container>,
+    containerargs: Vec<TemplateArg>,
+    // Whether commands are run on remote hosts with ssh, and how files are
+    // staged to and from the hosts.
+    ssh:        bool,
+    staging:    Option<Staging>,
+    // A script too large to pass to the shell as an argument, written out
+    // for the shell to read.
+    _bigscript: Option<TempScript>
+}
+
+// The largest script passed to the shell as an argument.  Linux limits each
+// argument to 128 KiB.
+const SCRIPT_ARG_MAX: usize = 128 * 1024 - 1;
+
+// A file removed when dropped.
+struct TempScript {
+    path:       PathBuf
+}
+
+// What tokens other than those of `subst_columns` are replaced with.  Tokens
+// with nothing to replace them are left alone.
+#[derive(Default)]
+struct Vars<'a> {
+    doc:        Option<&'a json::Value>,
+    cwd:        Option<&'a [u8]>,
+    host:       Option<&'a [u8]>,
+    label:      Option<&'a [u8]>,
+    slot:       Option<&'a [u8]>
+}
+
+struct TemplateArg {
+    arg:        OsString,
+    hassubst:   bool
+}
+
+/// A shell command line to be run for each task, such as the guard of
+/// `run_if`.  On Unix the substitutions are passed to the shell as
+/// positional parameters, so a task needs no quoting.
+pub(crate) struct ShellTemplate {
+    shell:      OsString,
+    script:     OsString,
+    // What each positional parameter is substituted from.
+    params:     Vec<Vec<u8>>
+}
+
+impl CommandTemplate {
+    /// Fails if an argument has `{cwd}` or there is a container, but the
+    /// current directory cannot be found, or if there is no container
+    /// engine.
+    pub fn new(opts: &Options, cmd: &OsStr, cmdargs: &[OsString])
+        -> Result<CommandTemplate, Error> {
+
+        let prefix = match opts.shell {
+            Some(ref shell) if opts.shellliteral => {
+                let cmd = literal_command(cmd.as_encoded_bytes());
+                shell_prefix(shell, &from_os_bytes(cmd))
+            },
+            Some(ref shell) => shell_prefix(shell, cmd),
+            None => vec![cmd.to_os_string()]
+        };
+        CommandTemplate::with_prefix(opts, prefix, cmdargs, None)
+    }
+
+    /// The command is `script`, run with the `shell` of `opts` or else the
+    /// default shell.  As for ShellTemplate, on Unix the substitution
+    /// strings in the script are passed as positional parameters.  A
+    /// script too large to be an argument is written to a temporary file
+    /// for the shell to read, which cannot be done with ssh or containers.
+    pub fn script(opts: &Options, script: &[u8])
+        -> Result<CommandTemplate, Error> {
+
+        let shell = opts.shell.clone().unwrap_or_else(default_shell);
+        let (script, params) = positional(script);
+        let params: Vec<OsString> = params.into_iter()
+            .map(from_os_bytes)
+            .collect();
+        let script = from_os_bytes(script);
+        if script.len() <= SCRIPT_ARG_MAX {
+            let prefix = shell_prefix(&shell, &script);
+            return CommandTemplate::with_prefix(opts, prefix, &params, None);
+        }
+        if !opts.sshlogins.is_empty() || opts.sshloginfile.is_some() ||
+            opts.container.is_some()
+        {
+            return Err(Error::Usage(String::from(
+                "script too large to run with ssh or in a container")));
+        }
+        let file = TempScript::create(script.as_encoded_bytes())?;
+        let prefix = vec![shell, file.path.clone().into_os_string()];
+        CommandTemplate::with_prefix(opts, prefix, &params, Some(file))
+    }
+
+    fn with_prefix(opts: &Options, prefix: Vec<OsString>,
+                   cmdargs: &[OsString], bigscript: Option<TempScript>)
+        -> Result<CommandTemplate, Error> {
+
+        let json = opts.jsoninput;
+        let ssh = !opts.sshlogins.is_empty() || opts.sshloginfile.is_some();
+        let label = opts.label.is_some();
+        let mut fields: Vec<Vec<u8>> = Vec::new();
+        let mut maxcolumn = 0;
+        let mut cwd = None;
+        let mut args = Vec::with_capacity(
+            cmdargs.len() + opts.containerargs.len());
+        for arg in cmdargs.iter().chain(&opts.containerargs) {
+            let bytes = arg.as_encoded_bytes();
+            let mut hasfield = false;
+            let mut hasvar = false;
+            for mid in tokens(bytes) {
+                maxcolumn = maxcolumn.max(column_token(mid).unwrap_or(0));
+                if let (true, Some(path)) = (json, field_token(mid)) {
+                    hasfield = true;
+                    if !fields.iter().any(|f| f == path) {
+                        fields.push(path.to_vec());
+                    }
+                }
+                if mid == b"cwd" && cwd.is_none() {
+                    cwd = Some(current_dir()?);
+                }
+                hasvar |= mid == b"cwd" || mid == b"%" ||
+                    (mid == b"host" && ssh) ||
+                    (mid == b"label" && label);
+            }
+            args.push(TemplateArg {
+                arg:      arg.clone(),
+                hassubst: hasfield || hasvar || subst(bytes, 0, b"").is_some()
+            });
+        }
+        let containerargs = args.split_off(cmdargs.len());
+        let container = match opts.container {
+            Some(ref image) => {
+                if cwd.is_none() {
+                    cwd = Some(current_dir()?);
+                }
+                let cwd = cwd.as_deref().expect("cwd");
+                Some(Container::new(opts, image, cwd)?)
+            },
+            None => None
+        };
+        Ok(CommandTemplate {
+            prefix,
+            args,
+            colsep:     opts.colsep.clone().filter(|sep| !sep.is_empty()),
+            strict:     opts.strict,
+            maxcolumn,
+            stdincolumn: opts.stdincolumn,
+            pipe:       opts.blocksize.is_some(),
+            json,
+            fields,
+            cwd,
+            ionice:     opts.ionice.filter(|_| !ionice::supported()),
+            scope:      SystemdScope::new(opts),
+            container,
+            containerargs,
+            ssh,
+            staging:    Staging::new(opts),
+            _bigscript: bigscript
+        })
+    }
+
+    /// Substitute the task into the command arguments.  With `strict`, it
+    /// is an error for an argument to refer to a column or field that the
+    /// task does not have.  With ssh, the command is to be run on `login`.
+    /// With `label`, the job has that label, and with `slot`, it holds
+    /// that job slot.
+    pub fn build_argv(&self, tasknum: usize, task: &OsStr,
+                      login: Option<&OsStr>, label: Option<&OsStr>,
+                      slot: Option<usize>)
+        -> Result<Vec<OsString>, Error> {
+
+        let localtask = task.as_encoded_bytes();
+        // A task transferred to the host is known there by another path.
+        let remotetask = match (&self.staging, login) {
+            (Some(staging), Some(_)) =>
+                staging.remote_task(tasknum, localtask),
+            _ => None
+        };
+        let task = remotetask.as_deref().unwrap_or(localtask);
+        let (columns, doc) = self.parse_task(tasknum, task)?;
+        let slot = slot.map(|slot| slot.to_string());
+
+        let vars = Vars {
+            doc:  doc.as_ref(),
+            cwd:  self.cwd.as_deref(),
+            host:  login.map(OsStr::as_encoded_bytes),
+            label: label.map(OsStr::as_encoded_bytes),
+            slot:  slot.as_deref().map(str::as_bytes)
+        };
+        let substitute = |a: &TemplateArg| {
+            if a.hassubst {
+                let substarg =
+                    subst_task(a.arg.as_encoded_bytes(), tasknum, task,
+                               &columns, &vars)
+                        .expect("substitution");
+                from_os_bytes(substarg)
+            } else {
+                a.arg.clone()
+            }
+        };
+        let mut argv = Vec::with_capacity(
+            self.prefix.len() + self.args.len() + 1);
+
+        if let Some(ionice) = self.ionice {
+            argv.extend(ionice.command_prefix());
+        }
+
+        if let Some(ref scope) = self.scope {
+            argv.extend(scope.prefix(tasknum));
+        }
+
+        if let Some(ref container) = self.container {
+            argv.extend_from_slice(&container.prefix);
+            // The engine does not pass its own environment on.
+            if let Some(label) = label {
+                argv.push(OsString::from("--env"));
+                argv.push(env_assignment(LABEL_VAR, label));
+            }
+            if let Some(ref slot) = slot {
+                argv.push(OsString::from("--env"));
+                argv.push(env_assignment(SLOT_VAR, OsStr::new(slot)));
+            }
+            argv.extend(self.containerargs.iter().map(substitute));
+            argv.push(container.image.clone());
+        }
+
+        argv.extend_from_slice(&self.prefix);
+        argv.extend(self.args.iter().map(substitute));
+
+        if !self.pipe && !self.args.iter().any(|a| a.hassubst) {
+            argv.push(from_os_bytes(task.to_vec()));
+        }
+
+        if let (Some(staging), Some(login)) = (&self.staging, login) {
+            return Ok(staging.script(tasknum, login, localtask,
+                                     remotetask.as_deref(), &argv));
+        }
+        if let (true, Some(login)) = (self.ssh, login) {
+            let remote = quote_sh_cmd(&argv);
+            argv = ssh_prefix(login);
+            argv.push(remote);
+        }
+
+        Ok(argv)
+    }
+
+    /// How the command line for a task is built, for `--explain`: the
+    /// arguments of the template, the substitution strings in each and
+    /// what they are replaced with, and the command line that results, one
+    /// argument per line.  The command is taken to run here, not with ssh,
+    /// in job slot `slot`.
+    pub fn explain(&self, tasknum: usize, task: &OsStr, label: Option<&OsStr>,
+                   slot: usize) -> Result<String, Error> {
+
+        let argv = self.build_argv(tasknum, task, None, label, Some(slot))?;
+        let slot = slot.to_string();
+        let task = task.as_encoded_bytes();
+        let (columns, doc) = self.parse_task(tasknum, task)?;
+        let vars = Vars {
+            doc:   doc.as_ref(),
+            cwd:   self.cwd.as_deref(),
+            host:  None,
+            label: label.map(OsStr::as_encoded_bytes),
+            slot:  Some(slot.as_bytes())
+        };
+        let show = |s: &[u8]| quote_arg(&String::from_utf8_lossy(s));
+        // Writing to a Vec cannot fail.
+        let mut out = Vec::new();
+        let _ = writeln!(out, "task {}: {}", tasknum, show(task));
+        match self.prefix.len() {
+            1 => {
+                let _ = writeln!(out, "command: {}",
+                                 show(self.prefix[0].as_encoded_bytes()));
+            },
+            _ => {
+                let _ = writeln!(out, "shell: {}", quote_cmd(&self.prefix));
+                let _ = writeln!(out, "    the arguments below follow the \
+                                       script as $1, $2, ...");
+            }
+        }
+        for (i, arg) in self.args.iter().enumerate() {
+            let bytes = arg.arg.as_encoded_bytes();
+            let _ = writeln!(out, "argument {}: {}", i + 1, show(bytes));
+            for mid in tokens(bytes) {
+                let mut token = b"{".to_vec();
+                token.extend_from_slice(mid);
+                token.push(b'}');
+                let token = String::from_utf8_lossy(&token);
+                let _ = match subst_task(token.as_bytes(), tasknum, task,
+                                         &columns, &vars) {
+                    Some(value) =>
+                        writeln!(out, "    {} -> {}", token, show(&value)),
+                    None => writeln!(out, "    {} is left as it is", token)
+                };
+            }
+        }
+        if !self.args.iter().any(|a| a.hassubst) {
+            let _ = writeln!(out, "argument {}: the task, as no argument \
+                                   has a substitution string",
+                             self.args.len() + 1);
+        }
+        let _ = writeln!(out, "argv:");
+        for (i, arg) in argv.iter().enumerate() {
+            let _ = writeln!(out, "    [{}] {}", i,
+                             show(arg.as_encoded_bytes()));
+        }
+        let _ = writeln!(out, "command line: {}", quote_cmd(&argv));
+        // Everything written was quoted lossily.
+        Ok(String::from_utf8(out).expect("UTF-8"))
+    }
+
+    // The columns of a task and, with JSON input, its document.  With
+    // `strict`, the task must have every column and field referred to.
+    fn parse_task<'t>(&self, tasknum: usize, task: &'t [u8])
+        -> Result<(Vec<&'t [u8]>, Option<json::Value>), Error> {
+
+        let columns = match self.colsep {
+            Some(ref sep) if self.maxcolumn > 0 =>
+                split_columns(task, sep.as_encoded_bytes()),
+            _ => vec![task]
+        };
+        if self.strict && self.maxcolumn > columns.len() {
+            return Err(Error::Template { tasknum, column: self.maxcolumn });
+        }
+        let doc = if self.json && !self.fields.is_empty() {
+            match json::parse(task) {
+                Ok(doc) => Some(doc),
+                Err(err) => return Err(Error::Json {
+                    tasknum,
+                    message: err.to_string()
+                })
+            }
+        } else {
+            None
+        };
+        if let (true, Some(doc)) = (self.strict, &doc) {
+            if let Some(path) = self.fields.iter()
+                .find(|path| doc.lookup(path).is_none())
+            {
+                return Err(Error::Field {
+                    tasknum,
+                    field: String::from_utf8_lossy(path).into_owned()
+                });
+            }
+        }
+        Ok((columns, doc))
+    }
+
+    /// What to write to the standard input of the command for a task, with
+    /// `stdin_column` or `pipe`.  Fails if `strict` and the task has no
+    /// such column.
+    pub fn build_input(&self, tasknum: usize, task: &OsStr)
+        -> Result<Option<Vec<u8>>, Error> {
+
+        if self.pipe {
+            return Ok(Some(task.as_encoded_bytes().to_vec()));
+        }
+        let (column, sep) = match (self.stdincolumn, &self.colsep) {
+            (Some(column), Some(sep)) => (column, sep),
+            _ => return Ok(None)
+        };
+        let columns = split_columns(task.as_encoded_bytes(),
+                                    sep.as_encoded_bytes());
+        match columns.get(column - 1) {
+            Some(data) => Ok(Some(data.to_vec())),
+            None if self.strict => Err(Error::Template { tasknum, column }),
+            None => Ok(Some(Vec::new()))
+        }
+    }
+}
+
+impl TempScript {
+    fn create(script: &[u8]) -> io::Result<TempScript> {
+        let path = env::temp_dir()
+            .join(format!("ljobs-script-{}", process::id()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let script_error = |err: io::Error| {
+            io::Error::new(err.kind(), format!("script file {}: {}",
+                                               path.display(), err))
+        };
+        let mut file = options.open(&path).map_err(script_error)?;
+        // Removed again if it cannot be written.
+        let temp = TempScript { path: path.clone() };
+        file.write_all(script).map_err(script_error)?;
+        Ok(temp)
+    }
+}
+
+impl Drop for TempScript {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl ShellTemplate {
+    pub fn new(shell: &OsStr, cmdline: &OsStr) -> ShellTemplate {
+        let (script, params) = positional(cmdline.as_encoded_bytes());
+        ShellTemplate {
+            shell:  shell.to_os_string(),
+            script: from_os_bytes(script),
+            params
+        }
+    }
+
+    pub fn build_argv(&self, tasknum: usize, task: &OsStr) -> Vec<OsString> {
+        let task = task.as_encoded_bytes();
+        let mut argv = shell_prefix(&self.shell,
+                                    &self.script_for(tasknum, task));
+        for param in &self.params {
+            let arg = subst(param, tasknum, task).expect("substitution");
+            argv.push(from_os_bytes(arg));
+        }
+        argv
+    }
+
+    #[cfg(unix)]
+    fn script_for(&self, _tasknum: usize, _task: &[u8]) -> OsString {
+        self.script.clone()
+    }
+
+    // Without positional parameters the task is substituted as it is.
+    #[cfg(windows)]
+    fn script_for(&self, tasknum: usize, task: &[u8]) -> OsString {
+        match subst(self.script.as_encoded_bytes(), tasknum, task) {
+            Some(script) => from_os_bytes(script),
+            None => self.script.clone()
+        }
+    }
+}
+
+/// The shell to run command lines with: `SHELL` or else `/bin/sh` on Unix,
+/// and `COMSPEC` or else `cmd.exe` on Windows.
+#[cfg(unix)]
+pub fn default_shell() -> OsString {
+    env::var_os("SHELL").unwrap_or_else(|| OsString::from("/bin/sh"))
+}
+
+/// The shell to run command lines with: `SHELL` or else `/bin/sh` on Unix,
+/// and `COMSPEC` or else `cmd.exe` on Windows.
+#[cfg(windows)]
+pub fn default_shell() -> OsString {
+    env::var_os("COMSPEC").unwrap_or_else(|| OsString::from("cmd.exe"))
+}
+
+/// The default shell and the arguments to give it before a command line
+/// for it to run, for tasks that are whole command lines.
+pub fn shell_invocation() -> Vec<OsString> {
+    let mut argv = shell_prefix(&default_shell(), OsStr::new(""));
+    argv.truncate(2);
+    argv
+}
+
+// Replace each substitution string in a shell command line with a
+// positional parameter, returning the new command line and the
+// substitution string for each parameter in turn.
+#[cfg(unix)]
+fn positional(s: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+    let mut script = Vec::with_capacity(s.len());
+    let mut params = Vec::new();
+    let mut ss = s;
+    while let Some(open) = find(ss, b'{') {
+        let token = match find(&ss[open..], b'}') {
+            Some(close0) => &ss[open..open+close0+1],
+            None => break
+        };
+        script.extend_from_slice(&ss[..open]);
+        if subst(token, 0, b"").is_some() {
+            params.push(token.to_vec());
+            // Writing to a Vec cannot fail.
+            let _ = write!(script, "\"${{{}}}\"", params.len());
+            ss = &ss[open+token.len()..];
+        } else {
+            script.push(b'{');
+            ss = &ss[open+1..];
+        }
+    }
+    script.extend_from_slice(ss);
+    (script, params)
+}
+
+#[cfg(windows)]
+fn positional(s: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+    (s.to_vec(), Vec::new())
+}
+
+// The shell command line `cmd` with every `$` and backquote escaped, except
+// within single quotes where they mean nothing, and `~` escaped outside
+// quotes, so that the shell makes no expansions or substitutions, and with
+// globbing turned off.  The command arguments are appended to the command.
+#[cfg(unix)]
+fn literal_command(cmd: &[u8]) -> Vec<u8> {
+    let mut literal = b"set -f; ".to_vec();
+    let mut quote = None;
+    let mut escaped = false;
+    for &c in cmd {
+        match c {
+            _ if escaped => escaped = false,
+            b'\\' if quote != Some(b'\'') => escaped = true,
+            b'\'' | b'"' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            b'$' | b'`' if quote != Some(b'\'') => literal.push(b'\\'),
+            b'~' if quote.is_none() => literal.push(b'\\'),
+            _ => ()
+        }
+        literal.push(c);
+    }
+    literal.extend_from_slice(b" \"$@\"");
+    literal
+}
+
+// Windows shells are not given literal commands (see run_with_events).
+#[cfg(windows)]
+fn literal_command(cmd: &[u8]) -> Vec<u8> {
+    cmd.to_vec()
+}
+
+// The command arguments are passed to a Unix shell as positional parameters.
+#[cfg(unix)]
+fn shell_prefix(shell: &OsStr, cmd: &OsStr) -> Vec<OsString> {
+    vec![
+        shell.to_os_string(),
+        OsString::from("-c"),
+        cmd.to_os_string(),
+        OsString::from("-")
+    ]
+}
+
+// cmd.exe and PowerShell have no positional parameters, so the command
+// arguments are simply appended to the command.
+#[cfg(windows)]
+fn shell_prefix(shell: &OsStr, cmd: &OsStr) -> Vec<OsString> {
+    let name = basename(shell.as_encoded_bytes()).to_ascii_lowercase();
+    let flag = if name.starts_with(b"powershell") || name.starts_with(b"pwsh")
+    {
+        "-Command"
+    } else {
+        "/C"
+    };
+    vec![shell.to_os_string(), OsString::from(flag), cmd.to_os_string()]
+}
+
+/// Replace the substitution strings `{}`, `{.}`, `{/}`, `{//}`, `{/.}` and
+/// `{#}` in `s` with parts of `task` or with `tasknum`.  Returns `None` if
+/// there were no substitution strings in `s`.
+///
+/// ```
+/// use ljobs::template::subst;
+///
+/// assert_eq!(subst(b"{/.}.png", 0, b"photos/cat.jpg").unwrap(), b"cat.png");
+/// assert_eq!(subst(b"part{#}", 7, b"x").unwrap(), b"part7");
+/// assert_eq!(subst(b"{x}", 0, b"x"), None);
+/// ```
+pub fn subst(s: &[u8], tasknum: usize, task: &[u8]) -> Option<Vec<u8>> {
+    subst_columns(s, tasknum, task, &[task])
+}
+
+/// As `subst`, but also replace `{N}` with column `N` of the task, counting
+/// from 1.  A column that the task does not have is replaced with nothing.
+///
+/// ```
+/// use ljobs::template::subst_columns;
+///
+/// let columns = [&b"cat.jpg"[..], b"90"];
+/// assert_eq!(subst_columns(b"-q{2}", 0, b"cat.jpg,90", &columns).unwrap(),
+///            b"-q90");
+/// assert_eq!(subst_columns(b"[{3}]", 0, b"cat.jpg,90", &columns).unwrap(),
+///            b"[]");
+/// ```
+pub fn subst_columns(s: &[u8], tasknum: usize, task: &[u8],
+                     columns: &[&[u8]]) -> Option<Vec<u8>> {
+    subst_task(s, tasknum, task, columns, &Vars::default())
+}
+
+// As `subst_columns`, but also replace `{.field}` with a field of the task
+// given as a JSON document, `{cwd}` with the current directory, `{host}`
+// with the ssh login, `{label}` with the label of the job and `{%}` with its
+// job slot.
+fn subst_task(s: &[u8], tasknum: usize, task: &[u8], columns: &[&[u8]],
+              vars: &Vars) -> Option<Vec<u8>> {
+
+    let mut acc = Vec::with_capacity(s.len() + task.len());
+    let mut ss = s;
+    let mut found = false;
+
+    while !ss.is_empty() {
+        if let Some(open) = find(ss, b'{') {
+            if let Some(close0) = find(&ss[open..], b'}') {
+                acc.extend_from_slice(&ss[..open]);
+                let close = open + close0;
+                let mid = &ss[open+1..close];
+                let next;
+                match mid {
+                    b"" => {
+                        acc.extend_from_slice(task);
+                        next = close+1;
+                        found = true;
+                    },
+                    b"." => {
+                        acc.extend_from_slice(remove_extension(task));
+                        next = close+1;
+                        found = true;
+                    },
+                    b"/" => {
+                        acc.extend_from_slice(basename(task));
+                        next = close+1;
+                        found = true;
+                    },
+                    b"//" => {
+                        acc.extend_from_slice(dirname(task));
+                        next = close+1;
+                        found = true;
+                    },
+                    b"/." => {
+                        acc.extend_from_slice(
+                            remove_extension(basename(task)));
+                        next = close+1;
+                        found = true;
+                    },
+                    b"#" => {
+                        // Writing to a Vec cannot fail.
+                        let _ = write!(acc, "{}", tasknum);
+                        next = close+1;
+                        found = true;
+                    },
+                    _ => {
+                        if let Some(n) = column_token(mid) {
+                            if let Some(column) = columns.get(n-1) {
+                                acc.extend_from_slice(column);
+                            }
+                            next = close+1;
+                            found = true;
+                        } else if let (Some(doc), Some(path)) =
+                            (vars.doc, field_token(mid))
+                        {
+                            if let Some(value) = doc.lookup(path) {
+                                value.write_text(&mut acc);
+                            }
+                            next = close+1;
+                            found = true;
+                        } else if let Some(value) = vars.get(mid) {
+                            acc.extend_from_slice(value);
+                            next = close+1;
+                            found = true;
+                        } else {
+                            acc.push(b'{');
+                            next = open+1;
+                        }
+                    }
+                }
+                ss = &ss[next..];
+            } else {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+
+    acc.extend_from_slice(ss);
+
+    if found {
+        Some(acc)
+    } else {
+        None
+    }
+}
+
+impl<'a> Vars<'a> {
+    // The value of a `{cwd}`, `{host}` or `{label}` token, given what is
+    // between the braces.
+    fn get(&self, mid: &[u8]) -> Option<&'a [u8]> {
+        match mid {
+            b"cwd" => self.cwd,
+            b"host" => self.host,
+            b"label" => self.label,
+            b"%" => self.slot,
+            _ => None
+        }
+    }
+}
+
+// The command line to run a command on `login` with ssh, to which the
+// command is added as a single argument to be run by the remote shell.
+// Batch mode stops ssh from asking for a password.
+pub(crate) fn ssh_prefix(login: &OsStr) -> Vec<OsString> {
+    vec![
+        OsString::from("ssh"),
+        OsString::from("-o"),
+        OsString::from("BatchMode=yes"),
+        login.to_os_string()
+    ]
+}
+
+// The argument `NAME=VALUE` to set a variable in a container with `--env`.
+fn env_assignment(name: &str, value: &OsStr) -> OsString {
+    let mut arg = OsString::from(name);
+    arg.push("=");
+    arg.push(value);
+    arg
+}
+
+// The current directory as bytes, for `{cwd}`.
+fn current_dir() -> Result<Vec<u8>, Error> {
+    match env::current_dir() {
+        Ok(dir) => Ok(dir.into_os_string().into_encoded_bytes()),
+        Err(err) => Err(Error::Io(io::Error::new(err.kind(),
+            format!("cannot get current directory: {}", err))))
+    }
+}
+
+// The column number of a `{N}` token, given what is between the braces.
+fn column_token(mid: &[u8]) -> Option<usize> {
+    if mid.is_empty() || !mid.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    // Digits are valid UTF-8.
+    match std::str::from_utf8(mid).ok()?.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(n) => Some(n)
+    }
+}
+
+// The field path of a `{.field}` token, given what is between the braces.
+// Fields are named by letters, digits, `_` and `-`, and nested fields are
+// separated by dots.
+fn field_token(mid: &[u8]) -> Option<&[u8]> {
+    let path = mid.strip_prefix(b".")?;
+    let valid = |name: &[u8]| !name.is_empty() && name.iter()
+        .all(|&c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+    if path.split(|&c| c == b'.').all(valid) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+// What is between the braces of everything in `s` that may be a token, as
+// `subst` would come across them.
+fn tokens(s: &[u8]) -> Vec<&[u8]> {
+    let mut mids = Vec::new();
+    let mut ss = s;
+    while let Some(open) = find(ss, b'{') {
+        match find(&ss[open..], b'}') {
+            Some(close0) => {
+                mids.push(&ss[open+1..open+close0]);
+                ss = &ss[open+1..];
+            },
+            None => break
+        }
+    }
+    mids
+}
+
+// Split a task into columns at each occurrence of `sep`.
+fn split_columns<'a>(task: &'a [u8], sep: &[u8]) -> Vec<&'a [u8]> {
+    let mut columns = Vec::new();
+    let mut rest = task;
+    while let Some(i) = rest.windows(sep.len()).position(|w| w == sep) {
+        columns.push(&rest[..i]);
+        rest = &rest[i+sep.len()..];
+    }
+    columns.push(rest);
+    columns
+}
+
+/*
+fn subst(s: &str, tasknum: usize, task: &str) -> String {
+
+    // Using regex as Rust standard library does not provide a simple way to
+    // perform multiple string replacement. It could be written more directly.
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"\{\}|\{\.\}|\{/\}|\{//\}|\{/\.\}|\{#\}").unwrap();
+    }
+    let subster = Subster {
+        tasknum: tasknum,
+        task: task
+    };
+    return RE.replace_all(s, subster);
+}
+
+struct Subster<'a> {
+    tasknum: usize,
+    task: &'a str
+}
+
+impl<'a> regex::Replacer for Subster<'a> {
+
+    fn reg_replace(&mut self, caps: &regex::Captures) -> Cow<str> {
+        match caps.at(0) {
+            Some("{}") => Borrowed(&self.task),
+            Some("{.}") => Borrowed(remove_extension(&self.task)),
+            Some("{/}") => Borrowed(basename(&self.task)),
+            Some("{//}") => Borrowed(dirname(&self.task)),
+            Some("{/.}") => Borrowed(remove_extension(basename(&self.task))),
+            Some("{#}") => Owned(self.tasknum.to_string()),
+            Some(s) => panic!("unexpected capture {}", s),
+            None => panic!("unexpected capture None")
+        }
+    }
+
+    fn no_expand(&mut self) -> Option<Cow<str>> {
+        None
+    }
+}
+*/
+
+// std::path is too subtle...
+
+/// The last component of a path, ignoring trailing slashes.
+///
+/// ```
+/// use ljobs::template::basename;
+///
+/// assert_eq!(basename(b"dir/file.txt"), b"file.txt");
+/// assert_eq!(basename(b"dir/sub/"), b"sub");
+/// assert_eq!(basename(b"/"), b"/");
+/// ```
+pub fn basename(s: &[u8]) -> &[u8] {
+    let s = remove_redundant_trailing_slashes(s);
+    match rfind_sep(s) {
+        Some(i) if i + 1 < s.len() => &s[i+1..],
+        _ => s
+    }
+}
+
+/// The extension of the last component of a path, including the dot.
+/// Dots at the start of the last component do not begin an extension, so
+/// dotfiles such as `.bashrc`, and `.` and `..`, have none.
+///
+/// ```
+/// use ljobs::template::extension;
+///
+/// assert_eq!(extension(b"dir.d/file.tar.gz"), Some(&b".gz"[..]));
+/// assert_eq!(extension(b"dir.d/file"), None);
+/// assert_eq!(extension(b"home/.bashrc"), None);
+/// assert_eq!(extension(b"home/.config.bak"), Some(&b".bak"[..]));
+/// ```
+pub fn extension(s: &[u8]) -> Option<&[u8]> {
+    let base = basename(s);
+    let i = rfind(base, b'.')?;
+    if base[..i].iter().all(|&c| c == b'.') {
+        None
+    } else {
+        Some(&base[i..]) // including dot
+    }
+}
+
+/// A path without the extension of its last component.  A path ending in a
+/// slash is left alone.
+///
+/// ```
+/// use ljobs::template::remove_extension;
+///
+/// assert_eq!(remove_extension(b"dir/file.tar.gz"), b"dir/file.tar");
+/// assert_eq!(remove_extension(b"dir.d/"), b"dir.d/");
+/// assert_eq!(remove_extension(b"dir/.bashrc"), b"dir/.bashrc");
+/// ```
+pub fn remove_extension(s: &[u8]) -> &[u8] {
+    match extension(s) {
+        Some(ext) if s.ends_with(ext) => &s[..s.len()-ext.len()],
+        _ => s
+    }
+}
+
+/// All but the last component of a path, or `.` if there is only one.
+///
+/// ```
+/// use ljobs::template::dirname;
+///
+/// assert_eq!(dirname(b"dir/sub/file.txt"), b"dir/sub");
+/// assert_eq!(dirname(b"file.txt"), b".");
+/// assert_eq!(dirname(b"/file.txt"), b"/");
+/// ```
+pub fn dirname(s: &[u8]) -> &[u8] {
+    let s = remove_redundant_trailing_slashes(s);
+    match rfind_sep(s) {
+        None => b".",
+        Some(0) => &s[..1],
+        Some(i) => remove_redundant_trailing_slashes(&s[..i])
+    }
+}
+
+// Remove trailing slashes but not a leading slash.
+fn remove_redundant_trailing_slashes(s: &[u8]) -> &[u8] {
+    let mut end = s.len();
+    while end > 1 && is_sep(s[end-1]) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(unix)]
+fn is_sep(c: u8) -> bool {
+    c == b'/'
+}
+
+#[cfg(windows)]
+fn is_sep(c: u8) -> bool {
+    c == b'/' || c == b'\\'
+}
+
+fn find(s: &[u8], c: u8) -> Option<usize> {
+    s.iter().position(|&x| x == c)
+}
+
+fn rfind(s: &[u8], c: u8) -> Option<usize> {
+    s.iter().rposition(|&x| x == c)
+}
+
+fn rfind_sep(s: &[u8]) -> Option<usize> {
+    s.iter().rposition(|&x| is_sep(x))
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::{basename, dirname, remove_extension};
+
+    #[test]
+    fn either_separator() {
+        assert_eq!(basename(br"dir\sub\file.txt"), b"file.txt");
+        assert_eq!(basename(br"dir/sub\file.txt"), b"file.txt");
+        assert_eq!(basename(br"dir\sub/file.txt"), b"file.txt");
+        assert_eq!(dirname(br"dir\sub\file.txt"), br"dir\sub");
+        assert_eq!(dirname(br"dir\sub/file.txt"), br"dir\sub");
+        assert_eq!(remove_extension(br"dir.d\file"), br"dir.d\file");
+        assert_eq!(remove_extension(br"C:\dir\file.txt"), br"C:\dir\file");
+    }
+
+    #[test]
+    fn trailing_separators() {
+        assert_eq!(dirname(br"dir\sub\\"), b"dir");
+        assert_eq!(dirname(br"\file"), br"\");
+        assert_eq!(dirname(br"\"), br"\");
+        assert_eq!(dirname(b"file"), b".");
+    }
+}
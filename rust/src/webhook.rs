@@ -0,0 +1,323 @@
+/*
+** Telling a web service that a run has ended.
+*/
+
+// With --webhook, a small JSON document about the run is POSTed to a URL
+// once the run has ended:
+//
+//     {"ok":9,"failed":1,"runtime":12.345,"exit_status":1,
+//      "hostname":"build1"}
+//
+// These members are always present and keep their meaning; members may be
+// added.  A plain http:// URL is handled by a minimal HTTP/1.1 client
+// built in with the `http` feature, which is on by default.  Other URLs,
+// such as https://, and all URLs without the feature, are handed to curl.
+// Either way the request gives up after TIMEOUT.
+
+#[cfg(unix)]
+use libc;
+use std::io::{self, Write};
+#[cfg(feature = "http")]
+use std::io::{BufRead, BufReader};
+#[cfg(feature = "http")]
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use json::{self, Value};
+use {RunSummary, TaskStatus};
+
+/// How long to wait for the request to be answered.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/*---------------------------------------------------------------------------*/
+
+/// The document to POST for a run that ended with `exit_status`.
+pub fn payload(summary: &RunSummary, exit_status: i32, hostname: &str)
+    -> Vec<u8> {
+
+    let ok = summary.tasks.iter()
+        .filter(|t| matches!(t.status, TaskStatus::Exited(0)))
+        .count();
+    let members = vec![
+        ("ok", Value::Number(ok.to_string())),
+        ("failed", Value::Number(summary.errors.to_string())),
+        ("runtime",
+         Value::Number(format!("{:.3}", summary.elapsed.as_secs_f64()))),
+        ("exit_status", Value::Number(exit_status.to_string())),
+        ("hostname", Value::String(String::from(hostname)))
+    ];
+    let mut out = Vec::new();
+    json::object(members).write_json(&mut out);
+    out
+}
+
+/// The name of this host, or nothing if it cannot be found.
+#[cfg(unix)]
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let ret = unsafe {
+        libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len())
+    };
+    if ret < 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// The name of this host, or nothing if it cannot be found.
+#[cfg(windows)]
+pub fn hostname() -> String {
+    ::std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// Whether `url` is one that `post` can try.
+pub fn valid_url(url: &str) -> bool {
+    ["http://", "https://"].iter()
+        .any(|scheme| url.len() > scheme.len() &&
+                      has_scheme(url, scheme))
+}
+
+// Whether `url` starts with `scheme`, in any case.  The prefix of the same
+// length may not end at a character boundary.
+fn has_scheme(url: &str, scheme: &str) -> bool {
+    url.get(..scheme.len()).is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+}
+
+/// POST `body` as JSON to `url`.  An error is returned if the service could
+/// not be reached in time or did not answer with a 2xx status.
+pub fn post(url: &str, body: &[u8]) -> io::Result<()> {
+    #[cfg(feature = "http")]
+    {
+        if let Some(target) = HttpUrl::parse(url) {
+            return post_http(&target, body);
+        }
+    }
+    post_curl(url, body)
+}
+
+/*---------------------------------------------------------------------------*/
+
+// The parts of a plain http:// URL.
+#[cfg(feature = "http")]
+#[derive(Debug, PartialEq)]
+struct HttpUrl<'a> {
+    host:       &'a str,
+    port:       u16,
+    path:       &'a str
+}
+
+#[cfg(feature = "http")]
+impl<'a> HttpUrl<'a> {
+    // None unless `url` is http://HOST[:PORT][/PATH].
+    fn parse(url: &'a str) -> Option<HttpUrl<'a>> {
+        const SCHEME: &str = "http://";
+        if url.len() <= SCHEME.len() || !has_scheme(url, SCHEME) {
+            return None;
+        }
+        let rest = &url[SCHEME.len()..];
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/")
+        };
+        // A bracketed IPv6 address has colons of its own.
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') =>
+                (&authority[..i], authority[i+1..].parse().ok()?),
+            _ => (authority, 80)
+        };
+        if host.is_empty() || host.contains('@') {
+            return None;
+        }
+        Some(HttpUrl { host, port, path })
+    }
+
+    // The host as it goes in the Host header.
+    fn host_header(&self) -> String {
+        if self.port == 80 {
+            String::from(self.host)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+fn post_http(url: &HttpUrl, body: &[u8]) -> io::Result<()> {
+    let host = url.host.trim_start_matches('[').trim_end_matches(']');
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound,
+                                      "host has no address");
+    let mut stream = None;
+    for addr in (host, url.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            },
+            Err(err) => last_err = err
+        }
+    }
+    let mut stream = stream.ok_or(last_err)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         User-Agent: ljobs\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        url.path, url.host_header(), body.len()).into_bytes();
+    request.extend_from_slice(body);
+    stream.write_all(&request)?;
+
+    // Only the status line matters.
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    check_status(status.trim_end())
+}
+
+// Whether the status line of a response says the request succeeded.
+#[cfg(feature = "http")]
+fn check_status(line: &str) -> io::Result<()> {
+    let mut words = line.splitn(3, ' ');
+    match (words.next(), words.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/") => {
+            if code.starts_with('2') {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("server said {}", code)))
+            }
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                "not an HTTP response"))
+    }
+}
+
+fn post_curl(url: &str, body: &[u8]) -> io::Result<()> {
+    let mut child = Command::new("curl")
+        .args(["-sS", "--fail", "--max-time"])
+        .arg(TIMEOUT.as_secs().to_string())
+        .args(["-o", if cfg!(windows) { "NUL" } else { "/dev/null" }])
+        .args(["-H", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", "--", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| io::Error::new(err.kind(),
+                                      format!("cannot run curl: {}", err)))?;
+    let written = child.stdin.take().expect("stdin").write_all(body);
+    let status = child.wait()?;
+    written?;
+    if !status.success() {
+        return Err(io::Error::other(format!("curl failed ({})", status)));
+    }
+    Ok(())
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::{payload, valid_url};
+    use std::time::Duration;
+    use RunSummary;
+
+    #[test]
+    fn document() {
+        let summary = RunSummary {
+            tasks:         Vec::new(),
+            filtered:      0,
+            errors:        2,
+            command_errors: 2,
+            failed_exit:   1,
+            aborted:       false,
+            peak_buffered: 0,
+            elapsed:       Duration::from_millis(1500)
+        };
+        assert_eq!(payload(&summary, 2, "build\"1"),
+                   &b"{\"ok\":0,\"failed\":2,\"runtime\":1.500,\
+                      \"exit_status\":2,\"hostname\":\"build\\\"1\"}"[..]);
+    }
+
+    #[test]
+    fn urls() {
+        assert!(valid_url("http://localhost:8080/hook"));
+        assert!(valid_url("HTTPS://example.com"));
+        assert!(!valid_url("http://"));
+        assert!(!valid_url("ftp://example.com"));
+        assert!(!valid_url("example.com"));
+        assert!(!valid_url("http:/éx"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn http_urls() {
+        use super::HttpUrl;
+        assert_eq!(HttpUrl::parse("http://example.com"),
+                   Some(HttpUrl { host: "example.com", port: 80, path: "/" }));
+        assert_eq!(HttpUrl::parse("http://[::1]:8080/a/b?c=d"),
+                   Some(HttpUrl { host: "[::1]", port: 8080,
+                                  path: "/a/b?c=d" }));
+        assert_eq!(HttpUrl::parse("http://[::1]/"),
+                   Some(HttpUrl { host: "[::1]", port: 80, path: "/" }));
+        assert_eq!(HttpUrl::parse("http://host:x/"), None);
+        assert_eq!(HttpUrl::parse("http://user@host/"), None);
+        assert_eq!(HttpUrl::parse("https://example.com/"), None);
+        assert_eq!(HttpUrl::parse("http:/éx"), None);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn post() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+        use std::thread;
+
+        // Answer one request with `status`, and return the request.
+        fn serve(status: &'static str) -> (String, thread::JoinHandle<Vec<u8>>)
+        {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/hook",
+                              listener.local_addr().unwrap());
+            let server = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = Vec::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    request.extend_from_slice(line.as_bytes());
+                    if let Some(n) = line.strip_prefix("Content-Length: ") {
+                        length = n.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                request.extend_from_slice(&body);
+                write!(reader.get_mut(), "HTTP/1.1 {}\r\n\r\n", status)
+                    .unwrap();
+                request
+            });
+            (url, server)
+        }
+
+        let (url, server) = serve("204 No Content");
+        super::post(&url, b"{\"ok\":1}").unwrap();
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"), "{}", request);
+        assert!(request.contains("\r\nContent-Type: application/json\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"ok\":1}"), "{}", request);
+
+        let (url, server) = serve("500 Internal Server Error");
+        let err = super::post(&url, b"{}").unwrap_err();
+        assert_eq!(err.to_string(), "server said 500");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+// Tests of the ljobs binary built by cargo.  They run Unix commands.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// Shell tests of options that need a feature, which are left out of a
+// build without it.
+const FEATURE_TESTS: &[(&str, bool)] = &[
+    ("abort_on_match.sh",  cfg!(feature = "regex")),
+    ("filter_tasks.sh",    cfg!(feature = "regex")),
+    ("output_compress.sh", cfg!(feature = "gzip")),
+    ("retry_after.sh",     cfg!(feature = "regex"))
+];
+
+fn feature_enabled(script: &str) -> bool {
+    FEATURE_TESTS.iter()
+        .all(|&(name, enabled)| name != script || enabled)
+}
+
+// Run the shell test suite in the top-level tests directory.
+#[test]
+fn shell_tests() {
+    let testdir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests");
+    let mut scripts: Vec<String> = fs::read_dir(&testdir)
+        .expect("could not read tests directory")
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".sh") && feature_enabled(name))
+        .collect();
+    scripts.sort();
+
+    let mut failed = Vec::new();
+    for script in &scripts {
+        let output = Command::new("sh")
+            .arg("./runtest")
+            .arg(script)
+            .current_dir(&testdir)
+            .env("TESTRUST", "1")
+            .env("LJOBS_BIN", env!("CARGO_BIN_EXE_ljobs"))
+            // Leave out any defaults of the user running the tests.
+            .env_remove("LJOBS")
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .output()
+            .expect("could not run runtest");
+        if !output.status.success() {
+            print!("{}", String::from_utf8_lossy(&output.stdout));
+            failed.push(script.clone());
+        }
+    }
+    assert!(failed.is_empty(), "failed tests: {:?}", failed);
+}
+
+// Peak resident set size of a process in kB.
+fn peak_rss(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status.lines()
+        .find(|line| line.starts_with("VmHWM:"))
+        .and_then(|line| line["VmHWM:".len()..].trim()
+                  .trim_end_matches(" kB").parse().ok())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn large_output_is_spilled() {
+    // About 100MB of output, which is reproduced exactly without being
+    // held in memory.
+    let numlines = 13_000_000;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ljobs"))
+        .args(["-c", "seq 1 \"$1\""])
+        .arg(":::")
+        .arg(numlines.to_string())
+        .env("SHELL", "/bin/sh")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("could not run ljobs");
+
+    let mut peak = 0;
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    for i in 1..=numlines {
+        let line = lines.next().expect("missing output").unwrap();
+        assert_eq!(line, i.to_string());
+        if i % 1_000_000 == 0 {
+            peak = peak.max(peak_rss(child.id()).unwrap_or(0));
+        }
+    }
+    assert!(lines.next().is_none());
+    assert!(child.wait().unwrap().success());
+    assert!(peak > 0 && peak < 40_000, "peak RSS {} kB", peak);
+}
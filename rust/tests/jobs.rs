@@ -0,0 +1,370 @@
+// Tests of the ljobs binary running fake jobs whose behaviour is given by
+// their task.  They run Unix commands.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// A fake job performs the actions in its last argument in turn:
+//
+//     sleep SECS      sleep
+//     out N           write N bytes "o" to standard output
+//     err N           write N bytes "e" to standard error
+//     exit N          exit with status N
+//     kill SIG        kill itself with signal SIG
+//
+// e.g. "out 3 sleep 0.1 exit 2".
+const FAKEJOB: &str = r#"#!/bin/sh
+set -- $1
+while test $# -gt 1
+do
+    case $1 in
+        sleep) sleep "$2" ;;
+        out) head -c "$2" /dev/zero | tr '\0' o ;;
+        err) head -c "$2" /dev/zero | tr '\0' e >&2 ;;
+        exit) exit "$2" ;;
+        kill) kill -"$2" $$ ;;
+        *) echo "fakejob: bad action $1" >&2; exit 99 ;;
+    esac
+    shift 2
+done
+"#;
+
+struct Harness {
+    dir:        PathBuf,
+    fakejob:    PathBuf
+}
+
+struct Run {
+    code:       i32,
+    stdout:     String,
+    stderr:     String
+}
+
+impl Harness {
+    fn new(name: &str) -> Harness {
+        let dir = std::env::temp_dir()
+            .join(format!("ljobs-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let fakejob = dir.join("fakejob");
+        fs::write(&fakejob, FAKEJOB).unwrap();
+        fs::set_permissions(&fakejob, fs::Permissions::from_mode(0o755))
+            .unwrap();
+        Harness { dir, fakejob }
+    }
+
+    // Run ljobs with `opts` on the fake job, with tasks after `:::`.
+    fn run(&self, opts: &[&str], tasks: &[&str]) -> Run {
+        let mut args: Vec<&str> = opts.to_vec();
+        args.push(self.fakejob.to_str().unwrap());
+        args.push(":::");
+        args.extend_from_slice(tasks);
+        self.ljobs(&args, None)
+    }
+
+    // Run ljobs with `args`, and `input` on standard input if given.
+    fn ljobs(&self, args: &[&str], input: Option<&str>) -> Run {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ljobs"))
+            .args(args)
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("could not run ljobs");
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(input) = input {
+            stdin.write_all(input.as_bytes()).unwrap();
+        }
+        drop(stdin);
+        let output = child.wait_with_output().unwrap();
+        Run {
+            code:   output.status.code().expect("ljobs killed by signal"),
+            stdout: String::from_utf8(output.stdout).unwrap(),
+            stderr: String::from_utf8(output.stderr).unwrap()
+        }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/*---------------------------------------------------------------------------*/
+
+#[test]
+fn success() {
+    let h = Harness::new("success");
+    let run = h.run(&["-j1"], &["out 1", "out 2 err 3", "exit 0"]);
+    assert_eq!(run.code, 0);
+    assert_eq!(run.stdout, "ooo");
+    assert_eq!(run.stderr, "eee");
+}
+
+#[test]
+fn exit_status_without_keep_going() {
+    // The status of the failed task, and nothing more is started.
+    let h = Harness::new("nokeepgoing");
+    let run = h.run(&["-j1"], &["out 1", "out 1 exit 3", "out 1", "exit 4"]);
+    assert_eq!(run.code, 3);
+    assert_eq!(run.stdout, "oo");
+}
+
+#[test]
+fn exit_status_with_keep_going() {
+    // The number of failed tasks, and everything is started.
+    let h = Harness::new("keepgoing");
+    let run = h.run(&["-j2", "-k"],
+                    &["out 1 exit 3", "out 1", "exit 1", "out 1 exit 200"]);
+    assert_eq!(run.code, 3);
+    assert_eq!(run.stdout, "ooo");
+}
+
+#[test]
+fn exit_status_cap() {
+    // More than 253 failures give 254, so 255 can mean another error.
+    let h = Harness::new("cap");
+    let tasks = vec!["exit 1"; 300];
+    assert_eq!(h.run(&["-j8", "-k"], &tasks).code, 254);
+    assert_eq!(h.run(&["-j8", "-k"], &tasks[..253]).code, 253);
+    assert_eq!(h.ljobs(&["--no-such-option"], None).code, 255);
+    assert_eq!(h.ljobs(&["--no-implicit-shell"], None).code, 255);
+}
+
+#[test]
+fn signals() {
+    let h = Harness::new("signals");
+    let run = h.run(&["-j1"], &["kill KILL", "out 1"]);
+    assert_eq!(run.code, 128 + 9);
+    assert_eq!(run.stdout, "");
+
+    // With --keep-going a signal is just another failure, unless
+    // --abort-on-signal is given.
+    let run = h.run(&["-j1", "-k"], &["kill TERM", "out 1", "exit 1"]);
+    assert_eq!(run.code, 2);
+    assert_eq!(run.stdout, "o");
+    let run = h.run(&["-j1", "-k", "--abort-on-signal"],
+                    &["exit 1", "kill TERM", "out 1"]);
+    assert_eq!(run.code, 128 + 15);
+    assert_eq!(run.stdout, "");
+}
+
+#[test]
+fn halt_timeout() {
+    // A task that ignores signals and never finishes does not keep ljobs
+    // waiting after another task fails.
+    let h = Harness::new("halttimeout");
+    let start = Instant::now();
+    let run = h.ljobs(&["-j2", "--halt-timeout", "0.5", "-c",
+                        "trap '' HUP INT TERM; $1", ":::",
+                        "sleep 10", "exit 3"], None);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(run.code, 3);
+    assert_eq!(run.stderr, "ljobs: halt timeout: 1 jobs still running\n");
+}
+
+#[test]
+fn spawn_retry() {
+    // With few file descriptors to spare, commands that cannot be started
+    // for want of them are started again later rather than failing.
+    let h = Harness::new("retry");
+    let output = Command::new("sh")
+        .args(["-c", "ulimit -n 12 && exec \"$0\" \"$@\"",
+               env!("CARGO_BIN_EXE_ljobs"), "-j8", "-v",
+               "--limit-open-files", "100",
+               h.fakejob.to_str().unwrap(), ":::"])
+        .args(vec!["sleep 0.2 out 1"; 16])
+        .current_dir(&h.dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(output.stdout, b"o".repeat(16));
+    assert!(stderr.contains(": retry\t"), "{}", stderr);
+    // Only commands that were started are shown as started.
+    assert_eq!(stderr.matches(": start\t").count(), 16);
+}
+
+#[test]
+fn open_file_limit() {
+    // With a low limit on open files, fewer jobs are run at once rather
+    // than any failing to start.
+    let h = Harness::new("openfiles");
+    let output = Command::new("sh")
+        .args(["-c", "ulimit -n 40 && exec \"$0\" \"$@\"",
+               env!("CARGO_BIN_EXE_ljobs"), "-j32", "-v",
+               h.fakejob.to_str().unwrap(), ":::"])
+        .args(vec!["sleep 0.05 out 1"; 32])
+        .current_dir(&h.dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(output.stdout, b"o".repeat(32));
+    assert!(stderr.starts_with("ljobs: running at most 4 jobs at once, \
+                                with a limit of 40 open files\n"),
+            "{}", stderr);
+    assert!(!stderr.contains(": retry\t"), "{}", stderr);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn closed_output() {
+    // Once the reader of the output goes away, the tasks still running are
+    // terminated and ljobs exits quietly.
+    let h = Harness::new("closedoutput");
+    let start = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ljobs"))
+        .args(["-j4", "-c", "echo $1; exec sleep $1", ":::",
+               "0", "0.5", "29.9", "29.9"])
+        .current_dir(&h.dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "0\n");
+    drop(stdout);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stderr, b"");
+    let sleeping = fs::read_dir("/proc").unwrap()
+        .filter_map(|entry| {
+            fs::read(entry.ok()?.path().join("cmdline")).ok()
+        })
+        .filter(|cmdline| cmdline == b"sleep\x0029.9\x00")
+        .count();
+    assert_eq!(sleeping, 0);
+}
+
+#[test]
+fn tasks_from_stdin() {
+    // Tasks from standard input behave as tasks after :::.
+    let h = Harness::new("stdin");
+    let tasks = ["out 1", "out 2 err 1", "out 3 exit 5"];
+    let fakejob = h.fakejob.to_str().unwrap();
+    let run1 = h.run(&["-j1", "-k"], &tasks);
+    let run2 = h.ljobs(&["-j1", "-k", fakejob],
+                       Some(&(tasks.join("\n") + "\n")));
+    assert_eq!(run1.code, 1);
+    assert_eq!(run1.stdout, "o".repeat(6));
+    assert_eq!(run1.stderr, "e");
+    assert_eq!((run1.code, run1.stdout, run1.stderr),
+               (run2.code, run2.stdout, run2.stderr));
+}
+
+#[test]
+fn dry_run() {
+    let h = Harness::new("dryrun");
+    let run = h.ljobs(&["-j1", "-n", "convert", "{}", "{.}.png", ":::",
+                        "a.jpg", "b c.jpg"], None);
+    assert_eq!(run.code, 0);
+    assert_eq!(run.stdout,
+               "[0]\tconvert a.jpg a.png\n[1]\tconvert 'b c.jpg' 'b c.png'\n");
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn verbose_separators() {
+    // With --verbose, standard error of a task is set off by separator
+    // lines naming the task, even without a final newline.
+    let h = Harness::new("verbose");
+    let run = h.run(&["-j1", "-v"], &["out 2 err 3"]);
+    let cmd = format!("{} 'out 2 err 3'", h.fakejob.display());
+    assert_eq!(run.code, 0);
+    assert_eq!(run.stdout, "oo");
+    assert_eq!(run.stderr, format!(
+        "ljobs[0]: start\t{cmd}\n\
+         -------- ljobs[0]: {cmd} --------\n\
+         eee--------\n\
+         ljobs[0]: done\t{cmd}\n", cmd = cmd));
+}
+
+#[test]
+fn large_output() {
+    // More output from each of many jobs than fits in a pipe.
+    let h = Harness::new("large");
+    let tasks = vec!["out 100000 err 70000 sleep 0.1 out 1"; 20];
+    let run = h.run(&["-j20"], &tasks);
+    assert_eq!(run.code, 0);
+    assert_eq!(run.stdout, "o".repeat(100_001 * 20));
+    assert_eq!(run.stderr, "e".repeat(70_000 * 20));
+}
+
+#[test]
+fn line_order() {
+    // Under high concurrency the lines of each job are written together and
+    // in order, not interleaved with those of other jobs.
+    let h = Harness::new("lines");
+    let tasks: Vec<String> = (0..64).map(|n| n.to_string()).collect();
+    let mut args = vec!["-j64", "-c",
+        "i=0; while [ $i -lt 200 ]; do echo $1 $i; i=$((i+1)); done", ":::"];
+    args.extend(tasks.iter().map(|t| t.as_str()));
+    let run = h.ljobs(&args, None);
+    assert_eq!(run.code, 0);
+    let lines: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(lines.len(), 64 * 200);
+    for chunk in lines.chunks(200) {
+        let task = chunk[0].split(' ').next().unwrap();
+        for (i, line) in chunk.iter().enumerate() {
+            assert_eq!(*line, format!("{} {}", task, i));
+        }
+    }
+}
+
+#[test]
+fn finish_order() {
+    // Output is written in the order that tasks finish.  Each task but a
+    // waits until the one before it has been reaped by ljobs, which is when
+    // kill -0 stops finding it, so the order does not depend on timing.
+    let h = Harness::new("order");
+    let script = "case $1 in b) prev=a ;; c) prev=b ;; *) prev= ;; esac
+        if test -n \"$prev\"; then
+            until test -s $prev.pid; do sleep 0.01; done
+            while kill -0 $(cat $prev.pid) 2>/dev/null; do sleep 0.01; done
+        fi
+        echo $1
+        echo $$ > $1.tmp && mv $1.tmp $1.pid";
+    let run = h.ljobs(&["-j3", "-c", script, ":::", "c", "a", "b"], None);
+    assert_eq!(run.code, 0);
+    assert_eq!(run.stdout, "a\nb\nc\n");
+}
+
+#[test]
+fn spawn_failure() {
+    // A command that cannot be started counts as a failed task, with the
+    // exit status that a shell would give.
+    let h = Harness::new("spawnfail");
+    let run = h.ljobs(&["-j1", "./no-such-command", ":::", "a", "b"], None);
+    assert_eq!(run.code, 127);
+    assert_eq!(run.stdout, "");
+    assert_eq!(run.stderr,
+               "ljobs[0]: command not found\t./no-such-command a\n");
+
+    let run = h.ljobs(&["-j1", "-k", "./no-such-command", ":::", "a", "b"],
+                      None);
+    assert_eq!(run.code, 2);
+    assert_eq!(run.stderr.lines().count(), 2);
+
+    fs::write(h.dir.join("not-executable"), "echo hello\n").unwrap();
+    let run = h.ljobs(&["-j1", "./not-executable", ":::", "a", "b"], None);
+    assert_eq!(run.code, 126);
+    assert_eq!(run.stdout, "");
+    assert_eq!(run.stderr, "ljobs[0]: cannot execute\t./not-executable a\n");
+
+    let run = h.ljobs(&["-j1", "-k", "./not-executable", ":::", "a", "b"],
+                      None);
+    assert_eq!(run.code, 2);
+    assert_eq!(run.stderr.lines().count(), 2);
+}
@@ -0,0 +1,78 @@
+// Tests that commands are always waited on.  These are in a test program of
+// their own so that no other test has child processes at the same time.
+
+#![cfg(target_os = "linux")]
+
+extern crate ljobs;
+
+use ljobs::{Error, Options, TaskSource};
+use std::ffi::OsString;
+use std::fs;
+use std::time::{Duration, Instant};
+
+// The processes whose parent is this process, including zombies.
+fn children() -> Vec<String> {
+    let me = std::process::id().to_string();
+    fs::read_dir("/proc").unwrap()
+        .filter_map(|entry| {
+            let stat = fs::read_to_string(entry.ok()?.path().join("stat"))
+                .ok()?;
+            // pid (comm) state ppid ...
+            let rest = &stat[stat.rfind(')')? + 1..];
+            let mut fields = rest.split_whitespace();
+            let state = fields.next()?;
+            let ppid = fields.next()?;
+            if ppid == me {
+                Some(format!("{} {}", stat.split(' ').next()?, state))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn error_mid_run() {
+    // A task without enough columns stops the run while earlier tasks are
+    // still running; they are waited on before the error is returned.
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep $1", "sh", "{1}", "{2}"])
+        .column_separator(",")
+        .strict(true)
+        .jobs(4);
+    let tasks: Vec<OsString> = ["0.3,a", "0.3,b", "0.3,c", "bad", "0.3,d"]
+        .iter()
+        .map(OsString::from)
+        .collect();
+
+    let err = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap_err();
+    assert!(matches!(err, Error::Template { tasknum: 3, column: 2 }),
+            "{:?}", err);
+    assert_eq!(children(), Vec::<String>::new());
+}
+
+#[test]
+fn error_past_halt_timeout() {
+    // Tasks still running when the halt timeout runs out after an error
+    // are killed and waited on, rather than left behind.
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep $1", "sh", "{1}", "{2}"])
+        .column_separator(",")
+        .strict(true)
+        .halt_timeout(Some(Duration::from_millis(100)))
+        .reap_zombies(true)
+        .jobs(4);
+    let tasks: Vec<OsString> = ["30,a", "30,b", "bad"]
+        .iter()
+        .map(OsString::from)
+        .collect();
+
+    let start = Instant::now();
+    let err = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap_err();
+    assert!(matches!(err, Error::Template { tasknum: 2, column: 2 }),
+            "{:?}", err);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(children(), Vec::<String>::new());
+}
@@ -0,0 +1,808 @@
+// Tests of the library interface.  They run Unix commands.
+
+#![cfg(unix)]
+
+extern crate libc;
+extern crate ljobs;
+
+use ljobs::{CgroupLimits, Error, IoPriority, JobEvent, Options, TaskSource,
+            TaskStatus};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Produces numbered lines one per read, sleeping before each.
+struct SlowLines {
+    next:       usize,
+    count:      usize,
+    delay:      Duration,
+    read:       Arc<AtomicUsize>
+}
+
+impl Read for SlowLines {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.next == self.count {
+            return Ok(0);
+        }
+        thread::sleep(self.delay);
+        let line = format!("{}\n", self.next);
+        buf[..line.len()].copy_from_slice(line.as_bytes());
+        self.next += 1;
+        self.read.store(self.next, Ordering::SeqCst);
+        Ok(line.len())
+    }
+}
+
+fn slow_source(count: usize, delay: Duration, read: &Arc<AtomicUsize>)
+    -> TaskSource<'static> {
+
+    let lines = SlowLines {
+        next: 0,
+        count,
+        delay,
+        read: read.clone()
+    };
+    TaskSource::Reader(Box::new(BufReader::new(lines)))
+}
+
+fn assert_dispatch_order(summary: &ljobs::RunSummary) {
+    for task in &summary.tasks {
+        assert_eq!(task.stdout, format!("{}\n", task.tasknum).as_bytes());
+    }
+}
+
+fn num_threads() -> usize {
+    let status = fs::read_to_string("/proc/self/status").unwrap();
+    status.lines()
+        .find(|line| line.starts_with("Threads:"))
+        .and_then(|line| line["Threads:".len()..].trim().parse().ok())
+        .unwrap()
+}
+
+// Run `f` and return its result with the most threads seen meanwhile.
+fn max_threads_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let sampler = {
+        let stop = stop.clone();
+        thread::spawn(move || {
+            let mut maxthreads = 0;
+            while !stop.load(Ordering::SeqCst) {
+                maxthreads = maxthreads.max(num_threads());
+                thread::sleep(Duration::from_millis(1));
+            }
+            maxthreads
+        })
+    };
+    let res = f();
+    stop.store(true, Ordering::SeqCst);
+    (res, sampler.join().unwrap())
+}
+
+// The completed and failed counts of a version of the progress file, which
+// must have all its members.
+fn progress_counts(doc: &str) -> (usize, usize) {
+    let body = doc.strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix("}\n"))
+        .unwrap_or_else(|| panic!("not a whole document: {:?}", doc));
+    let members: Vec<(&str, &str)> = body.split(',')
+        .map(|member| member.split_once(':').expect(doc))
+        .collect();
+    let keys: Vec<&str> = members.iter().map(|&(key, _)| key).collect();
+    assert_eq!(keys, ["\"completed\"", "\"total\"", "\"running\"",
+                      "\"failed\"", "\"finished\""]);
+    for &(_, value) in &members[..4] {
+        assert!(value == "null" || value.parse::<usize>().is_ok(), "{}", doc);
+    }
+    (members[0].1.parse().unwrap(), members[3].1.parse().unwrap())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn many_jobs_few_threads() {
+    let numtasks = 2000;
+    let maxjobs = 200;
+
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep 0.05; exit $(({} % 3 == 0))"])
+        .jobs(maxjobs)
+        .keep_going(true);
+    let tasks = (0..numtasks).map(|i| OsString::from(i.to_string()));
+    let (summary, maxthreads) = max_threads_during(|| {
+        ljobs::run(&opts, TaskSource::Iter(Box::new(tasks))).unwrap()
+    });
+
+    assert_eq!(summary.tasks.len(), numtasks);
+    assert_eq!(summary.errors as usize, numtasks.div_ceil(3));
+    for task in &summary.tasks {
+        match task.status {
+            TaskStatus::Exited(code) => {
+                assert_eq!(code == 1, task.tasknum % 3 == 0);
+            },
+            ref status => panic!("unexpected status {:?}", status)
+        }
+    }
+    assert!(maxthreads < maxjobs / 10, "{} threads", maxthreads);
+}
+
+// Each job needs three fds while it runs.
+fn raise_fd_limit() {
+    unsafe {
+        let mut lim: libc::rlimit = std::mem::zeroed();
+        assert_eq!(libc::getrlimit(libc::RLIMIT_NOFILE, &mut lim), 0);
+        lim.rlim_cur = lim.rlim_max;
+        libc::setrlimit(libc::RLIMIT_NOFILE, &lim);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn many_jobs_with_output() {
+    // Every job writes more than fits in a pipe, and all run at once.
+    raise_fd_limit();
+    let numtasks = 1000;
+    let size = 100_000;
+
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "yes | head -c $0; yes no | head -c $0 >&2"])
+        .jobs(numtasks);
+    let tasks = (0..numtasks).map(|_| OsString::from(size.to_string()));
+    let (summary, maxthreads) = max_threads_during(|| {
+        ljobs::run(&opts, TaskSource::Iter(Box::new(tasks))).unwrap()
+    });
+
+    assert_eq!(summary.errors, 0);
+    assert_eq!(summary.tasks.len(), numtasks);
+    let stdout = b"y\n".repeat(size / 2);
+    let stderr = b"no\n".repeat(size / 3 + 1);
+    for task in &summary.tasks {
+        assert!(task.stdout == stdout);
+        assert!(task.stderr == stderr[..size]);
+    }
+    assert!(maxthreads < 5, "{} threads", maxthreads);
+}
+
+#[test]
+fn slow_producer() {
+    // Every task is run, in order, as it arrives.
+    let read = Arc::new(AtomicUsize::new(0));
+    let opts = Options::new().command("echo").jobs(4);
+    let source = slow_source(20, Duration::from_millis(10), &read);
+    let summary = ljobs::run(&opts, source).unwrap();
+    assert_eq!(summary.errors, 0);
+    assert_eq!(summary.tasks.len(), 20);
+    assert_dispatch_order(&summary);
+
+    // A failed task is noticed while waiting for more input, so the run
+    // stops early without --keep-going.
+    let read = Arc::new(AtomicUsize::new(0));
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "exit $(({} == 0))"])
+        .jobs(4);
+    let source = slow_source(50, Duration::from_millis(20), &read);
+    let summary = ljobs::run(&opts, source).unwrap();
+    assert_eq!(summary.errors, 1);
+    // Reading all the input takes a second, and only a run that waited for
+    // it fails these however slowly the tasks are run.
+    let read = read.load(Ordering::SeqCst);
+    assert!(read < 50, "{} tasks read", read);
+    assert!(summary.tasks.len() < 50, "{} tasks", summary.tasks.len());
+
+    // With --keep-going everything still runs.
+    let read = Arc::new(AtomicUsize::new(0));
+    let opts = opts.keep_going(true);
+    let source = slow_source(10, Duration::from_millis(5), &read);
+    let summary = ljobs::run(&opts, source).unwrap();
+    assert_eq!(summary.errors, 1);
+    assert_eq!(summary.tasks.len(), 10);
+}
+
+#[test]
+fn slow_producer_overlap() {
+    // Tasks run while the next ones are still being read, so the run takes
+    // about as long as the slower of reading and running, not the sum.
+    // With a slot always free, each task starts before the next line is
+    // read, a tenth of a second later, rather than waiting on the jobs.
+    let read = Arc::new(AtomicUsize::new(0));
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep 0.5", "sh"])
+        .jobs(10);
+    let source = slow_source(10, Duration::from_millis(100), &read);
+    let mut late = Vec::new();
+    let summary = ljobs::run_with_events(&opts, source, |event| {
+        if let JobEvent::Started { tasknum, .. } = *event {
+            let read = read.load(Ordering::SeqCst);
+            if read > tasknum + 1 {
+                late.push((tasknum, read));
+            }
+        }
+    }).unwrap();
+    assert_eq!(summary.errors, 0);
+    assert_eq!(summary.tasks.len(), 10);
+    assert!(late.is_empty(), "started after more was read: {:?}", late);
+
+    // And the other way round, input is read while the only slot is busy,
+    // so that the next task is waiting when the first finishes.
+    let read = Arc::new(AtomicUsize::new(0));
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "test $0 = 0 && sleep 0.5; true"])
+        .jobs(1);
+    let source = slow_source(10, Duration::from_millis(10), &read);
+    let mut readahead = None;
+    let summary = ljobs::run_with_events(&opts, source, |event| {
+        if let JobEvent::Started { tasknum: 1, .. } = *event {
+            readahead = Some(read.load(Ordering::SeqCst));
+        }
+    }).unwrap();
+    assert_eq!(summary.errors, 0);
+    assert!(readahead > Some(2), "{:?} read as task 1 started", readahead);
+}
+
+#[test]
+fn slow_consumer() {
+    // Input is only read a bounded distance ahead of the running jobs.
+    let numtasks = 100;
+    let read = Arc::new(AtomicUsize::new(0));
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep 0.02; echo {}"])
+        .jobs(4);
+    let source = slow_source(numtasks, Duration::from_millis(0), &read);
+
+    // How far reading had got past each task as it started, which does not
+    // depend on how quickly the tasks run.
+    let mut ahead = 0;
+    let summary = ljobs::run_with_events(&opts, source, |event| {
+        if let JobEvent::Started { tasknum, .. } = *event {
+            ahead = ahead.max(read.load(Ordering::SeqCst) - tasknum);
+        }
+    }).unwrap();
+
+    assert_eq!(summary.errors, 0);
+    assert_eq!(summary.tasks.len(), numtasks);
+    assert_dispatch_order(&summary);
+    assert!(ahead < numtasks / 2, "{} tasks read ahead", ahead);
+}
+
+#[test]
+fn dry_run_command_lines() {
+    // Command lines are the same whether the task is borrowed from a slice
+    // or owned, with or without a shell.
+    let numtasks = 1000;
+    let tasks: Vec<OsString> = (0..numtasks)
+        .map(|i| OsString::from(format!("dir/file {}.txt", i)))
+        .collect();
+    let opts = Options::new()
+        .command("convert")
+        .args(vec!["-quality", "90", "{}", "{/.}-{#}.jpg"])
+        .dry_run(true);
+    let shopts = opts.clone().shell(Some(OsString::from("/bin/sh")));
+
+    let summaries = [
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap(),
+        ljobs::run(&opts, TaskSource::Iter(Box::new(tasks.iter().cloned())))
+            .unwrap(),
+        ljobs::run(&shopts, TaskSource::Slice(&tasks)).unwrap()
+    ];
+    for (i, summary) in summaries.iter().enumerate() {
+        assert_eq!(summary.tasks.len(), numtasks);
+        for task in &summary.tasks {
+            let n = task.tasknum;
+            let cmd = if i == 2 { "/bin/sh -c convert -" } else { "convert" };
+            assert_eq!(task.command(),
+                format!("{} -quality 90 'dir/file {}.txt' 'file {}-{}.jpg'",
+                        cmd, n, n, n));
+        }
+    }
+}
+
+#[test]
+fn sources_agree() {
+    // The same tasks give the same commands, in the same order, whichever
+    // way they are supplied.
+    let text = "a\n\nb c\n-d\ne\n";
+    let tasks: Vec<OsString> = text.lines().map(OsString::from).collect();
+    let reader = |s: &'static str| -> Box<dyn io::BufRead + Send> {
+        Box::new(BufReader::new(s.as_bytes()))
+    };
+    let opts = Options::new().command("echo").arg("[{}]").jobs(1);
+    let sources = vec![
+        TaskSource::Slice(&tasks),
+        TaskSource::Iter(Box::new(tasks.iter().cloned())),
+        TaskSource::Reader(reader(text)),
+        TaskSource::Readers(vec![reader("a\n\n"), reader("b c\n-d\ne")])
+    ];
+    for source in sources {
+        let summary = ljobs::run(&opts, source).unwrap();
+        let outputs: Vec<(usize, &[u8])> = summary.tasks.iter()
+            .map(|t| (t.tasknum, &t.stdout[..]))
+            .collect();
+        assert_eq!(outputs, vec![(0, &b"[a]\n"[..]), (1, b"[]\n"),
+                                 (2, b"[b c]\n"), (3, b"[-d]\n"),
+                                 (4, b"[e]\n")]);
+    }
+}
+
+#[test]
+fn ramp() {
+    // The jobs running are counted from the events as each one starts, and
+    // never exceed the slots open by then: one more every 100ms.  Timing
+    // from before the run only allows more, however slow the machine.
+    let maxjobs = 8;
+    let step = Duration::from_millis(100);
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep 0.1"])
+        .jobs(maxjobs)
+        .ramp(step * (maxjobs as u32 - 1));
+
+    let tasks = (0..100).map(|i| OsString::from(i.to_string()));
+    let start = Instant::now();
+    let mut running = 0;
+    let mut most = 0;
+    let summary = ljobs::run_with_events(&opts,
+        TaskSource::Iter(Box::new(tasks)), |event| match *event {
+            JobEvent::Started { time, .. } => {
+                running += 1;
+                let elapsed = time.saturating_duration_since(start);
+                let slots =
+                    1 + (elapsed.as_nanos() / step.as_nanos()) as usize;
+                assert!(running <= slots, "{} jobs at {:?}", running, elapsed);
+                most = most.max(running);
+            },
+            JobEvent::Finished { .. } => running -= 1,
+            JobEvent::Retrying { .. } => ()
+        }).unwrap();
+
+    assert_eq!(summary.errors, 0);
+    assert_eq!(most, maxjobs);
+}
+
+#[test]
+fn output_buffer() {
+    // While task 0 runs slowly, the other tasks between them write more
+    // than the buffer holds, so some of their output must be spilled.
+    let cap = 256 * 1024;
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "test $0 = 0 && sleep 0.5; head -c 100000 /dev/zero"])
+        .jobs(8)
+        .output_buffer(cap);
+    let tasks: Vec<OsString> = (0..16)
+        .map(|i| OsString::from(i.to_string()))
+        .collect();
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    assert_eq!(summary.errors, 0);
+    for task in &summary.tasks {
+        assert_eq!(task.stdout.len(), 100000, "task {}", task.tasknum);
+    }
+    assert!(summary.peak_buffered > 0);
+    assert!(summary.peak_buffered <= cap, "{}", summary.peak_buffered);
+}
+
+// Whether this machine takes ssh logins to itself without a password.  The
+// ssh tests are only run if it does.
+fn ssh_to_localhost() -> bool {
+    let login = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=2", "localhost",
+               "true"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    matches!(login, Ok(ref status) if status.success())
+}
+
+#[test]
+fn ssh_localhost() {
+    if !ssh_to_localhost() {
+        return;
+    }
+    let opts = Options::new()
+        .command("printf")
+        .args(vec!["%s:%s\\n", "{host}", "{}"])
+        .ssh_login("localhost")
+        .keep_going(true);
+    let tasks = vec![OsString::from("it's $HOME")];
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    assert_eq!(summary.tasks[0].stdout, b"localhost:it's $HOME\n");
+    let opts = Options::new().command("exit").ssh_login("localhost");
+    let tasks = vec![OsString::from("3")];
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    assert!(matches!(summary.tasks[0].status, TaskStatus::Exited(3)));
+}
+
+#[test]
+fn ssh_localhost_transfer() {
+    if !ssh_to_localhost() {
+        return;
+    }
+    let dir = std::env::temp_dir()
+        .join(format!("ljobs-transfer-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("in.txt");
+    fs::write(&file, "hello\n").unwrap();
+    let tasks = vec![file.clone().into_os_string()];
+    // The remote directory for the job, under the home directory.
+    let remote = Path::new(&std::env::var_os("HOME").unwrap())
+        .join(format!(".ljobs/{}.0", std::process::id()));
+
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "tr a-z A-Z < $0 > $0.out"])
+        .ssh_login("localhost")
+        .transfer(true)
+        .return_file("{}.out")
+        .cleanup(true);
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    assert_eq!(summary.errors, 0);
+    assert_eq!(fs::read(dir.join("in.txt.out")).unwrap(), b"HELLO\n");
+    assert!(!remote.exists());
+
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "cat $0 && exit 3"])
+        .ssh_login("localhost")
+        .transfer(true)
+        .cleanup(true);
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    assert!(matches!(summary.tasks[0].status, TaskStatus::Exited(3)));
+    assert_eq!(summary.tasks[0].stdout, b"hello\n");
+    assert!(!remote.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A container engine with the alpine image at hand, if there is one.
+fn container_engine() -> Option<&'static str> {
+    ["podman", "docker"].iter().cloned().find(|engine| {
+        let inspect = Command::new(engine)
+            .args(["image", "inspect", "alpine"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        matches!(inspect, Ok(ref status) if status.success())
+    })
+}
+
+#[test]
+fn container() {
+    let engine = match container_engine() {
+        Some(engine) => engine,
+        None => return
+    };
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "echo $T; pwd; exit $0"])
+        .container("alpine")
+        .container_engine(engine)
+        .container_arg("--env=T=task {}")
+        .keep_going(true);
+    let tasks = vec![OsString::from("0"), OsString::from("3")];
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+    assert_eq!(summary.tasks[0].stdout,
+               format!("task 0\n{}\n", cwd.display()).into_bytes());
+    assert!(matches!(summary.tasks[1].status, TaskStatus::Exited(3)));
+}
+
+#[test]
+fn ionice() {
+    // The job reads its priority back with the ionice utility, if there
+    // is one.
+    if Command::new("ionice").stdout(Stdio::null()).status().is_err() {
+        return;
+    }
+    for &(priority, expect) in &[
+        (IoPriority::Idle, "idle\n"),
+        (IoPriority::BestEffort(6), "best-effort: prio 6\n")
+    ] {
+        let opts = Options::new()
+            .command("sh")
+            .args(vec!["-c", "ionice -p $$"])
+            .ionice(priority);
+        let tasks = vec![OsString::from("x")];
+        let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&summary.tasks[0].stdout),
+                   expect);
+    }
+}
+
+#[test]
+fn status_file() {
+    // The file is polled while jobs run, long enough for it to be
+    // rewritten once, and read again at the end.
+    let path = std::env::temp_dir()
+        .join(format!("ljobs-status-{}.json", std::process::id()));
+    let opts = Options::new()
+        .command("sleep")
+        .jobs(2)
+        .status_file(&path);
+    let run = thread::spawn(move || {
+        let tasks = vec![OsString::from("2.5"), OsString::from("0.5"),
+                         OsString::from("0.5")];
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap()
+    });
+    let mut seen = String::new();
+    while !run.is_finished() {
+        if let Ok(status) = fs::read_to_string(&path) {
+            if status.contains("\"running\":[{") {
+                seen = status;
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let summary = run.join().unwrap();
+    assert_eq!(summary.tasks.len(), 3);
+    for key in &["\"started\":", "\"completed\":", "\"failed\":0",
+                 "\"command\":\"sleep ", "\"slots\":2", "\"elapsed\":",
+                 "\"eta\":", "\"finished\":false"] {
+        assert!(seen.contains(key), "{} not in {}", key, seen);
+    }
+    let status = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert!(status.starts_with("{\"started\":3,\"completed\":3,\"failed\":0,\
+                                \"running\":[],\"slots\":2,"), "{}", status);
+    assert!(status.ends_with(",\"finished\":true,\"exit_status\":0}\n"),
+            "{}", status);
+}
+
+#[test]
+fn progress_file() {
+    // Every version of the file seen while the run goes on is whole, and
+    // the counts only ever go up.
+    let path = std::env::temp_dir()
+        .join(format!("ljobs-progress-{}.json", std::process::id()));
+    let opts = Options::new()
+        .command("sleep")
+        .jobs(2)
+        .keep_going(true)
+        .progress_file(&path);
+    let run = thread::spawn(move || {
+        let tasks = vec![OsString::from("0.3"), OsString::from("0.1"),
+                         OsString::from("x"), OsString::from("0.2")];
+        ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap()
+    });
+    let mut last = (0, 0);
+    while !run.is_finished() {
+        if let Ok(doc) = fs::read_to_string(&path) {
+            let counts = progress_counts(&doc);
+            assert!(counts.0 >= last.0 && counts.1 >= last.1, "{}", doc);
+            last = counts;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    let summary = run.join().unwrap();
+    assert_eq!(summary.tasks.len(), 4);
+    let doc = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(doc, "{\"completed\":4,\"total\":4,\"running\":0,\
+                     \"failed\":1,\"finished\":true}\n");
+}
+
+#[test]
+fn private_namespaces() {
+    // Only where the kernel allows it, with user namespaces if need be.
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "touch /tmp/ljobs-$0; ls -A /tmp; \
+                          tail -n +3 /proc/net/dev | cut -d: -f1"])
+        .private_tmp(true)
+        .isolate_network(true);
+    let tasks = vec![OsString::from("a"), OsString::from("b")];
+    let summary = match ljobs::run(&opts, TaskSource::Slice(&tasks)) {
+        Err(Error::Spawn(_)) => return,
+        result => result.unwrap()
+    };
+    for (task, name) in summary.tasks.iter().zip(&["a", "b"]) {
+        assert!(matches!(task.status, TaskStatus::Exited(0)));
+        assert_eq!(String::from_utf8_lossy(&task.stdout),
+                   format!("ljobs-{}\n    lo\n", name));
+    }
+    assert!(!Path::new("/tmp/ljobs-a").exists());
+}
+
+#[test]
+fn systemd_run() {
+    // Only where a user service manager is reachable.
+    let probe = Command::new("systemd-run")
+        .args(["--user", "--scope", "--quiet", "true"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if !matches!(probe, Ok(ref status) if status.success()) {
+        return;
+    }
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "cat /proc/self/cgroup; exit $0"])
+        .systemd_run(true)
+        .systemd_prop("CPUWeight=50")
+        .keep_going(true);
+    let tasks = vec![OsString::from("0"), OsString::from("3")];
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    let stdout = String::from_utf8_lossy(&summary.tasks[0].stdout);
+    assert!(stdout.contains("/ljobs-"), "{}", stdout);
+    assert!(matches!(summary.tasks[0].status, TaskStatus::Exited(0)));
+    assert!(matches!(summary.tasks[1].status, TaskStatus::Exited(3)));
+}
+
+#[test]
+fn resource_usage() {
+    // The shell builds a string of 16 MiB and counts for a while.
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "x=x; i=0; while [ $i -lt 24 ]; do x=$x$x; \
+                          i=$((i+1)); done; i=0; \
+                          while [ $i -lt 500000 ]; do i=$((i+1)); done"]);
+    let tasks = vec![OsString::from("x")];
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    let result = &summary.tasks[0];
+    let usage = result.usage.expect("usage");
+    assert!(usage.cpu >= Duration::from_millis(50), "{:?}", usage);
+    assert!(usage.cpu <= result.duration + Duration::from_millis(100),
+            "{:?} in {:?}", usage, result.duration);
+    assert!(usage.maxrss >= 16 << 20, "{:?}", usage);
+    assert!(usage.maxrss < 1 << 30, "{:?}", usage);
+}
+
+#[test]
+fn cgroup() {
+    // The job shows its cgroup and memory limit, wherever cgroup v2 is
+    // mounted.
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "p=$(sed -n 's/^0:://p' /proc/self/cgroup); \
+                          echo $p; cat /sys/fs/cgroup$p/memory.max \
+                          /sys/fs/cgroup/unified$p/memory.max 2>/dev/null"])
+        .cgroup(CgroupLimits::parse("mem=64M,cpu=50%").unwrap());
+    let tasks = vec![OsString::from("x")];
+    let summary = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap();
+    let stdout = String::from_utf8_lossy(&summary.tasks[0].stdout);
+    let mut lines = stdout.lines();
+    // Without a cgroup that ljobs may manage, the job runs as it is.
+    if !lines.next().is_some_and(|path| path.contains("/ljobs.")) {
+        return;
+    }
+    assert_eq!(lines.next(), Some("67108864"));
+}
+
+// A reader that always fails.
+struct Failing;
+
+impl Read for Failing {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::other("oops"))
+    }
+}
+
+#[test]
+fn errors() {
+    let tasks = vec![OsString::from("a,b")];
+
+    let err = ljobs::run(&Options::new(), TaskSource::Slice(&tasks))
+        .unwrap_err();
+    assert!(matches!(err, Error::Usage(_)), "{:?}", err);
+    assert_eq!(err.to_string(), "no command");
+
+    let opts = Options::new()
+        .command("echo")
+        .args(vec!["{1}", "{3}"])
+        .column_separator(",")
+        .strict(true);
+    let err = ljobs::run(&opts, TaskSource::Slice(&tasks)).unwrap_err();
+    assert!(matches!(err, Error::Template { tasknum: 0, column: 3 }),
+            "{:?}", err);
+    assert_eq!(err.to_string(), "no column {3} in task 0");
+
+    let source = TaskSource::Reader(Box::new(BufReader::new(Failing)));
+    let err = ljobs::run(&Options::new().command("echo"), source)
+        .unwrap_err();
+    assert!(matches!(err, Error::Input(_)), "{:?}", err);
+    assert_eq!(err.to_string(), "error reading tasks: oops");
+}
+
+// Produces one task, then panics.
+struct Panicking {
+    done:       bool
+}
+
+impl Read for Panicking {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            panic!("injected panic");
+        }
+        self.done = true;
+        buf[..2].copy_from_slice(b"5\n");
+        Ok(2)
+    }
+}
+
+#[test]
+fn thread_panic() {
+    // A panic in the reader thread stops the run with an error, rather than
+    // leaving the master waiting for tasks forever.
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let opts = Options::new().command("sleep").jobs(2);
+        let reader = BufReader::new(Panicking { done: false });
+        let source = TaskSource::Reader(Box::new(reader));
+        let _ = tx.send(ljobs::run(&opts, source));
+    });
+    let err = rx.recv_timeout(Duration::from_secs(4))
+        .expect("run did not stop")
+        .unwrap_err();
+    assert!(matches!(err, Error::Internal(ref name) if name == "ljobs-reader"),
+            "{:?}", err);
+}
+
+// An owned copy of a JobEvent.
+#[derive(Debug)]
+enum Seen {
+    Started(usize, u32, Instant),
+    Finished(usize, Option<u32>, Instant, Duration)
+}
+
+#[test]
+fn job_events() {
+    let opts = Options::new()
+        .command("sh")
+        .args(vec!["-c", "sleep 0.0$0; exit $0"])
+        .jobs(3)
+        .keep_going(true);
+    let tasks: Vec<OsString> = (0..10)
+        .map(|i| OsString::from((i % 4).to_string()))
+        .collect();
+    let mut seen = Vec::new();
+    let summary = ljobs::run_with_events(&opts, TaskSource::Slice(&tasks),
+        |event| seen.push(match *event {
+            JobEvent::Started { tasknum, pid, time, .. } =>
+                Seen::Started(tasknum, pid, time),
+            JobEvent::Finished { result, time } =>
+                Seen::Finished(result.tasknum, result.pid, time,
+                               result.duration),
+            JobEvent::Retrying { .. } => panic!("unexpected retry")
+        })).unwrap();
+
+    assert_eq!(seen.len(), tasks.len() * 2);
+    let mut started = vec![None; tasks.len()];
+    let mut finished = vec![false; tasks.len()];
+    for event in &seen {
+        match *event {
+            Seen::Started(n, pid, time) => {
+                assert!(started[n].is_none(), "task {} started twice", n);
+                assert!(pid > 0);
+                started[n] = Some((pid, time));
+            },
+            Seen::Finished(n, pid, time, duration) => {
+                let (startpid, start) = started[n]
+                    .unwrap_or_else(|| panic!("task {} not started", n));
+                assert!(!finished[n], "task {} finished twice", n);
+                assert_eq!(pid, Some(startpid));
+                assert_eq!(time, start + duration);
+                finished[n] = true;
+            }
+        }
+    }
+    assert!(finished.iter().all(|&f| f));
+
+    // Events arrive in the order the summary records the results.
+    let order: Vec<usize> = seen.iter()
+        .filter_map(|event| match *event {
+            Seen::Finished(n, ..) => Some(n),
+            _ => None
+        })
+        .collect();
+    let summary_order: Vec<usize> = summary.tasks.iter()
+        .map(|t| t.tasknum)
+        .collect();
+    assert_eq!(order, summary_order);
+}
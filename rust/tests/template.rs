@@ -0,0 +1,275 @@
+// Property tests of the substitution, path and quoting helpers, checked
+// against the shell and the basename and dirname utilities.
+
+#![cfg(unix)]
+
+extern crate ljobs;
+extern crate quickcheck;
+
+use ljobs::quote::{quote_arg, quote_sh_cmd};
+use ljobs::template::{basename, dirname, extension, remove_extension, subst};
+use quickcheck::{Arbitrary, Gen, QuickCheck, TestResult};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::process::Command;
+
+// Paths made up of the characters that matter to the path helpers.
+#[derive(Clone, Debug)]
+struct TrickyPath(Vec<u8>);
+
+impl Arbitrary for TrickyPath {
+    fn arbitrary(g: &mut Gen) -> TrickyPath {
+        let len = usize::arbitrary(g) % 10;
+        let path = (0..len)
+            .map(|_| *g.choose(b"a/./.x").unwrap())
+            .collect();
+        TrickyPath(path)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = TrickyPath>> {
+        Box::new(self.0.shrink().map(TrickyPath))
+    }
+}
+
+// Templates with many braces and substitution strings.
+#[derive(Clone, Debug)]
+struct BraceSoup(Vec<u8>);
+
+impl Arbitrary for BraceSoup {
+    fn arbitrary(g: &mut Gen) -> BraceSoup {
+        let len = usize::arbitrary(g) % 20;
+        let mut s = Vec::new();
+        for _ in 0..len {
+            let piece: &[u8] = g.choose(&[
+                &b"{"[..], b"}", b"{}", b"{.}", b"{/}", b"{//}", b"{/.}",
+                b"{#}", b".", b"/", b"a", b"\xff"
+            ]).unwrap();
+            s.extend_from_slice(piece);
+        }
+        BraceSoup(s)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = BraceSoup>> {
+        Box::new(self.0.shrink().map(BraceSoup))
+    }
+}
+
+// Run a utility on `path` and return its output without the final newline.
+fn utility(name: &str, path: &[u8]) -> Vec<u8> {
+    let output = Command::new(name)
+        .arg("--")
+        .arg(OsStr::from_bytes(path))
+        .output()
+        .expect("could not run utility");
+    assert!(output.status.success());
+    let mut out = output.stdout;
+    assert_eq!(out.pop(), Some(b'\n'));
+    out
+}
+
+// Each property runs the utilities many times, so keep the number of cases
+// modest.
+fn quickcheck() -> QuickCheck {
+    QuickCheck::new().tests(200)
+}
+
+/*---------------------------------------------------------------------------*/
+
+const CORPUS: &[&[u8]] = &[
+    b"", b"/", b"//", b"///", b".", b"..", b"./", b"../", b"/.", b"/..",
+    b"a", b"a/", b"a//", b"/a", b"//a", b"/a/", b"a/b", b"a//b", b"a/b/",
+    b"a/b//", b"./a", b"../a", b"a/.", b"a/..", b"/a/b/c", b"a.b/c",
+    b"a/b.c", b".a", b"a/.b", b"a.b.c/"
+];
+
+#[test]
+fn basename_corpus() {
+    for &path in CORPUS {
+        // GNU basename gives nothing for an empty path.
+        if !path.is_empty() {
+            assert_eq!(basename(path), &utility("basename", path)[..],
+                       "basename {:?}", OsStr::from_bytes(path));
+        }
+    }
+}
+
+#[test]
+fn dirname_corpus() {
+    for &path in CORPUS {
+        assert_eq!(dirname(path), &utility("dirname", path)[..],
+                   "dirname {:?}", OsStr::from_bytes(path));
+    }
+}
+
+// Each path with its `{/}`, `{//}`, `{.}` and `{/.}`.
+const TABLE: &[(&str, &str, &str, &str, &str)] = &[
+    ("",            "",         ".",    "",             ""),
+    ("/",           "/",        "/",    "/",            "/"),
+    ("//",          "/",        "/",    "//",           "/"),
+    ("///",         "/",        "/",    "///",          "/"),
+    (".",           ".",        ".",    ".",            "."),
+    ("..",          "..",       ".",    "..",           ".."),
+    ("...",         "...",      ".",    "...",          "..."),
+    ("./",          ".",        ".",    "./",           "."),
+    ("../",         "..",       ".",    "../",          ".."),
+    ("/.",          ".",        "/",    "/.",           "."),
+    ("/..",         "..",       "/",    "/..",          ".."),
+    ("a/.",         ".",        "a",    "a/.",          "."),
+    ("a/..",        "..",       "a",    "a/..",         ".."),
+    ("foo",         "foo",      ".",    "foo",          "foo"),
+    ("foo/",        "foo",      ".",    "foo/",         "foo"),
+    ("foo//",       "foo",      ".",    "foo//",        "foo"),
+    ("/foo",        "foo",      "/",    "/foo",         "foo"),
+    ("//foo",       "foo",      "/",    "//foo",        "foo"),
+    ("/foo/",       "foo",      "/",    "/foo/",        "foo"),
+    ("a/b",         "b",        "a",    "a/b",          "b"),
+    ("a//b",        "b",        "a",    "a//b",         "b"),
+    ("a/b//",       "b",        "a",    "a/b//",        "b"),
+    ("/a/b/c",      "c",        "/a/b", "/a/b/c",       "c"),
+    ("a.txt",       "a.txt",    ".",    "a",            "a"),
+    ("a.tar.gz",    "a.tar.gz", ".",    "a.tar",        "a.tar"),
+    ("a.",          "a.",       ".",    "a",            "a"),
+    ("d.d/a",       "a",        "d.d",  "d.d/a",        "a"),
+    ("d.d/a.b",     "a.b",      "d.d",  "d.d/a",        "a"),
+    ("d.d/",        "d.d",      ".",    "d.d/",         "d"),
+    (".bashrc",     ".bashrc",  ".",    ".bashrc",      ".bashrc"),
+    ("~/.bashrc",   ".bashrc",  "~",    "~/.bashrc",    ".bashrc"),
+    (".config.bak", ".config.bak", ".", ".config",      ".config"),
+    ("..foo",       "..foo",    ".",    "..foo",        "..foo"),
+    ("..foo.x",     "..foo.x",  ".",    "..foo",        "..foo"),
+    ("a/.b.c",      ".b.c",     "a",    "a/.b",         ".b"),
+    ("a.b/.c",      ".c",       "a.b",  "a.b/.c",       ".c"),
+    ("a b/c d.e",   "c d.e",    "a b",  "a b/c d",      "c d")
+];
+
+#[test]
+fn path_table() {
+    for &(path, base, dir, noext, basenoext) in TABLE {
+        let got = |template: &str| String::from_utf8(
+            subst(template.as_bytes(), 0, path.as_bytes()).unwrap()).unwrap();
+        assert_eq!((got("{/}"), got("{//}"), got("{.}"), got("{/.}")),
+                   (base.to_string(), dir.to_string(), noext.to_string(),
+                    basenoext.to_string()),
+                   "path {:?}", path);
+    }
+}
+
+#[test]
+fn basename_like_utility() {
+    fn prop(path: TrickyPath) -> TestResult {
+        if path.0.is_empty() {
+            return TestResult::discard();
+        }
+        TestResult::from_bool(basename(&path.0) == &utility("basename",
+                                                            &path.0)[..])
+    }
+    quickcheck().quickcheck(prop as fn(TrickyPath) -> TestResult);
+}
+
+#[test]
+fn dirname_like_utility() {
+    fn prop(path: TrickyPath) -> bool {
+        dirname(&path.0) == &utility("dirname", &path.0)[..]
+    }
+    quickcheck().quickcheck(prop as fn(TrickyPath) -> bool);
+}
+
+#[test]
+fn extension_is_suffix_of_basename() {
+    fn prop(path: TrickyPath) -> bool {
+        match extension(&path.0) {
+            Some(ext) => ext.starts_with(b".") &&
+                         basename(&path.0).ends_with(ext),
+            None => remove_extension(&path.0) == &path.0[..]
+        }
+    }
+    quickcheck().quickcheck(prop as fn(TrickyPath) -> bool);
+}
+
+#[test]
+fn remove_extension_is_prefix() {
+    fn prop(path: TrickyPath) -> bool {
+        path.0.starts_with(remove_extension(&path.0))
+    }
+    quickcheck().quickcheck(prop as fn(TrickyPath) -> bool);
+}
+
+#[test]
+fn subst_any_template() {
+    // Substitution never panics, and a template without any substitution
+    // strings is left alone.
+    fn prop(template: BraceSoup, task: TrickyPath, tasknum: usize) -> bool {
+        match subst(&template.0, tasknum, &task.0) {
+            Some(_) => true,
+            None => !template.0.windows(2).any(|w| w == b"{}")
+        }
+    }
+    QuickCheck::new().tests(2000)
+        .quickcheck(prop as fn(BraceSoup, TrickyPath, usize) -> bool);
+}
+
+#[test]
+fn subst_arbitrary_bytes() {
+    fn prop(template: Vec<u8>, task: Vec<u8>, tasknum: usize) -> bool {
+        // Without braces there is nothing to substitute.
+        subst(&template, tasknum, &task).is_none() ||
+            template.contains(&b'{')
+    }
+    QuickCheck::new().tests(2000)
+        .quickcheck(prop as fn(Vec<u8>, Vec<u8>, usize) -> bool);
+}
+
+#[test]
+fn quote_arg_round_trips() {
+    // The shell sees a quoted argument as the original string.
+    fn prop(arg: String) -> TestResult {
+        if arg.contains('\0') {
+            return TestResult::discard();
+        }
+        let script = format!("printf '%s' {}", quote_arg(&arg));
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&script)
+            .output()
+            .expect("could not run sh");
+        TestResult::from_bool(output.status.success() &&
+                              output.stdout == arg.as_bytes())
+    }
+    quickcheck().quickcheck(prop as fn(String) -> TestResult);
+}
+
+#[test]
+fn quote_arg_corpus() {
+    for arg in &["", "a b", "it's", "'", "\"", "$HOME", "`ls`", "a\nb",
+                 "\\", "*", "~", "#", "!", "{}", "-n"] {
+        let script = format!("printf '%s' {}", quote_arg(arg));
+        let output = Command::new("sh").arg("-c").arg(&script).output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), *arg,
+                   "{}", script);
+    }
+}
+
+#[test]
+fn quote_sh_cmd_round_trips() {
+    // A remote shell sees each argument as it was, whatever its bytes.
+    fn prop(args: Vec<Vec<u8>>) -> TestResult {
+        // printf prints its format once even without arguments.
+        if args.is_empty() || args.iter().any(|arg| arg.contains(&0)) {
+            return TestResult::discard();
+        }
+        let mut argv = vec![OsString::from("printf"), OsString::from("%s\\0")];
+        argv.extend(args.iter().cloned().map(OsString::from_vec));
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(quote_sh_cmd(&argv))
+            .output()
+            .expect("could not run sh");
+        let expected: Vec<u8> = args.iter()
+            .flat_map(|arg| arg.iter().copied().chain([0]))
+            .collect();
+        TestResult::from_bool(output.status.success() &&
+                              output.stdout == expected)
+    }
+    quickcheck().quickcheck(prop as fn(Vec<Vec<u8>>) -> TestResult);
+}
@@ -0,0 +1,12 @@
+# Task 0 prints the pattern while task 1 is still running, which is then
+# terminated, and task 2 is never started.
+./testbin -j2 --abort-on-match '^FATAL' sh -c \
+    'if test $0 = 0; then echo ok; sleep 0.2; echo "FATAL: $0" >&2; \
+     sleep 0.2; echo after; else exec sleep 5; fi' ::: 0 1 2
+echo "exit $?"
+# A match in a partial last line counts.  The job may be terminated as it
+# exits, so its status is not shown.
+./testbin --abort-on-match 'FATAL$' printf 'a\nFATAL%s' ::: ''; echo
+./testbin --abort-on-match FATAL echo ::: 'no problem'
+echo "exit $?"
+./testbin --abort-on-match '(' echo ::: x 2>&1 | head -n 1
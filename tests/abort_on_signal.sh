@@ -0,0 +1,6 @@
+ulimit -c 0
+SHELL=/bin/sh ./testbin -j1 -k -c 'test "$1" = b && kill -SEGV $$; echo $1' ::: a b c
+echo "exit $?"
+SHELL=/bin/sh ./testbin -j1 -k --abort-on-signal -c 'test "$1" = b && kill -SEGV $$; echo $1' ::: a b c
+echo "exit $?"
+exit 0
@@ -0,0 +1,11 @@
+# Tasks, and command arguments, may contain ::: when another separator is
+# chosen.
+./testbin -j1 --arg-sep %% printf '<%s>' ::: %% 'a ::: b' ':::'
+echo
+SHELL=/bin/sh ./testbin -j1 --arg-sep %% -c 'echo "$#: $1"' %% ':::'
+./testbin --arg-sep %% -a /dev/null echo %% a
+echo "exit $?"
+./testbin --arg-sep '' echo '' a
+echo "exit $?"
+./testbin --arg-sep ' ' echo ' ' a
+echo "exit $?"
@@ -0,0 +1,18 @@
+rm -rf cache.tmp cache.log
+run() {
+    SHELL=/bin/sh ./testbin -j1 -k --cache cache.tmp "$@" 2>&1
+}
+# Successful tasks are replayed from the cache without being run again.
+run -c 'echo "$1" >>cache.log; echo "out $1"; echo "err $1" >&2' ::: a b
+run -c 'echo "$1" >>cache.log; echo "out $1"; echo "err $1" >&2' ::: a b c
+# Failed tasks are not cached.
+run -c 'echo "$1" >>cache.log; exit 1' ::: x
+run -c 'echo "$1" >>cache.log; exit 1' ::: x
+# With --cache-input, a task is run again when its input file changes.
+echo one >cache.in
+run --cache-input -c 'echo "$1" >>cache.log; cat "$1"' ::: cache.in
+run --cache-input -c 'echo "$1" >>cache.log; cat "$1"' ::: cache.in
+echo two >cache.in
+run --cache-input -c 'echo "$1" >>cache.log; cat "$1"' ::: cache.in
+cat cache.log
+rm -rf cache.tmp cache.log cache.in
@@ -0,0 +1,6 @@
+# Whether jobs can be put in cgroups depends on the system, so only the
+# limits are checked here.
+for limits in mem=2X cpu=0% io=1 mem; do
+    ./testbin --cgroup $limits echo ::: a
+    echo "exit $?"
+done
@@ -0,0 +1,3 @@
+./testbin -j1 --color=always echo ::: a 'b c'
+NO_COLOR=1 ./testbin -j1 --color echo ::: d
+./testbin -j1 --color=never echo ::: e
@@ -0,0 +1,21 @@
+# A command read from a file runs as though given on the command line.
+cat > command_file.cmd <<'END'
+# Show what each picture would be called.
+printf '%s -> %s (%s)\n'
+    {} {/.}.png {#}
+END
+./testbin -j1 -f command_file.cmd ::: a.jpg 'dir/b c.jpg' > command_file.f
+./testbin -j1 printf '%s -> %s (%s)\n' {} {/.}.png {#} \
+    ::: a.jpg 'dir/b c.jpg' > command_file.inline
+cmp command_file.f command_file.inline && cat command_file.f
+printf '%s\n' a.jpg b.jpg | ./testbin -j1 --command-file command_file.cmd
+./testbin --dry-run -f command_file.cmd ::: a.jpg
+./testbin --dry-run printf '%s -> %s (%s)\n' {} {/.}.png {#} ::: a.jpg
+./testbin -f command_file.cmd echo ::: a
+echo "exit $?"
+printf '# nothing\n\n' > command_file.cmd
+./testbin -f command_file.cmd ::: a
+echo "exit $?"
+rm command_file.cmd command_file.f command_file.inline
+./testbin -f command_file.missing ::: a
+echo "exit $?"
@@ -0,0 +1,34 @@
+for shell in bash zsh fish; do
+    ./testbin --completions $shell > completions.tmp
+    echo "exit $?"
+    cat completions.tmp
+    # Check the syntax with the shell if it is installed.
+    if command -v $shell > /dev/null; then
+        $shell -n completions.tmp || echo "$shell: syntax error"
+    fi
+done
+
+# Complete the last word of some command lines as bash would.
+./testbin --completions bash > completions.tmp
+bash <<'END'
+. ./completions.tmp
+complete_words() {
+    COMP_WORDS=("$@")
+    COMP_CWORD=$(($# - 1))
+    COMPREPLY=()
+    _ljobs
+    echo "$*: ${COMPREPLY[*]}"
+}
+complete_words ljobs --sk
+complete_words ljobs -k --col
+complete_words ljobs --color = al
+complete_words ljobs --net ""
+complete_words ljobs -kj 4 --ionice id
+complete_words ljobs -j4 --output-separators = ""
+complete_words ljobs --skip 2 echo --sk
+complete_words ljobs -- --sk
+complete_words ljobs --completions ""
+END
+rm -f completions.tmp
+./testbin --completions tcsh
+echo "exit $?"
@@ -0,0 +1,33 @@
+# Defaults come from the config file, then LJOBS, then the command line,
+# with the last of them winning.
+XDG_CONFIG_HOME=$PWD/config.tmp
+export XDG_CONFIG_HOME
+unset LJOBS
+mkdir -p config.tmp/ljobs
+cat > config.tmp/ljobs/config <<'END'
+# Defaults for every run.
+--tag
+--label 'cfg {}'
+-j 3
+
+--tag-delimiter :
+END
+paths() {
+    sed "s|$PWD/||"
+}
+
+./testbin --dump-config echo | paths
+LJOBS="--label 'env {}' -j2" ./testbin --dump-config -j1 echo | paths
+./testbin -j1 echo ::: a
+LJOBS="--label 'env {}'" ./testbin -j1 echo ::: a
+LJOBS="--label 'env {}'" ./testbin -j1 --label 'cli {}' echo ::: a
+LJOBS="--label 'env {}'" ./testbin -j1 --no-config echo ::: a
+LJOBS="-v" ./testbin -v -j1 echo ::: a 2>&1 >/dev/null | grep ')$' | paths
+LJOBS="-j" ./testbin echo ::: a
+echo "exit $?"
+LJOBS="--tag 'a" ./testbin echo ::: a
+echo "exit $?"
+echo 'echo' >> config.tmp/ljobs/config
+./testbin echo ::: a 2>&1 | paths
+./testbin --no-config echo ::: a
+rm -r config.tmp
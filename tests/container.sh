@@ -0,0 +1,37 @@
+# A stand-in for podman that shows its arguments, then runs the command
+# after the image "img" here with any --env variables set.  It fails to run
+# the image "missing".
+mkdir -p container.d
+cat > container.d/podman <<'END'
+#!/bin/sh
+echo "podman $*" | sed "s|$PWD|PWD|g" >&2
+while test "$1" != img && test "$1" != missing; do
+    case $1 in
+    --env=*) export "${1#--env=}";;
+    --env) shift; export "$1";;
+    esac
+    shift
+done
+test "$1" = missing && exit 125
+shift
+exec "$@"
+END
+chmod +x container.d/podman
+PATH=$PWD/container.d:$PATH
+./testbin -n --container img echo ::: a | sed "s|$PWD|PWD|g"
+./testbin -j1 --container img echo ::: a b
+./testbin -j1 --container img --container-arg --env=T={} sh -c 'echo $T' \
+    ::: c
+./testbin -j1 --container img --label 'job{#}' sh -c 'echo $LJOBS_LABEL' \
+    ::: f
+./testbin -j2 --start-slot-env-offset 3 --container img \
+    sh -c 'echo $LJOBS_SLOT' ::: g
+./testbin --container missing true ::: x
+echo "exit $?"
+./testbin --container img sh -c 'exit 126' ::: x
+echo "exit $?"
+./testbin --container img container.d/nonexistent ::: x 2>&1 | grep ljobs
+./testbin --container-engine container.d/podman --container img echo ::: d
+PATH=$PWD/container.d/none ./testbin --container img echo ::: e
+echo "exit $?"
+rm -r container.d
@@ -0,0 +1,12 @@
+./testbin --count-failures-only -k sh -c 'exit $0' ::: 0 3 5 0 1
+echo "exit $?"
+# Without --keep-going, tasks stop being started after the first failure.
+./testbin --count-failures-only -j1 sh -c 'exit $0' ::: 0 3 5
+echo "exit $?"
+./testbin --count-failures-only -k true ::: a b
+echo "exit $?"
+# The count is capped.
+seq 300 | ./testbin --count-failures-only -k -j8 false
+echo "exit $?"
+./testbin --count-failures-only --keep-going-default-exit=2 true ::: a
+echo "exit $?"
@@ -0,0 +1,10 @@
+./testbin -j1 echo {cwd}/{} ::: a 'b c' | sed "s|^$PWD/|PWD/|"
+./testbin -j1 echo {cwd} ::: a | sed "s|^$PWD\$|PWD|"
+# Relative paths cannot be followed from a directory that is gone, and a
+# shell started there would complain.
+ljobs=${LJOBS_BIN:-$PWD/../rust/target/debug/ljobs}
+mkdir cwd.gone
+cd cwd.gone
+rmdir ../cwd.gone
+"$ljobs" echo {cwd} ::: a
+echo "exit $?"
@@ -0,0 +1,4 @@
+./testbin -j1 -k --dots sh -c 'exit $0' ::: 0 1 0 2>&1
+echo "exit $?"
+./testbin -j1 --dots -c 'kill $$' ::: a 2>&1
+echo "exit $?"
@@ -0,0 +1,4 @@
+./testbin --dump-args -j 3 echo x ::: a 'b c' | grep -E 'maxjobs|cmd|^tasks'
+./testbin --dump-args --jobs=5 echo </dev/null | grep -E 'maxjobs|^tasks'
+./testbin --dump-args echo ::: a >/dev/null
+echo "exit $?"
@@ -0,0 +1 @@
+./testbin -j3 --echo true ::: a 'b c' d 2>&1
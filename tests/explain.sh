@@ -0,0 +1,9 @@
+./testbin --explain --colsep , convert -q '{2}' '{}' '{/.}.png' \
+    '{//}/{.}' 'n{#}' '{3}' ::: 'photos/cat.jpg,90'
+./testbin --explain-task 'a b' --label 'job{#}' echo '{/}' '{label}' '{x}'
+./testbin --explain echo ::: first second
+echo first second | SHELL=/bin/sh ./testbin --explain -c 'echo "$1"'
+./testbin --explain --json-input --task-prefix '{"n":' --task-suffix '}' \
+    echo '{.n}' ::: 7
+./testbin --explain echo '{}' < /dev/null
+echo "exit $?"
@@ -0,0 +1,20 @@
+# Without tasks nothing is run, as with xargs --no-run-if-empty.
+./testbin echo run < /dev/null
+echo "exit $?"
+./testbin echo run :::
+echo "exit $?"
+./testbin -r echo run < /dev/null
+echo "exit $?"
+./testbin --fail-if-empty echo run < /dev/null
+echo "exit $?"
+./testbin --fail-if-empty echo run :::
+echo "exit $?"
+# Blank tasks are dropped without being numbered.
+printf 'a\n\n \nb\n' | ./testbin -j1 --skip-empty echo '{#}'
+echo "exit $?"
+printf '\n \t\n' | ./testbin --skip-empty --fail-if-empty echo run
+echo "exit $?"
+printf ' \n' | ./testbin --skip-empty --fail-if-empty echo run
+echo "exit $?"
+printf 'a\n' | ./testbin --skip-empty --fail-if-empty echo run
+echo "exit $?"
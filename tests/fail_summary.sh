@@ -0,0 +1,7 @@
+./testbin -j2 -k --fail-summary sh -c 'eval "$1"' - ::: true 'exit 3' true \
+    'kill $$'
+echo "exit $?"
+./testbin -j1 --fail-summary sh -c 'exit $1' - ::: 0 1 2
+echo "exit $?"
+./testbin --fail-summary true ::: a b
+echo "exit $?"
@@ -0,0 +1,29 @@
+./testbin -j1 --filter-tasks '^a' echo '{#}' ::: a1 b1 a2 b2 a3
+echo "exit $?"
+./testbin -j1 --exclude 2 echo '{#}' ::: a1 b1 a2 b2 a3
+echo "exit $?"
+# Both together, and with the numbers of the unfiltered places.
+./testbin -j1 --filter-tasks '^a' --exclude 2 echo '{#}' ::: a1 b1 a2 b2 a3
+echo "exit $?"
+./testbin -j1 --filter-tasks '^a' --exclude 2 --keep-numbering \
+    echo '{#}' ::: a1 b1 a2 b2 a3
+echo "exit $?"
+./testbin -j1 --exclude b --keep-numbering --max-tasks 3 \
+    echo '{#}' ::: a1 b1 a2 b2 a3
+echo "exit $?"
+# Matched against the whole record, before it is split into columns.
+printf 'x,1\ny,2\nx,3\n' |
+    ./testbin -j1 --colsep , --filter-tasks '^x,' echo '{2}'
+echo "exit $?"
+printf 'x,1\ny,2\nx,3\n' |
+    ./testbin -j1 --colsep , --exclude ',[12]$' echo '{1}'
+echo "exit $?"
+./testbin --exclude . --summary-json filter_tasks.tmp --fail-if-empty \
+    echo ::: a b
+echo "exit $?"
+sed 's/"wall_time":[0-9.]*,"throughput":[0-9.]*/TIMES/' filter_tasks.tmp
+rm -f filter_tasks.tmp
+./testbin --filter-tasks '(' echo ::: a
+echo "exit $?"
+./testbin --keep-numbering echo ::: a
+echo "exit $?"
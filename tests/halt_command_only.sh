@@ -0,0 +1,6 @@
+./testbin -j1 --halt-on-nonzero-from-command-only ./no-such-command ::: a b
+echo "exit $?"
+./testbin -j1 ./no-such-command ::: a b
+echo "exit $?"
+./testbin -j1 --halt-on-nonzero-from-command-only sh -c 'echo $0; exit 2' ::: a b
+echo "exit $?"
@@ -0,0 +1,7 @@
+# Task a fails while task b is still running, which is waited for when the
+# halt timeout is too long to be reached.
+./testbin -j2 --halt-timeout 1e19 -c \
+    'test $1 = a && exit 1; sleep 0.2; echo $1' ::: a b
+echo "exit $?"
+./testbin -j2 --halt-timeout 1e20 true ::: a
+echo "exit $?"
@@ -0,0 +1,2 @@
+./testbin -j2 echo ::: $(seq 1000) | head -1 | sed 's/[0-9]*/N/'
+./testbin -n echo ::: $(seq 100000) | head -1
@@ -0,0 +1,18 @@
+# Without a command, each task is a command line for the shell.
+SHELL=/bin/sh
+export SHELL
+cat > implicit_shell.tmp <<'END'
+echo one
+echo "two $((1 + 1))" | tr a-z A-Z
+false
+echo four; exit 3
+echo five >&2
+END
+./testbin -j1 -k < implicit_shell.tmp
+echo "exit $?"
+./testbin -j1 -a implicit_shell.tmp
+echo "exit $?"
+./testbin --dry-run ::: 'echo a b'
+./testbin --no-implicit-shell ::: 'echo a'
+echo "exit $?"
+rm implicit_shell.tmp
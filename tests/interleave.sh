@@ -0,0 +1,4 @@
+printf 'B1\nB2\n' | ./testbin -j1 -a interleave.inp -a - echo
+printf 'B1\nB2\n' | ./testbin -j1 --interleave -a interleave.inp -a - echo
+./testbin -j1 -a no-such-file echo
+echo "exit $?"
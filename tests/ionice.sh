@@ -0,0 +1,7 @@
+./testbin --ionice idle:2 true ::: x
+echo "exit $?"
+./testbin --ionice best-effort:8 true ::: x
+echo "exit $?"
+./testbin --ionice lazy true ::: x
+echo "exit $?"
+./testbin --ionice best-effort:7 echo ::: ok
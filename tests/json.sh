@@ -0,0 +1,5 @@
+printf '%s\n' '{"name":"a","size":{"w":1}}' '{"name":"b c"}' | ./testbin -j1 --json-input echo {.name}:{.size.w}
+printf '%s\n' '{"name":"a"}' | ./testbin -j1 --json-input --strict echo {.size}
+echo "exit $?"
+printf '%s\n' 'a' | ./testbin -j1 --json-input echo {.name}
+echo "exit $?"
@@ -0,0 +1,8 @@
+./testbin -k false ::: $(seq 300)
+echo "exit $?"
+./testbin -k --keep-going-default-exit false ::: $(seq 300)
+echo "exit $?"
+./testbin -k --keep-going-default-exit=3 false ::: a b
+echo "exit $?"
+./testbin -k --keep-going-default-exit true ::: a b
+echo "exit $?"
@@ -0,0 +1,8 @@
+# The label in the environment of each job is the one in the manifest.
+./testbin -j1 --label 'batch42:{#}:{/.}' --manifest label.manifest \
+    sh -c 'echo "$LJOBS_LABEL"' ::: photos/cat.jpg dog.png > label.env
+cut -f6 label.manifest | diff label.env - && cat label.env
+rm -f label.env label.manifest
+./testbin -j1 --label 'job{#}' --tag echo '[{label}]' ::: a b
+./testbin -j1 -v --label '{.}' true ::: x.txt
+./testbin -j1 --label fixed sh -c 'echo "$LJOBS_LABEL"' ::: a
@@ -0,0 +1,13 @@
+# Megabytes of input without a newline.
+long() {
+    head -c 3000000 /dev/zero | tr '\0' x
+    printf '\nshort\n'
+}
+long | ./testbin -j1 --stdin-buffer-line-limit 1K echo
+echo "exit $?"
+long | ./testbin -j1 --stdin-buffer-line-limit 4 --long-line-policy truncate \
+    echo
+echo "exit $?"
+printf 'abcd\n' | ./testbin --stdin-buffer-line-limit 4 echo
+./testbin --long-line-policy truncate echo ::: a
+./testbin --stdin-buffer-line-limit 0 echo ::: a
@@ -0,0 +1,10 @@
+./testbin -j1 -k --manifest manifest.tmp1 ./echo2 ::: a 'b c' >/dev/null 2>&1
+./testbin -j1 -k --manifest manifest.tmp2 ./echo2 ::: a 'b c' >/dev/null 2>&1
+# The CPU time and peak memory of each command vary from run to run.
+cut -f1-3,6,7 manifest.tmp1 > manifest.tmp3
+cut -f1-3,6,7 manifest.tmp2 | cmp manifest.tmp3 - && cat manifest.tmp3
+awk -F '\t' '$4 !~ /^[0-9]+\.[0-9][0-9][0-9]$/ || $5 !~ /^[1-9][0-9]*$/' \
+    manifest.tmp1
+./testbin -j1 -k --manifest manifest.tmp1 false ::: x
+cut -f1-3,6,7 manifest.tmp1
+rm -f manifest.tmp1 manifest.tmp2 manifest.tmp3
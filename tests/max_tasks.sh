@@ -0,0 +1,4 @@
+./testbin -j1 --head 2 echo ::: $(seq 100)
+seq 100 | ./testbin -j1 --max-tasks 3 echo
+./testbin -n --max-tasks 0 echo ::: a
+echo "exit $?"
@@ -0,0 +1,4 @@
+./testbin --net all true ::: x
+echo "exit $?"
+./testbin --net host echo ::: ok
+echo "exit $?"
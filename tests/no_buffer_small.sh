@@ -0,0 +1,6 @@
+SHELL=/bin/sh ./testbin -j1 --output-separators both --no-buffer-small=16 -c 'echo $1; echo $1$1$1$1 >&2' ::: a bbbb
+echo "exit $?"
+SHELL=/bin/sh ./testbin -j1 --output-separators stdout --no-buffer-small -c 'printf "%s\n" $1' ::: x
+echo "exit $?"
+./testbin --no-buffer-small=lots true ::: x
+echo "exit $?"
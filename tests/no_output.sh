@@ -0,0 +1,6 @@
+SHELL=/bin/sh ./testbin -j1 -k --fail-on-no-output -c 'test "$1" = b || echo $1' ::: a b c
+echo "exit $?"
+SHELL=/bin/sh ./testbin -j1 --fail-on-no-output -c 'exit 3' ::: a
+echo "exit $?"
+./testbin -j1 -k true ::: a b
+echo "exit $?"
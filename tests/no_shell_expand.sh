@@ -0,0 +1,8 @@
+HOME=/home/nobody SHELL=/bin/sh ./testbin -j1 -c --no-shell-expand 'echo $HOME `id` ~ *' ::: a
+echo "exit $?"
+x=1 SHELL=/bin/sh ./testbin -j1 -c --no-shell-expand "echo '\$x' \"\$x \\\$x\" \\\$x" ::: '$x'
+echo "exit $?"
+SHELL=/bin/sh ./testbin -j1 -c --no-shell-expand 'echo {} {#}' a b ::: ignored
+echo "exit $?"
+./testbin --no-shell-expand echo '$HOME' ::: a
+echo "exit $?"
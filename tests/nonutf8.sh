@@ -0,0 +1,6 @@
+# Arguments which are not valid UTF-8 are passed through unchanged.
+x=$(printf 'a\377b')
+y=$(printf 'c\376')
+SHELL=/bin/sh ./testbin -j1 -c 'printf "%s|%s" "$1" "$2" | od -An -tx1' "$y" {} ::: "$x"
+printf '%s\n' "$x" | SHELL=/bin/sh ./testbin -j1 -c 'printf "%s" "$1" | od -An -tx1'
+./testbin -j1 -n echo "$y" ::: "$x"
@@ -0,0 +1,7 @@
+./testbin -j1 --oom-score-adj 500 sh -c 'cat /proc/self/oom_score_adj' \
+    ::: a b
+./testbin -vv --oom-score-adj 1000 true ::: c 2>&1 | grep start
+./testbin --oom-score-adj 1001 true ::: x
+echo "exit $?"
+./testbin --oom-score-adj high true ::: x
+echo "exit $?"
@@ -0,0 +1,13 @@
+./testbin --results-path output_compress.tmp/{}.out --output-compress \
+    printf '%s\n' {} {.} ::: a.txt b.txt
+ls output_compress.tmp
+gzip -dc output_compress.tmp/a.txt.out.gz
+gzip -dc output_compress.tmp/b.txt.out.gz
+# Large output is compressed as it streams from where it was spilled.
+./testbin --spill-size 1K --results-path output_compress.tmp/{} \
+    --output-compress seq 1 ::: 100000
+gzip -dc output_compress.tmp/100000.gz | cksum
+seq 1 100000 | cksum
+rm -r output_compress.tmp
+./testbin --output-compress echo ::: a
+echo "exit $?"
@@ -0,0 +1,9 @@
+printf 'a b\0c\nd\0' | ./testbin -j1 -0 sh -c 'echo "[$1]"' sh
+./testbin -j1 --halt never --quote -c 'echo "$1"; false' ::: a b
+echo "exit $?"
+./testbin -j1 --max-args 1 -c 'echo "$1"' ::: 'x y'
+./testbin --keep-order echo ::: a
+echo "exit $?"
+./testbin --halt now,fail=3 echo ::: a
+echo "exit $?"
+./testbin echo --quote ::: a
@@ -0,0 +1,27 @@
+# Records of a varying number of lines, each starting with '>'.
+awk 'BEGIN {
+    for (i = 1; i <= 50; i++) {
+        print ">" i
+        for (j = 0; j < i % 7; j++) print "ACGT"
+    }
+}' > pipe.tmp
+# No record is split between two commands: each block starts with a
+# record, and the blocks in order are the input.
+mkdir pipe.d
+./testbin -j4 --pipe --block-size 40 --recstart '>' sh -c '
+    cat > pipe.d/{#}
+    sed -n "1{/^>/!s/^/block {#} starts in a record: /p;}" pipe.d/{#}' \
+    < pipe.tmp
+echo "blocks $(ls pipe.d | wc -l)"
+ls pipe.d | sort -n | (cd pipe.d && xargs cat) | cmp - pipe.tmp && echo same
+# Lines by default, a block at a time.
+seq 1 10 | ./testbin -j1 --pipe --block-size 8 sh -c 'echo "{#}: $(paste -sd,)"'
+# A line longer than the block size is kept whole.
+printf 'a\nbbbbbbbbbb\nc\n' | ./testbin -j1 --pipe --block-size 3 wc -c
+rm -r pipe.tmp pipe.d
+./testbin --recstart '>' echo ::: a
+echo "exit $?"
+./testbin --pipe --block-size 0 cat < /dev/null
+echo "exit $?"
+./testbin --pipe cat ::: a
+echo "exit $?"
@@ -0,0 +1,35 @@
+# Profiles are taken after the defaults and LJOBS, in the order given,
+# and before the command line.
+XDG_CONFIG_HOME=$PWD/profile.tmp
+export XDG_CONFIG_HOME
+unset LJOBS
+mkdir -p profile.tmp/ljobs
+cat > profile.tmp/ljobs/config <<'END'
+-j 3
+--label default
+[profile.net]
+--label net
+--tag
+[profile.cpu]
+-j 1
+--label cpu
+END
+paths() {
+    sed "s|$PWD/||"
+}
+
+./testbin --profile net --dump-config echo | paths
+./testbin --profile net --profile cpu --dump-config echo | paths
+./testbin --profile cpu --profile net -j2 --dump-config echo | paths
+LJOBS="--profile cpu --label env" ./testbin --dump-config echo | paths
+./testbin --profile cpu --profile net echo ::: a
+./testbin --profile gpu echo ::: a
+echo "exit $?"
+./testbin --no-config --profile net echo ::: a
+echo "exit $?"
+echo '[net]' >> profile.tmp/ljobs/config
+./testbin echo ::: a 2>&1 | paths
+printf -- '-j 2\n' > profile.tmp/ljobs/config
+./testbin --profile net echo ::: a
+echo "exit $?"
+rm -r profile.tmp
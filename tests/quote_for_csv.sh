@@ -0,0 +1,7 @@
+# The duration column varies, so it is blanked.
+./testbin -j1 --quote-for-csv printf '%s,x\n' ::: a 'b "c"' |
+    sed 's/,[0-9]*\.[0-9]*,"/,D,"/'
+SHELL=/bin/sh ./testbin -j1 --quote-for-csv -c 'printf "1\n2\n"; exit 3' \
+    ::: multi | sed 's/,[0-9]*\.[0-9]*,"/,D,"/'
+./testbin --quote-for-csv --results-path 'r/{}' echo ::: a
+echo "exit $?"
@@ -0,0 +1,7 @@
+# A ramp too long for the clock never opens the second slot.
+./testbin --ramp 1.5e19 -j2 echo ::: a b
+echo "exit $?"
+./testbin --ramp 1e20 -j2 echo ::: a b
+echo "exit $?"
+./testbin --ramp 1m -j1 echo ::: c
+echo "exit $?"
@@ -0,0 +1,11 @@
+rm -rf results.tmp
+./testbin -j2 --results-path 'results.tmp/{//}/{/.}.log' echo ::: a/x.c a/b/y.c z
+find results.tmp -type f | sort
+cat results.tmp/a/x.log results.tmp/a/b/y.log results.tmp/z.log
+# Standard error is still printed.
+SHELL=/bin/sh ./testbin --results-path 'results.tmp/{}' \
+    -c 'echo "out $1"; echo "err $1" >&2' ::: q 2>&1
+cat results.tmp/q
+./testbin --results-path results.tmp/out echo ::: a
+echo "exit $?"
+rm -rf results.tmp
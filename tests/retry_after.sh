@@ -0,0 +1,24 @@
+# Task a fails the first time, asking to be tried again after a second.
+rm -f retry_after.tmp
+cat > retry_after.job <<'END'
+if test "$1" = a && ! test -e retry_after.tmp; then
+    touch retry_after.tmp
+    echo "Retry-After: 1"
+    exit 1
+fi
+echo "$1"
+END
+./testbin -v -j1 --retry-after-pattern '^Retry-After: *([0-9.]+)' \
+    sh retry_after.job ::: a b 2>&1
+echo "exit $?"
+# Without a number to go by, the task is not tried again.
+./testbin --retry-after-pattern '^Retry-After' sh -c \
+    'echo "Retry-After: later"; exit 1' ::: x
+echo "exit $?"
+./testbin --retry-after-pattern '(' echo ::: x 2>&1 | sed -n 1p
+rm -f retry_after.tmp retry_after.job
+# Task a asks to be tried again as the last one running.
+rm -f retry_after.tmp
+./testbin -j2 --retry-after-pattern '^Retry-After: *([0-9.]+)' sh -c '[ "$0" = b ] && exit 0; sleep 0.3; [ -e retry_after.tmp ] && echo ok || { touch retry_after.tmp; echo "Retry-After: 0.1"; exit 1; }' ::: a b
+echo "exit $?"
+rm -f retry_after.tmp
@@ -0,0 +1,14 @@
+rm -f retry_until.tmp
+./testbin --retry-until 10s -c 'n=$(cat retry_until.tmp 2>/dev/null || echo 0); echo $((n + 1)) > retry_until.tmp; echo "attempt $n"; test $n -ge 2' ::: a
+echo "exit $?"
+cat retry_until.tmp
+rm -f retry_until.tmp
+./testbin --retry-until 0.2 -c 'echo failed; exit 3' ::: a
+echo "exit $?"
+./testbin --retry-until soon true ::: a
+echo "exit $?"
+# Task a fails as the last one running, after all tasks have started.
+rm -f retry_until.tmp
+./testbin -j2 --retry-until 5 sh -c '[ "$0" = b ] && exit 0; sleep 0.3; [ -e retry_until.tmp ] && echo ok || { touch retry_until.tmp; exit 1; }' ::: a b
+echo "exit $?"
+rm -f retry_until.tmp
@@ -0,0 +1,5 @@
+touch run_if.a 'run_if c'
+SHELL=/bin/sh ./testbin -j1 --run-if 'test -f {}' echo ::: run_if.a run_if.b 'run_if c'
+echo "exit $?"
+SHELL=/bin/sh ./testbin -j1 -v --run-if 'test {#} = 1' true ::: x y 2>&1
+rm -f run_if.a 'run_if c'
@@ -0,0 +1 @@
+./testbin --dump-args --allow-read /usr --allow-read '{//}' --allow-write 'out/{/.}' --sandbox-best-effort echo ::: a | sed -n '/allowread/,/sandboxbest/p'
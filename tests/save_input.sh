@@ -0,0 +1,8 @@
+printf 'a\nb\nc' | ./testbin -j1 --skip 1 --save-input save_input.tmp echo
+printf 'a\nb\nc' | cmp save_input.tmp - && echo same
+printf 'B1\nB2\n' | ./testbin -j1 --interleave --save-input save_input.tmp -a interleave.inp -a - echo > /dev/null
+cat save_input.tmp
+rm -f save_input.tmp
+./testbin --save-input save_input.tmp echo ::: a
+echo "exit $?"
+test -e save_input.tmp && echo created
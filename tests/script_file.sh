@@ -0,0 +1,17 @@
+cat > script_file.sh.in <<'END'
+printf 'task %s: ' {#}
+echo {} | tr a-z A-Z
+END
+./testbin -j1 --script-file script_file.sh.in ::: "it's a" 'b $HOME'
+SHELL=/bin/sh ./testbin -n --script-file script_file.sh.in ::: c
+# Too large to pass to the shell as an argument.
+{
+    awk 'BEGIN { for (i = 0; i < 5000; i++) printf "# %064d\n", i }'
+    echo 'echo "large {.}"'
+} > script_file.sh.in
+./testbin -j1 --script-file script_file.sh.in ::: d.txt e.txt
+rm script_file.sh.in
+./testbin --script-file script_file.sh.in ::: f
+echo "exit $?"
+./testbin --script-file script_file.sh.in echo ::: f
+echo "exit $?"
@@ -0,0 +1,20 @@
+cat > self_contained.tmp <<'END'
+# Resize some pictures.
+printf '%s -> %s\n' '{}' '{.}.png'
+a.jpg
+b c.jpg
+d.jpg
+END
+./testbin -j1 --self-contained self_contained.tmp
+./testbin -j1 --self-contained self_contained.tmp echo
+echo "exit $?"
+./testbin -j1 --self-contained self_contained.tmp ::: x
+echo "exit $?"
+./testbin -j1 --self-contained self_contained.tmp -a /dev/null
+echo "exit $?"
+printf '# nothing\n\n' > self_contained.tmp
+./testbin --self-contained self_contained.tmp
+echo "exit $?"
+./testbin --self-contained self_contained.missing
+echo "exit $?"
+rm self_contained.tmp
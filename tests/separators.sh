@@ -0,0 +1,3 @@
+./testbin -j1 --output-separators=stdout sh -c 'echo out; echo err >&2' ::: a 2>&1
+./testbin -j1 --output-separators=both sh -c 'echo out; echo err >&2' ::: a 2>&1
+./testbin -j1 -v --output-separators=none sh -c 'echo out; echo err >&2' ::: a 2>&1
@@ -0,0 +1,18 @@
+./testbin -j1 --seq 1-3 echo task
+./testbin -j1 --seq 3 echo '{#}:{}'
+./testbin -j1 --input-range '10 1 -4' echo
+./testbin -j1 --seq -2--1 echo
+./testbin -j1 --seq 2-1 echo
+# LAST alone only counts up, so these have no tasks.
+./testbin -j1 --seq 0 echo
+echo "exit $?"
+./testbin -j1 --seq -2 echo
+echo "exit $?"
+./testbin -j1 --seq '9223372036854775806 9223372036854775807' echo
+./testbin --seq 1-3 --head 2 echo | sort
+./testbin --seq 1-3 echo ::: a
+echo "exit $?"
+./testbin --seq 1-x echo
+echo "exit $?"
+./testbin --seq '1 3 0' echo
+echo "exit $?"
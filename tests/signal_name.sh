@@ -0,0 +1,2 @@
+./testbin -j1 -v sh -c 'kill -KILL $$' ::: a 2>&1 | cut -f1
+./testbin -j1 -v sh -c 'kill -SEGV $$' ::: a 2>&1 | cut -f1
@@ -0,0 +1,4 @@
+./testbin -j1 --skip 2 --head 2 echo {#} {} ::: a b c d e f
+seq 10 | ./testbin -j1 --skip 8 echo
+./testbin --skip 3 echo ::: a b
+echo "exit $?"
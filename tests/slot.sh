@@ -0,0 +1,14 @@
+# Each running job holds the lowest job slot that is free.
+./testbin -j4 sh -c 'echo "$LJOBS_SLOT {%}"; sleep 0.2' ::: 1 2 3 4 | sort
+./testbin -j4 --start-slot-env-offset 0 sh -c 'echo {%}; sleep 0.2' \
+    ::: 1 2 3 4 | sort
+./testbin -j2 --start-slot-env-offset 1 sh -c 'echo "$LJOBS_SLOT"' \
+    ::: 1 2 3 4 5 | sort -u
+./testbin --dry-run --start-slot-env-offset 5 \
+    env CUDA_VISIBLE_DEVICES={%} cmd ::: a
+./testbin --start-slot-env-offset -1 echo ::: a
+echo "exit $?"
+./testbin -j2 --start-slot-env-offset 18446744073709551615 true ::: a
+echo "exit $?"
+./testbin -j1 --start-slot-env-offset 18446744073709551614 \
+    sh -c 'echo $LJOBS_SLOT' ::: a
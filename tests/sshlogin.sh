@@ -0,0 +1,25 @@
+# A stand-in for ssh that runs the command line here, except that it fails
+# to connect the first time it is asked to run a command with "flaky" in it.
+mkdir -p sshlogin.d
+cat > sshlogin.d/ssh <<'END'
+#!/bin/sh
+echo "ssh $1 $2 $3" >&2
+shift 3
+case $1 in
+*flaky*)
+    if ! test -f sshlogin.d/connected
+    then
+        touch sshlogin.d/connected
+        exit 255
+    fi
+esac
+exec /bin/sh -c "$1"
+END
+chmod +x sshlogin.d/ssh
+PATH=$PWD/sshlogin.d:$PATH
+./testbin -j1 -n --sshlogin me@there echo "it's" ::: 'a b'
+./testbin -j1 --sshlogin me@there echo {host} "it's" {} ::: 'a b' '$HOME' 2>&1
+./testbin -j1 -v --sshlogin me@there sh -c 'echo $0' ::: flaky 2>&1
+./testbin -j1 -k --sshlogin me@there exit ::: 3 255 2>&1
+echo "exit $?"
+rm -r sshlogin.d
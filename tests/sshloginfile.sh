@@ -0,0 +1,34 @@
+# A stand-in for ssh that runs the command line here, keeping count of the
+# commands running for each login at once.  It fails to connect to "down".
+mkdir -p sshloginfile.d
+cat > sshloginfile.d/ssh <<'END'
+#!/bin/sh
+d=sshloginfile.d
+test "$3" = down && exit 255
+touch $d/run.$3.$$
+# Another command may finish between the glob and ls.
+echo $(ls $d/run.$3.* 2>/dev/null | wc -l) >> $d/count.$3
+sleep 0.3
+rm $d/run.$3.$$
+exec /bin/sh -c "$4"
+END
+chmod +x sshloginfile.d/ssh
+PATH=$PWD/sshloginfile.d:$PATH
+cat > sshloginfile.d/logins <<'END'
+# Two names for this machine.
+2/one
+
+1/two
+END
+./testbin -j10 --sshloginfile sshloginfile.d/logins echo ::: 1 2 3 4 5 6 |
+    sort
+echo "most on one: $(sort -n sshloginfile.d/count.one | tail -1)"
+echo "most on two: $(sort -n sshloginfile.d/count.two | tail -1)"
+printf '1/down\n1/up\n' > sshloginfile.d/logins
+./testbin -j2 --sshloginfile sshloginfile.d/logins echo {host} {} ::: a b c d \
+    2> sshloginfile.d/err | sort
+sort -u sshloginfile.d/err
+echo bad/host > sshloginfile.d/logins
+./testbin --sshloginfile sshloginfile.d/logins echo ::: a
+echo "exit $?"
+rm -r sshloginfile.d
@@ -0,0 +1,6 @@
+SHELL=/bin/sh ./testbin -j1 --warn-on-stderr -c 'echo $1 >&2' ::: a ''
+echo "exit $?"
+SHELL=/bin/sh ./testbin -j1 -k --fail-on-stderr -c 'echo $1; if test "$1" = b; then echo oops >&2; fi' ::: a b c
+echo "exit $?"
+./testbin -j1 true ::: a
+echo "exit $?"
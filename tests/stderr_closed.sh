@@ -0,0 +1,8 @@
+# Warnings to a standard error that is closed or full are dropped, and the
+# run goes on.
+{ ./testbin -v -j2 true ::: $(seq 300) 2>&1 >/dev/null
+  echo "exit $?" > stderr_closed.tmp; } | head -1
+cat stderr_closed.tmp
+rm -f stderr_closed.tmp
+./testbin -v echo ::: a 2>/dev/full
+echo "exit $?"
@@ -0,0 +1,17 @@
+./testbin -j1 --colsep : --stdin-per-task-from-column 2 grep {1} \
+    ::: 'a:abc' 'x:xyz' 'q:abc'
+echo "exit $?"
+./testbin -j1 --colsep , --stdin-per-task-from-column 1 \
+    sh -c 'cat; echo " $0"' {2} ::: 'in one,out one' 'in two,' ',empty'
+# A task without the column.
+./testbin --colsep , --stdin-per-task-from-column 3 sh -c 'wc -c' {1} \
+    ::: a,b
+./testbin --strict --colsep , --stdin-per-task-from-column 3 cat {1} \
+    ::: a,b
+echo "exit $?"
+# A command that does not read its input.
+./testbin --colsep , --stdin-per-task-from-column 1 echo {2} ::: in,out
+./testbin --stdin-per-task-from-column 1 cat ::: a
+echo "exit $?"
+./testbin --colsep , --stdin-per-task-from-column 0 cat ::: a
+echo "exit $?"
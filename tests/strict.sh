@@ -0,0 +1,4 @@
+./testbin -j1 --colsep , echo {2}:{1} ::: a,b x
+./testbin -j1 --colsep , --strict echo {2}-{1} ::: a,b c,d
+./testbin -j1 --colsep , --strict echo {3} ::: a,b
+echo "exit $?"
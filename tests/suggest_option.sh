@@ -0,0 +1,17 @@
+./testbin --drg-run echo ::: x
+echo "exit $?"
+./testbin --tga echo ::: x
+echo "exit $?"
+# Too far from any option, or short.
+./testbin --zzzzzz echo ::: x
+echo "exit $?"
+./testbin -Q echo ::: x
+echo "exit $?"
+# A mistyped default names where it came from.
+LJOBS='--jbos 2' ./testbin echo ::: x
+echo "exit $?"
+mkdir -p suggest_option.tmp/ljobs
+echo '--halt-timout 5s' > suggest_option.tmp/ljobs/config
+XDG_CONFIG_HOME=$PWD/suggest_option.tmp ./testbin echo ::: x 2>&1 |
+    sed "s|$PWD/||"
+rm -r suggest_option.tmp
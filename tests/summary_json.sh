@@ -0,0 +1,8 @@
+SHELL=/bin/sh ./testbin -k --summary-json summary_json.tmp -c 'test $1 = s && kill -TERM $$; exit $1' ::: 0 1 s 0 > /dev/null
+echo "exit $?"
+sed 's/"wall_time":[0-9.]*,"throughput":[0-9.]*/TIMES/' summary_json.tmp
+rm -f summary_json.tmp
+./testbin --summary-json - --run-if 'test {} = b' echo ::: a b | sed 's/"wall_time":[0-9.]*,"throughput":[0-9.]*/TIMES/'
+echo "exit $?"
+./testbin -k --timeout 0.2 --summary-json - sleep ::: 0 5 | sed 's/"wall_time":[0-9.]*,"throughput":[0-9.]*/TIMES/'
+echo "exit $?"
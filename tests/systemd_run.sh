@@ -0,0 +1,22 @@
+# A stand-in for systemd-run that shows its arguments, then runs the
+# command after "--".
+mkdir -p systemd_run.d
+cat > systemd_run.d/systemd-run <<'END'
+#!/bin/sh
+echo "systemd-run $*" | sed 's/ljobs-[0-9]*-/ljobs-PID-/' >&2
+while test "$1" != --; do
+    shift
+done
+shift
+exec "$@"
+END
+chmod +x systemd_run.d/systemd-run
+PATH=$PWD/systemd_run.d:$PATH
+./testbin -n --systemd-run --systemd-prop MemoryMax=2G \
+    --systemd-prop CPUWeight=50 echo ::: a | sed 's/ljobs-[0-9]*-/ljobs-PID-/'
+./testbin -j1 --systemd-run echo ::: b c
+./testbin --systemd-run sh -c 'exit 3' ::: x
+echo "exit $?"
+./testbin --systemd-prop MemoryMax=2G echo ::: d
+echo "exit $?"
+rm -r systemd_run.d
@@ -0,0 +1,4 @@
+./testbin -j1 --tag-delimiter ': ' sh -c 'echo line {}; printf "last {}"' ::: a b
+echo
+./testbin -j1 --tag sh -c 'echo out {}; echo err {} >&2' ::: a
+./testbin -j1 --tag --tag-delimiter ' | ' echo ::: 'x y'
@@ -0,0 +1,2 @@
+./testbin -j1 --task-prefix data/ --task-suffix .txt echo {} {/.} ::: a b/c
+printf 'x\ny\n' | ./testbin -j1 --task-prefix '<' echo
@@ -0,0 +1,24 @@
+# The second task only finishes once the record of the first has been read,
+# or fails after five seconds.
+cat > tee_manifest.wait <<'END'
+test "$1" = quick && exit
+i=0
+until test -f tee_manifest.go
+do
+    i=$((i + 1))
+    test $i -gt 100 && exit 1
+    sleep 0.05
+done
+END
+rm -f tee_manifest.go
+./testbin -j2 --tee-manifest=3 sh tee_manifest.wait ::: quick waiting \
+    3>&1 >/dev/null |
+while read -r line
+do
+    printf '%s\n' "$line" | cut -f1,2
+    touch tee_manifest.go
+done
+rm -f tee_manifest.wait tee_manifest.go
+./testbin -j1 --tee-manifest echo ::: a b | cut -f1,2
+./testbin --tee-manifest=9 echo ::: a
+echo "exit $?"
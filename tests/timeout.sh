@@ -0,0 +1,22 @@
+# The second task runs past the timeout.
+for action in fail skip retry; do
+    ./testbin -j2 --timeout 0.3 --timeout-action $action sleep ::: 0 5
+    echo "$action: exit $?"
+done
+# A task that times out only the first time succeeds when retried.
+rm -f timeout.tmp
+./testbin --timeout 0.3 --timeout-action retry -c \
+    'if test -e timeout.tmp; then echo "$1 done"; else touch timeout.tmp; exec sleep 5; fi' \
+    ::: a
+echo "exit $?"
+rm -f timeout.tmp
+# A command that ignores SIGTERM is killed.
+./testbin --timeout 0.2 -c "trap '' TERM; exec sleep 3" ::: a
+echo "exit $?"
+./testbin --timeout-action skip true ::: a
+echo "exit $?"
+./testbin --timeout 0 true ::: a
+echo "exit $?"
+# A timeout too long to be reached is never reached.
+./testbin --timeout 1e19 echo ::: a
+echo "exit $?"
@@ -0,0 +1,27 @@
+# A stand-in for ssh that runs the command line in a directory standing in
+# for the home directory on the remote host.
+mkdir -p transfer.d/home
+cat > transfer.d/ssh <<'END'
+#!/bin/sh
+cd transfer.d/home || exit 255
+exec /bin/sh -c "$4"
+END
+chmod +x transfer.d/ssh
+PATH=$PWD/transfer.d:$PATH
+echo hello > transfer.d/a.txt
+echo world > transfer.d/b.txt
+./testbin -j1 --sshlogin there --transfer --return {}.up --cleanup \
+    sh -c 'tr a-z A-Z < $0 > $0.up' ::: transfer.d/a.txt transfer.d/b.txt
+cat transfer.d/a.txt.up transfer.d/b.txt.up
+ls -A transfer.d/home/.ljobs
+./testbin -j1 --sshlogin there --transfer --cleanup \
+    sh -c 'cat $0; exit 3' ::: transfer.d/a.txt
+echo "exit $?"
+ls -A transfer.d/home/.ljobs
+./testbin -j1 --sshlogin there --return missing.{#} --cleanup true ::: x \
+    2>&1 | grep ljobs
+./testbin -j1 -vv --sshlogin there --transfer echo ::: transfer.d/a.txt \
+    2>&1 >/dev/null | grep '^ljobs\[0\]: step' | sed 's/[0-9]*\.0/PID.0/g'
+./testbin --transfer echo ::: transfer.d/a.txt
+echo "exit $?"
+rm -r transfer.d